@@ -0,0 +1,23 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Compiles the current git commit into the binary as `SLOOP_GIT_HASH`, for `sloop version`.
+/// Builds from a crate tarball or without git simply leave it unset.
+fn main() {
+    for path in &[".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .filter(|hash| !hash.is_empty());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=SLOOP_GIT_HASH={}", hash);
+    }
+}
@@ -0,0 +1,121 @@
+use std::cell::RefCell;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Tells long-running loops to stop. Feed generation checks it between files, and hashing and
+/// uploads between chunks and parts, so a cancelled run ends within a chunk rather than after
+/// the next file. Code unwinding from the error cleans up as it would after any failure.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Fails with an `Interrupted` error once the token has been cancelled.
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        Ok(())
+    }
+}
+
+static INTERRUPT: OnceLock<CancelToken> = OnceLock::new();
+
+thread_local! {
+    /// The token of the run on this thread, set by `scoped`.
+    static CURRENT: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+/// The token Ctrl-C cancels, once `cancel_on_interrupt` has been called; until then it's never
+/// cancelled.
+pub fn on_interrupt() -> CancelToken {
+    INTERRUPT.get_or_init(CancelToken::default).clone()
+}
+
+/// The token that stops the run on this thread: the one `scoped` gave it, or else the one
+/// Ctrl-C cancels.
+pub fn current() -> CancelToken {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(on_interrupt)
+}
+
+/// Runs `f` with `token` as this thread's `current` token, so that cancelling it stops just
+/// this run, e.g. one of the daemon's, and not those before or after it.
+pub fn scoped<T>(token: &CancelToken, f: impl FnOnce() -> T) -> T {
+    /// Puts back the previous token however `f` ends, panics included.
+    struct Restore(Option<CancelToken>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(token.clone()));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Makes the first Ctrl-C cancel the `on_interrupt` token instead of killing sloop, so the run
+/// stops at the next check and cleans up. A second Ctrl-C kills sloop straight away.
+#[cfg(unix)]
+pub fn cancel_on_interrupt() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if let Some(token) = INTERRUPT.get() {
+            token.cancel();
+        }
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+    on_interrupt();
+    let handler: extern "C" fn(libc::c_int) = handle;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Ctrl-C still kills sloop straight away where there's no signal handler to catch it.
+#[cfg(not(unix))]
+pub fn cancel_on_interrupt() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_cancellation() {
+        let token = CancelToken::default();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(
+            clone.check().unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+    }
+
+    #[test]
+    fn scoped_tokens_only_stop_their_own_run() {
+        let first = CancelToken::default();
+        scoped(&first, || {
+            assert!(!current().is_cancelled());
+            first.cancel();
+            assert!(current().is_cancelled());
+        });
+        assert!(!current().is_cancelled());
+        let second = CancelToken::default();
+        scoped(&second, || assert!(current().check().is_ok()));
+    }
+}
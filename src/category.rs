@@ -0,0 +1,307 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Apple Podcasts' categories, each with its subcategories, as listed at
+/// <https://podcasters.apple.com/support/1691-apple-podcasts-categories>.
+const CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "Arts",
+        &[
+            "Books",
+            "Design",
+            "Fashion & Beauty",
+            "Food",
+            "Performing Arts",
+            "Visual Arts",
+        ],
+    ),
+    (
+        "Business",
+        &[
+            "Careers",
+            "Entrepreneurship",
+            "Investing",
+            "Management",
+            "Marketing",
+            "Non-Profit",
+        ],
+    ),
+    ("Comedy", &["Comedy Interviews", "Improv", "Stand-Up"]),
+    (
+        "Education",
+        &["Courses", "How To", "Language Learning", "Self-Improvement"],
+    ),
+    ("Fiction", &["Comedy Fiction", "Drama", "Science Fiction"]),
+    ("Government", &[]),
+    (
+        "Health & Fitness",
+        &[
+            "Alternative Health",
+            "Fitness",
+            "Medicine",
+            "Mental Health",
+            "Nutrition",
+            "Sexuality",
+        ],
+    ),
+    ("History", &[]),
+    (
+        "Kids & Family",
+        &[
+            "Education for Kids",
+            "Parenting",
+            "Pets & Animals",
+            "Stories for Kids",
+        ],
+    ),
+    (
+        "Leisure",
+        &[
+            "Animation & Manga",
+            "Automotive",
+            "Aviation",
+            "Crafts",
+            "Games",
+            "Hobbies",
+            "Home & Garden",
+            "Video Games",
+        ],
+    ),
+    (
+        "Music",
+        &["Music Commentary", "Music History", "Music Interviews"],
+    ),
+    (
+        "News",
+        &[
+            "Business News",
+            "Daily News",
+            "Entertainment News",
+            "News Commentary",
+            "Politics",
+            "Sports News",
+            "Tech News",
+        ],
+    ),
+    (
+        "Religion & Spirituality",
+        &[
+            "Buddhism",
+            "Christianity",
+            "Hinduism",
+            "Islam",
+            "Judaism",
+            "Religion",
+            "Spirituality",
+        ],
+    ),
+    (
+        "Science",
+        &[
+            "Astronomy",
+            "Chemistry",
+            "Earth Sciences",
+            "Life Sciences",
+            "Mathematics",
+            "Natural Sciences",
+            "Nature",
+            "Physics",
+            "Social Sciences",
+        ],
+    ),
+    (
+        "Society & Culture",
+        &[
+            "Documentary",
+            "Personal Journals",
+            "Philosophy",
+            "Places & Travel",
+            "Relationships",
+        ],
+    ),
+    (
+        "Sports",
+        &[
+            "Baseball",
+            "Basketball",
+            "Cricket",
+            "Fantasy Sports",
+            "Football",
+            "Golf",
+            "Hockey",
+            "Rugby",
+            "Running",
+            "Soccer",
+            "Swimming",
+            "Tennis",
+            "Volleyball",
+            "Wilderness",
+            "Wrestling",
+        ],
+    ),
+    ("Technology", &[]),
+    ("True Crime", &[]),
+    (
+        "TV & Film",
+        &[
+            "After Shows",
+            "Film History",
+            "Film Interviews",
+            "Film Reviews",
+            "TV Reviews",
+        ],
+    ),
+];
+
+/// An Apple Podcasts category, optionally narrowed to one of its subcategories, given on the
+/// command line as e.g. `Arts` or `Arts:Books`. Names match case-insensitively and are kept
+/// as Apple spells them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Category {
+    pub name: &'static str,
+    pub subcategory: Option<&'static str>,
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, subcategory) = match value.split_once(':') {
+            Some((name, subcategory)) => (name.trim(), Some(subcategory.trim())),
+            None => (value.trim(), None),
+        };
+        let names: Vec<&str> = CATEGORIES.iter().map(|(name, _)| *name).collect();
+        let (name, subcategories) = CATEGORIES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .ok_or_else(|| unknown(&format!("category {:?}", name), name, &names))?;
+        let subcategory = match subcategory {
+            None => None,
+            Some(subcategory) => Some(
+                *subcategories
+                    .iter()
+                    .find(|known| known.eq_ignore_ascii_case(subcategory))
+                    .ok_or_else(|| match subcategories.is_empty() {
+                        true => format!("{} has no subcategories", name),
+                        false => unknown(
+                            &format!("subcategory {:?} of {}", subcategory, name),
+                            subcategory,
+                            subcategories,
+                        ),
+                    })?,
+            ),
+        };
+        Ok(Category { name, subcategory })
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.subcategory {
+            Some(subcategory) => write!(f, "{}:{}", self.name, subcategory),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Explains that `what` isn't one of `known`, suggesting the names closest to `given`, or
+/// listing them all if none are close.
+fn unknown(what: &str, given: &str, known: &[&str]) -> String {
+    let given = given.to_lowercase();
+    let mut close: Vec<(usize, &str)> = known
+        .iter()
+        .map(|name| (edit_distance(&given, &name.to_lowercase()), *name))
+        .filter(|(distance, name)| {
+            *distance <= 2.max(given.len() / 3) || name.to_lowercase().contains(&given)
+        })
+        .collect();
+    close.sort();
+    let closest = match close.first() {
+        Some((distance, _)) => *distance,
+        None => return format!("Unknown {}; Apple's are {}", what, known.join(", ")),
+    };
+    let close: Vec<&str> = close
+        .into_iter()
+        .take_while(|(distance, _)| *distance == closest)
+        .map(|(_, name)| name)
+        .collect();
+    format!("Unknown {}; did you mean {}?", what, close.join(" or "))
+}
+
+/// The number of single-character insertions, deletions and substitutions that turn `a`
+/// into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_categories_and_subcategories() {
+        assert_eq!(
+            "Arts".parse(),
+            Ok(Category {
+                name: "Arts",
+                subcategory: None
+            })
+        );
+        assert_eq!(
+            "kids & family : parenting".parse(),
+            Ok(Category {
+                name: "Kids & Family",
+                subcategory: Some("Parenting")
+            })
+        );
+        assert_eq!(
+            "TV & Film:After Shows"
+                .parse::<Category>()
+                .unwrap()
+                .to_string(),
+            "TV & Film:After Shows"
+        );
+    }
+
+    #[test]
+    fn suggests_close_matches_for_unknown_categories() {
+        assert_eq!(
+            "Art".parse::<Category>(),
+            Err("Unknown category \"Art\"; did you mean Arts?".to_owned())
+        );
+        assert_eq!(
+            "Arts:Book".parse::<Category>(),
+            Err("Unknown subcategory \"Book\" of Arts; did you mean Books?".to_owned())
+        );
+        assert_eq!(
+            "Science:Sci-Fi".parse::<Category>(),
+            Err(
+                "Unknown subcategory \"Sci-Fi\" of Science; Apple's are Astronomy, Chemistry, \
+                 Earth Sciences, Life Sciences, Mathematics, Natural Sciences, Nature, Physics, \
+                 Social Sciences"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            "History:Ancient".parse::<Category>(),
+            Err("History has no subcategories".to_owned())
+        );
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("arts", "arts"), 0);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::log::Log;
+use hyper::Uri;
+use rusoto_cloudfront::{
+    CloudFront, CloudFrontClient, CreateInvalidationRequest, GetInvalidationRequest,
+    InvalidationBatch, Paths,
+};
+use rusoto_core::Region;
+use sloop::naming;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// CloudFront's status for an invalidation whose paths are all cleared.
+const COMPLETED: &str = "Completed";
+
+/// An invalidation as CloudFront reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalidation {
+    pub id: String,
+    /// `InProgress` until every edge location has dropped the paths, then `Completed`.
+    pub status: String,
+}
+
+/// The CloudFront calls invalidating cached objects takes. Abstracted so invalidations can be
+/// tested without AWS.
+pub trait InvalidationClient {
+    /// Starts clearing `paths` from the cache of `distribution_id`. CloudFront treats requests
+    /// with the same `caller_reference` as one.
+    fn create_invalidation(
+        &self,
+        distribution_id: &str,
+        paths: &[String],
+        caller_reference: &str,
+    ) -> Result<Invalidation, String>;
+
+    /// The invalidation `id` of `distribution_id` as it stands.
+    fn get_invalidation(&self, distribution_id: &str, id: &str) -> Result<Invalidation, String>;
+}
+
+impl InvalidationClient for CloudFrontClient {
+    fn create_invalidation(
+        &self,
+        distribution_id: &str,
+        paths: &[String],
+        caller_reference: &str,
+    ) -> Result<Invalidation, String> {
+        let request = CreateInvalidationRequest {
+            distribution_id: distribution_id.to_owned(),
+            invalidation_batch: InvalidationBatch {
+                caller_reference: caller_reference.to_owned(),
+                paths: Paths {
+                    items: Some(paths.to_vec()),
+                    quantity: paths.len() as i64,
+                },
+            },
+        };
+        let result = CloudFront::create_invalidation(self, request)
+            .sync()
+            .map_err(|err| err.to_string())?;
+        result
+            .invalidation
+            .map(invalidation)
+            .ok_or_else(|| "CloudFront didn't describe the invalidation".to_owned())
+    }
+
+    fn get_invalidation(&self, distribution_id: &str, id: &str) -> Result<Invalidation, String> {
+        let request = GetInvalidationRequest {
+            distribution_id: distribution_id.to_owned(),
+            id: id.to_owned(),
+        };
+        let result = CloudFront::get_invalidation(self, request)
+            .sync()
+            .map_err(|err| err.to_string())?;
+        result
+            .invalidation
+            .map(invalidation)
+            .ok_or_else(|| format!("CloudFront didn't describe invalidation {}", id))
+    }
+}
+
+fn invalidation(invalidation: rusoto_cloudfront::Invalidation) -> Invalidation {
+    Invalidation {
+        id: invalidation.id,
+        status: invalidation.status,
+    }
+}
+
+/// Clears replaced objects from a CloudFront distribution's cache, so listeners get them before
+/// the cached copies expire.
+pub struct Invalidator {
+    client: Box<dyn InvalidationClient>,
+    distribution_id: String,
+    /// Whether `invalidate` waits for CloudFront to finish, which usually takes a minute or two.
+    wait: bool,
+    poll_interval: Duration,
+    /// How long to wait before giving up on an invalidation that's still in progress.
+    timeout: Duration,
+    log: Log,
+}
+
+impl Invalidator {
+    /// Invalidates in `distribution_id` with the default AWS credentials. CloudFront is global,
+    /// so its API is always reached through us-east-1.
+    pub fn new(distribution_id: &str, wait: bool, log: Log) -> Self {
+        Self {
+            client: Box::new(CloudFrontClient::new(Region::UsEast1)),
+            distribution_id: distribution_id.to_owned(),
+            wait,
+            poll_interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(15 * 60),
+            log,
+        }
+    }
+
+    /// Invalidates `paths`, logging the invalidation's ID, and unless told not to, waits for
+    /// it to complete.
+    pub fn invalidate(&self, paths: &[String]) -> Result<Invalidation, String> {
+        let mut invalidation =
+            self.client
+                .create_invalidation(&self.distribution_id, paths, &caller_reference())?;
+        (self.log)(&format!(
+            "Invalidating {} in CloudFront distribution {} ({})",
+            paths.join(", "),
+            self.distribution_id,
+            invalidation.id
+        ));
+        if !self.wait {
+            return Ok(invalidation);
+        }
+        let started = Instant::now();
+        while invalidation.status != COMPLETED {
+            if started.elapsed() >= self.timeout {
+                return Err(format!(
+                    "invalidation {} was still {} after {}s",
+                    invalidation.id,
+                    invalidation.status,
+                    self.timeout.as_secs()
+                ));
+            }
+            thread::sleep(self.poll_interval);
+            invalidation = self
+                .client
+                .get_invalidation(&self.distribution_id, &invalidation.id)?;
+        }
+        (self.log)(&format!("Invalidation {} complete", invalidation.id));
+        Ok(invalidation)
+    }
+}
+
+/// Unique to this run, so a retried request isn't taken for an earlier one.
+fn caller_reference() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("sloop-{}-{}", now.as_nanos(), process::id())
+}
+
+/// The path CloudFront serves the object `key` at: under the path of `public_url` if the
+/// distribution is reached there, or else at the key itself, percent-encoded as in URLs.
+pub fn path_for_key(public_url: Option<&str>, key: &str) -> String {
+    let base_path = public_url
+        .and_then(|url| url.parse::<Uri>().ok())
+        .map(|uri| uri.path().to_owned())
+        .unwrap_or_default();
+    naming::url_for_key(&base_path, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Reports each invalidation in progress for `polls` checks, then complete, recording the
+    /// calls made.
+    #[derive(Default)]
+    struct MockClient {
+        polls: usize,
+        fail_create: bool,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl InvalidationClient for MockClient {
+        fn create_invalidation(
+            &self,
+            distribution_id: &str,
+            paths: &[String],
+            _caller_reference: &str,
+        ) -> Result<Invalidation, String> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(format!("create {} {}", distribution_id, paths.join(" ")));
+            if self.fail_create {
+                return Err("AccessDenied".to_owned());
+            }
+            Ok(Invalidation {
+                id: "I2J0I21PCUYOIK".to_owned(),
+                status: "InProgress".to_owned(),
+            })
+        }
+
+        fn get_invalidation(
+            &self,
+            distribution_id: &str,
+            id: &str,
+        ) -> Result<Invalidation, String> {
+            let mut calls = self.calls.lock().unwrap();
+            let polls = calls.iter().filter(|call| call.starts_with("get")).count();
+            calls.push(format!("get {} {}", distribution_id, id));
+            let status = if polls + 1 < self.polls {
+                "InProgress"
+            } else {
+                COMPLETED
+            };
+            Ok(Invalidation {
+                id: id.to_owned(),
+                status: status.to_owned(),
+            })
+        }
+    }
+
+    fn invalidator(client: MockClient, wait: bool) -> (Invalidator, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log: Log = {
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        };
+        let invalidator = Invalidator {
+            client: Box::new(client),
+            distribution_id: "E2QWRUHAPOMQZL".to_owned(),
+            wait,
+            poll_interval: Duration::from_millis(0),
+            timeout: Duration::from_secs(60),
+            log,
+        };
+        (invalidator, lines)
+    }
+
+    #[test]
+    fn waits_for_the_invalidation_to_complete() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = MockClient {
+            polls: 2,
+            calls: Arc::clone(&calls),
+            ..Default::default()
+        };
+        let (invalidator, lines) = invalidator(client, true);
+        let paths = vec!["/feed.xml".to_owned(), "/cover.jpg".to_owned()];
+        let invalidation = invalidator.invalidate(&paths).unwrap();
+        assert_eq!(invalidation.status, COMPLETED);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "create E2QWRUHAPOMQZL /feed.xml /cover.jpg",
+                "get E2QWRUHAPOMQZL I2J0I21PCUYOIK",
+                "get E2QWRUHAPOMQZL I2J0I21PCUYOIK",
+            ]
+        );
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "Invalidating /feed.xml, /cover.jpg in CloudFront distribution E2QWRUHAPOMQZL \
+                 (I2J0I21PCUYOIK)",
+                "Invalidation I2J0I21PCUYOIK complete",
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_the_invalidation_running_without_waiting() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let client = MockClient {
+            polls: 2,
+            calls: Arc::clone(&calls),
+            ..Default::default()
+        };
+        let (invalidator, _) = invalidator(client, false);
+        let invalidation = invalidator.invalidate(&["/feed.xml".to_owned()]).unwrap();
+        assert_eq!(invalidation.status, "InProgress");
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn gives_up_on_invalidations_that_take_too_long() {
+        let client = MockClient {
+            polls: usize::MAX,
+            ..Default::default()
+        };
+        let (mut invalidator, _) = invalidator(client, true);
+        invalidator.timeout = Duration::from_millis(0);
+        let err = invalidator
+            .invalidate(&["/feed.xml".to_owned()])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "invalidation I2J0I21PCUYOIK was still InProgress after 0s"
+        );
+    }
+
+    #[test]
+    fn reports_invalidations_cloudfront_refuses() {
+        let client = MockClient {
+            fail_create: true,
+            ..Default::default()
+        };
+        let (invalidator, lines) = invalidator(client, true);
+        let err = invalidator
+            .invalidate(&["/feed.xml".to_owned()])
+            .unwrap_err();
+        assert_eq!(err, "AccessDenied");
+        assert!(lines.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn paths_follow_the_public_url() {
+        assert_eq!(path_for_key(None, "feed.xml"), "/feed.xml");
+        assert_eq!(
+            path_for_key(None, "books/candide/Candide feed.xml"),
+            "/books/candide/Candide%20feed.xml"
+        );
+        assert_eq!(
+            path_for_key(Some("https://cdn.eg.test"), "books/feed.xml"),
+            "/books/feed.xml"
+        );
+        assert_eq!(
+            path_for_key(Some("https://cdn.eg.test:8443/audio"), "books/feed.xml"),
+            "/audio/books/feed.xml"
+        );
+    }
+}
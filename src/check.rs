@@ -0,0 +1,234 @@
+use crate::http::HttpGet;
+use rss::Channel;
+use std::collections::HashSet;
+use std::io::BufRead;
+
+/// Re-reads a generated feed and returns everything that would make it broken in practice, even
+/// though it may be valid XML. An empty list means the feed is safe to publish. Enclosures
+/// should be under `base_url`, apart from the `hosted` media a manifest links to elsewhere.
+pub fn check_feed<R: BufRead>(
+    reader: R,
+    base_url: &str,
+    hosted: &[&str],
+    allow_empty: bool,
+) -> Vec<String> {
+    let channel = match Channel::read_from(reader) {
+        Ok(channel) => channel,
+        Err(err) => return vec![format!("feed could not be parsed: {}", err)],
+    };
+    let mut problems = Vec::new();
+    if channel.items().is_empty() && !allow_empty {
+        problems.push("feed has no items (use --allow-empty to publish it anyway)".to_owned());
+    }
+    let prefix = format!("{}/", base_url);
+    let mut guids = HashSet::new();
+    for (i, item) in channel.items().iter().enumerate() {
+        let name = item
+            .title()
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("item {}", i + 1));
+        // Podcast apps fall back to the enclosure URL when an item has no guid.
+        let guid = item
+            .guid()
+            .map(|guid| guid.value())
+            .or_else(|| item.enclosure().map(|enclosure| enclosure.url()));
+        if let Some(guid) = guid {
+            if !guids.insert(guid.to_owned()) {
+                problems.push(format!("{} has duplicate guid {}", name, guid));
+            }
+        }
+        let enclosure = match item.enclosure() {
+            Some(enclosure) => enclosure,
+            None => {
+                problems.push(format!("{} has no enclosure", name));
+                continue;
+            }
+        };
+        if enclosure.length().parse::<u64>().unwrap_or(0) == 0 {
+            problems.push(format!("{} has an enclosure length of 0", name));
+        }
+        if !enclosure.url().starts_with(&prefix) && !hosted.contains(&enclosure.url()) {
+            problems.push(format!(
+                "{} has enclosure URL {} outside {}",
+                name,
+                enclosure.url(),
+                base_url
+            ));
+        }
+    }
+    if let Some(image) = channel.itunes_ext().and_then(|ext| ext.image()) {
+        if !image.starts_with(&prefix) {
+            problems.push(format!("image URL {} is outside {}", image, base_url));
+        }
+    }
+    problems
+}
+
+/// Fetches an enclosure from its public URL after publishing, catching feeds whose URLs point
+/// somewhere other than where the files were uploaded.
+pub fn verify_enclosure<H: HttpGet + ?Sized>(http: &H, url: &str) -> Result<(), String> {
+    let status = http
+        .get(url, &[])
+        .map_err(|err| format!("Failed to fetch enclosure {}: {}", url, err))?;
+    if status != 200 {
+        return Err(format!(
+            "Enclosure {} returned status {} instead of 200",
+            url, status
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rss::extension::itunes::ITunesChannelExtensionBuilder;
+    use rss::{ChannelBuilder, EnclosureBuilder, GuidBuilder, Item, ItemBuilder};
+
+    const BASE_URL: &str = "https://bucket.s3.amazonaws.com";
+
+    fn item(title: &str, url: &str, length: &str) -> Item {
+        ItemBuilder::default()
+            .title(Some(title.to_owned()))
+            .enclosure(Some(
+                EnclosureBuilder::default()
+                    .url(url.to_owned())
+                    .length(length.to_owned())
+                    .mime_type("audio/mpeg".to_owned())
+                    .build(),
+            ))
+            .build()
+    }
+
+    fn check(channel: Channel, allow_empty: bool) -> Vec<String> {
+        let xml = channel.to_string();
+        check_feed(xml.as_bytes(), BASE_URL, &[], allow_empty)
+    }
+
+    fn channel(items: Vec<Item>) -> Channel {
+        ChannelBuilder::default()
+            .title("Dune".to_owned())
+            .items(items)
+            .build()
+    }
+
+    #[test]
+    fn accepts_a_valid_feed() {
+        let items = vec![
+            item("ch1", &format!("{}/ch1.mp3", BASE_URL), "100"),
+            item("ch2", &format!("{}/ch2.mp3", BASE_URL), "200"),
+        ];
+        assert!(check(channel(items), false).is_empty());
+    }
+
+    #[test]
+    fn reports_unparseable_feed() {
+        let problems = check_feed(&b"<rss><channel>"[..], BASE_URL, &[], false);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("feed could not be parsed"));
+    }
+
+    #[test]
+    fn reports_feed_with_no_items() {
+        let problems = check(channel(vec![]), false);
+        assert_eq!(
+            problems,
+            vec!["feed has no items (use --allow-empty to publish it anyway)"]
+        );
+        assert!(check(channel(vec![]), true).is_empty());
+    }
+
+    #[test]
+    fn reports_duplicate_guids() {
+        let mut items = vec![
+            item("ch1", &format!("{}/ch1.mp3", BASE_URL), "100"),
+            item("ch2", &format!("{}/ch2.mp3", BASE_URL), "200"),
+        ];
+        for item in &mut items {
+            item.set_guid(GuidBuilder::default().value("same".to_owned()).build());
+        }
+        assert_eq!(
+            check(channel(items), false),
+            vec!["ch2 has duplicate guid same"]
+        );
+    }
+
+    #[test]
+    fn treats_enclosure_url_as_guid_when_missing() {
+        let url = format!("{}/ch1.mp3", BASE_URL);
+        let items = vec![item("ch1", &url, "100"), item("ch1 again", &url, "100")];
+        assert_eq!(
+            check(channel(items), false),
+            vec![format!("ch1 again has duplicate guid {}", url)]
+        );
+    }
+
+    #[test]
+    fn reports_zero_length_enclosures() {
+        let items = vec![item("ch1", &format!("{}/ch1.mp3", BASE_URL), "0")];
+        assert_eq!(
+            check(channel(items), false),
+            vec!["ch1 has an enclosure length of 0"]
+        );
+    }
+
+    #[test]
+    fn reports_urls_outside_base_url() {
+        let mut channel = channel(vec![item("ch1", "https://elsewhere/ch1.mp3", "100")]);
+        channel.set_itunes_ext(
+            ITunesChannelExtensionBuilder::default()
+                .image(Some("https://elsewhere/cover.jpg".to_owned()))
+                .build(),
+        );
+        assert_eq!(
+            check(channel, false),
+            vec![
+                format!(
+                    "ch1 has enclosure URL https://elsewhere/ch1.mp3 outside {}",
+                    BASE_URL
+                ),
+                format!(
+                    "image URL https://elsewhere/cover.jpg is outside {}",
+                    BASE_URL
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_enclosures_hosted_elsewhere() {
+        let items = vec![
+            item("ch1", &format!("{}/ch1.mp3", BASE_URL), "100"),
+            item("ch2", "https://archive.org/download/dune/ch2.mp3", "200"),
+        ];
+        let xml = channel(items).to_string();
+        let hosted = ["https://archive.org/download/dune/ch2.mp3"];
+        assert!(check_feed(xml.as_bytes(), BASE_URL, &hosted, false).is_empty());
+    }
+
+    struct StaticHttp(Result<u16, String>);
+
+    impl HttpGet for StaticHttp {
+        fn get(&self, _url: &str, headers: &[(&str, &str)]) -> Result<u16, String> {
+            assert!(headers.is_empty());
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn verify_enclosure_expects_ok_status() {
+        let url = "https://bucket.s3.amazonaws.com/ch1.mp3";
+        assert_eq!(verify_enclosure(&StaticHttp(Ok(200)), url), Ok(()));
+        assert_eq!(
+            verify_enclosure(&StaticHttp(Ok(404)), url),
+            Err(format!(
+                "Enclosure {} returned status 404 instead of 200",
+                url
+            ))
+        );
+        assert_eq!(
+            verify_enclosure(&StaticHttp(Err("timed out".to_owned())), url),
+            Err(format!("Failed to fetch enclosure {}: timed out", url))
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use crate::cancel::{self, CancelToken};
+use crate::log::{self, Log};
+use crate::outcome::{Failure, Published};
+use crate::version;
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// Header clients must send the shared token in.
+pub const TOKEN_HEADER: &str = "X-Sloop-Token";
+
+/// Serves `POST /publish`, which runs `publish` and streams its log lines back, and
+/// `GET /status`, which reports the last run as JSON. Only one publish runs at a time; a request
+/// made while one is in progress gets `409 Conflict`. Each publish has its own cancel token,
+/// cancelled if its client goes away, and its warnings and other messages are streamed to the
+/// client along with its log lines.
+pub struct Daemon<P> {
+    token: String,
+    publish: P,
+    running: AtomicBool,
+    last_run: Mutex<Value>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn read(stream: &TcpStream) -> io::Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let path = parts.next().unwrap_or_default().to_owned();
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+        Ok(Self {
+            method,
+            path,
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl<P> Daemon<P>
+where
+    P: Fn(Log) -> Result<Published, Failure> + Send + Sync + 'static,
+{
+    /// `publish` returns the URLs of the published feeds, or why publishing failed.
+    pub fn new(token: String, publish: P) -> Self {
+        Self {
+            token,
+            publish,
+            running: AtomicBool::new(false),
+            last_run: Mutex::new(Value::Null),
+        }
+    }
+
+    /// Handles connections on `listener` until it fails, each on its own thread.
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let daemon = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = Arc::clone(&daemon);
+            thread::spawn(move || {
+                if let Err(err) = daemon.handle(&stream) {
+                    eprintln!("Daemon request failed: {}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: &TcpStream) -> io::Result<()> {
+        let request = Request::read(stream)?;
+        if !request
+            .header(TOKEN_HEADER)
+            .is_some_and(|given| same_token(given, &self.token))
+        {
+            return respond(stream, "401 Unauthorized", "invalid or missing token\n");
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/publish") => self.publish(stream),
+            ("GET", "/status") => {
+                let status = json!({
+                    "running": self.running.load(Ordering::SeqCst),
+                    "last_run": *self.last_run(),
+                });
+                respond_with(
+                    stream,
+                    "200 OK",
+                    "application/json",
+                    &format!("{}\n", status),
+                )
+            }
+            (_, "/publish") | (_, "/status") => {
+                respond(stream, "405 Method Not Allowed", "method not allowed\n")
+            }
+            _ => respond(stream, "404 Not Found", "not found\n"),
+        }
+    }
+
+    /// The last run's status. A run that panicked while holding the lock can't have left it
+    /// half-written, as it's only ever replaced whole, so a poisoned lock is used as it is.
+    fn last_run(&self) -> MutexGuard<'_, Value> {
+        self.last_run.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn publish(&self, stream: &TcpStream) -> io::Result<()> {
+        let out = stream.try_clone()?;
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return respond(stream, "409 Conflict", "a publish is already running\n");
+        }
+        let _running = Running(&self.running);
+        // The status line goes out before publishing starts so that log lines can be streamed;
+        // the last line of the body says whether it succeeded.
+        let result = write!(
+            &mut &*stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n"
+        );
+        let started_at = Utc::now().to_rfc3339();
+        let cancel = CancelToken::default();
+        let log: Log = {
+            let cancel = cancel.clone();
+            Arc::new(move |line| {
+                // No one is left to see the run finish, so it stops at the next check.
+                if writeln!(&mut &out, "{}", line).is_err() {
+                    cancel.cancel();
+                }
+            })
+        };
+        let run = || {
+            cancel::scoped(&cancel, || {
+                log::redirect(&log, || (self.publish)(Arc::clone(&log)))
+            })
+        };
+        let outcome = panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|_| {
+            Err(Failure::failed(
+                "Publishing panicked; see the daemon's stderr".to_owned(),
+            ))
+        });
+        let finished_at = Utc::now().to_rfc3339();
+        let last_run = match outcome {
+            Ok(published) => {
+                for feed_url in &published.feed_urls {
+                    log(&format!("Podcast available at {}", feed_url));
+                }
+                json!({
+                    "started_at": started_at,
+                    "finished_at": finished_at,
+                    "ok": true,
+                    "outcome": published.outcome.name(),
+                    "exit_code": published.outcome.exit_code(),
+                    "version": version::VERSION,
+                    "feed_url": published.feed_urls.first(),
+                    "feed_urls": published.feed_urls,
+                })
+            }
+            Err(failure) => {
+                log(&format!("Error: {}", failure));
+                json!({
+                    "started_at": started_at,
+                    "finished_at": finished_at,
+                    "ok": false,
+                    "outcome": failure.outcome.name(),
+                    "exit_code": failure.outcome.exit_code(),
+                    "version": version::VERSION,
+                    "error": failure.message,
+                })
+            }
+        };
+        *self.last_run() = last_run;
+        result
+    }
+}
+
+/// Marks the daemon as no longer publishing when dropped, however the publish ended.
+struct Running<'a>(&'a AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Whether `given` is `token`, taking as long to tell however much of it is right, so that
+/// timing responses doesn't reveal the token a byte at a time. Only its length can be told.
+fn same_token(given: &str, token: &str) -> bool {
+    let differences = given
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |differences, (a, b)| differences | (a ^ b));
+    given.len() == token.len() && differences == 0
+}
+
+fn respond(stream: &TcpStream, status: &str, body: &str) -> io::Result<()> {
+    respond_with(stream, status, "text/plain", body)
+}
+
+fn respond_with(
+    stream: &TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        &mut &*stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outcome::Outcome;
+    use crate::warning;
+    use std::io::Read;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc;
+
+    fn start<P>(publish: P) -> String
+    where
+        P: Fn(Log) -> Result<Published, Failure> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let daemon = Daemon::new("secret".to_owned(), publish);
+        thread::spawn(move || daemon.serve(listener));
+        address
+    }
+
+    fn published(feed_urls: Vec<String>) -> Published {
+        Published {
+            outcome: Outcome::Published,
+            feed_urls,
+        }
+    }
+
+    fn request(address: &str, method: &str, path: &str, token: Option<&str>) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            method, path, address
+        )
+        .unwrap();
+        if let Some(token) = token {
+            write!(stream, "{}: {}\r\n", TOKEN_HEADER, token).unwrap();
+        }
+        write!(stream, "\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn body(response: &str) -> &str {
+        &response[response.find("\r\n\r\n").unwrap() + 4..]
+    }
+
+    #[test]
+    fn rejects_requests_without_the_token() {
+        let address = start(|_| Ok(published(vec![])));
+        let response = request(&address, "POST", "/publish", None);
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        let response = request(&address, "GET", "/status", Some("wrong"));
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+    }
+
+    #[test]
+    fn publish_streams_log_lines() {
+        let address = start(|log| {
+            log("Uploading ch1.mp3");
+            Ok(published(vec![
+                "https://bucket/feed.xml".to_owned(),
+                "https://bucket/feed.json".to_owned(),
+            ]))
+        });
+        let response = request(&address, "POST", "/publish", Some("secret"));
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert_eq!(
+            body(&response),
+            "Uploading ch1.mp3\n\
+             Podcast available at https://bucket/feed.xml\n\
+             Podcast available at https://bucket/feed.json\n"
+        );
+    }
+
+    #[test]
+    fn status_reports_last_run() {
+        let address = start(|_| Err(Failure::failed("Failed to create bucket".to_owned())));
+        let status: Value =
+            serde_json::from_str(body(&request(&address, "GET", "/status", Some("secret"))))
+                .unwrap();
+        assert_eq!(status["running"], false);
+        assert_eq!(status["last_run"], Value::Null);
+
+        let response = request(&address, "POST", "/publish", Some("secret"));
+        assert!(body(&response).ends_with("Error: Failed to create bucket\n"));
+        let status: Value =
+            serde_json::from_str(body(&request(&address, "GET", "/status", Some("secret"))))
+                .unwrap();
+        assert_eq!(status["last_run"]["ok"], false);
+        assert_eq!(status["last_run"]["outcome"], "failed");
+        assert_eq!(status["last_run"]["exit_code"], 1);
+        assert_eq!(status["last_run"]["version"], version::VERSION);
+        assert_eq!(status["last_run"]["error"], "Failed to create bucket");
+        assert!(status["last_run"]["finished_at"].is_string());
+    }
+
+    #[test]
+    fn status_reports_unchanged_runs() {
+        let address = start(|_| {
+            Ok(Published {
+                outcome: Outcome::Unchanged,
+                feed_urls: vec!["https://bucket/feed.xml".to_owned()],
+            })
+        });
+        request(&address, "POST", "/publish", Some("secret"));
+        let status: Value =
+            serde_json::from_str(body(&request(&address, "GET", "/status", Some("secret"))))
+                .unwrap();
+        assert_eq!(status["last_run"]["ok"], true);
+        assert_eq!(status["last_run"]["outcome"], "unchanged");
+        assert_eq!(status["last_run"]["exit_code"], 3);
+        assert_eq!(status["last_run"]["feed_url"], "https://bucket/feed.xml");
+    }
+
+    #[test]
+    fn rejects_publish_while_one_is_running() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let started_tx = Mutex::new(started_tx);
+        let finish_rx = Mutex::new(finish_rx);
+        let address = start(move |_| {
+            started_tx.lock().unwrap().send(()).unwrap();
+            finish_rx.lock().unwrap().recv().unwrap();
+            Ok(published(vec![]))
+        });
+        let first = {
+            let address = address.clone();
+            thread::spawn(move || request(&address, "POST", "/publish", Some("secret")))
+        };
+        started_rx.recv().unwrap();
+        let second = request(&address, "POST", "/publish", Some("secret"));
+        assert!(second.starts_with("HTTP/1.1 409"), "{}", second);
+        finish_tx.send(()).unwrap();
+        assert!(first.join().unwrap().starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn panicking_publishes_are_reported_and_dont_block_the_next() {
+        let runs = AtomicUsize::new(0);
+        let address = start(move |_| match runs.fetch_add(1, Ordering::SeqCst) {
+            0 => panic!("the first publish panics"),
+            _ => Ok(published(vec!["https://bucket/feed.xml".to_owned()])),
+        });
+        let response = request(&address, "POST", "/publish", Some("secret"));
+        assert!(
+            body(&response).ends_with("Error: Publishing panicked; see the daemon's stderr\n"),
+            "{}",
+            response
+        );
+        let status: Value =
+            serde_json::from_str(body(&request(&address, "GET", "/status", Some("secret"))))
+                .unwrap();
+        assert_eq!(status["running"], false);
+        assert_eq!(status["last_run"]["ok"], false);
+        let response = request(&address, "POST", "/publish", Some("secret"));
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(body(&response).ends_with("Podcast available at https://bucket/feed.xml\n"));
+    }
+
+    #[test]
+    fn status_survives_a_poisoned_lock() {
+        let daemon = Daemon::new("secret".to_owned(), |_| Ok(published(vec![])));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _status = daemon.last_run.lock().unwrap();
+            panic!("poisons the lock");
+        }));
+        assert!(daemon.last_run.is_poisoned());
+        assert_eq!(*daemon.last_run(), Value::Null);
+    }
+
+    #[test]
+    fn warnings_and_messages_are_streamed_to_the_client() {
+        let address = start(|_| {
+            warning::warn("the daemon test cover is too small");
+            log::message("Found 2 files in Disc 1");
+            Ok(published(vec![]))
+        });
+        let response = request(&address, "POST", "/publish", Some("secret"));
+        assert_eq!(
+            body(&response),
+            "Warning: the daemon test cover is too small\nFound 2 files in Disc 1\n"
+        );
+    }
+
+    #[test]
+    fn each_publish_has_its_own_cancel_token() {
+        let address = start(|_| {
+            let cancel = cancel::current();
+            assert!(!cancel.is_cancelled());
+            cancel.cancel();
+            Ok(published(vec![]))
+        });
+        for _ in 0..2 {
+            let response = request(&address, "POST", "/publish", Some("secret"));
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+            assert!(!body(&response).contains("Error"), "{}", response);
+        }
+        assert!(!cancel::on_interrupt().is_cancelled());
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(same_token("secret", "secret"));
+        assert!(!same_token("secreT", "secret"));
+        assert!(!same_token("secret!", "secret"));
+        assert!(!same_token("secre", "secret"));
+        assert!(!same_token("", "secret"));
+    }
+
+    #[test]
+    fn rejects_unknown_routes() {
+        let address = start(|_| Ok(published(vec![])));
+        let response = request(&address, "GET", "/publish", Some("secret"));
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+        let response = request(&address, "GET", "/", Some("secret"));
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    }
+}
@@ -0,0 +1,185 @@
+//! Expands the directories given on the command line into the media files inside them, so a
+//! book can be published without the shell's help, e.g. from PowerShell or a script.
+
+use crate::feed;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files that operating systems leave in folders, which are never media.
+const JUNK_FILES: &[&str] = &["Thumbs.db", "desktop.ini"];
+
+/// `paths` with each directory replaced by the files under it that `wanted` accepts, in natural
+/// order of name, walking into subdirectories in the same order. Hidden files and directories
+/// are left out, and each directory is walked at most once, even if symlinks lead back to it.
+/// Files given themselves are kept, in their place, whatever they are. `found` is told how many
+/// files each directory given held.
+pub fn expand<F>(
+    paths: &[PathBuf],
+    wanted: F,
+    found: &mut dyn FnMut(&Path, usize),
+) -> Result<Vec<PathBuf>, String>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut files = Vec::new();
+    let mut walked = HashSet::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let before = files.len();
+        walk(path, &wanted, &mut walked, &mut files)?;
+        found(path, files.len() - before);
+    }
+    Ok(files)
+}
+
+fn walk<F>(
+    dir: &Path,
+    wanted: &F,
+    walked: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), String>
+where
+    F: Fn(&Path) -> bool,
+{
+    let read_error = |err| format!("Failed to read the directory {}: {}", dir.display(), err);
+    if !walked.insert(fs::canonicalize(dir).map_err(read_error)?) {
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(read_error)?;
+    entries.retain(|path| !is_hidden(path));
+    entries.sort_by(|a, b| {
+        let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+        feed::natural_cmp(&name(a), &name(b))
+    });
+    for path in entries {
+        if path.is_dir() {
+            walk(&path, wanted, walked, files)?;
+        } else if wanted(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Dot files, e.g. `.DS_Store`, and the junk other systems leave.
+fn is_hidden(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with('.')
+        || JUNK_FILES
+            .iter()
+            .any(|junk| name.eq_ignore_ascii_case(junk))
+}
+
+/// Whether `path` has one of `extensions`, ignoring case.
+pub fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    extensions.iter().any(|wanted| {
+        wanted
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(&extension)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// A scratch directory tree, removed when dropped.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root =
+                env::temp_dir().join(format!("sloop-discover-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for file in files {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, "").unwrap();
+            }
+            Tree(root)
+        }
+
+        /// The files `expand` finds under the tree, relative to it, with the counts it reports.
+        fn expand(&self, extensions: &[&str]) -> (Vec<String>, Vec<usize>) {
+            let extensions: Vec<String> = extensions.iter().map(|e| e.to_string()).collect();
+            let mut counts = Vec::new();
+            let files = expand(
+                std::slice::from_ref(&self.0),
+                |path| has_extension(path, &extensions),
+                &mut |_, count| counts.push(count),
+            )
+            .unwrap();
+            let files = files
+                .iter()
+                .map(|file| file.strip_prefix(&self.0).unwrap().display().to_string())
+                .collect();
+            (files, counts)
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_wanted_files_recursively_in_natural_order() {
+        let tree = Tree::new(
+            "order",
+            &[
+                "CD 10/Track 1.mp3",
+                "CD 2/Track 10.MP3",
+                "CD 2/Track 9.mp3",
+                "CD 2/cover.jpg",
+                "CD 2/.DS_Store",
+                "CD 2/Thumbs.db",
+                ".hidden/Track 1.mp3",
+                "Intro.m4b",
+            ],
+        );
+        assert_eq!(
+            tree.expand(&["mp3", ".m4b"]),
+            (
+                vec![
+                    "CD 2/Track 9.mp3".to_owned(),
+                    "CD 2/Track 10.MP3".to_owned(),
+                    "CD 10/Track 1.mp3".to_owned(),
+                    "Intro.m4b".to_owned(),
+                ],
+                vec![4]
+            )
+        );
+        assert_eq!(tree.expand(&["m4b"]).0, ["Intro.m4b"]);
+    }
+
+    #[test]
+    fn keeps_files_given_in_their_place() {
+        let tree = Tree::new("given", &["book/Chapter 1.mp3", "notes.txt"]);
+        let given = [tree.0.join("notes.txt"), tree.0.join("book")];
+        let files = expand(&given, |_| true, &mut |_, _| {}).unwrap();
+        assert_eq!(
+            files,
+            [tree.0.join("notes.txt"), tree.0.join("book/Chapter 1.mp3")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinked_directories_once() {
+        let tree = Tree::new("links", &["book/Chapter 1.mp3"]);
+        std::os::unix::fs::symlink(&tree.0, tree.0.join("book/loop")).unwrap();
+        std::os::unix::fs::symlink(tree.0.join("book"), tree.0.join("link")).unwrap();
+        assert_eq!(tree.expand(&["mp3"]).0, ["book/Chapter 1.mp3"]);
+    }
+}
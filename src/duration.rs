@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long the MP3 file at `path` plays for, added up from its frame headers.
+pub fn mp3(path: &Path) -> Result<Duration, String> {
+    mp3_duration::from_path(path).map_err(|err| err.to_string())
+}
+
+/// How long the MP4 file at `path` (`.m4a`, `.m4b`, `.mp4`) plays for, read from its movie
+/// header. Raw AAC streams don't record their length, so aren't supported.
+pub fn mp4(path: &Path) -> Result<Duration, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    mp4_duration(&mut BufReader::new(file))
+}
+
+/// Reads the duration from the `mvhd` box inside the `moov` box of an MP4 file.
+fn mp4_duration<R: Read + Seek>(reader: &mut R) -> Result<Duration, String> {
+    let moov_end = find_box(reader, b"moov", None)?.ok_or("no moov box")?;
+    find_box(reader, b"mvhd", Some(moov_end))?.ok_or("no mvhd box")?;
+    let mut version_and_flags = [0; 4];
+    reader
+        .read_exact(&mut version_and_flags)
+        .map_err(|err| err.to_string())?;
+    // Version 1 boxes have 64-bit creation and modification times and duration.
+    let long = version_and_flags[0] == 1;
+    reader
+        .seek(SeekFrom::Current(if long { 16 } else { 8 }))
+        .map_err(|err| err.to_string())?;
+    let timescale = read_u32(reader).map_err(|err| err.to_string())?;
+    let duration = if long {
+        read_u64(reader)
+    } else {
+        read_u32(reader).map(u64::from)
+    }
+    .map_err(|err| err.to_string())?;
+    if timescale == 0 {
+        return Err("mvhd box has a timescale of 0".to_owned());
+    }
+    Ok(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// Skips boxes from the reader's position until one of type `kind` starts, stopping at `end`
+/// or the end of the file. Leaves the reader at the start of the box's contents and returns
+/// the offset the box ends at, or `None` if there's no such box.
+pub(crate) fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    kind: &[u8; 4],
+    end: Option<u64>,
+) -> Result<Option<u64>, String> {
+    loop {
+        let start = reader.stream_position().map_err(|err| err.to_string())?;
+        if end.is_some_and(|end| start >= end) {
+            return Ok(None);
+        }
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.to_string()),
+        }
+        let (size, header_len) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                // The size follows the type as a 64-bit number.
+                1 => (read_u64(reader).map_err(|err| err.to_string())?, 16),
+                // The box runs to the end of the file.
+                0 => (u64::MAX - start, 8),
+                size => (u64::from(size), 8),
+            };
+        if size < header_len {
+            return Err(format!("box at offset {} has a size of {}", start, size));
+        }
+        if &header[4..] == kind {
+            return Ok(Some(start.saturating_add(size)));
+        }
+        reader
+            .seek(SeekFrom::Start(start.saturating_add(size)))
+            .map_err(|err| err.to_string())?;
+    }
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    /// An `mvhd` box of `version` for `duration` units of `timescale` per second.
+    fn mvhd(version: u8, timescale: u32, duration: u64) -> Vec<u8> {
+        let mut contents = vec![version, 0, 0, 0];
+        if version == 1 {
+            contents.extend_from_slice(&[0; 16]);
+            contents.extend_from_slice(&timescale.to_be_bytes());
+            contents.extend_from_slice(&duration.to_be_bytes());
+        } else {
+            contents.extend_from_slice(&[0; 8]);
+            contents.extend_from_slice(&timescale.to_be_bytes());
+            contents.extend_from_slice(&(duration as u32).to_be_bytes());
+        }
+        contents.extend_from_slice(&[0; 80]);
+        mp4_box(b"mvhd", &contents)
+    }
+
+    fn mp4(mvhd: Vec<u8>) -> Vec<u8> {
+        let mut moov = mp4_box(b"iods", &[0; 12]);
+        moov.extend(mvhd);
+        let mut file = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        file.extend(mp4_box(b"free", &[]));
+        file.extend(mp4_box(b"moov", &moov));
+        file.extend(mp4_box(b"mdat", &[0; 32]));
+        file
+    }
+
+    #[test]
+    fn reads_mp4_durations_from_the_movie_header() {
+        assert_eq!(
+            mp4_duration(&mut Cursor::new(mp4(mvhd(0, 1000, 754_500)))),
+            Ok(Duration::from_millis(754_500))
+        );
+        assert_eq!(
+            mp4_duration(&mut Cursor::new(mp4(mvhd(1, 44100, 44100 * 36_000)))),
+            Ok(Duration::from_secs(36_000))
+        );
+    }
+
+    #[test]
+    fn mp4s_without_a_movie_header_have_no_duration() {
+        let file = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        assert_eq!(
+            mp4_duration(&mut Cursor::new(file)),
+            Err("no moov box".to_owned())
+        );
+        assert_eq!(
+            mp4_duration(&mut Cursor::new(mp4(mvhd(0, 0, 10)))),
+            Err("mvhd box has a timescale of 0".to_owned())
+        );
+    }
+
+    #[test]
+    fn reads_mp3_durations_from_frame_headers() {
+        // MPEG-1 layer III frames at 128 kbit/s and 44.1 kHz, each 417 bytes and 1152 samples.
+        let mut file = Vec::new();
+        for _ in 0..100 {
+            file.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            file.extend_from_slice(&[0; 413]);
+        }
+        let path = env::temp_dir().join(format!("sloop-duration-{}.mp3", std::process::id()));
+        fs::write(&path, file).unwrap();
+        let duration = mp3(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(duration.unwrap().as_millis(), 100 * 1152 * 1000 / 44100);
+    }
+}
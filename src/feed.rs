@@ -1,348 +1,5022 @@
-use chrono::{Duration, Utc};
-use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
-use rss::extension::itunes::{ITunesChannelExtensionBuilder, NAMESPACE};
-use rss::{ChannelBuilder, EnclosureBuilder, Item, ItemBuilder};
-use std::collections::BTreeMap;
+use crate::cancel::CancelToken;
+use crate::category::Category;
+use crate::discover;
+use crate::human;
+use crate::listing::ListedObject;
+use crate::mime;
+use crate::timezone::Timezone;
+use crate::urls::ObjectUrls;
+use crate::version;
+use crate::warning;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use glob::Pattern;
+use rss::extension::itunes::{
+    ITunesCategory, ITunesChannelExtensionBuilder, ITunesItemExtensionBuilder, NAMESPACE,
+};
+use rss::extension::{Extension, ExtensionMap};
+use rss::{ChannelBuilder, EnclosureBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder};
+use serde_json::{json, Value};
+use sloop::metadata::{MediaMetadata, Registry};
+use sloop::naming::{self, ObjectKey};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::io::prelude::*;
-use std::io::Error;
-use std::path::PathBuf;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{self, SystemTime};
 
-const ESCAPE_CHAR_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'.').remove(b'_');
+/// With `mark_new`, episodes that shouldn't look new are dated at least this long ago, beyond the
+/// window podcast apps treat as recent.
+const OLD_EPISODE_AGE_DAYS: i64 = 90;
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// Apple recommends keeping `itunes:subtitle` to roughly this many characters.
+const MAX_SUBTITLE_LEN: usize = 255;
 
 pub trait MediaFileLike {
-    fn name(&self) -> &str;
-    fn stem(&self) -> &str;
-    fn extension(&self) -> &str;
+    /// The file's name, or `None` if it isn't UTF-8.
+    fn name(&self) -> Option<&str>;
+    fn stem(&self) -> Option<&str>;
+    /// The file's extension, or `None` if it has none or it isn't UTF-8.
+    fn extension(&self) -> Option<&str>;
     fn len(&self) -> Result<u64, Error>;
+
+    /// How to name the file in messages, even if its name isn't UTF-8.
+    fn display_name(&self) -> String {
+        self.name().unwrap_or_default().to_owned()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether this episode is explicit, overriding the channel-level setting when present.
+    fn explicit(&self) -> Option<bool> {
+        None
+    }
+
+    /// The episode's title; by default derived from its file name.
+    fn title(&self) -> String {
+        naming::title_from_stem(self.stem().unwrap_or_default())
+    }
+
+    /// The title the file's tags give it, if they give one.
+    fn tag_title(&self) -> Option<String> {
+        None
+    }
+
+    /// The episode's show notes, if it has any.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// The season the episode belongs to and its number within it, if the feed has seasons.
+    fn season(&self) -> Option<Season> {
+        None
+    }
+
+    /// The file name of artwork for just this episode, published in the podcast's folder. Apps
+    /// show the channel image for episodes without one.
+    fn image(&self) -> Option<String> {
+        None
+    }
+
+    /// How long the episode plays for, if it can be told.
+    fn duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// The episode's `guid`, replacing the one derived from its URL, e.g. to keep the GUIDs a
+    /// feed had before it moved to sloop so podcast apps don't download every episode again.
+    fn guid(&self) -> Option<String> {
+        None
+    }
+
+    /// When the file was last modified, if the filesystem records it.
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// What a manifest says about the episode, which replaces anything sloop would derive.
+    fn given(&self) -> Option<&Given> {
+        None
+    }
+
+    /// The local file the episode is uploaded from, if it isn't already in the bucket.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// The episode's artwork, if it was written out to be uploaded with it.
+    fn art(&self) -> Option<&Art> {
+        None
+    }
+
+    /// Where the media is hosted outside the bucket, if it is, which its enclosure links to as
+    /// it is.
+    fn url(&self) -> Option<&str> {
+        None
+    }
+
+    /// The media's MIME type, if it's given rather than told from its extension.
+    fn mime_type(&self) -> Option<&str> {
+        None
+    }
 }
 
-pub struct MediaFile<'a> {
-    pub path: &'a PathBuf,
+/// What a manifest gives for an episode, each replacing what would otherwise be derived from its
+/// file or the options: its title, even one from a template, its description, even without
+/// `--descriptions-from`, its publication date and whether it's explicit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Given {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub pub_date: Option<DateTime<Utc>>,
+    pub explicit: Option<bool>,
 }
 
-impl<'a> MediaFileLike for MediaFile<'a> {
-    fn name(&self) -> &str {
-        self.path.file_name().unwrap().to_str().unwrap()
+/// A media file as it will be published, with the title and object key derived from its name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    pub path: PathBuf,
+    /// The title derived from the file's name.
+    pub title: String,
+    /// What its title, from its name or tags, starts with, e.g. `Disc 1` with
+    /// `--season-titles`.
+    pub title_prefix: Option<String>,
+    /// The name it's published under in the podcast's folder: its file name or, with
+    /// `--seasons-from-dirs`, its season's directory and file name, e.g. `Disc 1/track.mp3`.
+    pub name: String,
+    pub key: String,
+    pub season: Option<Season>,
+    pub guid: Option<String>,
+    pub given: Given,
+    /// The cover art embedded in the file, written out by `--extract-art`.
+    pub art: Option<Art>,
+    /// Read from the file the first time it's needed, and shared with clones so that no file is
+    /// read twice.
+    pub metadata: Arc<OnceLock<Option<MediaMetadata>>>,
+}
+
+impl Input {
+    /// What the file's metadata says, or `None`, with a warning, if it can't be read.
+    pub fn metadata(&self) -> Option<&MediaMetadata> {
+        self.metadata
+            .get_or_init(|| match extractors().extract(&self.path) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => {
+                    warning::warn(&format!(
+                        "couldn't read the metadata of {}: {}; its item won't have an \
+                         itunes:duration",
+                        self.path.display(),
+                        err
+                    ));
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+/// The extensions of the files beside a media file that hold its description, in the order
+/// they're looked for.
+const SIDECAR_EXTENSIONS: &[&str] = &["txt", "md"];
+
+/// The extractors `Input`s read their metadata with.
+fn extractors() -> &'static Registry {
+    static EXTRACTORS: OnceLock<Registry> = OnceLock::new();
+    EXTRACTORS.get_or_init(Registry::default)
+}
+
+/// Artwork written out from a media file to be published beside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Art {
+    /// Where the image was written.
+    pub path: PathBuf,
+    /// Its name in the podcast's folder, e.g. `art/Chapter_1.mp3.jpg`.
+    pub name: String,
+}
+
+/// An episode's place in a feed divided into seasons, both numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Season {
+    pub number: usize,
+    pub episode: usize,
+}
+
+impl MediaFileLike for Input {
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn stem(&self) -> Option<&str> {
+        self.path.file_stem()?.to_str()
     }
 
-    fn stem(&self) -> &str {
-        self.path.file_stem().unwrap().to_str().unwrap()
+    fn extension(&self) -> Option<&str> {
+        self.path.extension()?.to_str()
     }
 
-    fn extension(&self) -> &str {
-        self.path.extension().unwrap().to_str().unwrap()
+    fn display_name(&self) -> String {
+        self.path.display().to_string()
     }
 
     fn len(&self) -> Result<u64, Error> {
-        Ok(std::fs::metadata(self.path)?.len())
+        Ok(std::fs::metadata(&self.path)?.len())
+    }
+
+    fn title(&self) -> String {
+        self.prefixed(self.title.clone())
+    }
+
+    fn season(&self) -> Option<Season> {
+        self.season
+    }
+
+    fn guid(&self) -> Option<String> {
+        self.guid.clone()
+    }
+
+    fn image(&self) -> Option<String> {
+        self.art.as_ref().map(|art| art.name.clone())
+    }
+
+    /// Read from the file. Failing to only loses the item's `itunes:duration`, so it's a
+    /// warning rather than an error.
+    fn duration(&self) -> Option<std::time::Duration> {
+        self.metadata()?.duration
+    }
+
+    /// The ID3 or MP4 title, read with the duration.
+    fn tag_title(&self) -> Option<String> {
+        let title = self.metadata()?.title.clone()?;
+        Some(self.prefixed(title))
+    }
+
+    /// Read from the first sidecar file there is, e.g. `Chapter_1.txt` beside `Chapter_1.mp3`.
+    /// A sidecar that can't be read is left out with a warning, like a file's metadata.
+    fn description(&self) -> Option<String> {
+        for extension in SIDECAR_EXTENSIONS {
+            let sidecar = self.path.with_extension(extension);
+            match std::fs::read_to_string(&sidecar) {
+                Ok(text) => return Some(text.trim().to_owned()).filter(|text| !text.is_empty()),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => {
+                    warning::warn(&format!(
+                        "couldn't read {}: {}; its episode won't have a description",
+                        sidecar.display(),
+                        err
+                    ));
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn given(&self) -> Option<&Given> {
+        Some(&self.given)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn art(&self) -> Option<&Art> {
+        self.art.as_ref()
     }
 }
 
-pub struct Image {
-    pub path: PathBuf,
+/// An object already in the bucket, published as an episode without a local copy, e.g. one
+/// uploaded by other tools. Its name is its key under the podcast's folder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteFile {
+    pub name: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
 }
 
-impl Image {
-    fn name(&self) -> &str {
-        self.path.file_name().unwrap().to_str().unwrap()
+impl MediaFileLike for RemoteFile {
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    /// Of the last part of the key, so `Disc 1/Track_1.mp3` is titled `Track 1`.
+    fn stem(&self) -> Option<&str> {
+        Path::new(&self.name).file_stem()?.to_str()
+    }
+
+    fn extension(&self) -> Option<&str> {
+        Path::new(&self.name).extension()?.to_str()
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        Ok(self.size)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.last_modified.map(SystemTime::from)
     }
 }
 
-pub struct FeedGenerator {
-    pub title: String,
-    pub base_url: String,
-    pub image: Option<Image>,
+/// Media hosted somewhere other than the bucket, e.g. on archive.org, which a manifest lists
+/// by URL. Its enclosure links to the URL, with the length and type given, and it's never
+/// uploaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hosted {
+    pub url: String,
+    pub length: u64,
+    pub mime_type: String,
+    pub given: Given,
 }
 
-impl FeedGenerator {
-    pub fn generate_for_files<W: Write, M: MediaFileLike>(
-        &self,
-        files: Vec<M>,
-        mut writer: W,
-    ) -> Result<(), Error> {
-        let namespaces: BTreeMap<String, String> = [("itunes".to_string(), NAMESPACE.to_string())]
-            .iter()
-            .cloned()
-            .collect();
-        let itunes_ext = ITunesChannelExtensionBuilder::default()
-            .image(
-                self.image
-                    .as_ref()
-                    .map(|image| format!("{}/{}", self.base_url, image.name())),
-            )
-            .block("Yes".to_string())
-            .build();
-        let mut items: Vec<Item> = Default::default();
-        let today = Utc::now()
-            .date_naive()
-            .and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_utc();
-        for (i, file) in files.iter().enumerate() {
-            let pub_date = (today - Duration::days(i as i64)).to_rfc2822();
-            let escaped_name = utf8_percent_encode(file.name(), ESCAPE_CHAR_SET);
-            let enclosure = EnclosureBuilder::default()
-                .url(format!("{}/{}", self.base_url, escaped_name))
-                .mime_type(FeedGenerator::mime_type(file.extension()))
-                .length(file.len()?.to_string())
-                .build();
-            let item = ItemBuilder::default()
-                .title(Some(file.stem().replace('_', " ").to_owned()))
-                .enclosure(Some(enclosure))
-                .pub_date(pub_date)
-                .build();
-            items.push(item);
-        }
-        let channel = ChannelBuilder::default()
-            .namespaces(namespaces)
-            .title(self.title.clone())
-            .itunes_ext(itunes_ext)
-            .items(items)
-            .build();
-        channel.pretty_write_to(&mut writer, b' ', 2).unwrap();
-        Ok(())
+impl MediaFileLike for Hosted {
+    /// The last part of the URL's path.
+    fn name(&self) -> Option<&str> {
+        without_query(&self.url).rsplit('/').next()
+    }
+
+    fn stem(&self) -> Option<&str> {
+        Path::new(self.name()?).file_stem()?.to_str()
+    }
+
+    fn extension(&self) -> Option<&str> {
+        Path::new(self.name()?).extension()?.to_str()
+    }
+
+    fn display_name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        Ok(self.length)
+    }
+
+    /// The URL, as that's all that identifies the media.
+    fn guid(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    fn given(&self) -> Option<&Given> {
+        Some(&self.given)
+    }
+
+    fn url(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+
+    fn mime_type(&self) -> Option<&str> {
+        Some(&self.mime_type)
     }
+}
+
+/// An episode's media: a file to upload, or media hosted elsewhere that a manifest lists among
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    File(Input),
+    Hosted(Hosted),
+}
 
-    fn mime_type(extension: &str) -> String {
-        match extension {
-            "aac" => "audio/aac".to_owned(),
-            "m4a" => "audio/mp4".to_owned(),
-            "m4b" => "audio/mp4".to_owned(),
-            "mp3" => "audio/mpeg".to_owned(),
-            "mp4" => "audio/mp4".to_owned(),
-            _ => unimplemented!(),
+impl Entry {
+    fn media(&self) -> &dyn MediaFileLike {
+        match self {
+            Entry::File(input) => input,
+            Entry::Hosted(hosted) => hosted,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use roxmltree::{Document, Node};
-    use std::path::Path;
+impl MediaFileLike for Entry {
+    fn name(&self) -> Option<&str> {
+        self.media().name()
+    }
 
-    fn get_child_node_text<'a>(parent: &'a Node<'_, '_>, child_tag: &str) -> &'a str {
-        parent
-            .descendants()
-            .find(|n| n.tag_name().name() == child_tag)
-            .unwrap()
-            .text()
-            .unwrap()
+    fn stem(&self) -> Option<&str> {
+        self.media().stem()
     }
 
-    struct MockMediaFile {
-        name: String,
-        stem: String,
-        extension: String,
-        len: u64,
+    fn extension(&self) -> Option<&str> {
+        self.media().extension()
     }
 
-    impl Default for MockMediaFile {
-        fn default() -> Self {
-            Self {
-                name: "name1.mp3".to_owned(),
-                stem: "name1".to_owned(),
-                extension: "mp3".to_owned(),
-                len: 123,
-            }
+    fn len(&self) -> Result<u64, Error> {
+        self.media().len()
+    }
+
+    fn display_name(&self) -> String {
+        self.media().display_name()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        self.media().subtitle()
+    }
+
+    fn explicit(&self) -> Option<bool> {
+        self.media().explicit()
+    }
+
+    fn title(&self) -> String {
+        self.media().title()
+    }
+
+    fn tag_title(&self) -> Option<String> {
+        self.media().tag_title()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.media().description()
+    }
+
+    fn season(&self) -> Option<Season> {
+        self.media().season()
+    }
+
+    fn image(&self) -> Option<String> {
+        self.media().image()
+    }
+
+    fn duration(&self) -> Option<std::time::Duration> {
+        self.media().duration()
+    }
+
+    fn guid(&self) -> Option<String> {
+        self.media().guid()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.media().modified()
+    }
+
+    fn given(&self) -> Option<&Given> {
+        self.media().given()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.media().path()
+    }
+
+    fn art(&self) -> Option<&Art> {
+        self.media().art()
+    }
+
+    fn url(&self) -> Option<&str> {
+        self.media().url()
+    }
+
+    fn mime_type(&self) -> Option<&str> {
+        self.media().mime_type()
+    }
+}
+
+/// The objects under `folder` with one of `include_ext`, or, if none are given, a known audio
+/// type, as episodes in `sort` order, which for `FileSort::None` is the listing's order by
+/// key. Feeds are left out, as they're never episodes.
+pub fn remote_files(
+    objects: Vec<ListedObject>,
+    folder: &str,
+    include_ext: &[String],
+    sort: FileSort,
+) -> Vec<RemoteFile> {
+    let mut files: BTreeMap<PathBuf, RemoteFile> = BTreeMap::new();
+    let mut names = Vec::new();
+    for object in objects {
+        let name = match object.key.strip_prefix(folder) {
+            Some(name) if !name.is_empty() && !object.feed => name,
+            _ => continue,
+        };
+        let path = PathBuf::from(name);
+        let wanted = match include_ext.is_empty() {
+            true => path
+                .extension()
+                .and_then(|extension| mime::audio_type(extension.to_str()?))
+                .is_some(),
+            false => discover::has_extension(&path, include_ext),
+        };
+        if !wanted {
+            continue;
         }
+        let file = RemoteFile {
+            name: name.to_owned(),
+            size: object.size,
+            last_modified: object.last_modified,
+        };
+        names.push(path.clone());
+        files.insert(path, file);
     }
+    sort.sort(&mut names);
+    names.iter().filter_map(|name| files.remove(name)).collect()
+}
 
-    impl MediaFileLike for MockMediaFile {
-        fn name(&self) -> &str {
-            &self.name
+impl Input {
+    /// The file at `path`, published under `prefix`, or why it can't be published.
+    fn new(path: &Path, prefix: &str) -> Result<Self, String> {
+        let (stem, name) = match (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.file_name().and_then(|name| name.to_str()),
+        ) {
+            (Some(stem), Some(name)) => (stem, name),
+            _ => return Err(non_utf8_name(&path.display().to_string())),
+        };
+        let input = Input {
+            path: path.to_path_buf(),
+            title: naming::title_from_stem(stem),
+            title_prefix: None,
+            name: name.to_owned(),
+            key: ObjectKey::new(prefix, name).key().to_owned(),
+            season: None,
+            guid: None,
+            given: Given::default(),
+            art: None,
+            metadata: Default::default(),
+        };
+        match unpublishable(&input) {
+            Some(reason) => Err(reason),
+            None => Ok(input),
+        }
+    }
+
+    fn prefixed(&self, title: String) -> String {
+        match &self.title_prefix {
+            Some(prefix) => format!("{}: {}", prefix, title),
+            None => title,
+        }
+    }
+}
+
+fn non_utf8_name(name: &str) -> String {
+    format!(
+        "file '{}' has a name that isn't UTF-8, so has no title or URL; rename it to publish it",
+        name
+    )
+}
+
+/// Why `file` can't be published, if it can't: its title and URL come from its name, which
+/// must be UTF-8, and its MIME type from its extension.
+fn unpublishable<M: MediaFileLike>(file: &M) -> Option<String> {
+    if file.mime_type().is_some() {
+        return file
+            .name()
+            .is_none()
+            .then(|| non_utf8_name(&file.display_name()));
+    }
+    let (name, extension) = match (file.name(), file.stem(), file.extension()) {
+        (Some(name), Some(_), Some(extension)) => (name, extension),
+        (None, _, _) | (_, None, _) => return Some(non_utf8_name(&file.display_name())),
+        (_, _, None) => {
+            return Some(format!(
+                "file '{}' has no extension; cannot determine MIME type",
+                file.display_name()
+            ))
+        }
+    };
+    FeedGenerator::mime_type(name, extension)
+        .err()
+        .map(|err| err.to_string())
+}
+
+/// Fails with why a file can't be published or, with `skip_invalid`, warns that it's left out.
+fn skip_or_fail(reason: String, skip_invalid: bool) -> Result<(), String> {
+    if !skip_invalid {
+        return Err(reason);
+    }
+    warning::warn(&format!("{}; skipping it", reason));
+    Ok(())
+}
+
+/// Resolves the media files given on the command line into the episodes of the feed, in order.
+/// Episode order, and so publication dates, follow this list, and `--list-files` prints it, so
+/// what's printed is exactly what's published. Fails for files that can't be published at all
+/// unless `skip_invalid`, which leaves them out. Once any seasons are assigned, `check_keys`
+/// must pass.
+pub fn resolve_inputs(
+    files: &[PathBuf],
+    prefix: &str,
+    skip_invalid: bool,
+) -> Result<Vec<Input>, String> {
+    let mut inputs = Vec::new();
+    for path in files {
+        match Input::new(path, prefix) {
+            Ok(input) => inputs.push(input),
+            Err(reason) => skip_or_fail(reason, skip_invalid)?,
+        }
+    }
+    Ok(inputs)
+}
+
+/// Fails for inputs that would be published under the same key, e.g. two `track.mp3`s in
+/// different directories without `--seasons-from-dirs`.
+pub fn check_keys(inputs: &[Input]) -> Result<(), String> {
+    let mut paths_by_key: BTreeMap<&str, &Path> = BTreeMap::new();
+    for input in inputs {
+        if let Some(other) = paths_by_key.insert(&input.key, &input.path) {
+            return Err(format!(
+                "{} and {} would both be published as {}",
+                other.display(),
+                input.path.display(),
+                input.key
+            ));
         }
+    }
+    Ok(())
+}
+
+/// Extensions browsers and rippers give files they're still writing.
+const IN_PROGRESS_EXTENSIONS: &[&str] = &["part", "partial", "tmp", "crdownload"];
+
+/// Why the file at `path` looks like it's still being written, if it does: it has the
+/// extension of an unfinished download, is empty, or was modified less than `settle_time`
+/// before `now`. Files that can't be read are left for publishing to report.
+pub fn unsettled_reason(
+    path: &Path,
+    settle_time: time::Duration,
+    now: SystemTime,
+) -> Option<String> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    if IN_PROGRESS_EXTENSIONS
+        .iter()
+        .any(|partial| extension.eq_ignore_ascii_case(partial))
+    {
+        return Some(format!("it looks like an unfinished .{} file", extension));
+    }
+    let metadata = path.metadata().ok()?;
+    if metadata.is_file() && metadata.len() == 0 {
+        return Some("it's empty".to_owned());
+    }
+    let modified = metadata.modified().ok()?;
+    // A modification time ahead of the clock counts as just now.
+    let age = now.duration_since(modified).unwrap_or_default();
+    if age < settle_time {
+        return Some(format!(
+            "it was modified {}s ago, within the {}s settle time",
+            age.as_secs(),
+            settle_time.as_secs()
+        ));
+    }
+    None
+}
+
+/// Gives the inputs named in `guids`, as `NAME=GUID` with the name each input is published
+/// under, the GUID after the `=` instead of one derived from their URL. Fails for names that
+/// aren't inputs.
+pub fn assign_guids(inputs: &mut [Input], guids: &[String]) -> Result<(), String> {
+    for guid in guids {
+        let (name, guid) = match guid.split_once('=') {
+            Some((name, guid)) if !name.is_empty() && !guid.is_empty() => (name, guid),
+            _ => return Err(format!("--guid {:?} must be NAME=GUID", guid)),
+        };
+        let input = inputs
+            .iter_mut()
+            .find(|input| input.name() == Some(name))
+            .ok_or_else(|| format!("--guid names {}, which isn't one of the files", name))?;
+        input.guid = Some(guid.to_owned());
+    }
+    Ok(())
+}
+
+/// With `--strip-track-numbers`, titles each input after its file name without the track number
+/// it starts with, e.g. `03 - The Council of Elrond`. Keys, and so URLs, are left as they are.
+pub fn strip_track_numbers(inputs: &mut [Input]) {
+    for input in inputs {
+        let stem = input.stem().unwrap_or_default();
+        input.title = naming::title_from_stem(naming::strip_track_number(stem));
+    }
+}
 
-        fn stem(&self) -> &str {
-            &self.stem
+/// With `--extract-art`, writes the cover art embedded in each input to `dir`, named after the
+/// input's whole file name, e.g. `Chapter_1.mp3.jpg`, to be published as
+/// `art/Chapter_1.mp3.jpg`, so that `Chapter_1.mp3` and `Chapter_1.m4a` don't share a picture.
+/// Inputs published in a season's folder have their art in the same folder under `art/`.
+/// Inputs without art are left without, so apps show the channel image for them.
+pub fn extract_art(inputs: &mut [Input], dir: &Path) -> Result<(), String> {
+    for input in inputs {
+        let artwork = match input
+            .metadata()
+            .and_then(|metadata| metadata.artwork.as_ref())
+        {
+            Some(artwork) => artwork,
+            None => continue,
+        };
+        let extension = match artwork.mime_type.as_str() {
+            "image/png" => "png",
+            _ => "jpg",
+        };
+        let file_name = format!("{}.{}", input.name().unwrap_or_default(), extension);
+        let path = dir.join(&file_name);
+        let written = match path.parent() {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        };
+        written
+            .and_then(|_| std::fs::write(&path, &artwork.data))
+            .map_err(|err| {
+                format!(
+                    "Failed to write the art of {}: {}",
+                    input.path.display(),
+                    err
+                )
+            })?;
+        input.art = Some(Art {
+            path,
+            name: format!("art/{}", file_name),
+        });
+    }
+    Ok(())
+}
+
+/// With `--seasons-from-dirs`, makes each directory holding inputs a season, when there's more
+/// than one. Seasons are numbered in order of directory name, comparing numbers by value so
+/// `Disc 2` comes before `Disc 10`, and episodes within a season in feed order. Each is
+/// published in a folder named after its directory, e.g. `Disc 1/track.mp3`, as discs often
+/// repeat file names. With `season_titles`, titles start with the directory name, e.g.
+/// `Disc 1: Track 1`.
+pub fn assign_seasons(inputs: &mut [Input], season_titles: bool) {
+    let dir_name = |input: &Input| -> String {
+        input
+            .path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let mut dirs: Vec<(PathBuf, String)> = Vec::new();
+    for input in inputs.iter() {
+        let dir = input.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if !dirs.iter().any(|(seen, _)| *seen == dir) {
+            dirs.push((dir, dir_name(input)));
+        }
+    }
+    if dirs.len() < 2 {
+        return;
+    }
+    dirs.sort_by(|(a_dir, a_name), (b_dir, b_name)| {
+        natural_cmp(a_name, b_name).then_with(|| a_dir.cmp(b_dir))
+    });
+    let mut episodes = vec![0; dirs.len()];
+    for input in inputs.iter_mut() {
+        let dir = input.path.parent().unwrap_or(Path::new(""));
+        let index = dirs.iter().position(|(seen, _)| seen == dir).unwrap();
+        episodes[index] += 1;
+        input.season = Some(Season {
+            number: index + 1,
+            episode: episodes[index],
+        });
+        // Files given without a directory, or under `..`, stay where they are.
+        if !dirs[index].1.is_empty() {
+            let name = format!("{}/{}", dirs[index].1, input.name);
+            input.key = format!("{}{}", input.key.strip_suffix(&input.name).unwrap(), name);
+            input.name = name;
+        }
+        if season_titles {
+            input.title_prefix = Some(dirs[index].1.clone());
         }
+    }
+}
+
+/// How the media files given are put in order before they become episodes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileSort {
+    /// In the order given, e.g. by the shell, which puts `Chapter 10` before `Chapter 2`.
+    #[default]
+    None,
+    /// By path, character by character.
+    Name,
+    /// By path the way people sort them, ignoring case and comparing numbers by value, so
+    /// `Chapter 2` comes before `Chapter 10`.
+    Natural,
+}
+
+impl FileSort {
+    pub const VALUES: &'static [&'static str] = &["none", "name", "natural"];
+
+    /// Puts `files` in this order, before they're resolved into episodes so the feed, the
+    /// uploads and `--list-files` all follow it.
+    pub fn sort(self, files: &mut [PathBuf]) {
+        match self {
+            FileSort::None => {}
+            FileSort::Name => files.sort(),
+            FileSort::Natural => {
+                files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+            }
+        }
+    }
+}
+
+impl FromStr for FileSort {
+    type Err = String;
+
+    fn from_str(sort: &str) -> Result<Self, Self::Err> {
+        match sort {
+            "none" => Ok(FileSort::None),
+            "name" => Ok(FileSort::Name),
+            "natural" => Ok(FileSort::Natural),
+            _ => Err(format!(
+                "{} is not one of {}",
+                sort,
+                FileSort::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Compares names the way people sort them, ignoring case, with runs of digits compared by
+/// value. Names that differ only in case or leading zeros are then compared as they are, so
+/// the order never depends on the order they were given in.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(name: &str) -> Vec<&str> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut chars = name.char_indices().peekable();
+        while let Some((_, c)) = chars.next() {
+            let end = chars.peek().map_or(name.len(), |(i, _)| *i);
+            let next_is_digit = chars.peek().map(|(_, next)| next.is_ascii_digit());
+            if next_is_digit != Some(c.is_ascii_digit()) {
+                chunks.push(&name[start..end]);
+                start = end;
+            }
+        }
+        chunks
+    }
+    let (a_chunks, b_chunks) = (chunks(a), chunks(b));
+    for (a_chunk, b_chunk) in a_chunks.iter().zip(&b_chunks) {
+        let both_numbers = a_chunk.starts_with(|c: char| c.is_ascii_digit())
+            && b_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let order = if both_numbers {
+            let (a_digits, b_digits) = (
+                a_chunk.trim_start_matches('0'),
+                b_chunk.trim_start_matches('0'),
+            );
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            a_chunk.to_lowercase().cmp(&b_chunk.to_lowercase())
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+/// One line per entry, numbered from 1 in feed order, with tab-separated path, key and title,
+/// one of `titles` in the same order, so scripts can check the order. Hosted media have their
+/// URL in place of a path and `-` for a key, as they aren't uploaded.
+pub fn list_inputs(entries: &[Entry], titles: &[String]) -> String {
+    entries
+        .iter()
+        .zip(titles)
+        .enumerate()
+        .map(|(i, (entry, title))| {
+            let (path, key) = match entry {
+                Entry::File(input) => (input.path.display().to_string(), input.key.as_str()),
+                Entry::Hosted(hosted) => (hosted.url.clone(), "-"),
+            };
+            format!("{}\t{}\t{}\t{}\n", i + 1, path, key, title)
+        })
+        .collect()
+}
+
+pub struct Image {
+    pub path: PathBuf,
+}
+
+impl Image {
+    fn name(&self) -> &str {
+        self.path.file_name().unwrap().to_str().unwrap()
+    }
+}
+
+/// A URL for each episode's web page, with `{slug}`, `{index}` and `{name}` replaced by the
+/// episode's slugified title, 1-based position in the feed and percent-encoded file name.
+#[derive(Debug, Clone)]
+pub struct LinkTemplate(String);
+
+impl LinkTemplate {
+    pub fn render<M: MediaFileLike>(&self, file: &M, index: usize) -> Result<String, String> {
+        let mut link = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            link.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in {}", self.0))?;
+            match &rest[start + 1..start + end] {
+                "slug" => link.push_str(&naming::slug_from_title(file.stem().unwrap_or_default())),
+                "index" => link.push_str(&index.to_string()),
+                "name" => link.push_str(&naming::escape_file_name(file.name().unwrap_or_default())),
+                other => return Err(format!("unknown placeholder {{{}}} in {}", other, self.0)),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        link.push_str(rest);
+        validate_url(&link)?;
+        Ok(link)
+    }
+}
+
+impl FromStr for LinkTemplate {
+    type Err = String;
+
+    /// Checks the template by rendering it for an example episode.
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        struct Example;
+        impl MediaFileLike for Example {
+            fn name(&self) -> Option<&str> {
+                Some("Chapter_1.mp3")
+            }
+            fn stem(&self) -> Option<&str> {
+                Some("Chapter_1")
+            }
+            fn extension(&self) -> Option<&str> {
+                Some("mp3")
+            }
+            fn len(&self) -> Result<u64, Error> {
+                Ok(0)
+            }
+        }
+        let template = LinkTemplate(template.to_owned());
+        template.render(&Example, 1)?;
+        Ok(template)
+    }
+}
+
+/// Each episode's title, with `{title}` replaced by the title from its name or tags, `{stem}` by
+/// its file name without the extension, `{n}` by its 1-based position in the feed, `{total}` by
+/// the number of episodes and `{feed_title}` by the podcast's title. Numbers can be padded to a
+/// width, with zeros if it starts with one, e.g. `{n:02}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleTemplate(Vec<TitlePiece>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum TitlePiece {
+    Text(String),
+    Title,
+    Stem,
+    Number(Padding),
+    Total(Padding),
+    FeedTitle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Padding {
+    width: usize,
+    zeros: bool,
+}
+
+impl Padding {
+    fn pad(self, number: usize) -> String {
+        match self.zeros {
+            true => format!("{:0width$}", number, width = self.width),
+            false => format!("{:width$}", number, width = self.width),
+        }
+    }
+}
+
+impl TitleTemplate {
+    pub fn render<M: MediaFileLike>(
+        &self,
+        file: &M,
+        titles_from: TitleSource,
+        (n, total): (usize, usize),
+        feed_title: &str,
+    ) -> String {
+        self.0
+            .iter()
+            .map(|piece| match piece {
+                TitlePiece::Text(text) => text.clone(),
+                TitlePiece::Title => titles_from.title(file),
+                TitlePiece::Stem => file.stem().unwrap_or_default().to_owned(),
+                TitlePiece::Number(padding) => padding.pad(n),
+                TitlePiece::Total(padding) => padding.pad(total),
+                TitlePiece::FeedTitle => feed_title.to_owned(),
+            })
+            .collect()
+    }
+}
+
+/// Titles episodes as they always have been: from their names or tags alone.
+impl Default for TitleTemplate {
+    fn default() -> Self {
+        TitleTemplate(vec![TitlePiece::Title])
+    }
+}
+
+impl FromStr for TitleTemplate {
+    type Err = String;
+
+    /// Fails naming any placeholder it doesn't know, so typos don't end up in every title.
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut pieces = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                pieces.push(TitlePiece::Text(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in {:?}", template))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, width) = match placeholder.split_once(':') {
+                Some((name, width)) => (name, Some(width)),
+                None => (placeholder, None),
+            };
+            let padding = match width {
+                Some(width) => Some(Padding {
+                    width: width.parse().map_err(|_| {
+                        format!(
+                            "{{{}}} in {:?} must be padded to a width, e.g. {{{}:02}}",
+                            placeholder, template, name
+                        )
+                    })?,
+                    zeros: width.starts_with('0'),
+                }),
+                None => None,
+            };
+            pieces.push(match (name, padding) {
+                ("n", padding) => TitlePiece::Number(padding.unwrap_or_default()),
+                ("total", padding) => TitlePiece::Total(padding.unwrap_or_default()),
+                ("title" | "stem" | "feed_title", Some(_)) => {
+                    return Err(format!(
+                        "{{{}}} in {:?} can't be padded; only {{n}} and {{total}} can",
+                        placeholder, template
+                    ))
+                }
+                ("title", None) => TitlePiece::Title,
+                ("stem", None) => TitlePiece::Stem,
+                ("feed_title", None) => TitlePiece::FeedTitle,
+                _ => {
+                    return Err(format!(
+                        "unknown placeholder {{{}}} in {:?}; use {{title}}, {{stem}}, {{n}}, \
+                         {{total}} or {{feed_title}}",
+                        placeholder, template
+                    ))
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(TitlePiece::Text(rest.to_owned()));
+        }
+        Ok(TitleTemplate(pieces))
+    }
+}
+
+/// The title of each of `files`, which are all of a feed's episodes, in order. Titles a
+/// manifest gives are used as they are.
+pub fn episode_titles<M: MediaFileLike>(
+    files: &[M],
+    titles_from: TitleSource,
+    template: &TitleTemplate,
+    feed_title: &str,
+) -> Vec<String> {
+    files
+        .iter()
+        .enumerate()
+        .map(
+            |(i, file)| match file.given().and_then(|given| given.title.clone()) {
+                Some(title) => title,
+                None => template.render(file, titles_from, (i + 1, files.len()), feed_title),
+            },
+        )
+        .collect()
+}
+
+/// Parses `--link`, which must be an absolute http(s) URL.
+pub fn parse_link(url: &str) -> Result<String, String> {
+    validate_url(url)?;
+    Ok(url.to_owned())
+}
+
+fn validate_url(url: &str) -> Result<(), String> {
+    let uri = url
+        .parse::<hyper::Uri>()
+        .map_err(|err| format!("{} is not a valid URL: {}", url, err))?;
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http"), Some(_)) | (Some("https"), Some(_)) => Ok(()),
+        _ => Err(format!("{} is not an absolute http(s) URL", url)),
+    }
+}
+
+/// The language of feeds that don't say otherwise.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+#[derive(Default)]
+pub struct FeedGenerator {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
+    /// The channel's description, which defaults to the subtitle or else the title.
+    pub description: Option<String>,
+    /// The feed's language code, which defaults to [`DEFAULT_LANGUAGE`].
+    pub language: Option<String>,
+    /// The podcast's web site, which defaults to where its files are hosted.
+    pub link: Option<String>,
+    /// Apple Podcasts categories, in order. A category also given with a subcategory is only
+    /// written with the subcategory.
+    pub categories: Vec<Category>,
+    /// Where the files are fetched from: public URLs under the podcast's folder, or presigned
+    /// ones.
+    pub urls: ObjectUrls,
+    pub image: Option<Image>,
+    pub explicit: Option<bool>,
+    /// Leave out `itunes:block`, which otherwise keeps the feed out of Apple Podcasts and the
+    /// directories that follow it. Some directories take any block tag, even `No`, as a request
+    /// not to list the feed, so there's none at all.
+    pub listed: bool,
+    /// Episodes whose file names match any of these patterns are marked explicit.
+    pub explicit_files: Vec<Pattern>,
+    /// Only date this many of the newest episodes within the last week, so podcast apps don't
+    /// announce every episode as new.
+    pub mark_new: Option<usize>,
+    /// Keep only this many of the newest items in the feed, by publication date, so feeds of
+    /// daily episodes don't grow without end. Their files are still published.
+    pub max_items: Option<usize>,
+    /// The first episode's publication date, from midnight UTC; today if not given. Giving one
+    /// keeps the dates the same from run to run, so podcast apps don't re-sort the episodes.
+    pub start_date: Option<NaiveDate>,
+    /// How long before the one above each episode is dated; a day if not given. Zero dates
+    /// every episode the same.
+    pub interval: Option<time::Duration>,
+    /// Which way publication dates run down the feed.
+    pub order: DateOrder,
+    /// Whether episodes are dated by the schedule above or when their files were modified.
+    pub pub_date_from: PubDateSource,
+    /// The zone dates are written in; UTC if not given.
+    pub timezone: Option<Timezone>,
+    /// The local time of day the schedule dates episodes at, in `timezone`; midnight UTC if not
+    /// given.
+    pub time_of_day: Option<NaiveTime>,
+    /// When the feed is built, written as its `lastBuildDate`; now if not given. Giving one
+    /// also starts the schedule on its day, unless `start_date` is given, so that building the
+    /// same files twice writes the same feed.
+    pub build_date: Option<DateTime<Utc>>,
+    /// Whether episodes are titled after their file names or their tags.
+    pub titles_from: TitleSource,
+    /// What each episode's title is made of, which by default is just the title above.
+    pub title_template: TitleTemplate,
+    /// Where episodes' descriptions come from, if anywhere.
+    pub descriptions_from: DescriptionSource,
+    /// Written as `itunes:type`, telling apps whether to list the newest episode first or the
+    /// first episode first. Serial feeds number their items in feed order.
+    pub podcast_type: Option<PodcastType>,
+    /// The season every item belongs to, numbering them in feed order, unless they have
+    /// seasons from their directories.
+    pub season: Option<usize>,
+    /// Sets each item's `link` to its episode page.
+    pub episode_link: Option<LinkTemplate>,
+    /// Checked before each file, so generating a feed of hundreds of episodes stops promptly.
+    pub cancel: CancelToken,
+    /// Leave out files that can't be published, with a warning, instead of failing.
+    pub skip_invalid: bool,
+}
+
+/// Which way publication dates run through the files, which stay in the order given either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateOrder {
+    /// The first file is dated the start date and each after it earlier, so apps sorting by
+    /// date list the files in order.
+    #[default]
+    NewestFirst,
+    /// The last file is dated the start date and each before it earlier, so the first file is
+    /// the oldest and apps sorting by date play the files in order.
+    OldestFirst,
+}
+
+impl DateOrder {
+    pub const VALUES: &'static [&'static str] = &["newest-first", "oldest-first"];
+}
+
+impl FromStr for DateOrder {
+    type Err = String;
+
+    fn from_str(order: &str) -> Result<Self, Self::Err> {
+        match order {
+            "newest-first" => Ok(DateOrder::NewestFirst),
+            "oldest-first" => Ok(DateOrder::OldestFirst),
+            _ => Err(format!(
+                "{} is not one of {}",
+                order,
+                DateOrder::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Where episodes' publication dates come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PubDateSource {
+    /// A date per file, `interval` apart from the start date in `order`.
+    #[default]
+    Schedule,
+    /// When each file was last modified, e.g. as it was ripped. Files modified in the same
+    /// second are a second apart, in `order`, so apps sort them the same way every time.
+    Mtime,
+}
+
+impl PubDateSource {
+    pub const VALUES: &'static [&'static str] = &["schedule", "mtime"];
+}
+
+impl FromStr for PubDateSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "schedule" => Ok(PubDateSource::Schedule),
+            "mtime" => Ok(PubDateSource::Mtime),
+            _ => Err(format!(
+                "{} is not one of {}",
+                source,
+                PubDateSource::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Where episodes' titles come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TitleSource {
+    /// The file's name, without its extension and with underscores as spaces.
+    #[default]
+    Name,
+    /// The title in the file's ID3 or MP4 tags, or its name if it has none, e.g. for files
+    /// named `01.mp3`, `02.mp3` and so on.
+    Tags,
+}
+
+impl TitleSource {
+    pub const VALUES: &'static [&'static str] = &["name", "tags"];
+
+    pub fn title<M: MediaFileLike>(self, file: &M) -> String {
+        match self {
+            TitleSource::Name => file.title(),
+            TitleSource::Tags => file.tag_title().unwrap_or_else(|| file.title()),
+        }
+    }
+}
+
+impl FromStr for TitleSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "name" => Ok(TitleSource::Name),
+            "tags" => Ok(TitleSource::Tags),
+            _ => Err(format!(
+                "{} is not one of {}",
+                source,
+                TitleSource::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Where episodes' descriptions come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DescriptionSource {
+    /// Episodes have no description.
+    #[default]
+    None,
+    /// A text or Markdown file beside each media file with the same name, e.g. `Chapter_1.txt`
+    /// for `Chapter_1.mp3`. Episodes without one have no description.
+    Sidecar,
+}
+
+impl DescriptionSource {
+    pub const VALUES: &'static [&'static str] = &["none", "sidecar"];
+}
+
+impl FromStr for DescriptionSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "none" => Ok(DescriptionSource::None),
+            "sidecar" => Ok(DescriptionSource::Sidecar),
+            _ => Err(format!(
+                "{} is not one of {}",
+                source,
+                DescriptionSource::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Whether a podcast is listened to in any order, newest first, or from its first episode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PodcastType {
+    Episodic,
+    Serial,
+}
+
+impl PodcastType {
+    pub const VALUES: &'static [&'static str] = &["episodic", "serial"];
+}
+
+impl FromStr for PodcastType {
+    type Err = String;
+
+    fn from_str(podcast_type: &str) -> Result<Self, Self::Err> {
+        match podcast_type {
+            "episodic" => Ok(PodcastType::Episodic),
+            "serial" => Ok(PodcastType::Serial),
+            _ => Err(format!(
+                "{} is not one of {}",
+                podcast_type,
+                PodcastType::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PodcastType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PodcastType::Episodic => "episodic",
+            PodcastType::Serial => "serial",
+        })
+    }
+}
+
+/// One episode as it appears in every feed format, so that the formats can't disagree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Episode {
+    pub title: String,
+    pub url: String,
+    /// Identifies the episode to podcast apps, so they don't download it again when its URL or
+    /// anything else about it changes. Items of existing feeds may have none, and apps then
+    /// fall back to the URL.
+    pub guid: Option<String>,
+    pub mime_type: String,
+    pub length: u64,
+    pub pub_date: DateTime<Utc>,
+    pub link: Option<String>,
+    pub subtitle: Option<String>,
+    /// The episode's show notes, as plain text.
+    pub description: Option<String>,
+    pub explicit: Option<bool>,
+    /// The URL of the episode's own artwork, if it has any.
+    pub image: Option<String>,
+    /// The `itunes:season` and `itunes:episode` numbers, both from 1.
+    pub season: Option<usize>,
+    pub number: Option<usize>,
+    /// How long the episode plays for, in whole seconds.
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    /// [JSON Feed](https://jsonfeed.org/version/1.1).
+    Json,
+}
+
+impl FeedFormat {
+    /// JSON Feed for `.json` files and RSS for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => FeedFormat::Json,
+            _ => FeedFormat::Rss,
+        }
+    }
+}
+
+impl FeedGenerator {
+    pub fn episodes<M: MediaFileLike>(&self, files: Vec<M>) -> Result<Vec<Episode>, Error> {
+        let mut publishable = Vec::new();
+        for file in files {
+            match unpublishable(&file) {
+                None => publishable.push(file),
+                Some(reason) => skip_or_fail(reason, self.skip_invalid)
+                    .map_err(|reason| Error::new(ErrorKind::InvalidInput, reason))?,
+            }
+        }
+        let files = publishable;
+        let too_long = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "--interval {} is too long to date {} episodes that far apart",
+                    human::format_duration(self.interval.unwrap_or_default()),
+                    files.len()
+                ),
+            )
+        };
+        let interval = match self.interval {
+            Some(interval) => interval_duration(interval).ok_or_else(too_long)?,
+            None => Duration::days(1),
+        };
+        let mut pub_dates = match self.time_of_day {
+            None => {
+                let start = self
+                    .start_date
+                    .unwrap_or_else(|| self.build_date().date_naive())
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+                    .and_utc();
+                pub_dates(files.len(), start, interval, self.mark_new).ok_or_else(too_long)?
+            }
+            // The schedule runs on local clocks, so episodes keep their time of day when
+            // daylight saving starts or ends.
+            Some(time) => {
+                let start = self
+                    .start_date
+                    .unwrap_or_else(|| self.local(self.build_date()).date_naive())
+                    .and_time(time)
+                    .and_utc();
+                pub_dates(files.len(), start, interval, self.mark_new)
+                    .ok_or_else(too_long)?
+                    .into_iter()
+                    .map(|local| self.utc(local.naive_utc()))
+                    .collect()
+            }
+        };
+        if self.order == DateOrder::OldestFirst {
+            pub_dates.reverse();
+        }
+        if self.pub_date_from == PubDateSource::Mtime {
+            match modified_dates(&files, self.order) {
+                Ok(modified) => pub_dates = modified,
+                Err(name) => warning::warn(&format!(
+                    "couldn't tell when {} was modified, so episodes are dated by the schedule \
+                     instead",
+                    name
+                )),
+            }
+        }
+        let titles = episode_titles(&files, self.titles_from, &self.title_template, &self.title);
+        let mut episodes = Vec::new();
+        for (i, ((file, pub_date), title)) in files.iter().zip(pub_dates).zip(titles).enumerate() {
+            self.cancel.check()?;
+            let link = match &self.episode_link {
+                Some(template) => Some(
+                    template
+                        .render(file, i + 1)
+                        .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?,
+                ),
+                None => None,
+            };
+            let given = file.given().cloned().unwrap_or_default();
+            let (season, number) = match file.season() {
+                Some(season) => (Some(season.number), Some(season.episode)),
+                None => (self.season, self.numbered().then_some(i + 1)),
+            };
+            // Checked by `unpublishable` above, as is the extension of media without a type.
+            let name = file.name().unwrap();
+            let mime_type = match file.mime_type() {
+                Some(mime_type) => mime_type.to_owned(),
+                None => FeedGenerator::mime_type(name, file.extension().unwrap())?,
+            };
+            episodes.push(Episode {
+                title,
+                url: match file.url() {
+                    Some(url) => url.to_owned(),
+                    None => self.urls.url(name),
+                },
+                guid: Some(file.guid().unwrap_or_else(|| self.urls.stable_id(name))),
+                mime_type,
+                length: file.len()?,
+                pub_date: given.pub_date.unwrap_or(pub_date),
+                link,
+                subtitle: file
+                    .subtitle()
+                    .map(|subtitle| truncate_subtitle(&subtitle, name)),
+                description: match (given.description.clone(), self.descriptions_from) {
+                    (Some(description), _) => Some(description),
+                    (None, DescriptionSource::None) => None,
+                    (None, DescriptionSource::Sidecar) => file.description(),
+                },
+                explicit: self.item_explicit(file),
+                image: file.image().map(|name| self.urls.url(&name)),
+                season,
+                number,
+                duration: file
+                    .duration()
+                    .map(|duration| duration.as_secs_f64().round() as u64),
+            });
+        }
+        Ok(episodes)
+    }
+
+    /// Drops all but the `max_items` newest of `episodes`, keeping the rest in order, and
+    /// returns how many were dropped. Episodes are numbered before they're trimmed, so the
+    /// numbers of those kept don't change as older ones go.
+    pub fn trim(&self, episodes: &mut Vec<Episode>) -> usize {
+        let max_items = match self.max_items {
+            Some(max_items) => max_items,
+            None => return 0,
+        };
+        let dates: Vec<_> = episodes
+            .iter()
+            .map(|episode| Some(episode.pub_date))
+            .collect();
+        let mut keep = newest(&dates, max_items).into_iter();
+        let count = episodes.len();
+        episodes.retain(|_| keep.next().unwrap());
+        count - episodes.len()
+    }
+
+    /// Drops the items of `existing` and the `new` episodes after them beyond the `max_items`
+    /// newest of both together, and returns how many were dropped. New episodes are dated
+    /// after every existing item, so the existing ones go first.
+    pub fn trim_appended(&self, existing: &mut ExistingFeed, new: &mut Vec<Episode>) -> usize {
+        let max_items = match self.max_items {
+            Some(max_items) => max_items,
+            None => return 0,
+        };
+        let items = &mut existing.channel.items;
+        let dates: Vec<_> = items
+            .iter()
+            .map(item_pub_date)
+            .chain(new.iter().map(|episode| Some(episode.pub_date)))
+            .collect();
+        let mut keep = newest(&dates, max_items).into_iter();
+        let count = items.len() + new.len();
+        items.retain(|_| keep.next().unwrap());
+        new.retain(|_| keep.next().unwrap());
+        count - items.len() - new.len()
+    }
+
+    /// Whether items without seasons from their directories are numbered in feed order.
+    fn numbered(&self) -> bool {
+        self.podcast_type == Some(PodcastType::Serial) || self.season.is_some()
+    }
+
+    /// The enclosure length `episodes` give each of the local `files` they link to, so
+    /// publishing can check the files haven't changed since.
+    pub fn enclosure_lengths<'a, M: MediaFileLike>(
+        &self,
+        files: &'a [M],
+        episodes: &[Episode],
+    ) -> Vec<(&'a Path, u64)> {
+        files
+            .iter()
+            .filter_map(|file| {
+                let path = file.path()?;
+                let id = self.urls.stable_id(file.name()?);
+                episodes
+                    .iter()
+                    .find(|episode| without_query(&episode.url) == id)
+                    .map(|episode| (path, episode.length))
+            })
+            .collect()
+    }
+
+    /// Writes the feed in `format`. `feed_url` is where the feed itself is published, which
+    /// RSS gives as an `atom:link` to itself and JSON Feed as `feed_url`.
+    pub fn write<W: Write>(
+        &self,
+        episodes: &[Episode],
+        format: FeedFormat,
+        feed_url: Option<&str>,
+        writer: W,
+    ) -> Result<(), Error> {
+        match format {
+            FeedFormat::Rss => self.write_rss(episodes, feed_url, writer),
+            FeedFormat::Json => self.write_json(episodes, feed_url, writer),
+        }
+    }
+
+    /// The episodes for those of `files` that `existing` doesn't link to yet, dated after its
+    /// newest item and a second apart in the order given, so that podcast apps announce only
+    /// these as new.
+    ///
+    /// Given `same_contents`, which tells whether a local file holds the same bytes as a
+    /// published one, or `None` if it can't tell, files renamed since the feed was published
+    /// aren't new: the item linking to the old name is pointed at the new one instead, keeping
+    /// its guid and date.
+    pub fn new_episodes<M: MediaFileLike>(
+        &self,
+        files: Vec<M>,
+        existing: &mut ExistingFeed,
+        same_contents: Option<&SameContents>,
+    ) -> Result<Vec<Episode>, Error> {
+        let paths: HashMap<String, PathBuf> = files
+            .iter()
+            .filter_map(|file| Some((self.urls.stable_id(file.name()?), file.path()?.to_owned())))
+            .collect();
+        let all = self.episodes(files)?;
+        let linked: HashSet<String> = all
+            .iter()
+            .map(|episode| without_query(&episode.url).to_owned())
+            .collect();
+        let mut episodes = Vec::new();
+        for episode in all {
+            if existing.links_to(&episode.url) {
+                continue;
+            }
+            if let Some(same_contents) = same_contents {
+                let path = paths.get(without_query(&episode.url)).map(PathBuf::as_path);
+                let renamed =
+                    existing.renamed_from(&episode, path, &linked, &self.urls, same_contents)?;
+                if let Some(item) = renamed {
+                    existing.rename(item, &episode);
+                    continue;
+                }
+            }
+            episodes.push(episode);
+        }
+        let dates = appended_pub_dates(
+            episodes.len(),
+            existing.newest_pub_date(),
+            self.build_date(),
+        );
+        for (episode, pub_date) in episodes.iter_mut().zip(dates) {
+            episode.pub_date = pub_date;
+        }
+        Ok(episodes)
+    }
+
+    /// Writes `existing` in `format` with the `new` episodes after its items. In RSS every
+    /// existing item is kept as it was, as is anything in the channel sloop doesn't write
+    /// itself; sloop's own channel fields are updated, and `lastBuildDate` and `pubDate` are set
+    /// if there are new episodes.
+    pub fn write_appended<W: Write>(
+        &self,
+        existing: &ExistingFeed,
+        new: &[Episode],
+        format: FeedFormat,
+        feed_url: Option<&str>,
+        mut writer: W,
+    ) -> Result<(), Error> {
+        if format == FeedFormat::Json {
+            let mut episodes = existing.episodes();
+            episodes.extend_from_slice(new);
+            return self.write_json(&episodes, feed_url, writer);
+        }
+        let fresh = self.rss_channel(new, feed_url);
+        let mut channel = existing.channel.clone();
+        channel.title = fresh.title;
+        channel.image = fresh.image;
+        // The defaults are for new feeds; an existing feed keeps what it has unless told
+        // otherwise.
+        if self.link.is_some() {
+            channel.link = fresh.link;
+        }
+        if self.description.is_some() {
+            channel.description = fresh.description;
+        }
+        if self.language.is_some() {
+            channel.language = fresh.language;
+        }
+        channel.generator = fresh.generator;
+        channel.namespaces.extend(fresh.namespaces);
+        for (namespace, elements) in fresh.extensions {
+            channel
+                .extensions
+                .entry(namespace)
+                .or_default()
+                .extend(elements);
+        }
+        let fresh_itunes = fresh.itunes_ext.unwrap_or_default();
+        let itunes = channel.itunes_ext.get_or_insert_with(Default::default);
+        itunes.image = fresh_itunes.image;
+        if self.author.is_some() {
+            itunes.author = fresh_itunes.author;
+        }
+        if !self.categories.is_empty() {
+            itunes.categories = fresh_itunes.categories;
+        }
+        itunes.block = fresh_itunes.block;
+        itunes.subtitle = fresh_itunes.subtitle;
+        itunes.explicit = fresh_itunes.explicit;
+        if self.podcast_type.is_some() {
+            itunes.r#type = fresh_itunes.r#type;
+        }
+        if !new.is_empty() {
+            channel.last_build_date = fresh.last_build_date;
+            // New episodes are dated after every existing item.
+            channel.pub_date = fresh.pub_date;
+        }
+        channel.items.extend(fresh.items);
+        write_channel(&channel, &mut writer)
+    }
+
+    /// The channel fields and episodes of the feed, one per line of a `--plan`. Publication
+    /// dates and URL signatures are left out, since they move with the day the plan is made.
+    pub fn channel_summary(&self, episodes: &[Episode]) -> Vec<(&'static str, String)> {
+        let mut summary = vec![("title", self.title.clone())];
+        if let Some(subtitle) = &self.subtitle {
+            summary.push(("subtitle", subtitle.clone()));
+        }
+        if let Some(author) = &self.author {
+            summary.push(("author", author.clone()));
+        }
+        if let Some(language) = &self.language {
+            summary.push(("language", language.clone()));
+        }
+        if let Some(link) = &self.link {
+            summary.push(("link", link.clone()));
+        }
+        for category in &self.categories {
+            summary.push(("category", category.to_string()));
+        }
+        if let Some(image) = &self.image {
+            summary.push(("image", self.urls.stable_id(image.name())));
+        }
+        if let Some(explicit) = self.explicit {
+            summary.push(("explicit", explicit.to_string()));
+        }
+        if self.listed {
+            summary.push(("listed", true.to_string()));
+        }
+        if let Some(podcast_type) = self.podcast_type {
+            summary.push(("type", podcast_type.to_string()));
+        }
+        for episode in episodes {
+            let explicit = if episode.explicit == Some(true) {
+                " [explicit]"
+            } else {
+                ""
+            };
+            let season = match (episode.season, episode.number) {
+                (Some(season), Some(number)) => format!(" [S{}E{}]", season, number),
+                (Some(season), None) => format!(" [S{}]", season),
+                (None, Some(number)) => format!(" [E{}]", number),
+                (None, None) => String::new(),
+            };
+            summary.push((
+                "episode",
+                format!(
+                    "{} <{}>{}{}",
+                    episode.title,
+                    episode.guid.as_ref().unwrap_or(&episode.url),
+                    season,
+                    explicit
+                ),
+            ));
+        }
+        summary
+    }
+
+    fn itunes_categories(&self) -> Vec<ITunesCategory> {
+        let mut categories: Vec<ITunesCategory> = Vec::new();
+        for category in &self.categories {
+            let narrowed = self
+                .categories
+                .iter()
+                .any(|other| other.name == category.name && other.subcategory.is_some());
+            if category.subcategory.is_none() && narrowed {
+                continue;
+            }
+            let itunes = ITunesCategory {
+                text: category.name.to_owned(),
+                subcategory: category.subcategory.map(|subcategory| {
+                    Box::new(ITunesCategory {
+                        text: subcategory.to_owned(),
+                        subcategory: None,
+                    })
+                }),
+            };
+            if !categories.contains(&itunes) {
+                categories.push(itunes);
+            }
+        }
+        categories
+    }
+
+    fn channel_link(&self) -> String {
+        self.link.clone().unwrap_or_else(|| self.urls.base_url())
+    }
+
+    fn channel_description(&self) -> &str {
+        self.description
+            .as_deref()
+            .or(self.subtitle.as_deref())
+            .unwrap_or(&self.title)
+    }
+
+    fn image_url(&self) -> Option<String> {
+        self.image.as_ref().map(|image| self.urls.url(image.name()))
+    }
+
+    fn write_rss<W: Write>(
+        &self,
+        episodes: &[Episode],
+        feed_url: Option<&str>,
+        mut writer: W,
+    ) -> Result<(), Error> {
+        let channel = self.rss_channel(episodes, feed_url);
+        write_channel(&channel, &mut writer)
+    }
+
+    fn rss_channel(&self, episodes: &[Episode], feed_url: Option<&str>) -> rss::Channel {
+        let mut namespaces: BTreeMap<String, String> =
+            [("itunes".to_string(), NAMESPACE.to_string())]
+                .iter()
+                .cloned()
+                .collect();
+        let mut extensions = ExtensionMap::new();
+        if let Some(feed_url) = feed_url {
+            namespaces.insert("atom".to_string(), ATOM_NAMESPACE.to_string());
+            let mut link = Extension {
+                name: "atom:link".to_string(),
+                ..Default::default()
+            };
+            link.attrs.insert("href".to_string(), feed_url.to_string());
+            link.attrs.insert("rel".to_string(), "self".to_string());
+            link.attrs
+                .insert("type".to_string(), "application/rss+xml".to_string());
+            extensions.insert(
+                "atom".to_string(),
+                BTreeMap::from([("link".to_string(), vec![link])]),
+            );
+        }
+        let itunes_ext = ITunesChannelExtensionBuilder::default()
+            .author(self.author.clone())
+            .categories(self.itunes_categories())
+            .image(self.image_url())
+            .block(match self.listed {
+                true => None,
+                false => Some("Yes".to_string()),
+            })
+            .subtitle(
+                self.subtitle
+                    .as_ref()
+                    .map(|subtitle| truncate_subtitle(subtitle, "the feed")),
+            )
+            .explicit(self.explicit.map(explicit_value))
+            .r#type(
+                self.podcast_type
+                    .map(|podcast_type| podcast_type.to_string()),
+            )
+            .build();
+        let items: Vec<Item> = episodes
+            .iter()
+            .map(|episode| {
+                let enclosure = EnclosureBuilder::default()
+                    .url(episode.url.clone())
+                    .mime_type(episode.mime_type.clone())
+                    .length(episode.length.to_string())
+                    .build();
+                let itunes_ext = ITunesItemExtensionBuilder::default()
+                    .subtitle(episode.subtitle.clone())
+                    .summary(episode.description.clone())
+                    .explicit(episode.explicit.map(explicit_value))
+                    .image(episode.image.clone())
+                    .season(episode.season.map(|season| season.to_string()))
+                    .episode(episode.number.map(|number| number.to_string()))
+                    .duration(episode.duration.map(itunes_duration))
+                    .build();
+                let guid = episode.guid.as_ref().map(|guid| {
+                    GuidBuilder::default()
+                        .value(guid.clone())
+                        .permalink(false)
+                        .build()
+                });
+                ItemBuilder::default()
+                    .title(Some(episode.title.clone()))
+                    .guid(guid)
+                    .link(episode.link.clone())
+                    .description(episode.description.as_deref().map(cdata_safe))
+                    .enclosure(Some(enclosure))
+                    .pub_date(self.local(episode.pub_date).to_rfc2822())
+                    .itunes_ext(Some(itunes_ext))
+                    .build()
+            })
+            .collect();
+        // RSS readers show the standard image, and podcast apps `itunes:image`.
+        let image = self.image_url().map(|url| {
+            ImageBuilder::default()
+                .url(url)
+                .title(self.title.clone())
+                .link(self.channel_link())
+                .build()
+        });
+        ChannelBuilder::default()
+            .namespaces(namespaces)
+            .title(self.title.clone())
+            .link(self.channel_link())
+            .description(self.channel_description().to_owned())
+            .language(Some(
+                self.language
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_LANGUAGE.to_owned()),
+            ))
+            .image(image)
+            .pub_date(
+                episodes
+                    .iter()
+                    .map(|episode| episode.pub_date)
+                    .max()
+                    .map(|date| self.local(date).to_rfc2822()),
+            )
+            .last_build_date(Some(self.local(self.build_date()).to_rfc2822()))
+            .generator(Some(format!("sloop {}", version::VERSION)))
+            .itunes_ext(itunes_ext)
+            .extensions(extensions)
+            .items(items)
+            .build()
+    }
+
+    /// JSON Feed has no equivalent of `itunes:block` or `itunes:explicit`, and no length limit
+    /// on the description, so the subtitle is written in full.
+    fn write_json<W: Write>(
+        &self,
+        episodes: &[Episode],
+        feed_url: Option<&str>,
+        writer: W,
+    ) -> Result<(), Error> {
+        let items: Vec<Value> = episodes
+            .iter()
+            .map(|episode| {
+                let mut item = json!({
+                    "id": episode.guid.as_ref().unwrap_or(&episode.url),
+                    "title": episode.title,
+                    "date_published": self.local(episode.pub_date).to_rfc3339(),
+                    "attachments": [{
+                        "url": episode.url,
+                        "mime_type": episode.mime_type,
+                        "size_in_bytes": episode.length,
+                    }],
+                });
+                if let Some(link) = &episode.link {
+                    item["url"] = json!(link);
+                }
+                if let Some(subtitle) = &episode.subtitle {
+                    item["summary"] = json!(subtitle);
+                }
+                if let Some(description) = &episode.description {
+                    item["content_text"] = json!(description);
+                }
+                if let Some(image) = &episode.image {
+                    item["image"] = json!(image);
+                }
+                if let Some(duration) = episode.duration {
+                    item["attachments"][0]["duration_in_seconds"] = json!(duration);
+                }
+                item
+            })
+            .collect();
+        let mut feed = json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "items": items,
+        });
+        if let Some(feed_url) = feed_url {
+            feed["feed_url"] = json!(feed_url);
+        }
+        if let Some(description) = self.description.as_ref().or(self.subtitle.as_ref()) {
+            feed["description"] = json!(description);
+        }
+        if let Some(link) = &self.link {
+            feed["home_page_url"] = json!(link);
+        }
+        if let Some(author) = &self.author {
+            feed["authors"] = json!([{ "name": author }]);
+        }
+        feed["language"] = json!(self.language.as_deref().unwrap_or(DEFAULT_LANGUAGE));
+        if let Some(image) = self.image_url() {
+            feed["icon"] = json!(image);
+        }
+        serde_json::to_writer_pretty(writer, &feed)?;
+        Ok(())
+    }
+
+    /// `date` in the feed's timezone.
+    /// When the feed is built, to the second.
+    fn build_date(&self) -> DateTime<Utc> {
+        self.build_date
+            .unwrap_or_else(|| Utc.timestamp_opt(Utc::now().timestamp(), 0).unwrap())
+    }
+
+    fn local(&self, date: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match &self.timezone {
+            Some(timezone) => date.with_timezone(&timezone.offset_at(date)),
+            None => date.with_timezone(&FixedOffset::east_opt(0).unwrap()),
+        }
+    }
+
+    /// When it's `local` in the feed's timezone.
+    fn utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match &self.timezone {
+            Some(timezone) => timezone.to_utc(local),
+            None => local.and_utc(),
+        }
+    }
+
+    fn item_explicit<M: MediaFileLike>(&self, file: &M) -> Option<bool> {
+        let given = file.given().and_then(|given| given.explicit);
+        given.or_else(|| file.explicit()).or_else(|| {
+            self.explicit_files
+                .iter()
+                .any(|pattern| file.name().is_some_and(|name| pattern.matches(name)))
+                .then_some(true)
+        })
+    }
+
+    /// The enclosure type of the file called `name`, or an error naming it if its extension
+    /// isn't one of a known audio format.
+    fn mime_type(name: &str, extension: &str) -> Result<String, Error> {
+        match mime::audio_type(extension) {
+            Some(mime_type) => Ok(mime_type.to_owned()),
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} has the extension .{}, which isn't an audio format sloop knows ({})",
+                    name,
+                    extension,
+                    mime::audio_extensions().join(", ")
+                ),
+            )),
+        }
+    }
+}
+
+/// Writes `channel` as RSS, failing instead of panicking if it can't be, e.g. as the disk is full.
+fn write_channel<W: Write>(channel: &rss::Channel, writer: W) -> Result<(), Error> {
+    channel
+        .pretty_write_to(writer, b' ', 2)
+        .map_err(|err| rss_error("the RSS feed", err))?;
+    Ok(())
+}
+
+/// `err`, from the rss crate, as an error saying `what` was being written. An I/O error behind
+/// it keeps its kind, so e.g. a full disk is still reported as one.
+fn rss_error(what: &str, err: rss::Error) -> Error {
+    let mut kind = ErrorKind::InvalidData;
+    let mut source = std::error::Error::source(&err);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<Arc<Error>>() {
+            kind = io.kind();
+            break;
+        }
+        source = cause.source();
+    }
+    Error::new(kind, format!("Failed to write {}: {}", what, err))
+}
+
+/// Publication dates for `count` episodes, newest first, `interval` apart and starting at
+/// `start`. With `mark_new`, only the first `mark_new` episodes fall within the week before
+/// `start` and the rest are dated at least `OLD_EPISODE_AGE_DAYS` before it. Dates strictly
+/// decrease unless `interval` is zero. `None` if any would be earlier than dates can go.
+fn pub_dates(
+    count: usize,
+    start: DateTime<Utc>,
+    interval: Duration,
+    mark_new: Option<usize>,
+) -> Option<Vec<DateTime<Utc>>> {
+    let new = match mark_new {
+        Some(new) => new.min(count),
+        None => return (0..count).map(|i| before(start, interval, i)).collect(),
+    };
+    // Space new episodes `interval` apart unless there are too many to fit in a week.
+    let step = interval.min(Duration::seconds(7 * 24 * 60 * 60 / new.max(1) as i64));
+    let old_start = start.checked_sub_signed(Duration::days(OLD_EPISODE_AGE_DAYS))?;
+    (0..count)
+        .map(|i| {
+            if i < new {
+                before(start, step, i)
+            } else {
+                before(old_start, interval, i - new)
+            }
+        })
+        .collect()
+}
+
+/// The date `count` times `interval` before `date`, or `None` if that's before any date can be.
+fn before(date: DateTime<Utc>, interval: Duration, count: usize) -> Option<DateTime<Utc>> {
+    let seconds = interval
+        .to_std()
+        .ok()?
+        .as_secs()
+        .checked_mul(count as u64)?;
+    let offset = Duration::from_std(time::Duration::from_secs(seconds)).ok()?;
+    date.checked_sub_signed(offset)
+}
+
+/// `interval` to the second, as dates are reckoned, or `None` if it's too long for them.
+pub fn interval_duration(interval: time::Duration) -> Option<Duration> {
+    Duration::from_std(time::Duration::from_secs(interval.as_secs())).ok()
+}
+
+/// When each of `files` was modified, to the second, or the name of the first whose filesystem
+/// doesn't say. Files modified in the same second are spread a second apart going back in time
+/// down the list, or forward with `OldestFirst`, so their order doesn't depend on the app.
+fn modified_dates<M: MediaFileLike>(
+    files: &[M],
+    order: DateOrder,
+) -> Result<Vec<DateTime<Utc>>, String> {
+    let mut seen: BTreeMap<i64, i64> = BTreeMap::new();
+    files
+        .iter()
+        .map(|file| {
+            let modified = file.modified().ok_or_else(|| file.display_name())?;
+            let seconds = DateTime::<Utc>::from(modified).timestamp();
+            let earlier = seen.entry(seconds).or_insert(0);
+            let tiebreak = match order {
+                DateOrder::NewestFirst => -*earlier,
+                DateOrder::OldestFirst => *earlier,
+            };
+            *earlier += 1;
+            Ok(Utc.timestamp_opt(seconds + tiebreak, 0).unwrap())
+        })
+        .collect()
+}
+
+/// Publication dates for `count` appended episodes, a second apart and increasing, so the
+/// last is the newest. They end at `now` unless that would date any of them before `newest`.
+fn appended_pub_dates(
+    count: usize,
+    newest: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    let mut start = now - Duration::seconds(count.saturating_sub(1) as i64);
+    if let Some(newest) = newest {
+        start = start.max(newest + Duration::seconds(1));
+    }
+    (0..count)
+        .map(|i| start + Duration::seconds(i as i64))
+        .collect()
+}
+
+/// `seconds` as `itunes:duration` is written, e.g. `01:02:03`.
+fn itunes_duration(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parses an `itunes:duration`, which may be `HH:MM:SS`, `MM:SS` or a number of seconds.
+fn parse_itunes_duration(duration: &str) -> Option<u64> {
+    duration.split(':').try_fold(0, |total: u64, part| {
+        let part: u64 = part.trim().parse().ok()?;
+        Some(total * 60 + part)
+    })
+}
+
+fn explicit_value(explicit: bool) -> String {
+    explicit.to_string()
+}
+
+/// `text` ready for the CDATA section the rss crate writes descriptions in, which would end at
+/// the first `]]>`: each is split across two sections, so it still reads as `]]>`.
+fn cdata_safe(text: &str) -> String {
+    text.replace("]]>", "]]]]><![CDATA[>")
+}
+
+fn truncate_subtitle(subtitle: &str, source: &str) -> String {
+    if subtitle.chars().count() <= MAX_SUBTITLE_LEN {
+        return subtitle.to_owned();
+    }
+    warning::warn(&format!(
+        "subtitle for {} is longer than {} characters and has been truncated",
+        source, MAX_SUBTITLE_LEN
+    ));
+    subtitle.chars().take(MAX_SUBTITLE_LEN).collect()
+}
+
+/// Whether the local file holds the same bytes as the published file of the name given, or
+/// `None` if that can't be told without downloading it.
+pub type SameContents<'a> = dyn Fn(&Path, &str) -> Result<Option<bool>, String> + 'a;
+
+/// A feed published earlier, which `--append` adds episodes to without changing its items,
+/// other than pointing those for renamed files at their new names.
+#[derive(Debug, Clone)]
+pub struct ExistingFeed {
+    channel: rss::Channel,
+    /// The URLs of the files found renamed, each with the URL of its new name.
+    renamed: Vec<(String, String)>,
+}
+
+impl ExistingFeed {
+    pub fn parse(feed: &[u8]) -> Result<Self, String> {
+        let channel = rss::Channel::read_from(feed).map_err(|e| e.to_string())?;
+        Ok(Self {
+            channel,
+            renamed: Vec::new(),
+        })
+    }
+
+    /// The URLs of the files found renamed, each with the URL of its new name.
+    pub fn renamed(&self) -> &[(String, String)] {
+        &self.renamed
+    }
+
+    /// The index of the item for the file that `episode`'s file at `path` was renamed from:
+    /// one linking to a file none of the episodes (`linked`) link to any more, of the same
+    /// length, and with the same contents or, if `same_contents` can't tell, the same
+    /// duration. A file matching only in length is new.
+    fn renamed_from(
+        &self,
+        episode: &Episode,
+        path: Option<&Path>,
+        linked: &HashSet<String>,
+        urls: &ObjectUrls,
+        same_contents: &SameContents,
+    ) -> Result<Option<usize>, Error> {
+        for (i, item) in self.channel.items.iter().enumerate() {
+            let enclosure = match &item.enclosure {
+                Some(enclosure) => enclosure,
+                None => continue,
+            };
+            let url = without_query(&enclosure.url);
+            if linked.contains(url) || enclosure.length.parse() != Ok(episode.length) {
+                continue;
+            }
+            let same = match (path, urls.name(url)) {
+                (Some(path), Some(name)) => same_contents(path, &name).map_err(Error::other)?,
+                _ => None,
+            };
+            let renamed = match same {
+                Some(same) => same,
+                None => {
+                    let duration = item
+                        .itunes_ext
+                        .as_ref()
+                        .and_then(|itunes| itunes.duration.as_deref())
+                        .and_then(parse_itunes_duration);
+                    duration.is_some() && duration == episode.duration
+                }
+            };
+            if renamed {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Points item `i` at `episode`'s file and title, keeping its guid and date so podcast
+    /// apps take it for the episode they already have. An item without a guid is identified
+    /// by its URL, so that becomes its guid.
+    fn rename(&mut self, i: usize, episode: &Episode) {
+        let item = &mut self.channel.items[i];
+        let enclosure = item.enclosure.as_mut().unwrap();
+        let old_url = std::mem::replace(&mut enclosure.url, episode.url.clone());
+        enclosure.mime_type = episode.mime_type.clone();
+        if item.guid.is_none() {
+            item.guid = Some(
+                GuidBuilder::default()
+                    .value(old_url.clone())
+                    .permalink(false)
+                    .build(),
+            );
+        }
+        item.title = Some(episode.title.clone());
+        self.renamed.push((old_url, episode.url.clone()));
+    }
+
+    /// Whether any item's enclosure links to `url`, ignoring query strings so that presigned
+    /// URLs match from one run to the next.
+    fn links_to(&self, url: &str) -> bool {
+        let url = without_query(url);
+        self.channel.items.iter().any(|item| {
+            item.enclosure
+                .as_ref()
+                .is_some_and(|enclosure| without_query(&enclosure.url) == url)
+        })
+    }
+
+    fn newest_pub_date(&self) -> Option<DateTime<Utc>> {
+        self.channel.items.iter().filter_map(item_pub_date).max()
+    }
+
+    /// The items that have an enclosure, as episodes, for writing JSON feeds and plans.
+    pub fn episodes(&self) -> Vec<Episode> {
+        self.channel.items.iter().filter_map(item_episode).collect()
+    }
+}
+
+fn item_episode(item: &Item) -> Option<Episode> {
+    let enclosure = item.enclosure.as_ref()?;
+    let itunes = item.itunes_ext.as_ref();
+    let number = |value: Option<&String>| value.and_then(|value| value.parse().ok());
+    let pub_date = item_pub_date(item).unwrap_or_else(Utc::now);
+    Some(Episode {
+        title: item.title.clone().unwrap_or_default(),
+        url: enclosure.url.clone(),
+        guid: item.guid.as_ref().map(|guid| guid.value.clone()),
+        mime_type: enclosure.mime_type.clone(),
+        length: enclosure.length.parse().unwrap_or(0),
+        pub_date,
+        link: item.link.clone(),
+        subtitle: itunes.and_then(|itunes| itunes.subtitle.clone()),
+        description: item.description.clone(),
+        explicit: itunes
+            .and_then(|itunes| itunes.explicit.as_deref())
+            .map(|explicit| matches!(explicit, "true" | "yes")),
+        image: itunes.and_then(|itunes| itunes.image.clone()),
+        season: number(itunes.and_then(|itunes| itunes.season.as_ref())),
+        number: number(itunes.and_then(|itunes| itunes.episode.as_ref())),
+        duration: itunes
+            .and_then(|itunes| itunes.duration.as_deref())
+            .and_then(parse_itunes_duration),
+    })
+}
+
+fn item_pub_date(item: &Item) -> Option<DateTime<Utc>> {
+    let date = DateTime::parse_from_rfc2822(item.pub_date.as_deref()?).ok()?;
+    Some(date.with_timezone(&Utc))
+}
+
+/// Which of the items dated `dates` are among the `count` newest. Items dated the same are
+/// kept from the top of the feed down, and undated ones are taken to be the oldest.
+fn newest(dates: &[Option<DateTime<Utc>>], count: usize) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..dates.len()).collect();
+    order.sort_by(|&a, &b| dates[b].cmp(&dates[a]).then(a.cmp(&b)));
+    let mut keep = vec![false; dates.len()];
+    for &i in order.iter().take(count) {
+        keep[i] = true;
+    }
+    keep
+}
+
+fn without_query(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+/// Removes the items whose enclosure links to `enclosure_url` from the RSS feed `feed`,
+/// returning the edited feed, or `None` if no item matched. Query strings are ignored, so
+/// items with presigned URLs match the object's plain URL. Everything else in the feed,
+/// including elements and namespaces sloop doesn't write itself, is kept.
+pub fn remove_item(feed: &[u8], enclosure_url: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut channel = rss::Channel::read_from(feed).map_err(|e| e.to_string())?;
+    let count = channel.items.len();
+    channel.items.retain(|item| {
+        let url = item
+            .enclosure
+            .as_ref()
+            .map(|enclosure| enclosure.url.as_str());
+        url.map(without_query) != Some(enclosure_url)
+    });
+    if channel.items.len() == count {
+        return Ok(None);
+    }
+    let mut edited = Vec::new();
+    channel
+        .pretty_write_to(&mut edited, b' ', 2)
+        .map_err(|e| e.to_string())?;
+    Ok(Some(edited))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::urls::Presigner;
+    use roxmltree::{Document, Node};
+    use rusoto_core::credential::AwsCredentials;
+    use std::path::Path;
+
+    fn get_child_node_text<'a>(parent: &'a Node<'_, '_>, child_tag: &str) -> &'a str {
+        parent
+            .descendants()
+            .find(|n| n.tag_name().name() == child_tag)
+            .unwrap()
+            .text()
+            .unwrap()
+    }
+
+    impl FeedGenerator {
+        /// Writes an RSS feed for `files`, as `sloop feed` does for a single `.xml` output.
+        pub(crate) fn generate_for_files<W: Write, M: MediaFileLike>(
+            &self,
+            files: Vec<M>,
+            writer: W,
+        ) -> Result<(), Error> {
+            let episodes = self.episodes(files)?;
+            self.write(&episodes, FeedFormat::Rss, None, writer)
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockMediaFile {
+        name: String,
+        stem: String,
+        extension: String,
+        len: u64,
+        subtitle: Option<String>,
+        explicit: Option<bool>,
+        image: Option<String>,
+        duration: Option<std::time::Duration>,
+        modified: Option<SystemTime>,
+        tag_title: Option<String>,
+        description: Option<String>,
+        path: Option<PathBuf>,
+    }
+
+    impl Default for MockMediaFile {
+        fn default() -> Self {
+            Self {
+                name: "name1.mp3".to_owned(),
+                stem: "name1".to_owned(),
+                extension: "mp3".to_owned(),
+                len: 123,
+                subtitle: None,
+                explicit: None,
+                image: None,
+                duration: None,
+                modified: None,
+                tag_title: None,
+                description: None,
+                path: None,
+            }
+        }
+    }
+
+    impl MediaFileLike for MockMediaFile {
+        fn name(&self) -> Option<&str> {
+            Some(&self.name)
+        }
+
+        fn stem(&self) -> Option<&str> {
+            Some(&self.stem)
+        }
+
+        /// An empty `extension` stands for a file without one.
+        fn extension(&self) -> Option<&str> {
+            Some(self.extension.as_str()).filter(|extension| !extension.is_empty())
+        }
+
+        fn len(&self) -> Result<u64, Error> {
+            Ok(self.len)
+        }
+
+        fn subtitle(&self) -> Option<String> {
+            self.subtitle.clone()
+        }
+
+        fn explicit(&self) -> Option<bool> {
+            self.explicit
+        }
+
+        fn image(&self) -> Option<String> {
+            self.image.clone()
+        }
+
+        fn duration(&self) -> Option<std::time::Duration> {
+            self.duration
+        }
+
+        fn modified(&self) -> Option<SystemTime> {
+            self.modified
+        }
+
+        fn tag_title(&self) -> Option<String> {
+            self.tag_title.clone()
+        }
+
+        fn description(&self) -> Option<String> {
+            self.description.clone()
+        }
+
+        fn path(&self) -> Option<&Path> {
+            self.path.as_deref()
+        }
+    }
+
+    #[macro_export]
+    macro_rules! assert_contains {
+        ($haystack:expr, $needle:expr) => {{
+            assert!(
+                $haystack.contains($needle),
+                "expected string to contain \"{}\", got:\n\n{}\n\n",
+                $needle,
+                $haystack
+            );
+        }};
+    }
+
+    #[test]
+    fn generates_xml_for_files() {
+        let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+        let file = resolve_inputs(&[path], "", false).unwrap().remove(0);
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            image: Some(Image {
+                path: "/path/to/image1.png".into(),
+            }),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let result = generator.generate_for_files(vec![file], &mut buffer);
+        assert!(result.is_ok(), "expected generate_for_files to return ok");
+        let feed = String::from_utf8(buffer).unwrap();
+        assert_contains!(feed, "<title>Feed Title 1</title>");
+        assert_contains!(
+            feed,
+            &format!("<generator>sloop {}</generator>", version::VERSION)
+        );
+        assert_contains!(feed, "xmlns:itunes");
+        assert_contains!(feed, "<itunes:block>Yes</itunes:block>");
+        assert_contains!(feed, "<itunes:image href=\"https://eg.test/image1.png\"/>");
+        assert_contains!(
+            feed,
+            "<image>\n      <url>https://eg.test/image1.png</url>\n      \
+             <title>Feed Title 1</title>\n      <link>https://eg.test</link>\n    </image>"
+        );
+        assert_contains!(
+            feed,
+            "url=\"https://eg.test/file1.mp3\" length=\"6\" type=\"audio/mpeg\""
+        );
+        assert_contains!(feed, "<title>file1</title>");
+    }
+
+    #[test]
+    fn image_urls_are_percent_encoded() {
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test/candide"),
+            image: Some(Image {
+                path: "/covers/Cover Art #1.jpg".into(),
+            }),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let url = "https://eg.test/candide/Cover%20Art%20%231.jpg";
+        let channel = doc
+            .descendants()
+            .find(|n| n.has_tag_name("channel"))
+            .unwrap();
+        let itunes_image = channel
+            .children()
+            .find(|n| n.has_tag_name((NAMESPACE, "image")))
+            .and_then(|n| n.attribute("href"));
+        assert_eq!(itunes_image, Some(url));
+        let image_url = channel
+            .children()
+            .find(|n| n.has_tag_name("image"))
+            .and_then(|n| n.children().find(|n| n.has_tag_name("url")))
+            .and_then(|n| n.text());
+        assert_eq!(image_url, Some(url));
+    }
+
+    /// The text of the `channel` child of `feed` called `name` in `namespace`, if any.
+    fn channel_text(feed: &str, namespace: Option<&str>, name: &str) -> Option<String> {
+        let doc = Document::parse(feed).unwrap();
+        let channel = doc
+            .descendants()
+            .find(|n| n.has_tag_name("channel"))
+            .unwrap();
+        let found = channel
+            .children()
+            .find(|n| n.tag_name().name() == name && n.tag_name().namespace() == namespace);
+        found.map(|n| n.text().unwrap_or("").to_owned())
+    }
+
+    #[test]
+    fn listed_feeds_have_no_block_tag() {
+        let mut generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let write = |generator: &FeedGenerator| {
+            let mut buffer = Vec::new();
+            generator
+                .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let blocked = write(&generator);
+        assert_contains!(blocked, "<itunes:block>Yes</itunes:block>");
+
+        generator.listed = true;
+        assert!(!write(&generator).contains("itunes:block"));
+        let existing = ExistingFeed::parse(blocked.as_bytes()).unwrap();
+        let mut appended = Vec::new();
+        generator
+            .write_appended(&existing, &[], FeedFormat::Rss, None, &mut appended)
+            .unwrap();
+        assert!(!String::from_utf8(appended)
+            .unwrap()
+            .contains("itunes:block"));
+    }
+
+    #[test]
+    fn channels_have_an_author_description_language_and_link() {
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            subtitle: Some("Or, Optimism".to_owned()),
+            author: Some("Voltaire".to_owned()),
+            description: Some(
+                "Pangloss & <Candide> in \"the best of all possible worlds\"".to_owned(),
+            ),
+            language: Some("fr".to_owned()),
+            link: Some("https://eg.test/candide?ref=feed&x=1".to_owned()),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        assert_eq!(
+            channel_text(&feed, Some(NAMESPACE), "author").as_deref(),
+            Some("Voltaire")
+        );
+        assert_eq!(
+            channel_text(&feed, None, "description").as_deref(),
+            Some("Pangloss & <Candide> in \"the best of all possible worlds\"")
+        );
+        assert_contains!(feed, "Pangloss &amp; &lt;Candide&gt;");
+        assert_eq!(channel_text(&feed, None, "language").as_deref(), Some("fr"));
+        assert_eq!(
+            channel_text(&feed, None, "link").as_deref(),
+            Some("https://eg.test/candide?ref=feed&x=1")
+        );
+
+        let mut json = Vec::new();
+        generator
+            .write(&[], FeedFormat::Json, None, &mut json)
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["authors"][0]["name"], "Voltaire");
+        assert_eq!(json["language"], "fr");
+        assert_eq!(
+            json["home_page_url"],
+            "https://eg.test/candide?ref=feed&x=1"
+        );
+        assert_eq!(
+            json["description"],
+            "Pangloss & <Candide> in \"the best of all possible worlds\""
+        );
+    }
+
+    #[test]
+    fn channel_metadata_has_defaults_rather_than_empty_elements() {
+        let mut generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test/candide"),
+            ..Default::default()
+        };
+        let generate = |generator: &FeedGenerator| {
+            let mut buffer = Vec::new();
+            generator
+                .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let feed = generate(&generator);
+        assert_eq!(channel_text(&feed, Some(NAMESPACE), "author"), None);
+        assert_eq!(
+            channel_text(&feed, None, "description").as_deref(),
+            Some("Candide")
+        );
+        assert_eq!(channel_text(&feed, None, "language").as_deref(), Some("en"));
+        assert_eq!(
+            channel_text(&feed, None, "link").as_deref(),
+            Some("https://eg.test/candide")
+        );
+        generator.subtitle = Some("Or, Optimism".to_owned());
+        assert_eq!(
+            channel_text(&generate(&generator), None, "description").as_deref(),
+            Some("Or, Optimism")
+        );
+    }
+
+    #[test]
+    fn channels_list_their_categories() {
+        let categories = [
+            "Arts",
+            "Arts:Books",
+            "Kids & Family",
+            "Science:Physics",
+            "arts:books",
+        ];
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            categories: categories.iter().map(|c| c.parse().unwrap()).collect(),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        assert_contains!(feed, "<itunes:category text=\"Kids &amp; Family\">");
+        let doc = Document::parse(&feed).unwrap();
+        let channel = doc
+            .descendants()
+            .find(|n| n.has_tag_name("channel"))
+            .unwrap();
+        let categories: Vec<(&str, Vec<&str>)> = channel
+            .children()
+            .filter(|n| n.has_tag_name((NAMESPACE, "category")))
+            .map(|n| {
+                let subcategories = n
+                    .children()
+                    .filter(|n| n.has_tag_name((NAMESPACE, "category")))
+                    .filter_map(|n| n.attribute("text"))
+                    .collect();
+                (n.attribute("text").unwrap(), subcategories)
+            })
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                ("Arts", vec!["Books"]),
+                ("Kids & Family", vec![]),
+                ("Science", vec!["Physics"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_must_be_absolute_urls() {
+        assert_eq!(
+            parse_link("https://eg.test/"),
+            Ok("https://eg.test/".to_owned())
+        );
+        assert!(parse_link("eg.test/candide").is_err());
+    }
+
+    #[test]
+    fn feeds_without_an_image_have_no_artwork() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        assert!(!feed.contains("<image>"), "{}", feed);
+        assert!(!feed.contains("<itunes:image"), "{}", feed);
+    }
+
+    #[test]
+    fn presigned_feeds_give_episodes_guids_that_survive_resigning() {
+        let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::Presigned(Presigner {
+                bucket_url: "https://bucket1.s3.region1.amazonaws.com".to_owned(),
+                prefix: String::new(),
+                region: "region1".to_owned(),
+                credentials: AwsCredentials::new("AKIDEXAMPLE", "secret", None, None),
+                expires_in: std::time::Duration::from_secs(3600),
+            }),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(resolve_inputs(&[path], "", false).unwrap(), &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        assert_contains!(
+            feed,
+            "url=\"https://bucket1.s3.region1.amazonaws.com/file1.mp3?"
+        );
+        assert_contains!(feed, "X-Amz-Signature=");
+        assert_contains!(
+            feed,
+            "<guid isPermaLink=\"false\">https://bucket1.s3.region1.amazonaws.com/file1.mp3</guid>"
+        );
+    }
+
+    #[test]
+    fn every_item_has_a_guid_that_is_the_same_each_run() {
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test/candide"),
+            ..Default::default()
+        };
+        let generate = || {
+            let mut buffer = Vec::new();
+            generator
+                .generate_for_files(mock_files(&["Chapter 1", "Chapter 2"]), &mut buffer)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let guids = |feed: &str| -> Vec<(Option<String>, Option<String>)> {
+            let doc = Document::parse(feed).unwrap();
+            let guids = doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "guid")
+                .map(|n| {
+                    (
+                        n.attribute("isPermaLink").map(str::to_owned),
+                        n.text().map(str::to_owned),
+                    )
+                })
+                .collect();
+            guids
+        };
+        let guid = |url: &str| (Some("false".to_owned()), Some(url.to_owned()));
+        let first = guids(&generate());
+        assert_eq!(
+            first,
+            vec![
+                guid("https://eg.test/candide/Chapter%201.mp3"),
+                guid("https://eg.test/candide/Chapter%202.mp3"),
+            ]
+        );
+        assert_eq!(guids(&generate()), first);
+    }
+
+    #[test]
+    fn guids_can_be_given_for_each_file() {
+        let paths = [
+            Path::new("test_fixtures/plan/Chapter_1.mp3").to_path_buf(),
+            Path::new("test_fixtures/plan/Chapter_2.mp3").to_path_buf(),
+        ];
+        let mut inputs = resolve_inputs(&paths, "", false).unwrap();
+        assign_guids(
+            &mut inputs,
+            &["Chapter_2.mp3=tag:old-host,2019:ep2".to_owned()],
+        )
+        .unwrap();
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let guids: Vec<Option<String>> = generator
+            .episodes(inputs.clone())
+            .unwrap()
+            .into_iter()
+            .map(|episode| episode.guid)
+            .collect();
+        assert_eq!(
+            guids,
+            vec![
+                Some("https://eg.test/Chapter_1.mp3".to_owned()),
+                Some("tag:old-host,2019:ep2".to_owned())
+            ]
+        );
+        assert_eq!(
+            assign_guids(&mut inputs, &["Chapter_3.mp3=x".to_owned()]),
+            Err("--guid names Chapter_3.mp3, which isn't one of the files".to_owned())
+        );
+        assert_eq!(
+            assign_guids(&mut inputs, &["Chapter_1.mp3".to_owned()]),
+            Err("--guid \"Chapter_1.mp3\" must be NAME=GUID".to_owned())
+        );
+    }
+
+    #[test]
+    fn files_modified_within_the_settle_time_are_unsettled() {
+        let path = Path::new("test_fixtures/dir1/file1.mp3");
+        let modified = path.metadata().unwrap().modified().unwrap();
+        let settle_time = time::Duration::from_secs(30);
+        assert_eq!(
+            unsettled_reason(path, settle_time, modified + time::Duration::from_secs(10)),
+            Some("it was modified 10s ago, within the 30s settle time".to_owned())
+        );
+        assert_eq!(
+            unsettled_reason(path, settle_time, modified - time::Duration::from_secs(5)),
+            Some("it was modified 0s ago, within the 30s settle time".to_owned())
+        );
+        assert_eq!(
+            unsettled_reason(path, settle_time, modified + settle_time),
+            None
+        );
+        assert_eq!(unsettled_reason(path, time::Duration::ZERO, modified), None);
+    }
+
+    #[test]
+    fn partial_and_empty_files_are_unsettled() {
+        let now = SystemTime::now() + time::Duration::from_secs(3600);
+        let settle_time = time::Duration::from_secs(30);
+        for name in [
+            "Chapter_1.mp3.part",
+            "Chapter_1.partial",
+            "x.TMP",
+            "x.crdownload",
+        ] {
+            assert!(
+                unsettled_reason(Path::new(name), settle_time, now)
+                    .unwrap()
+                    .starts_with("it looks like an unfinished ."),
+                "{}",
+                name
+            );
+        }
+        let empty = std::env::temp_dir().join(format!("sloop-empty-{}.mp3", std::process::id()));
+        std::fs::write(&empty, "").unwrap();
+        let reason = unsettled_reason(&empty, settle_time, now);
+        std::fs::remove_file(&empty).unwrap();
+        assert_eq!(reason, Some("it's empty".to_owned()));
+        assert_eq!(
+            unsettled_reason(Path::new("missing.mp3"), settle_time, now),
+            None
+        );
+    }
+
+    #[test]
+    fn enclosure_lengths_are_matched_to_their_files() {
+        let paths = [
+            Path::new("test_fixtures/plan/Chapter_1.mp3").to_path_buf(),
+            Path::new("test_fixtures/plan/Chapter_2.mp3").to_path_buf(),
+        ];
+        let inputs = resolve_inputs(&paths, "", false).unwrap();
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test/candide"),
+            ..Default::default()
+        };
+        let mut episodes = generator.episodes(inputs[1..].to_vec()).unwrap();
+        episodes[0].url.push_str("?X-Amz-Signature=abc");
+        episodes[0].length = 99;
+        assert_eq!(
+            generator.enclosure_lengths(&inputs, &episodes),
+            vec![(paths[1].as_path(), 99)]
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_generating_episodes() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        assert_eq!(
+            generator
+                .episodes(vec![MockMediaFile::default()])
+                .unwrap()
+                .len(),
+            1
+        );
+        generator.cancel.cancel();
+        let err = generator
+            .episodes(vec![MockMediaFile::default()])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn items_give_their_duration() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let files = vec![
+            MockMediaFile {
+                duration: Some(std::time::Duration::from_millis(3_723_600)),
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "name2.mp3".to_owned(),
+                stem: "name2".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let episodes = generator.episodes(files).unwrap();
+        let mut rss = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Rss, None, &mut rss)
+            .unwrap();
+        let rss = String::from_utf8(rss).unwrap();
+        assert_contains!(rss, "<itunes:duration>01:02:04</itunes:duration>");
+        assert_eq!(rss.matches("itunes:duration>").count(), 2);
+        let mut json = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Json, None, &mut json)
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json["items"][0]["attachments"][0]["duration_in_seconds"],
+            3724
+        );
+        assert!(json["items"][1]["attachments"][0]
+            .get("duration_in_seconds")
+            .is_none());
+    }
+
+    #[test]
+    fn parses_itunes_durations() {
+        assert_eq!(itunes_duration(59), "00:00:59");
+        assert_eq!(itunes_duration(36_000 + 61), "10:01:01");
+        assert_eq!(parse_itunes_duration("10:01:01"), Some(36_061));
+        assert_eq!(parse_itunes_duration("2:05"), Some(125));
+        assert_eq!(parse_itunes_duration("3600"), Some(3600));
+        assert_eq!(parse_itunes_duration("1h"), None);
+    }
+
+    #[test]
+    fn feeds_link_to_their_own_url() {
+        let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let episodes = generator
+            .episodes(resolve_inputs(&[path], "", false).unwrap())
+            .unwrap();
+        let mut rss = Vec::new();
+        generator
+            .write(
+                &episodes,
+                FeedFormat::Rss,
+                Some("https://eg.test/feed.xml"),
+                &mut rss,
+            )
+            .unwrap();
+        let rss = String::from_utf8(rss).unwrap();
+        assert_contains!(rss, "xmlns:atom=\"http://www.w3.org/2005/Atom\"");
+        assert_contains!(rss, "<atom:link ");
+        assert_contains!(rss, "href=\"https://eg.test/feed.xml\"");
+        assert_contains!(rss, "rel=\"self\"");
+        let mut json = Vec::new();
+        generator
+            .write(
+                &episodes,
+                FeedFormat::Json,
+                Some("https://eg.test/feed.json"),
+                &mut json,
+            )
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["feed_url"], "https://eg.test/feed.json");
+    }
+
+    #[test]
+    fn inputs_read_their_metadata_once_between_clones() {
+        let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+        let input = resolve_inputs(&[path], "", false).unwrap().remove(0);
+        let clone = input.clone();
+        assert!(input.metadata.get().is_none());
+        assert_eq!(clone.duration(), None);
+        assert_eq!(input.metadata.get(), Some(&None));
+    }
+
+    #[test]
+    fn resolves_inputs_in_the_order_given() {
+        let files = vec![
+            PathBuf::from("audio/Chapter_2.mp3"),
+            PathBuf::from("Chapter_10.m4a"),
+            PathBuf::from("audio/Chapter_1.mp3"),
+        ];
+        let inputs: Vec<Entry> = resolve_inputs(&files, "books/candide/", false)
+            .unwrap()
+            .into_iter()
+            .map(Entry::File)
+            .collect();
+        assert_eq!(
+            list_inputs(
+                &inputs,
+                &episode_titles(&inputs, TitleSource::Name, &Default::default(), "")
+            ),
+            "1\taudio/Chapter_2.mp3\tbooks/candide/Chapter_2.mp3\tChapter 2\n\
+             2\tChapter_10.m4a\tbooks/candide/Chapter_10.m4a\tChapter 10\n\
+             3\taudio/Chapter_1.mp3\tbooks/candide/Chapter_1.mp3\tChapter 1\n"
+        );
+    }
+
+    #[test]
+    fn episodes_follow_resolved_inputs() {
+        let files = vec![
+            PathBuf::from("test_fixtures/plan/Chapter_2.mp3"),
+            PathBuf::from("test_fixtures/plan/Chapter_1.mp3"),
+        ];
+        let inputs = resolve_inputs(&files, "", false).unwrap();
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let episodes = generator.episodes(inputs.clone()).unwrap();
+        let titles: Vec<&str> = episodes.iter().map(|e| e.title.as_str()).collect();
+        let expected: Vec<String> = inputs.iter().map(|i| i.title()).collect();
+        assert_eq!(titles, expected);
+        assert_eq!(episodes[0].url, "https://eg.test/Chapter_2.mp3");
+    }
+
+    #[test]
+    fn rejects_inputs_without_an_extension() {
+        assert_eq!(
+            resolve_inputs(&[PathBuf::from("audio/Chapter_1")], "", false),
+            Err("file 'audio/Chapter_1' has no extension; cannot determine MIME type".to_owned())
+        );
+    }
+
+    #[test]
+    fn skips_invalid_inputs_when_asked() {
+        let files = [
+            PathBuf::from("audio/Chapter_1"),
+            PathBuf::from("audio/Chapter_2.mp3"),
+            PathBuf::from("audio/notes.txt"),
+        ];
+        let inputs = resolve_inputs(&files, "", true).unwrap();
+        let names: Vec<_> = inputs.iter().map(|input| input.name()).collect();
+        assert_eq!(names, [Some("Chapter_2.mp3")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_inputs_whose_names_arent_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new("audio").join(OsStr::from_bytes(b"Chapter_\xff.mp3"));
+        assert_eq!(
+            resolve_inputs(&[path], "", false),
+            Err(
+                "file 'audio/Chapter_\u{FFFD}.mp3' has a name that isn't UTF-8, so has no title \
+                 or URL; rename it to publish it"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_inputs_published_under_the_same_key() {
+        let files = [
+            PathBuf::from("Disc 1/track.mp3"),
+            PathBuf::from("Disc 2/track.mp3"),
+        ];
+        let inputs = resolve_inputs(&files, "book", false).unwrap();
+        assert_eq!(
+            check_keys(&inputs),
+            Err(
+                "Disc 1/track.mp3 and Disc 2/track.mp3 would both be published as \
+                 book/track.mp3"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn seasons_publish_files_of_the_same_name_in_their_own_folders() {
+        let files = [
+            PathBuf::from("test_fixtures/seasons_same_names/Disc 1/track.mp3"),
+            PathBuf::from("test_fixtures/seasons_same_names/Disc 2/track.mp3"),
+        ];
+        let mut inputs = resolve_inputs(&files, "book", false).unwrap();
+        assign_seasons(&mut inputs, false);
+        assert_eq!(check_keys(&inputs), Ok(()));
+        let keys: Vec<&str> = inputs.iter().map(|input| input.key.as_str()).collect();
+        assert_eq!(keys, ["book/Disc 1/track.mp3", "book/Disc 2/track.mp3"]);
+        assert_eq!(inputs[1].name(), Some("Disc 2/track.mp3"));
+        assert_eq!(inputs[1].title(), "track");
+    }
+
+    /// The two-disc fixture, listed out of order.
+    fn disc_inputs() -> Vec<Input> {
+        let files = vec![
+            PathBuf::from("test_fixtures/seasons/Disc 2/Track_03.mp3"),
+            PathBuf::from("test_fixtures/seasons/Disc 1/Track_01.mp3"),
+            PathBuf::from("test_fixtures/seasons/Disc 1/Track_02.mp3"),
+        ];
+        resolve_inputs(&files, "", false).unwrap()
+    }
+
+    #[test]
+    fn seasons_follow_directory_names_and_episodes_follow_feed_order() {
+        let mut inputs = disc_inputs();
+        assign_seasons(&mut inputs, false);
+        let seasons: Vec<(usize, usize)> = inputs
+            .iter()
+            .map(|input| {
+                let season = input.season.unwrap();
+                (season.number, season.episode)
+            })
+            .collect();
+        assert_eq!(seasons, [(2, 1), (1, 1), (1, 2)]);
+        assert_eq!(inputs[0].title(), "Track 03");
+    }
+
+    #[test]
+    fn stripping_track_numbers_changes_titles_but_not_keys() {
+        let files = [PathBuf::from("rip/03 - The Council of Elrond.mp3")];
+        let mut inputs = resolve_inputs(&files, "lotr", false).unwrap();
+        strip_track_numbers(&mut inputs);
+        assert_eq!(inputs[0].title(), "The Council of Elrond");
+        assert_eq!(inputs[0].key, "lotr/03 - The Council of Elrond.mp3");
+    }
+
+    #[test]
+    fn season_titles_start_with_the_directory_name() {
+        let mut inputs = disc_inputs();
+        assign_seasons(&mut inputs, true);
+        let titles: Vec<String> = inputs.iter().map(|input| input.title()).collect();
+        assert_eq!(
+            titles,
+            ["Disc 2: Track 03", "Disc 1: Track 01", "Disc 1: Track 02"]
+        );
+    }
+
+    #[test]
+    fn inputs_in_one_directory_have_no_seasons() {
+        let files = vec![
+            PathBuf::from("test_fixtures/seasons/Disc 1/Track_01.mp3"),
+            PathBuf::from("test_fixtures/seasons/Disc 1/Track_02.mp3"),
+        ];
+        let mut inputs = resolve_inputs(&files, "", false).unwrap();
+        let flat = inputs.clone();
+        assign_seasons(&mut inputs, true);
+        assert_eq!(inputs, flat);
+    }
+
+    #[test]
+    fn feeds_with_seasons_number_their_items() {
+        let mut inputs = disc_inputs();
+        assign_seasons(&mut inputs, true);
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator.generate_for_files(inputs, &mut buffer).unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let items: Vec<(String, String, String)> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .map(|item| {
+                (
+                    get_child_node_text(&item, "title").to_owned(),
+                    get_child_node_text(&item, "season").to_owned(),
+                    get_child_node_text(&item, "episode").to_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (
+                    "Disc 2: Track 03".to_owned(),
+                    "2".to_owned(),
+                    "1".to_owned()
+                ),
+                (
+                    "Disc 1: Track 01".to_owned(),
+                    "1".to_owned(),
+                    "1".to_owned()
+                ),
+                (
+                    "Disc 1: Track 02".to_owned(),
+                    "1".to_owned(),
+                    "2".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn serial_feeds_declare_their_type_and_number_their_items() {
+        let numbers = |podcast_type, season| {
+            let files = ["Chapter_1", "Chapter_2", "Chapter_3"]
+                .iter()
+                .map(|stem| MockMediaFile {
+                    name: format!("{}.mp3", stem),
+                    stem: stem.to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            let generator = FeedGenerator {
+                title: "Candide".to_owned(),
+                urls: ObjectUrls::public("https://eg.test"),
+                podcast_type,
+                season,
+                ..Default::default()
+            };
+            let mut buffer = Vec::new();
+            generator.generate_for_files(files, &mut buffer).unwrap();
+            let feed = String::from_utf8(buffer).unwrap();
+            let doc = Document::parse(&feed).unwrap();
+            let channel_type = doc
+                .root_element()
+                .first_element_child()
+                .unwrap()
+                .children()
+                .find(|n| n.tag_name().name() == "type")
+                .map(|n| n.text().unwrap().to_owned());
+            let child = |item: Node, name| {
+                item.children()
+                    .find(|n| n.tag_name().name() == name)
+                    .map(|n| n.text().unwrap().to_owned())
+            };
+            let items: Vec<(Option<String>, Option<String>)> = doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "item")
+                .map(|item| (child(item, "season"), child(item, "episode")))
+                .collect();
+            (channel_type, items)
+        };
+        let numbered = |season: Option<&str>| {
+            ["1", "2", "3"]
+                .iter()
+                .map(|number| (season.map(str::to_owned), Some(number.to_string())))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            numbers(Some(PodcastType::Serial), None),
+            (Some("serial".to_owned()), numbered(None))
+        );
+        assert_eq!(numbers(None, Some(2)), (None, numbered(Some("2"))));
+        assert_eq!(
+            numbers(Some(PodcastType::Episodic), None),
+            (Some("episodic".to_owned()), vec![(None, None); 3])
+        );
+    }
+
+    #[test]
+    fn podcast_types_are_episodic_or_serial() {
+        assert_eq!("serial".parse(), Ok(PodcastType::Serial));
+        assert_eq!("episodic".parse(), Ok(PodcastType::Episodic));
+        assert_eq!(
+            "Serial".parse::<PodcastType>(),
+            Err("Serial is not one of episodic, serial".to_owned())
+        );
+    }
+
+    #[test]
+    fn sorts_numbers_in_names_by_value() {
+        let mut names = vec!["Disc 10", "Disc 2", "Disc 1", "Bonus", "Disc 02b"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["Bonus", "Disc 1", "Disc 2", "Disc 02b", "Disc 10"]);
+    }
+
+    #[test]
+    fn natural_order_ignores_case_and_leading_zeros() {
+        let mut names = vec![
+            "chapter 2",
+            "Chapter 010",
+            "CHAPTER 1",
+            "Chapter 02",
+            "Chapter 9",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            [
+                "CHAPTER 1",
+                "Chapter 02",
+                "chapter 2",
+                "Chapter 9",
+                "Chapter 010"
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_files_as_asked() {
+        let given: Vec<PathBuf> = [
+            "Chapter 10.mp3",
+            "chapter 9.mp3",
+            "Chapter 1.mp3",
+            "Disc 2/Chapter 1.mp3",
+            "Disc 10/Chapter 1.mp3",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let sorted = |sort: FileSort| {
+            let mut files = given.clone();
+            sort.sort(&mut files);
+            files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sorted(FileSort::None),
+            [
+                "Chapter 10.mp3",
+                "chapter 9.mp3",
+                "Chapter 1.mp3",
+                "Disc 2/Chapter 1.mp3",
+                "Disc 10/Chapter 1.mp3"
+            ]
+        );
+        assert_eq!(
+            sorted(FileSort::Name),
+            [
+                "Chapter 1.mp3",
+                "Chapter 10.mp3",
+                "Disc 10/Chapter 1.mp3",
+                "Disc 2/Chapter 1.mp3",
+                "chapter 9.mp3"
+            ]
+        );
+        assert_eq!(
+            sorted(FileSort::Natural),
+            [
+                "Chapter 1.mp3",
+                "chapter 9.mp3",
+                "Chapter 10.mp3",
+                "Disc 2/Chapter 1.mp3",
+                "Disc 10/Chapter 1.mp3"
+            ]
+        );
+    }
+
+    #[test]
+    fn returns_error_if_file_does_not_exist() {
+        let path = Path::new("invalid-file-1.mp3").to_path_buf();
+        let file = resolve_inputs(&[path], "", false).unwrap().remove(0);
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            image: None,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let result = generator.generate_for_files(vec![file], &mut buffer);
+        assert!(
+            result.is_err(),
+            "expected generate_for_files to return an error"
+        );
+    }
+
+    #[test]
+    fn outputs_correct_mime_type() {
+        let mime_type = |extension| FeedGenerator::mime_type("episode", extension).unwrap();
+        assert_eq!(mime_type("mp3"), "audio/mpeg");
+        assert_eq!(mime_type("mp4"), "audio/mp4");
+        assert_eq!(mime_type("aac"), "audio/aac");
+        assert_eq!(mime_type("m4a"), "audio/mp4");
+        assert_eq!(mime_type("m4b"), "audio/mp4");
+        assert_eq!(mime_type("ogg"), "audio/ogg");
+        assert_eq!(mime_type("oga"), "audio/ogg");
+        assert_eq!(mime_type("opus"), "audio/opus");
+        assert_eq!(mime_type("flac"), "audio/flac");
+        assert_eq!(mime_type("wav"), "audio/wav");
+        assert_eq!(mime_type("webm"), "audio/webm");
+    }
+
+    #[test]
+    fn files_of_unknown_types_fail_naming_the_file() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let file = MockMediaFile {
+            name: "notes.txt".to_owned(),
+            stem: "notes".to_owned(),
+            extension: "txt".to_owned(),
+            ..Default::default()
+        };
+        let err = generator
+            .generate_for_files(vec![file], &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "notes.txt has the extension .txt, which isn't an audio format sloop knows (aac, \
+             flac, m4a, m4b, mp3, mp4, oga, ogg, opus, wav, webm)"
+        );
+    }
+
+    #[test]
+    fn files_without_an_extension_fail_unless_skipped() {
+        let files = || {
+            vec![
+                MockMediaFile {
+                    name: "chapter01".to_owned(),
+                    stem: "chapter01".to_owned(),
+                    extension: String::new(),
+                    ..Default::default()
+                },
+                MockMediaFile::default(),
+            ]
+        };
+        let mut generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let err = generator.episodes(files()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "file 'chapter01' has no extension; cannot determine MIME type"
+        );
+
+        generator.skip_invalid = true;
+        let episodes = generator.episodes(files()).unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].url, "https://eg.test/name1.mp3");
+    }
+
+    #[test]
+    fn pub_dates_go_either_way_keeping_the_files_in_order() {
+        let today = Utc::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        for (order, days_ago) in [
+            (DateOrder::NewestFirst, [0, 1, 2]),
+            (DateOrder::OldestFirst, [2, 1, 0]),
+        ] {
+            let files = ["file1", "file2", "file3"]
+                .iter()
+                .map(|stem| MockMediaFile {
+                    stem: stem.to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            let generator = FeedGenerator {
+                title: "Feed Title 1".to_owned(),
+                urls: ObjectUrls::public("https://eg.test"),
+                order,
+                ..Default::default()
+            };
+            let mut buffer = Vec::new();
+            generator.generate_for_files(files, &mut buffer).unwrap();
+            let feed = String::from_utf8(buffer).unwrap();
+            let doc = Document::parse(&feed).unwrap();
+            let items: Vec<(String, String)> = doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "item")
+                .map(|item| {
+                    (
+                        get_child_node_text(&item, "title").to_owned(),
+                        get_child_node_text(&item, "pubDate").to_owned(),
+                    )
+                })
+                .collect();
+            let expected: Vec<(String, String)> = ["file1", "file2", "file3"]
+                .iter()
+                .zip(days_ago.iter())
+                .map(|(title, days)| {
+                    let date = today - Duration::days(*days);
+                    (title.to_string(), date.to_rfc2822())
+                })
+                .collect();
+            assert_eq!(items, expected, "{:?}", order);
+        }
+    }
+
+    fn pub_dates_from(generator: &FeedGenerator, count: usize) -> Vec<String> {
+        let files = (0..count).map(|_| MockMediaFile::default()).collect();
+        let mut buffer = Vec::new();
+        generator.generate_for_files(files, &mut buffer).unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let dates = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .map(|item| get_child_node_text(&item, "pubDate").to_owned())
+            .collect();
+        dates
+    }
+
+    #[test]
+    fn pub_dates_start_at_the_start_date_an_interval_apart() {
+        let mut generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            ..Default::default()
+        };
+        assert_eq!(
+            pub_dates_from(&generator, 3),
+            [
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "Thu, 29 Feb 2024 00:00:00 +0000",
+                "Wed, 28 Feb 2024 00:00:00 +0000",
+            ]
+        );
+        generator.interval = Some(time::Duration::from_secs(12 * 3600));
+        assert_eq!(
+            pub_dates_from(&generator, 3),
+            [
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "Thu, 29 Feb 2024 12:00:00 +0000",
+                "Thu, 29 Feb 2024 00:00:00 +0000",
+            ]
+        );
+        generator.interval = Some(time::Duration::ZERO);
+        assert_eq!(
+            pub_dates_from(&generator, 2),
+            [
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+            ]
+        );
+    }
+
+    #[test]
+    fn intervals_that_date_episodes_before_any_date_can_be_are_refused() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let longest =
+            Duration::from_std(time::Duration::from_secs(i64::MAX as u64 / 1000)).unwrap();
+        assert_eq!(pub_dates(1, start, longest, None), Some(vec![start]));
+        assert_eq!(pub_dates(2, start, longest, None), None);
+        assert_eq!(pub_dates(3, start, longest, Some(1)), None);
+        assert!(interval_duration(time::Duration::from_secs(u64::MAX)).is_none());
+
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            interval: Some(time::Duration::from_secs(9_999_999_999 * 7 * 24 * 3600)),
+            ..Default::default()
+        };
+        let files = vec![MockMediaFile::default(), MockMediaFile::default()];
+        assert_eq!(
+            generator.episodes(files).unwrap_err().to_string(),
+            "--interval 1679999999832h 0m is too long to date 2 episodes that far apart"
+        );
+        let files = vec![MockMediaFile::default()];
+        assert_eq!(generator.episodes(files).unwrap().len(), 1);
+    }
+
+    /// Fails every write, as a full disk would.
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> Result<usize, Error> {
+            Err(Error::new(
+                ErrorKind::StorageFull,
+                "no space left on device",
+            ))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failing_to_write_the_feed_is_an_error_saying_so() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let err = generator
+            .generate_for_files(vec![MockMediaFile::default()], FullDisk)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageFull);
+        assert_eq!(
+            err.to_string(),
+            "Failed to write the RSS feed: I/O error: no space left on device"
+        );
+    }
+
+    #[test]
+    fn rss_errors_without_an_io_cause_are_invalid_data() {
+        let err = rss_error("the appended feed", rss::Error::Eof);
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Failed to write the appended feed: reached end of input without finding a complete \
+             channel"
+        );
+    }
+
+    /// A file modified `seconds` after 2024-03-01 00:00:00 UTC.
+    fn modified_at(seconds: u64) -> MockMediaFile {
+        MockMediaFile {
+            modified: Some(
+                SystemTime::UNIX_EPOCH + time::Duration::from_secs(1709251200 + seconds),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pub_dates_can_come_from_modification_times() {
+        let mut generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            pub_date_from: PubDateSource::Mtime,
+            ..Default::default()
+        };
+        let files = || vec![modified_at(3600), modified_at(0), modified_at(0)];
+        let dates: Vec<_> = generator
+            .episodes(files())
+            .unwrap()
+            .iter()
+            .map(|episode| episode.pub_date.to_rfc2822())
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                "Fri, 01 Mar 2024 01:00:00 +0000",
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "Thu, 29 Feb 2024 23:59:59 +0000",
+            ]
+        );
+        generator.order = DateOrder::OldestFirst;
+        let dates: Vec<_> = generator
+            .episodes(files())
+            .unwrap()
+            .iter()
+            .map(|episode| episode.pub_date.to_rfc2822())
+            .collect();
+        assert_eq!(
+            dates[1..],
+            [
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "Fri, 01 Mar 2024 00:00:01 +0000"
+            ]
+        );
+    }
+
+    #[test]
+    fn files_without_modification_times_are_dated_by_the_schedule() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            pub_date_from: PubDateSource::Mtime,
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            ..Default::default()
+        };
+        let episodes = generator
+            .episodes(vec![modified_at(3600), MockMediaFile::default()])
+            .unwrap();
+        assert_eq!(
+            episodes[0].pub_date.to_rfc2822(),
+            "Fri, 01 Mar 2024 00:00:00 +0000"
+        );
+    }
+
+    #[test]
+    fn dates_are_written_in_the_timezone_given() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            timezone: Some(Timezone::Fixed(FixedOffset::east_opt(10 * 3600).unwrap())),
+            ..Default::default()
+        };
+        assert_eq!(
+            pub_dates_from(&generator, 1),
+            ["Fri, 01 Mar 2024 10:00:00 +1000"]
+        );
+        let mut json = Vec::new();
+        let episodes = generator.episodes(vec![MockMediaFile::default()]).unwrap();
+        generator
+            .write(&episodes, FeedFormat::Json, None, &mut json)
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json["items"][0]["date_published"],
+            "2024-03-01T10:00:00+10:00"
+        );
+    }
+
+    #[test]
+    fn the_schedule_keeps_its_local_time_of_day_through_daylight_saving() {
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            // Sydney's clocks go back an hour at 3am on 7 April 2024.
+            start_date: NaiveDate::from_ymd_opt(2024, 4, 8),
+            timezone: Some(crate::timezone::sydney()),
+            time_of_day: NaiveTime::from_hms_opt(6, 0, 0),
+            ..Default::default()
+        };
+        assert_eq!(
+            pub_dates_from(&generator, 3),
+            [
+                "Mon, 08 Apr 2024 06:00:00 +1000",
+                "Sun, 07 Apr 2024 06:00:00 +1000",
+                "Sat, 06 Apr 2024 06:00:00 +1100",
+            ]
+        );
+
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            timezone: Some("-05:00".parse().unwrap()),
+            time_of_day: NaiveTime::from_hms_opt(18, 30, 0),
+            ..Default::default()
+        };
+        assert_eq!(
+            pub_dates_from(&generator, 2),
+            [
+                "Fri, 01 Mar 2024 18:30:00 -0500",
+                "Thu, 29 Feb 2024 18:30:00 -0500"
+            ]
+        );
+    }
+
+    #[test]
+    fn links_items_to_episode_pages() {
+        let files = vec![
+            MockMediaFile {
+                name: "Chapter_1 (Intro).mp3".to_owned(),
+                stem: "Chapter_1 (Intro)".to_owned(),
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "Chapter_2.mp3".to_owned(),
+                stem: "Chapter_2".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            episode_link: Some(
+                "https://example.com/episodes/{slug}?n={index}&file={name}"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator.generate_for_files(files, &mut buffer).unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let links: Vec<String> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .map(|item| get_child_node_text(&item, "link").to_owned())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/episodes/chapter-1-intro?n=1&file=Chapter_1%20%28Intro%29.mp3",
+                "https://example.com/episodes/chapter-2?n=2&file=Chapter_2.mp3",
+            ]
+        );
+    }
+
+    #[test]
+    fn omits_item_links_by_default() {
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator
+            .generate_for_files(vec![MockMediaFile::default()], &mut buffer)
+            .unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let item = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "item")
+            .unwrap();
+        assert!(
+            !item.children().any(|n| n.tag_name().name() == "link"),
+            "{}",
+            feed
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_link_templates() {
+        assert!(LinkTemplate::from_str("https://example.com/{slug}").is_ok());
+        assert_eq!(
+            LinkTemplate::from_str("https://example.com/{title}").unwrap_err(),
+            "unknown placeholder {title} in https://example.com/{title}"
+        );
+        assert_eq!(
+            LinkTemplate::from_str("https://example.com/{slug").unwrap_err(),
+            "unclosed placeholder in https://example.com/{slug"
+        );
+        assert_eq!(
+            LinkTemplate::from_str("/episodes/{slug}").unwrap_err(),
+            "/episodes/chapter-1 is not an absolute http(s) URL"
+        );
+        assert!(LinkTemplate::from_str("https://example.com/{slug} x").is_err());
+    }
+
+    fn template_titles(template: &str, stems: &[&str]) -> Vec<String> {
+        let files: Vec<MockMediaFile> = stems
+            .iter()
+            .map(|stem| MockMediaFile {
+                name: format!("{}.mp3", stem),
+                stem: stem.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let template = template.parse().unwrap();
+        episode_titles(&files, TitleSource::Name, &template, "The Hobbit")
+    }
+
+    #[test]
+    fn title_templates_pad_numbers_and_count_the_episodes() {
+        let stems: Vec<String> = (1..=12).map(|n| format!("track_{}", n)).collect();
+        let stems: Vec<&str> = stems.iter().map(String::as_str).collect();
+        let titles = template_titles("{feed_title} \u{2014} Part {n:02} of {total}", &stems);
+        assert_eq!(titles[0], "The Hobbit \u{2014} Part 01 of 12");
+        assert_eq!(titles[11], "The Hobbit \u{2014} Part 12 of 12");
+        assert_eq!(
+            template_titles("{n:3}/{total:03}: {stem}", &["a_b"]),
+            ["  1/001: a_b"]
+        );
+    }
+
+    #[test]
+    fn the_default_title_template_titles_episodes_from_their_names() {
+        assert_eq!(template_titles("{title}", &["ab_cd"]), ["ab cd"]);
+        assert_eq!(TitleTemplate::default(), "{title}".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_title_templates() {
+        assert_eq!(
+            TitleTemplate::from_str("Part {num}").unwrap_err(),
+            "unknown placeholder {num} in \"Part {num}\"; use {title}, {stem}, {n}, {total} \
+             or {feed_title}"
+        );
+        assert_eq!(
+            TitleTemplate::from_str("Part {n").unwrap_err(),
+            "unclosed placeholder in \"Part {n\""
+        );
+        assert_eq!(
+            TitleTemplate::from_str("Part {n:two}").unwrap_err(),
+            "{n:two} in \"Part {n:two}\" must be padded to a width, e.g. {n:02}"
+        );
+        assert_eq!(
+            TitleTemplate::from_str("{title:02}").unwrap_err(),
+            "{title:02} in \"{title:02}\" can't be padded; only {n} and {total} can"
+        );
+    }
+
+    #[test]
+    fn rss_and_json_feeds_describe_the_same_enclosures() {
+        let files = vec![
+            MockMediaFile {
+                name: "Chapter 1.mp3".to_owned(),
+                stem: "Chapter 1".to_owned(),
+                len: 100,
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "Chapter 2.m4a".to_owned(),
+                stem: "Chapter 2".to_owned(),
+                extension: "m4a".to_owned(),
+                len: 200,
+                ..Default::default()
+            },
+        ];
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let episodes = generator.episodes(files).unwrap();
+        let mut rss = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Rss, None, &mut rss)
+            .unwrap();
+        let mut json = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Json, None, &mut json)
+            .unwrap();
+
+        let rss = String::from_utf8(rss).unwrap();
+        let doc = Document::parse(&rss).unwrap();
+        let rss_enclosures: Vec<(String, String, u64)> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "enclosure")
+            .map(|n| {
+                (
+                    n.attribute("url").unwrap().to_owned(),
+                    n.attribute("type").unwrap().to_owned(),
+                    n.attribute("length").unwrap().parse().unwrap(),
+                )
+            })
+            .collect();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        let json_enclosures: Vec<(String, String, u64)> = json["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                let attachment = &item["attachments"][0];
+                (
+                    attachment["url"].as_str().unwrap().to_owned(),
+                    attachment["mime_type"].as_str().unwrap().to_owned(),
+                    attachment["size_in_bytes"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(rss_enclosures, json_enclosures);
+        assert_eq!(
+            json_enclosures,
+            vec![
+                (
+                    "https://eg.test/Chapter%201.mp3".to_owned(),
+                    "audio/mpeg".to_owned(),
+                    100
+                ),
+                (
+                    "https://eg.test/Chapter%202.m4a".to_owned(),
+                    "audio/mp4".to_owned(),
+                    200
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_format_from_extension() {
+        assert_eq!(
+            FeedFormat::from_path(Path::new("feed.xml")),
+            FeedFormat::Rss
+        );
+        assert_eq!(
+            FeedFormat::from_path(Path::new("feed.rss")),
+            FeedFormat::Rss
+        );
+        assert_eq!(
+            FeedFormat::from_path(Path::new("feed.JSON")),
+            FeedFormat::Json
+        );
+        assert_eq!(FeedFormat::from_path(Path::new("feed")), FeedFormat::Rss);
+    }
+
+    fn mark_new_today() -> DateTime<Utc> {
+        DateTime::parse_from_rfc2822("Mon, 1 Jan 2024 00:00:00 +0000")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn assert_strictly_decreasing(dates: &[DateTime<Utc>]) {
+        for pair in dates.windows(2) {
+            assert!(pair[0] > pair[1], "{} is not after {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn mark_new_zero_dates_every_episode_as_old() {
+        let today = mark_new_today();
+        let dates = pub_dates(5, today, Duration::days(1), Some(0)).unwrap();
+        assert_eq!(dates[0], today - Duration::days(OLD_EPISODE_AGE_DAYS));
+        assert_eq!(dates[4], today - Duration::days(OLD_EPISODE_AGE_DAYS + 4));
+        assert_strictly_decreasing(&dates);
+    }
+
+    #[test]
+    fn mark_new_covering_every_episode_fits_them_in_a_week() {
+        let today = mark_new_today();
+        let dates = pub_dates(5, today, Duration::days(1), Some(5)).unwrap();
+        assert_eq!(dates, pub_dates(5, today, Duration::days(1), None).unwrap());
+        let dates = pub_dates(300, today, Duration::days(1), Some(300)).unwrap();
+        assert_eq!(dates[0], today);
+        assert!(dates[299] > today - Duration::days(7));
+        assert_strictly_decreasing(&dates);
+    }
+
+    #[test]
+    fn mark_new_pushes_later_episodes_into_the_past() {
+        let today = mark_new_today();
+        let dates = pub_dates(300, today, Duration::days(1), Some(3)).unwrap();
+        assert_eq!(
+            &dates[..3],
+            &[today, today - Duration::days(1), today - Duration::days(2)]
+        );
+        assert_eq!(dates[3], today - Duration::days(OLD_EPISODE_AGE_DAYS));
+        assert_eq!(
+            dates[299],
+            today - Duration::days(OLD_EPISODE_AGE_DAYS + 296)
+        );
+        assert_strictly_decreasing(&dates);
+        assert_eq!(
+            dates,
+            pub_dates(300, today, Duration::days(1), Some(3)).unwrap()
+        );
+    }
+
+    #[test]
+    fn handles_special_chars_in_filenames() {
+        let files = vec![MockMediaFile {
+            name: "a+b c&d.mp3".to_owned(),
+            stem: "a+b c&d".to_owned(),
+            ..Default::default()
+        }];
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            image: None,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator.generate_for_files(files, &mut buffer).unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let item = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "item")
+            .unwrap();
+        assert_eq!(get_child_node_text(&item, "title"), "a+b c&d");
+        let enclosure = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "enclosure")
+            .unwrap();
+        assert_eq!(
+            enclosure.attribute("url"),
+            Some("https://eg.test/a%2Bb%20c%26d.mp3")
+        );
+    }
+
+    #[test]
+    fn replaces_underscores_with_spaces_in_title() {
+        let files = vec![MockMediaFile {
+            name: "ab_cd.mp3".to_owned(),
+            stem: "ab_cd".to_owned(),
+            ..Default::default()
+        }];
+        let generator = FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            image: None,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        generator.generate_for_files(files, &mut buffer).unwrap();
+        let feed = String::from_utf8(buffer).unwrap();
+        let doc = Document::parse(&feed).unwrap();
+        let item = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "item")
+            .unwrap();
+        assert_eq!(get_child_node_text(&item, "title"), "ab cd");
+        let enclosure = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "enclosure")
+            .unwrap();
+        assert_eq!(
+            enclosure.attribute("url"),
+            Some("https://eg.test/ab_cd.mp3")
+        );
+    }
+
+    #[test]
+    fn titles_from_tags_fall_back_to_the_name() {
+        let files = vec![
+            MockMediaFile {
+                name: "01.mp3".to_owned(),
+                stem: "01".to_owned(),
+                tag_title: Some("The Castle of Thunder-ten-tronckh".to_owned()),
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "02_untagged.mp3".to_owned(),
+                stem: "02_untagged".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let titles = |titles_from| {
+            let generator = FeedGenerator {
+                urls: ObjectUrls::public("https://eg.test"),
+                titles_from,
+                ..Default::default()
+            };
+            let mut buffer = Vec::new();
+            generator
+                .generate_for_files(files.clone(), &mut buffer)
+                .unwrap();
+            let feed = String::from_utf8(buffer).unwrap();
+            let doc = Document::parse(&feed).unwrap();
+            doc.descendants()
+                .filter(|n| n.tag_name().name() == "item")
+                .map(|item| get_child_node_text(&item, "title").to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            titles(TitleSource::Tags),
+            ["The Castle of Thunder-ten-tronckh", "02 untagged"]
+        );
+        assert_eq!(titles(TitleSource::Name), ["01", "02 untagged"]);
+        assert_eq!("tags".parse(), Ok(TitleSource::Tags));
+        assert_eq!(
+            "id3".parse::<TitleSource>(),
+            Err("id3 is not one of name, tags".to_owned())
+        );
+    }
+
+    #[test]
+    fn descriptions_are_escaped_in_every_format() {
+        let notes = "Pangloss & <Candide> in \"the best of all possible worlds\".\n\n\
+                     <b>Chapter 2</b> ]]> follows.";
+        let files = vec![
+            MockMediaFile {
+                description: Some(notes.to_owned()),
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "name2.mp3".to_owned(),
+                stem: "name2".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let write = |descriptions_from, format| {
+            let generator = FeedGenerator {
+                urls: ObjectUrls::public("https://eg.test"),
+                descriptions_from,
+                ..Default::default()
+            };
+            let episodes = generator.episodes(files.clone()).unwrap();
+            let mut buffer = Vec::new();
+            generator
+                .write(&episodes, format, None, &mut buffer)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let feed = write(DescriptionSource::Sidecar, FeedFormat::Rss);
+        let doc = Document::parse(&feed).unwrap();
+        let items: Vec<Node> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .collect();
+        let text = |item: &Node, name: &str| {
+            item.children()
+                .find(|n| n.tag_name().name() == name)
+                .map(|n| n.text().unwrap_or_default().to_owned())
+        };
+        assert_eq!(text(&items[0], "description").as_deref(), Some(notes));
+        assert_eq!(text(&items[0], "summary").as_deref(), Some(notes));
+        assert_eq!(text(&items[1], "description"), None);
+        assert_eq!(text(&items[1], "summary"), None);
+        let existing = ExistingFeed::parse(feed.as_bytes()).unwrap();
+        assert_eq!(existing.episodes()[0].description.as_deref(), Some(notes));
+
+        let json: Value =
+            serde_json::from_str(&write(DescriptionSource::Sidecar, FeedFormat::Json)).unwrap();
+        assert_eq!(json["items"][0]["content_text"], notes);
+        assert!(json["items"][1].get("content_text").is_none());
+
+        assert!(!write(DescriptionSource::None, FeedFormat::Rss).contains("Chapter 2"));
+    }
+
+    #[test]
+    fn sidecar_descriptions_sit_beside_their_episodes() {
+        let dir = std::env::temp_dir().join(format!("sloop-sidecars-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let episode = |stem: &str| {
+            let path = dir.join(format!("{}.mp3", stem));
+            std::fs::write(&path, "episode").unwrap();
+            resolve_inputs(&[path], "", false).unwrap().remove(0)
+        };
+        let (text, markdown, neither) = (episode("one"), episode("two"), episode("three"));
+        std::fs::write(dir.join("one.txt"), "\nThe garden.\n").unwrap();
+        std::fs::write(dir.join("one.md"), "Not read").unwrap();
+        std::fs::write(dir.join("two.md"), "*Lisbon*").unwrap();
+        let descriptions = [
+            text.description(),
+            markdown.description(),
+            neither.description(),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            descriptions,
+            [
+                Some("The garden.".to_owned()),
+                Some("*Lisbon*".to_owned()),
+                None
+            ]
+        );
+        assert_eq!("sidecar".parse(), Ok(DescriptionSource::Sidecar));
+    }
+
+    #[test]
+    fn extracted_art_is_written_out_and_linked_from_its_item() {
+        let dir = std::env::temp_dir().join(format!("sloop-art-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut inputs = resolve_inputs(
+            &[
+                PathBuf::from("test_fixtures/art/Chapter_1.mp3"),
+                PathBuf::from("test_fixtures/dir1/file1.mp3"),
+            ],
+            "",
+            false,
+        )
+        .unwrap();
+        extract_art(&mut inputs, &dir).unwrap();
+        let art = inputs[0].art.clone().unwrap();
+        let written = std::fs::read(&art.path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(art.path, dir.join("Chapter_1.mp3.jpg"));
+        assert_eq!(art.name, "art/Chapter_1.mp3.jpg");
+        assert!(written.unwrap().starts_with(&[0xFF, 0xD8]));
+        assert_eq!(inputs[1].art, None);
+
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let images: Vec<Option<String>> = generator
+            .episodes(inputs)
+            .unwrap()
+            .into_iter()
+            .map(|episode| episode.image)
+            .collect();
+        assert_eq!(
+            images,
+            [
+                Some("https://eg.test/art/Chapter_1.mp3.jpg".to_owned()),
+                None
+            ]
+        );
+    }
 
-        fn extension(&self) -> &str {
-            &self.extension
-        }
+    #[test]
+    fn files_differing_only_in_extension_keep_their_own_art() {
+        let dir = std::env::temp_dir().join(format!("sloop-art-names-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut inputs = resolve_inputs(
+            &[PathBuf::from("test_fixtures/art/Chapter_1.mp3")],
+            "",
+            false,
+        )
+        .unwrap();
+        assert!(inputs[0].metadata().unwrap().artwork.is_some());
+        let mut m4a = inputs[0].clone();
+        m4a.path = PathBuf::from("test_fixtures/art/Chapter_1.m4a");
+        m4a.name = "Chapter_1.m4a".to_owned();
+        inputs.push(m4a);
+        let extracted = extract_art(&mut inputs, &dir);
+        let written = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        extracted.unwrap();
+        let names: Vec<String> = inputs
+            .iter()
+            .map(|input| input.art.clone().unwrap().name)
+            .collect();
+        assert_eq!(names, ["art/Chapter_1.mp3.jpg", "art/Chapter_1.m4a.jpg"]);
+        assert_eq!(written, 2);
+    }
 
-        fn len(&self) -> Result<u64, Error> {
-            Ok(self.len)
-        }
+    #[test]
+    fn remote_files_are_the_audio_objects_under_the_podcasts_folder() {
+        let object = |key: &str, size: u64| ListedObject {
+            key: key.to_owned(),
+            size,
+            last_modified: None,
+            feed: key.ends_with(".xml"),
+        };
+        let objects = vec![
+            object("books/Dune/Chapter_10.mp3", 10),
+            object("books/Dune/Chapter_2.mp3", 2),
+            object("books/Dune/Disc 2/Chapter_1.m4a", 1),
+            object("books/Dune/art/Chapter_2.jpg", 5),
+            object("books/Dune/feed.xml", 666),
+            object("books/Dune/notes.txt", 7),
+            object("books/Zadig/Chapter_1.mp3", 1),
+        ];
+        let names = |files: Vec<RemoteFile>| -> Vec<String> {
+            files.into_iter().map(|file| file.name).collect()
+        };
+        assert_eq!(
+            names(remote_files(
+                objects.clone(),
+                "books/Dune/",
+                &[],
+                FileSort::None
+            )),
+            ["Chapter_10.mp3", "Chapter_2.mp3", "Disc 2/Chapter_1.m4a"]
+        );
+        assert_eq!(
+            names(remote_files(
+                objects.clone(),
+                "books/Dune/",
+                &[],
+                FileSort::Natural
+            )),
+            ["Chapter_2.mp3", "Chapter_10.mp3", "Disc 2/Chapter_1.m4a"]
+        );
+        assert_eq!(
+            names(remote_files(
+                objects,
+                "books/Dune/",
+                &["txt".to_owned()],
+                FileSort::None
+            )),
+            ["notes.txt"]
+        );
     }
 
-    #[macro_export]
-    macro_rules! assert_contains {
-        ($haystack:expr, $needle:expr) => {{
-            assert!(
-                $haystack.contains($needle),
-                "expected string to contain \"{}\", got:\n\n{}\n\n",
-                $needle,
-                $haystack
-            );
-        }};
+    #[test]
+    fn remote_files_are_sized_and_dated_from_the_listing() {
+        let last_modified = DateTime::parse_from_rfc3339("2026-10-01T09:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let file = RemoteFile {
+            name: "Disc 2/Chapter_1.mp3".to_owned(),
+            size: 1234,
+            last_modified: Some(last_modified),
+        };
+        let generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test/dune"),
+            pub_date_from: PubDateSource::Mtime,
+            ..Default::default()
+        };
+        let episode = generator.episodes(vec![file]).unwrap().remove(0);
+        assert_eq!(episode.title, "Chapter 1");
+        assert_eq!(episode.url, "https://eg.test/dune/Disc%202/Chapter_1.mp3");
+        assert_eq!(episode.length, 1234);
+        assert_eq!(episode.pub_date, last_modified);
     }
 
     #[test]
-    fn generates_xml_for_files() {
-        let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
-        let file = MediaFile { path: &path };
+    fn includes_channel_and_item_subtitles() {
+        let files = vec![
+            MockMediaFile {
+                subtitle: Some("Item & Subtitle".to_owned()),
+                ..Default::default()
+            },
+            MockMediaFile::default(),
+        ];
         let generator = FeedGenerator {
             title: "Feed Title 1".to_owned(),
-            base_url: "https://eg.test".to_owned(),
-            image: Some(Image {
-                path: "/path/to/image1.png".into(),
-            }),
+            subtitle: Some("Channel Subtitle".to_owned()),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
         };
         let mut buffer = Vec::new();
-        let result = generator.generate_for_files(vec![file], &mut buffer);
-        assert!(result.is_ok(), "expected generate_for_files to return ok");
+        generator.generate_for_files(files, &mut buffer).unwrap();
         let feed = String::from_utf8(buffer).unwrap();
-        assert_contains!(feed, "<title>Feed Title 1</title>");
-        assert_contains!(feed, "xmlns:itunes");
-        assert_contains!(feed, "<itunes:block>Yes</itunes:block>");
-        assert_contains!(feed, "<itunes:image href=\"https://eg.test/image1.png\"/>");
-        assert_contains!(
-            feed,
-            "url=\"https://eg.test/file1.mp3\" length=\"6\" type=\"audio/mpeg\""
+        let doc = Document::parse(&feed).unwrap();
+        let channel = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "channel")
+            .unwrap();
+        let channel_subtitle = channel
+            .children()
+            .find(|n| n.tag_name().name() == "subtitle")
+            .unwrap();
+        assert_eq!(channel_subtitle.tag_name().namespace(), Some(NAMESPACE));
+        assert_eq!(channel_subtitle.text(), Some("Channel Subtitle"));
+        let items: Vec<Node<'_, '_>> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .collect();
+        assert_eq!(
+            get_child_node_text(&items[0], "subtitle"),
+            "Item & Subtitle"
         );
-        assert_contains!(feed, "<title>file1</title>");
+        assert!(!items[1]
+            .descendants()
+            .any(|n| n.tag_name().name() == "subtitle"));
     }
 
     #[test]
-    fn returns_error_if_file_does_not_exist() {
-        let path = Path::new("invalid-file-1.mp3").to_path_buf();
-        let file = MediaFile { path: &path };
+    fn items_with_their_own_artwork_link_to_it() {
+        let files = vec![
+            MockMediaFile {
+                image: Some("name1 chapter art.jpg".to_owned()),
+                ..Default::default()
+            },
+            MockMediaFile::default(),
+        ];
         let generator = FeedGenerator {
             title: "Feed Title 1".to_owned(),
-            base_url: "https://eg.test".to_owned(),
-            image: None,
+            urls: ObjectUrls::public("https://eg.test"),
+            image: Some(Image {
+                path: "cover.png".into(),
+            }),
+            ..Default::default()
         };
-        let mut buffer = Vec::new();
-        let result = generator.generate_for_files(vec![file], &mut buffer);
-        assert!(
-            result.is_err(),
-            "expected generate_for_files to return an error"
+        let episodes = generator.episodes(files).unwrap();
+        let mut rss = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Rss, None, &mut rss)
+            .unwrap();
+        let rss = String::from_utf8(rss).unwrap();
+        let doc = Document::parse(&rss).unwrap();
+        let images: Vec<Option<&str>> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .map(|item| {
+                item.children()
+                    .find(|n| n.tag_name().name() == "image")
+                    .and_then(|image| image.attribute("href"))
+            })
+            .collect();
+        assert_eq!(
+            images,
+            [Some("https://eg.test/name1%20chapter%20art.jpg"), None]
+        );
+        let mut json = Vec::new();
+        generator
+            .write(&episodes, FeedFormat::Json, None, &mut json)
+            .unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json["items"][0]["image"],
+            "https://eg.test/name1%20chapter%20art.jpg"
         );
+        assert!(json["items"][1].get("image").is_none());
     }
 
     #[test]
-    fn outputs_correct_mime_type() {
-        assert_eq!(FeedGenerator::mime_type("mp3"), "audio/mpeg");
-        assert_eq!(FeedGenerator::mime_type("mp4"), "audio/mp4");
-        assert_eq!(FeedGenerator::mime_type("aac"), "audio/aac");
-        assert_eq!(FeedGenerator::mime_type("m4a"), "audio/mp4");
+    fn truncates_long_subtitles() {
+        let subtitle = "é".repeat(300);
+        let truncated = truncate_subtitle(&subtitle, "test");
+        assert_eq!(truncated.chars().count(), 255);
+        assert_eq!(truncate_subtitle("short", "test"), "short");
     }
 
     #[test]
-    fn pub_dates_go_in_reverse() {
+    fn item_explicit_overrides_channel() {
         let files = vec![
             MockMediaFile {
-                stem: "file1".to_owned(),
+                name: "tagged.mp3".to_owned(),
+                explicit: Some(true),
+                ..Default::default()
+            },
+            MockMediaFile {
+                name: "bonus-1.mp3".to_owned(),
                 ..Default::default()
             },
             MockMediaFile {
-                stem: "file2".to_owned(),
+                name: "clean.mp3".to_owned(),
                 ..Default::default()
             },
             MockMediaFile {
-                stem: "file3".to_owned(),
+                name: "bonus-2.mp3".to_owned(),
+                explicit: Some(false),
                 ..Default::default()
             },
         ];
         let generator = FeedGenerator {
             title: "Feed Title 1".to_owned(),
-            base_url: "https://eg.test".to_owned(),
-            image: None,
+            urls: ObjectUrls::public("https://eg.test"),
+            explicit: Some(false),
+            explicit_files: vec![Pattern::new("bonus-*").unwrap()],
+            ..Default::default()
         };
         let mut buffer = Vec::new();
         generator.generate_for_files(files, &mut buffer).unwrap();
         let feed = String::from_utf8(buffer).unwrap();
         let doc = Document::parse(&feed).unwrap();
-        let items: Vec<Node<'_, '_>> = doc
+        let channel = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "channel")
+            .unwrap();
+        let channel_explicit = channel
+            .children()
+            .find(|n| n.tag_name().name() == "explicit")
+            .unwrap();
+        assert_eq!(channel_explicit.text(), Some("false"));
+        let item_explicit: Vec<Option<&str>> = doc
             .descendants()
             .filter(|n| n.tag_name().name() == "item")
+            .map(|item| {
+                item.children()
+                    .find(|n| n.tag_name().name() == "explicit")
+                    .and_then(|n| n.text())
+            })
             .collect();
-        assert_eq!(items.len(), 3);
-        let today = Utc::now()
-            .date_naive()
-            .and_hms_opt(0, 0, 0)
+        assert_eq!(
+            item_explicit,
+            vec![Some("true"), Some("true"), None, Some("false")]
+        );
+    }
+
+    /// A feed as another tool might have left it, with namespaces and elements sloop doesn't
+    /// write.
+    const FOREIGN_FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+  <channel>
+    <title>Candide</title>
+    <link>https://eg.test/</link>
+    <description>Candide</description>
+    <atom:link href="https://eg.test/feed.xml" rel="self" type="application/rss+xml"/>
+    <podcast:locked owner="me@eg.test">yes</podcast:locked>
+    <itunes:author>Voltaire</itunes:author>
+    <item>
+      <title>Chapter 1</title>
+      <enclosure url="https://eg.test/Chapter_1.mp3" length="12" type="audio/mpeg"/>
+      <podcast:transcript url="https://eg.test/Chapter_1.vtt" type="text/vtt"/>
+    </item>
+    <item>
+      <title>Chapter 2</title>
+      <enclosure url="https://eg.test/Chapter_2.mp3?X-Amz-Signature=abc" length="11" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn removes_the_item_linking_to_an_enclosure() {
+        let edited = remove_item(FOREIGN_FEED.as_bytes(), "https://eg.test/Chapter_2.mp3")
             .unwrap()
-            .and_utc();
-        let item = items.get(0).unwrap();
-        assert_eq!(get_child_node_text(item, "title"), "file1");
+            .unwrap();
+        let edited = String::from_utf8(edited).unwrap();
+        let doc = Document::parse(&edited).unwrap();
+        let titles: Vec<String> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .map(|item| get_child_node_text(&item, "title").to_owned())
+            .collect();
+        assert_eq!(titles, vec!["Chapter 1"]);
+    }
+
+    #[test]
+    fn removing_an_item_keeps_foreign_namespaces_and_elements() {
+        let edited = remove_item(FOREIGN_FEED.as_bytes(), "https://eg.test/Chapter_2.mp3")
+            .unwrap()
+            .unwrap();
+        let edited = String::from_utf8(edited).unwrap();
+        let doc = Document::parse(&edited).unwrap();
+        let podcast = "https://podcastindex.org/namespace/1.0";
+        let find = |namespace: &str, name: &str| {
+            doc.descendants().find(|n| {
+                n.tag_name().namespace() == Some(namespace) && n.tag_name().name() == name
+            })
+        };
+        let locked = find(podcast, "locked").expect("podcast:locked was dropped");
+        assert_eq!(locked.attribute("owner"), Some("me@eg.test"));
+        assert_eq!(locked.text(), Some("yes"));
+        let transcript = find(podcast, "transcript").expect("podcast:transcript was dropped");
         assert_eq!(
-            get_child_node_text(item, "pubDate"),
-            (today - Duration::days(0)).to_rfc2822()
+            transcript.attribute("url"),
+            Some("https://eg.test/Chapter_1.vtt")
         );
-        let item = items.get(1).unwrap();
-        assert_eq!(get_child_node_text(item, "title"), "file2");
+        let self_link = find("http://www.w3.org/2005/Atom", "link").expect("atom:link was dropped");
         assert_eq!(
-            get_child_node_text(item, "pubDate"),
-            (today - Duration::days(1)).to_rfc2822()
+            self_link.attribute("href"),
+            Some("https://eg.test/feed.xml")
         );
-        let item = items.get(2).unwrap();
-        assert_eq!(get_child_node_text(item, "title"), "file3");
+        let author = find("http://www.itunes.com/dtds/podcast-1.0.dtd", "author");
+        assert_eq!(author.and_then(|n| n.text()), Some("Voltaire"));
+    }
+
+    #[test]
+    fn removing_a_missing_item_changes_nothing() {
         assert_eq!(
-            get_child_node_text(item, "pubDate"),
-            (today - Duration::days(2)).to_rfc2822()
+            remove_item(FOREIGN_FEED.as_bytes(), "https://eg.test/Chapter_3.mp3"),
+            Ok(None)
         );
+        assert!(remove_item(b"not a feed", "https://eg.test/Chapter_1.mp3").is_err());
+    }
+
+    fn mock_files(names: &[&str]) -> Vec<MockMediaFile> {
+        names
+            .iter()
+            .map(|name| MockMediaFile {
+                name: format!("{}.mp3", name),
+                stem: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn items(feed: &str) -> Vec<&str> {
+        feed.match_indices("<item>")
+            .map(|(start, _)| {
+                let end = start + feed[start..].find("</item>").unwrap() + "</item>".len();
+                &feed[start..end]
+            })
+            .collect()
     }
 
     #[test]
-    fn handles_special_chars_in_filenames() {
-        let files = vec![MockMediaFile {
-            name: "a+b c&d.mp3".to_owned(),
-            stem: "a+b c&d".to_owned(),
-            ..Default::default()
-        }];
+    fn appending_keeps_existing_items_byte_for_byte() {
         let generator = FeedGenerator {
-            title: "Feed Title 1".to_owned(),
-            base_url: "https://eg.test".to_owned(),
-            image: None,
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
         };
-        let mut buffer = Vec::new();
-        generator.generate_for_files(files, &mut buffer).unwrap();
-        let feed = String::from_utf8(buffer).unwrap();
-        let doc = Document::parse(&feed).unwrap();
-        let item = doc
-            .descendants()
-            .find(|n| n.tag_name().name() == "item")
+        let mut original = Vec::new();
+        generator
+            .write(
+                &generator
+                    .episodes(mock_files(&["Chapter_1", "Chapter_2"]))
+                    .unwrap(),
+                FeedFormat::Rss,
+                Some("https://eg.test/feed.xml"),
+                &mut original,
+            )
             .unwrap();
-        assert_eq!(get_child_node_text(&item, "title"), "a+b c&d");
-        let enclosure = doc
-            .descendants()
-            .find(|n| n.tag_name().name() == "enclosure")
+        let original = String::from_utf8(original).unwrap();
+
+        let mut existing = ExistingFeed::parse(original.as_bytes()).unwrap();
+        let new = generator
+            .new_episodes(
+                mock_files(&["Chapter_1", "Chapter_2", "Chapter_3"]),
+                &mut existing,
+                None,
+            )
             .unwrap();
         assert_eq!(
-            enclosure.attribute("url"),
-            Some("https://eg.test/a%2Bb%20c%26d.mp3")
+            new.iter().map(|e| e.url.as_str()).collect::<Vec<_>>(),
+            vec!["https://eg.test/Chapter_3.mp3"]
         );
+        let mut appended = Vec::new();
+        generator
+            .write_appended(
+                &existing,
+                &new,
+                FeedFormat::Rss,
+                Some("https://eg.test/feed.xml"),
+                &mut appended,
+            )
+            .unwrap();
+        let appended = String::from_utf8(appended).unwrap();
+
+        let appended_items = items(&appended);
+        assert_eq!(appended_items.len(), 3);
+        assert_eq!(appended_items[..2], items(&original)[..]);
+        // Apart from the new item and the channel's dates, the feed is unchanged.
+        let undated = |feed: &str| -> Vec<String> {
+            feed.lines()
+                .filter(|line| {
+                    !line.trim().is_empty()
+                        && !line.starts_with("    <pubDate>")
+                        && !line.starts_with("    <lastBuildDate>")
+                })
+                .map(str::to_owned)
+                .collect()
+        };
+        assert!(appended.contains("    <lastBuildDate>"));
+        let without_new = appended.replacen(appended_items[2], "", 1);
+        assert_eq!(undated(&without_new), undated(&original));
     }
 
     #[test]
-    fn replaces_underscores_with_spaces_in_title() {
-        let files = vec![MockMediaFile {
-            name: "ab_cd.mp3".to_owned(),
-            stem: "ab_cd".to_owned(),
+    fn trimming_keeps_the_newest_items_in_feed_order() {
+        let titles = |episodes: &[Episode]| -> Vec<String> {
+            episodes
+                .iter()
+                .map(|episode| episode.title.clone())
+                .collect()
+        };
+        let mut generator = FeedGenerator {
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            max_items: Some(2),
             ..Default::default()
-        }];
+        };
+        let files = mock_files(&["Chapter_1", "Chapter_2", "Chapter_3"]);
+        let mut episodes = generator.episodes(files.clone()).unwrap();
+        assert_eq!(generator.trim(&mut episodes), 1);
+        assert_eq!(titles(&episodes), ["Chapter 1", "Chapter 2"]);
+
+        // The newest are then at the bottom, and stay numbered as they were.
+        generator.order = DateOrder::OldestFirst;
+        generator.podcast_type = Some(PodcastType::Serial);
+        let mut episodes = generator.episodes(files.clone()).unwrap();
+        assert_eq!(generator.trim(&mut episodes), 1);
+        assert_eq!(titles(&episodes), ["Chapter 2", "Chapter 3"]);
+        assert_eq!(episodes[0].number, Some(2));
+
+        // Episodes dated the same are kept from the top.
+        generator.interval = Some(time::Duration::ZERO);
+        let mut episodes = generator.episodes(files.clone()).unwrap();
+        assert_eq!(generator.trim(&mut episodes), 1);
+        assert_eq!(titles(&episodes), ["Chapter 1", "Chapter 2"]);
+
+        generator.max_items = None;
+        let mut episodes = generator.episodes(files).unwrap();
+        assert_eq!(generator.trim(&mut episodes), 0);
+        assert_eq!(episodes.len(), 3);
+    }
+
+    #[test]
+    fn trimming_an_appended_feed_drops_its_oldest_items() {
         let generator = FeedGenerator {
-            title: "Feed Title 1".to_owned(),
-            base_url: "https://eg.test".to_owned(),
-            image: None,
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            max_items: Some(2),
+            ..Default::default()
         };
-        let mut buffer = Vec::new();
-        generator.generate_for_files(files, &mut buffer).unwrap();
-        let feed = String::from_utf8(buffer).unwrap();
-        let doc = Document::parse(&feed).unwrap();
-        let item = doc
-            .descendants()
-            .find(|n| n.tag_name().name() == "item")
+        let mut original = Vec::new();
+        let episodes = generator
+            .episodes(mock_files(&["Chapter_1", "Chapter_2"]))
             .unwrap();
-        assert_eq!(get_child_node_text(&item, "title"), "ab cd");
-        let enclosure = doc
-            .descendants()
-            .find(|n| n.tag_name().name() == "enclosure")
+        generator
+            .write(&episodes, FeedFormat::Rss, None, &mut original)
+            .unwrap();
+        let original = String::from_utf8(original).unwrap();
+
+        let mut existing = ExistingFeed::parse(original.as_bytes()).unwrap();
+        let mut new = generator
+            .new_episodes(
+                mock_files(&["Chapter_1", "Chapter_2", "Chapter_3"]),
+                &mut existing,
+                None,
+            )
+            .unwrap();
+        assert_eq!(generator.trim_appended(&mut existing, &mut new), 1);
+        let mut appended = Vec::new();
+        generator
+            .write_appended(&existing, &new, FeedFormat::Rss, None, &mut appended)
+            .unwrap();
+        let appended = String::from_utf8(appended).unwrap();
+        // Chapter 2 is dated a day before Chapter 1, so it's the one dropped.
+        let appended_items = items(&appended);
+        assert_eq!(appended_items.len(), 2);
+        assert_eq!(appended_items[0], items(&original)[0]);
+        assert_contains!(appended_items[1], "<title>Chapter 3</title>");
+    }
+
+    /// A feed of Chapter_1 and Chapter_2, each 123 bytes and playing for a minute, published
+    /// at eg.test, and the generator that wrote it.
+    fn published_chapters() -> (FeedGenerator, ExistingFeed) {
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let mut feed = Vec::new();
+        generator
+            .write(
+                &generator
+                    .episodes(chapters(&["Chapter_1", "Chapter_2"]))
+                    .unwrap(),
+                FeedFormat::Rss,
+                None,
+                &mut feed,
+            )
+            .unwrap();
+        (generator, ExistingFeed::parse(&feed).unwrap())
+    }
+
+    /// Files a minute long at /audio/<name>.mp3.
+    fn chapters(names: &[&str]) -> Vec<MockMediaFile> {
+        mock_files(names)
+            .into_iter()
+            .map(|file| MockMediaFile {
+                duration: Some(std::time::Duration::from_secs(60)),
+                path: Some(Path::new("/audio").join(&file.name)),
+                ..file
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renamed_files_with_the_same_contents_keep_their_items() {
+        let (generator, mut existing) = published_chapters();
+        let pub_date = existing.episodes()[1].pub_date;
+        let compared = std::cell::RefCell::new(Vec::new());
+        let same_contents = |path: &Path, name: &str| {
+            compared
+                .borrow_mut()
+                .push((path.to_owned(), name.to_owned()));
+            Ok(Some(name == "Chapter_2.mp3"))
+        };
+        let new = generator
+            .new_episodes(
+                chapters(&["Chapter_1", "Chapter_02"]),
+                &mut existing,
+                Some(&same_contents),
+            )
             .unwrap();
+        assert!(new.is_empty());
         assert_eq!(
-            enclosure.attribute("url"),
-            Some("https://eg.test/ab_cd.mp3")
+            compared.into_inner(),
+            [(
+                PathBuf::from("/audio/Chapter_02.mp3"),
+                "Chapter_2.mp3".to_owned()
+            )]
+        );
+        assert_eq!(
+            existing.renamed(),
+            [(
+                "https://eg.test/Chapter_2.mp3".to_owned(),
+                "https://eg.test/Chapter_02.mp3".to_owned()
+            )]
+        );
+        let episodes = existing.episodes();
+        assert_eq!(episodes.len(), 2);
+        let renamed = &episodes[1];
+        assert_eq!(renamed.url, "https://eg.test/Chapter_02.mp3");
+        assert_eq!(renamed.title, "Chapter 02");
+        assert_eq!(
+            renamed.guid.as_deref(),
+            Some("https://eg.test/Chapter_2.mp3")
+        );
+        assert_eq!(renamed.pub_date, pub_date);
+    }
+
+    #[test]
+    fn files_that_only_match_in_length_are_new() {
+        let (generator, mut existing) = published_chapters();
+        // Different contents, with the same length and duration.
+        let new = generator
+            .new_episodes(
+                chapters(&["Chapter_1", "Chapter_2", "Chapter_3"]),
+                &mut existing,
+                Some(&|_: &Path, _: &str| Ok(Some(false))),
+            )
+            .unwrap();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].url, "https://eg.test/Chapter_3.mp3");
+
+        // Contents that can't be compared, and a different duration.
+        let (generator, mut existing) = published_chapters();
+        let mut files = chapters(&["Chapter_1", "Chapter_02"]);
+        files[1].duration = Some(std::time::Duration::from_secs(61));
+        let new = generator
+            .new_episodes(files, &mut existing, Some(&|_: &Path, _: &str| Ok(None)))
+            .unwrap();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].url, "https://eg.test/Chapter_02.mp3");
+        assert!(existing.renamed().is_empty());
+        let urls: Vec<String> = existing.episodes().into_iter().map(|e| e.url).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://eg.test/Chapter_1.mp3",
+                "https://eg.test/Chapter_2.mp3"
+            ]
+        );
+    }
+
+    #[test]
+    fn files_with_the_same_length_and_duration_are_renames_if_contents_cant_be_compared() {
+        let (generator, mut existing) = published_chapters();
+        let new = generator
+            .new_episodes(
+                chapters(&["Chapter_1", "Chapter_02"]),
+                &mut existing,
+                Some(&|_: &Path, _: &str| Ok(None)),
+            )
+            .unwrap();
+        assert!(new.is_empty());
+        assert_eq!(existing.renamed().len(), 1);
+    }
+
+    #[test]
+    fn rename_detection_can_be_turned_off() {
+        let (generator, mut existing) = published_chapters();
+        let new = generator
+            .new_episodes(chapters(&["Chapter_1", "Chapter_02"]), &mut existing, None)
+            .unwrap();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].url, "https://eg.test/Chapter_02.mp3");
+        assert_eq!(existing.episodes().len(), 2);
+    }
+
+    #[test]
+    fn each_item_is_taken_by_one_renamed_file_at_most() {
+        let (generator, mut existing) = published_chapters();
+        let new = generator
+            .new_episodes(
+                chapters(&["Chapter_1", "Chapter_02", "Chapter_002"]),
+                &mut existing,
+                Some(&|_: &Path, _: &str| Ok(Some(true))),
+            )
+            .unwrap();
+        assert_eq!(existing.renamed().len(), 1);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].url, "https://eg.test/Chapter_002.mp3");
+    }
+
+    #[test]
+    fn appended_episodes_are_dated_after_the_newest_item() {
+        let now = DateTime::parse_from_rfc3339("2026-10-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            appended_pub_dates(3, None, now),
+            vec![now - Duration::seconds(2), now - Duration::seconds(1), now]
+        );
+        let future = now + Duration::days(1);
+        assert_eq!(
+            appended_pub_dates(2, Some(future), now),
+            vec![future + Duration::seconds(1), future + Duration::seconds(2)]
+        );
+        assert!(appended_pub_dates(0, Some(future), now).is_empty());
+    }
+
+    #[test]
+    fn appending_keeps_foreign_channel_elements_and_updates_sloops_own() {
+        let generator = FeedGenerator {
+            title: "Candide, or Optimism".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            explicit: Some(true),
+            ..Default::default()
+        };
+        let existing = ExistingFeed::parse(FOREIGN_FEED.as_bytes()).unwrap();
+        let mut appended = Vec::new();
+        generator
+            .write_appended(
+                &existing,
+                &[],
+                FeedFormat::Rss,
+                Some("https://eg.test/feed.xml"),
+                &mut appended,
+            )
+            .unwrap();
+        let appended = String::from_utf8(appended).unwrap();
+        assert_contains!(appended, "<title>Candide, or Optimism</title>");
+        assert_contains!(appended, "<itunes:explicit>true</itunes:explicit>");
+        assert_contains!(appended, "<itunes:author>Voltaire</itunes:author>");
+        assert_contains!(appended, "podcast:locked");
+        assert_eq!(appended.matches("<atom:link ").count(), 1);
+        assert!(!appended.contains("lastBuildDate"));
+        assert_eq!(items(&appended).len(), 2);
+    }
+
+    #[test]
+    fn existing_items_become_episodes_for_json_feeds() {
+        let existing = ExistingFeed::parse(FOREIGN_FEED.as_bytes()).unwrap();
+        let episodes = existing.episodes();
+        assert_eq!(
+            episodes.iter().map(|e| e.url.as_str()).collect::<Vec<_>>(),
+            vec![
+                "https://eg.test/Chapter_1.mp3",
+                "https://eg.test/Chapter_2.mp3?X-Amz-Signature=abc"
+            ]
         );
+        assert_eq!(episodes[1].length, 11);
+        assert!(existing.links_to("https://eg.test/Chapter_2.mp3"));
+        assert!(!existing.links_to("https://eg.test/Chapter_3.mp3"));
     }
 }
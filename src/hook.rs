@@ -0,0 +1,317 @@
+use crate::human;
+use crate::warning;
+use std::ffi::OsString;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// When in a run a hook is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Before the feeds are written, e.g. to prepare artwork.
+    PreGenerate,
+    /// Once the feeds are written, given their paths as arguments.
+    PostGenerate,
+    /// Before anything is uploaded.
+    PreUpload,
+    /// Once everything is uploaded, given the run's report as JSON on stdin.
+    PostUpload,
+}
+
+impl Stage {
+    pub const VALUES: &'static [&'static str] =
+        &["pre_generate", "post_generate", "pre_upload", "post_upload"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::PreGenerate => "pre_generate",
+            Stage::PostGenerate => "post_generate",
+            Stage::PreUpload => "pre_upload",
+            Stage::PostUpload => "post_upload",
+        }
+    }
+
+    /// Hooks before a step can stop it; those after it only warn, as it's already done.
+    fn can_stop_the_run(self) -> bool {
+        matches!(self, Stage::PreGenerate | Stage::PreUpload)
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(stage: &str) -> Result<Self, Self::Err> {
+        match stage {
+            "pre_generate" => Ok(Stage::PreGenerate),
+            "post_generate" => Ok(Stage::PostGenerate),
+            "pre_upload" => Ok(Stage::PreUpload),
+            "post_upload" => Ok(Stage::PostUpload),
+            _ => Err(format!(
+                "{} is not one of {}",
+                stage,
+                Stage::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A shell command to run at a stage of the run, given as `STAGE=COMMAND`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hook {
+    pub stage: Stage,
+    pub command: String,
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(hook: &str) -> Result<Self, Self::Err> {
+        match hook.split_once('=') {
+            Some((stage, command)) if !command.trim().is_empty() => Ok(Hook {
+                stage: stage.trim().parse()?,
+                command: command.to_owned(),
+            }),
+            _ => Err(format!("--hook {:?} must be STAGE=COMMAND", hook)),
+        }
+    }
+}
+
+/// The hooks of a run, which all share a timeout.
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    pub hooks: Vec<Hook>,
+    pub timeout: Duration,
+}
+
+impl Hooks {
+    /// Runs the hooks for `stage` in the order given, with `env` set and `SLOOP_HOOK` naming
+    /// the stage, `args` after the command, and `stdin` fed to it. The first to fail, exit
+    /// non-zero or outlast the timeout fails the run at stages before a step, and is a warning
+    /// at those after.
+    pub fn run(
+        &self,
+        stage: Stage,
+        env: &[(&str, String)],
+        args: &[OsString],
+        stdin: Option<&str>,
+    ) -> Result<(), String> {
+        for hook in self.hooks.iter().filter(|hook| hook.stage == stage) {
+            if let Err(err) = execute(hook, env, args, stdin, self.timeout) {
+                if stage.can_stop_the_run() {
+                    return Err(format!("Stopping, as the {} {}", stage.name(), err));
+                }
+                warning::warn(&format!("the {} {}", stage.name(), err));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `hook`'s command with the shell, its output going to sloop's.
+fn execute(
+    hook: &Hook,
+    env: &[(&str, String)],
+    args: &[OsString],
+    stdin: Option<&str>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let mut child = shell(&hook.command, args)
+        .env("SLOOP_HOOK", hook.stage.name())
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .spawn()
+        .map_err(|err| format!("hook {:?} couldn't be run: {}", hook.command, err))?;
+    if let (Some(mut pipe), Some(input)) = (child.stdin.take(), stdin) {
+        // Written from another thread so a hook that doesn't read its input can't block sloop.
+        let input = input.to_owned();
+        thread::spawn(move || pipe.write_all(input.as_bytes()));
+    }
+    // A timeout too long to reach is no timeout.
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => {
+                return Err(format!("hook {:?} failed ({})", hook.command, status));
+            }
+            Ok(None) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "hook {:?} was stopped after running for {}",
+                    hook.command,
+                    human::format_duration(timeout)
+                ));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(err) => return Err(format!("hook {:?} failed: {}", hook.command, err)),
+        }
+    }
+}
+
+/// The shell command running `command` with `args` after it.
+#[cfg(unix)]
+fn shell(command: &str, args: &[OsString]) -> Command {
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg("sloop")
+        .args(args);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str, args: &[OsString]) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command).args(args);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    /// A scratch directory for hook scripts and what they write, removed when dropped.
+    struct ScriptDir(PathBuf);
+
+    impl ScriptDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("sloop-hook-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            ScriptDir(dir)
+        }
+
+        /// Writes an executable shell script called `name`, returning its path.
+        fn script(&self, name: &str, body: &str) -> String {
+            let path = self.0.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path.display().to_string()
+        }
+
+        fn read(&self, name: &str) -> String {
+            fs::read_to_string(self.0.join(name)).unwrap_or_default()
+        }
+    }
+
+    impl Drop for ScriptDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn hooks(hooks: &[String]) -> Hooks {
+        Hooks {
+            hooks: hooks.iter().map(|hook| hook.parse().unwrap()).collect(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn parses_hooks() {
+        assert_eq!(
+            "post_upload=./notify.sh --room podcasts".parse(),
+            Ok(Hook {
+                stage: Stage::PostUpload,
+                command: "./notify.sh --room podcasts".to_owned()
+            })
+        );
+        assert_eq!(
+            "after_upload=./notify.sh".parse::<Hook>(),
+            Err(
+                "after_upload is not one of pre_generate, post_generate, pre_upload, post_upload"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            "post_upload".parse::<Hook>(),
+            Err("--hook \"post_upload\" must be STAGE=COMMAND".to_owned())
+        );
+    }
+
+    #[test]
+    fn hooks_get_the_environment_arguments_and_input() {
+        let dir = ScriptDir::new("env");
+        let out = dir.0.join("out").display().to_string();
+        let script = dir.script(
+            "record.sh",
+            &format!(
+                "echo \"$SLOOP_HOOK $SLOOP_FEED_URL $1 $2\" > {out}; cat >> {out}",
+                out = out
+            ),
+        );
+        hooks(&[format!("post_generate={}", script)])
+            .run(
+                Stage::PostGenerate,
+                &[("SLOOP_FEED_URL", "https://eg.test/feed.xml".to_owned())],
+                &["feed.xml".into(), "feed.json".into()],
+                Some("{\"outcome\":\"published\"}"),
+            )
+            .unwrap();
+        assert_eq!(
+            dir.read("out"),
+            "post_generate https://eg.test/feed.xml feed.xml feed.json\n{\"outcome\":\"published\"}"
+        );
+    }
+
+    #[test]
+    fn only_the_stages_hooks_run_in_order() {
+        let dir = ScriptDir::new("order");
+        let out = dir.0.join("out").display().to_string();
+        let hooks = hooks(&[
+            format!("pre_upload=echo first >> {}", out),
+            format!("post_upload=echo other >> {}", out),
+            format!("pre_upload=echo second >> {}", out),
+        ]);
+        hooks.run(Stage::PreUpload, &[], &[], None).unwrap();
+        assert_eq!(dir.read("out"), "first\nsecond\n");
+    }
+
+    #[test]
+    fn failing_pre_hooks_stop_the_run_and_post_hooks_warn() {
+        let dir = ScriptDir::new("fail");
+        let script = dir.script("fail.sh", "exit 3");
+        let hooks = hooks(&[
+            format!("pre_generate={}", script),
+            format!("post_upload={}", script),
+        ]);
+        let err = hooks.run(Stage::PreGenerate, &[], &[], None).unwrap_err();
+        assert!(
+            err.starts_with(&format!(
+                "Stopping, as the pre_generate hook {:?} failed (exit status: 3)",
+                script
+            )),
+            "{}",
+            err
+        );
+        assert_eq!(hooks.run(Stage::PostUpload, &[], &[], None), Ok(()));
+    }
+
+    #[test]
+    fn hooks_are_stopped_after_the_timeout() {
+        let started = Instant::now();
+        let hooks = Hooks {
+            hooks: vec!["pre_upload=sleep 10".parse().unwrap()],
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(
+            hooks.run(Stage::PreUpload, &[], &[], None),
+            Err(
+                "Stopping, as the pre_upload hook \"sleep 10\" was stopped after running for 1s"
+                    .to_owned()
+            )
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
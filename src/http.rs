@@ -0,0 +1,30 @@
+use futures::Future;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use tokio::runtime::current_thread::Runtime;
+
+/// Fetches public URLs, e.g. to check that uploaded objects can be played. Abstracted so the
+/// checks can be tested without a network.
+pub trait HttpGet {
+    /// Sends a GET request and returns the response status code.
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<u16, String>;
+}
+
+pub struct HyperClient;
+
+impl HttpGet for HyperClient {
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<u16, String> {
+        let https = HttpsConnector::new(1).map_err(|err| err.to_string())?;
+        let client = Client::builder().build::<_, Body>(https);
+        let mut request = Request::get(url);
+        for (name, value) in headers {
+            request.header(*name, *value);
+        }
+        let request = request.body(Body::empty()).map_err(|err| err.to_string())?;
+        let response = client
+            .request(request)
+            .map(|response| response.status().as_u16());
+        let mut runtime = Runtime::new().map_err(|err| err.to_string())?;
+        runtime.block_on(response).map_err(|err| err.to_string())
+    }
+}
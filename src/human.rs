@@ -0,0 +1,236 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use std::time::Duration;
+
+const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+/// How to print sizes and durations: for people (`1.4 GiB`, `1h 23m`) or, with `raw`, as plain
+/// counts of bytes and seconds for scripts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Numbers {
+    pub raw: bool,
+}
+
+impl Numbers {
+    pub fn bytes(self, bytes: u64) -> String {
+        if self.raw {
+            format!("{} B", bytes)
+        } else {
+            format_bytes(bytes)
+        }
+    }
+
+    pub fn duration(self, duration: Duration) -> String {
+        if self.raw {
+            format!("{}s", duration.as_secs())
+        } else {
+            format_duration(duration)
+        }
+    }
+}
+
+/// Formats a size in binary units with one decimal place, e.g. `1023 B` or `1.4 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    for (i, unit) in UNITS.iter().enumerate() {
+        // Move up a unit when rounding would otherwise show e.g. `1024.0 KiB`.
+        if value < 1023.95 || i == UNITS.len() - 1 {
+            return format!("{:.1} {}", value, unit);
+        }
+        value /= 1024.0;
+    }
+    unreachable!()
+}
+
+/// Formats a duration to the second, using at most two units, e.g. `45s`, `3m 12s` or `26h 5m`.
+/// Hours are not rolled up into days.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Parses a duration given as a number of seconds, optionally followed by a unit: `s`, `m`, `h`
+/// or `d`, e.g. `90`, `45m` or `7d`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit_secs) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1),
+        Some((i, 'm')) => (&value[..i], 60),
+        Some((i, 'h')) => (&value[..i], 3600),
+        Some((i, 'd')) => (&value[..i], 24 * 3600),
+        Some((i, 'w')) => (&value[..i], 7 * 24 * 3600),
+        _ => (value, 1),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("{} is not a duration like 90s, 45m, 12h, 7d or 2w", value))?;
+    number
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("{} is too long a duration", value))
+}
+
+/// Parses an offset from UTC like `+10:00`, `-0530` or `UTC`.
+pub fn parse_utc_offset(value: &str) -> Result<FixedOffset, String> {
+    let invalid = || format!("{} is not an offset from UTC like +10:00 or -05:30", value);
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    let (sign, rest) = match value.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let digits = rest.replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours: i32 = digits[..2].parse().unwrap();
+    let minutes: i32 = digits[2..].parse().unwrap();
+    if minutes >= 60 {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+/// Parses a date, as midnight UTC, or a date and time, in UTC unless it has an offset, e.g.
+/// `2024-03-01`, `2024-03-01T06:00:00` or `2024-03-01T06:00:00+10:00`.
+pub fn parse_date_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(date.and_utc());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    Err(format!(
+        "{} is not a date like 2024-03-01 or 2024-03-01T06:00:00+10:00",
+        value
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: u64 = 1024;
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn formats_bytes_below_a_kibibyte_exactly() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+    }
+
+    #[test]
+    fn formats_bytes_with_one_decimal_place() {
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(GIB), "1.0 GiB");
+        assert_eq!(format_bytes(GIB * 14 / 10), "1.4 GiB");
+        assert_eq!(format_bytes(GIB - 1), "1.0 GiB");
+        assert_eq!(format_bytes(1023 * KIB), "1023.0 KiB");
+        assert_eq!(format_bytes(u64::MAX), "16384.0 PiB");
+    }
+
+    #[test]
+    fn formats_durations_with_two_units() {
+        assert_eq!(format_duration(Duration::from_millis(999)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1m 0s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m 12s");
+        assert_eq!(format_duration(Duration::from_secs(83 * 60 + 59)), "1h 23m");
+    }
+
+    #[test]
+    fn formats_durations_over_a_day_in_hours() {
+        assert_eq!(format_duration(Duration::from_secs(24 * 3600)), "24h 0m");
+        assert_eq!(
+            format_duration(Duration::from_secs(26 * 3600 + 5 * 60)),
+            "26h 5m"
+        );
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45m"), Ok(Duration::from_secs(45 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert_eq!(
+            parse_duration("2w"),
+            Ok(Duration::from_secs(14 * 24 * 3600))
+        );
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+        assert_eq!(
+            parse_duration("1y"),
+            Err("1y is not a duration like 90s, 45m, 12h, 7d or 2w".to_owned())
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert_eq!(
+            parse_duration("99999999999999999w"),
+            Err("99999999999999999w is too long a duration".to_owned())
+        );
+        assert_eq!(
+            parse_duration("18446744073709551615"),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn parses_utc_offsets() {
+        let offset = |value| parse_utc_offset(value).map(|offset| offset.local_minus_utc());
+        assert_eq!(offset("+10:00"), Ok(10 * 3600));
+        assert_eq!(offset("-0530"), Ok(-(5 * 3600 + 30 * 60)));
+        assert_eq!(offset("UTC"), Ok(0));
+        assert_eq!(
+            offset("10:00"),
+            Err("10:00 is not an offset from UTC like +10:00 or -05:30".to_owned())
+        );
+        assert!(offset("+10:75").is_err());
+        assert!(offset("+1").is_err());
+    }
+
+    #[test]
+    fn parses_dates_and_times() {
+        let date = |value| parse_date_time(value).map(|date| date.to_rfc3339());
+        assert_eq!(
+            date("2024-03-01"),
+            Ok("2024-03-01T00:00:00+00:00".to_owned())
+        );
+        assert_eq!(
+            date("2024-03-01T06:00:00+10:00"),
+            Ok("2024-02-29T20:00:00+00:00".to_owned())
+        );
+        assert_eq!(
+            date("2024-03-01T06:00:00"),
+            Ok("2024-03-01T06:00:00+00:00".to_owned())
+        );
+        assert_eq!(
+            date("1 March"),
+            Err("1 March is not a date like 2024-03-01 or 2024-03-01T06:00:00+10:00".to_owned())
+        );
+    }
+
+    #[test]
+    fn raw_numbers_are_plain_counts() {
+        let raw = Numbers { raw: true };
+        assert_eq!(raw.bytes(GIB), "1073741824 B");
+        assert_eq!(raw.duration(Duration::from_secs(26 * 3600)), "93600s");
+        let human = Numbers::default();
+        assert_eq!(human.bytes(GIB), "1.0 GiB");
+        assert_eq!(human.duration(Duration::from_secs(93600)), "26h 0m");
+    }
+}
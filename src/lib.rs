@@ -0,0 +1,7 @@
+//! Library interface to sloop, for tools that need to agree with it about where episodes end up
+//! and what it reads from their files.
+
+mod duration;
+pub mod metadata;
+pub mod naming;
+mod tags;
@@ -0,0 +1,117 @@
+use crate::human::Numbers;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+/// An object already in the bucket, as `sloop list` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// Whether the object is an RSS or JSON feed, judged by its key.
+    pub feed: bool,
+}
+
+/// The objects under a prefix, sorted by key, one per line with the date each was last
+/// modified and its size. Feeds are marked, since they're the URLs subscribers need.
+pub fn format(objects: &[ListedObject], numbers: Numbers) -> String {
+    let sizes: Vec<String> = objects
+        .iter()
+        .map(|object| numbers.bytes(object.size))
+        .collect();
+    let width = sizes.iter().map(|size| size.len()).max().unwrap_or(0);
+    let mut output = String::new();
+    for (object, size) in objects.iter().zip(sizes) {
+        let date = match object.last_modified {
+            Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+            None => format!("{:16}", "-"),
+        };
+        let marker = if object.feed { "  (feed)" } else { "" };
+        let _ = writeln!(
+            output,
+            "{}  {:>width$}  {}{}",
+            date,
+            size,
+            object.key,
+            marker,
+            width = width
+        );
+    }
+    output
+}
+
+/// The objects as a JSON array, with sizes in bytes and dates in RFC 3339, for scripts.
+pub fn to_json(objects: &[ListedObject]) -> Value {
+    objects
+        .iter()
+        .map(|object| {
+            json!({
+                "key": object.key,
+                "size": object.size,
+                "last_modified": object.last_modified.map(|date| date.to_rfc3339()),
+                "feed": object.feed,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objects() -> Vec<ListedObject> {
+        let date = DateTime::parse_from_rfc3339("2026-10-01T09:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        vec![
+            ListedObject {
+                key: "dune/Chapter_1.mp3".to_owned(),
+                size: 1_288_490_189,
+                last_modified: Some(date),
+                feed: false,
+            },
+            ListedObject {
+                key: "dune/feed.xml".to_owned(),
+                size: 666,
+                last_modified: None,
+                feed: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn formats_one_object_per_line_marking_feeds() {
+        assert_eq!(
+            format(&objects(), Numbers::default()),
+            "2026-10-01 09:30  1.2 GiB  dune/Chapter_1.mp3\n\
+             -                   666 B  dune/feed.xml  (feed)\n"
+        );
+        assert_eq!(
+            format(&objects(), Numbers { raw: true }),
+            "2026-10-01 09:30  1288490189 B  dune/Chapter_1.mp3\n\
+             -                        666 B  dune/feed.xml  (feed)\n"
+        );
+    }
+
+    #[test]
+    fn json_has_exact_sizes_and_dates() {
+        assert_eq!(
+            to_json(&objects()),
+            json!([
+                {
+                    "key": "dune/Chapter_1.mp3",
+                    "size": 1_288_490_189u64,
+                    "last_modified": "2026-10-01T09:30:00+00:00",
+                    "feed": false,
+                },
+                {
+                    "key": "dune/feed.xml",
+                    "size": 666,
+                    "last_modified": null,
+                    "feed": true,
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{DateTime, Utc};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Name of the lockfile in a podcast's state directory.
+pub const LOCK_FILE_NAME: &str = "run.lock";
+
+/// How often a run waiting with `--wait` checks whether the lock has been released.
+const WAIT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// An advisory lock held for the length of a run, so that two runs publishing the same podcast
+/// can't interleave their uploads. The lockfile records the holder's pid and start time; it is
+/// removed when the lock is dropped, and reclaimed by the next run if its holder died without
+/// removing it.
+#[derive(Debug)]
+pub struct RunLock {
+    path: PathBuf,
+}
+
+#[derive(Debug, PartialEq)]
+struct Holder {
+    pid: u32,
+    started_at: DateTime<Utc>,
+}
+
+impl Holder {
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let pid = lines.next()?.parse().ok()?;
+        let started_at = DateTime::parse_from_rfc3339(lines.next()?).ok()?;
+        Some(Self {
+            pid,
+            started_at: started_at.with_timezone(&Utc),
+        })
+    }
+}
+
+impl RunLock {
+    /// Takes the lock at `path`, creating its directory if needed. If another live process holds
+    /// it, waits for it to be released when `wait` is set and fails otherwise.
+    pub fn acquire(path: &Path, wait: bool) -> Result<Self, String> {
+        Self::acquire_with(path, wait, process_alive)
+    }
+
+    fn acquire_with<A: Fn(u32) -> bool>(path: &Path, wait: bool, alive: A) -> Result<Self, String> {
+        let lock_error = |err: io::Error| format!("Failed to lock {}: {}", path.display(), err);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(lock_error)?;
+        }
+        loop {
+            match Self::try_create(path) {
+                Ok(()) => {
+                    return Ok(Self {
+                        path: path.to_owned(),
+                    })
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(lock_error(err)),
+            }
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                // Released between our attempt and reading it.
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(lock_error(err)),
+            };
+            match Holder::parse(&contents) {
+                Some(holder) if alive(holder.pid) => {
+                    if !wait {
+                        return Err(format!(
+                            "Another sloop run (pid {}, started at {}) is in progress. Use \
+                             --wait to wait for it to finish, or remove {} if it is not running.",
+                            holder.pid,
+                            holder.started_at.to_rfc3339(),
+                            path.display()
+                        ));
+                    }
+                    thread::sleep(WAIT_INTERVAL);
+                }
+                // The holder crashed, or the file isn't a lock we wrote.
+                _ => Self::reclaim(path, &contents).map_err(lock_error)?,
+            }
+        }
+    }
+
+    /// Writes the holder details to a temporary file and links it into place, so the lockfile
+    /// either doesn't exist or is complete.
+    fn try_create(path: &Path) -> io::Result<()> {
+        static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+        let pid = process::id();
+        let attempt = ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        let temp = path.with_extension(format!("{}.{}.tmp", pid, attempt));
+        fs::write(&temp, format!("{}\n{}\n", pid, Utc::now().to_rfc3339()))?;
+        let result = fs::hard_link(&temp, path);
+        fs::remove_file(&temp)?;
+        result
+    }
+
+    /// Removes the lockfile at `path` if it still holds `stale`, as read from it, for the next
+    /// attempt to take its place. Runs reclaiming the same lock take turns, holding an OS lock on
+    /// a file beside it that's released even if they die, so that one that read the stale lock
+    /// just before another reclaimed it sees the new lock and leaves it be, rather than removing
+    /// it and leaving two runs holding the lock.
+    fn reclaim(path: &Path, stale: &str) -> io::Result<()> {
+        let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".reclaim");
+        let turn = File::create(path.with_file_name(name))?;
+        turn.lock()?;
+        match fs::read_to_string(path) {
+            Ok(contents) if contents == stale => match fs::remove_file(path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            },
+            // Reclaimed and taken by another run, or released, since it was read.
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // Signal 0 only checks whether the process exists. EPERM means it does but belongs to
+    // another user.
+    let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    exists || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a portable liveness check, assume the holder is running so a lock is never stolen.
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Arc, Barrier};
+
+    fn lock_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sloop-lock-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("state").join(LOCK_FILE_NAME)
+    }
+
+    fn write_lock(path: &Path, pid: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{}\n2024-01-01T00:00:00+00:00\n", pid)).unwrap();
+    }
+
+    #[test]
+    fn records_holder_and_removes_lock_when_dropped() {
+        let path = lock_path("drop");
+        let lock = RunLock::acquire(&path, false).unwrap();
+        let holder = Holder::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holder.pid, process::id());
+        drop(lock);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn fails_when_lock_is_held() {
+        let path = lock_path("contended");
+        write_lock(&path, 1234);
+        let err = RunLock::acquire_with(&path, false, |_| true).unwrap_err();
+        assert!(
+            err.starts_with(
+                "Another sloop run (pid 1234, started at 2024-01-01T00:00:00+00:00) is in progress"
+            ),
+            "{}",
+            err
+        );
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn reclaims_lock_from_dead_process() {
+        let path = lock_path("stale");
+        write_lock(&path, 1234);
+        let _lock = RunLock::acquire_with(&path, false, |pid| pid != 1234).unwrap();
+        let holder = Holder::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holder.pid, process::id());
+    }
+
+    #[test]
+    fn only_one_of_two_runs_reclaiming_a_lock_takes_it() {
+        let path = lock_path("race");
+        for _ in 0..200 {
+            write_lock(&path, 1234);
+            let start = Arc::new(Barrier::new(2));
+            let runs: Vec<_> = (0..2)
+                .map(|_| {
+                    let path = path.clone();
+                    let start = Arc::clone(&start);
+                    thread::spawn(move || {
+                        start.wait();
+                        RunLock::acquire_with(&path, false, |pid| pid != 1234)
+                    })
+                })
+                .collect();
+            let results: Vec<_> = runs.into_iter().map(|run| run.join().unwrap()).collect();
+            let taken = results.iter().filter(|result| result.is_ok()).count();
+            assert_eq!(taken, 1, "{:?}", results);
+            drop(results);
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn reclaims_unreadable_lock() {
+        let path = lock_path("garbage");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "not a lock").unwrap();
+        assert!(RunLock::acquire_with(&path, false, |_| true).is_ok());
+    }
+
+    #[test]
+    fn waits_for_lock_to_be_released() {
+        let path = lock_path("wait");
+        let lock = RunLock::acquire(&path, false).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let waiter = {
+            let path = path.clone();
+            thread::spawn(move || {
+                tx.send(()).unwrap();
+                RunLock::acquire(&path, true).map(|_| ())
+            })
+        };
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        drop(lock);
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detects_dead_processes() {
+        assert!(process_alive(process::id()));
+        let mut child = process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!process_alive(pid));
+    }
+}
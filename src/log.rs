@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// Destination for progress lines, so a run can report to the terminal or stream to a daemon
+/// client without changing the code doing the work. Shared with upload worker threads.
+pub type Log = Arc<dyn Fn(&str) + Send + Sync>;
+
+thread_local! {
+    /// Where `message` sends lines on this thread instead of stderr, set by `redirect`.
+    static MESSAGES: RefCell<Option<Log>> = const { RefCell::new(None) };
+}
+
+/// Prints each line to stdout.
+pub fn stdout() -> Log {
+    Arc::new(|line| println!("{}", line))
+}
+
+/// Reports a line about how the run is going that isn't part of its output, such as a warning
+/// or the number of files found: to stderr, or to the log `redirect` gave this thread.
+pub fn message(line: &str) {
+    let redirected = MESSAGES.with(|messages| messages.borrow().clone());
+    match redirected {
+        Some(log) => log(line),
+        None => eprintln!("{}", line),
+    }
+}
+
+/// Runs `f` with the `message`s it reports on this thread sent to `log`, e.g. so that a daemon
+/// client sees the warnings of the run it asked for rather than the daemon's stderr. Messages
+/// from threads `f` starts still go to stderr.
+pub fn redirect<T>(log: &Log, f: impl FnOnce() -> T) -> T {
+    /// Puts back the previous destination however `f` ends, panics included.
+    struct Restore(Option<Log>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            MESSAGES.with(|messages| *messages.borrow_mut() = previous);
+        }
+    }
+
+    let previous = MESSAGES.with(|messages| messages.borrow_mut().replace(Arc::clone(log)));
+    let _restore = Restore(previous);
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn messages_go_to_the_redirected_log_until_it_returns() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log: Log = {
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line| lines.lock().unwrap().push(line.to_owned()))
+        };
+        redirect(&log, || {
+            message("Found 2 files in Disc 1");
+            std::thread::spawn(|| message("from another thread"))
+                .join()
+                .unwrap();
+        });
+        message("after");
+        assert_eq!(*lines.lock().unwrap(), ["Found 2 files in Disc 1"]);
+    }
+}
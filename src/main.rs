@@ -1,90 +1,2087 @@
-use std::fs::File;
-use std::path::PathBuf;
+use hook::Stage;
+use outcome::{Failure, Outcome, Published};
+use serde_json::json;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use structopt::clap::ErrorKind;
 use structopt::StructOpt;
+use upload::Uploader;
 
+mod cancel;
+mod category;
+mod cdn;
+mod check;
+mod config;
+mod daemon;
+mod discover;
 mod feed;
+mod hook;
+mod http;
+mod human;
+mod listing;
+mod lock;
+mod log;
+mod manifest;
+mod mime;
+mod outcome;
+mod output;
+mod paths;
+mod plan;
+mod progress;
+mod secret;
+mod stats;
+mod timezone;
 mod upload;
+mod urls;
+mod version;
+mod warning;
+
+#[derive(Debug, StructOpt)]
+struct S3Args {
+    /// AWS region, or any region name the service accepts when --endpoint-url is given
+    #[structopt(long)]
+    region: Option<String>,
+    #[structopt(long)]
+    bucket: Option<String>,
+    /// Use an S3-compatible API at this URL instead of AWS, e.g. a MinIO server
+    #[structopt(long, alias = "endpoint", env = "AWS_ENDPOINT_URL")]
+    endpoint_url: Option<String>,
+    /// The S3-compatible service the bucket is with, which sets its endpoint, how objects are
+    /// addressed and made public, and where they're served from. --endpoint-url and the
+    /// addressing options override it
+    #[structopt(long, possible_values = upload::Provider::VALUES)]
+    provider: Option<upload::Provider>,
+    /// With --provider r2, the Cloudflare account ID the endpoint is under
+    #[structopt(long, value_name = "ID")]
+    account_id: Option<String>,
+    /// Use path-style URLs ({endpoint}/{bucket}/{key}) for uploaded objects. This is the default
+    /// with --endpoint-url
+    #[structopt(long)]
+    path_style: bool,
+    /// With --endpoint-url or --provider, use virtual-hosted URLs ({bucket}.{endpoint}/{key})
+    /// instead
+    #[structopt(long, conflicts_with = "path-style")]
+    virtual_hosted_style: bool,
+    /// Use the old dash-style AWS endpoint (s3-{region}.amazonaws.com) in URLs, to keep the
+    /// URLs in an existing feed unchanged
+    #[structopt(long, conflicts_with = "endpoint-url")]
+    legacy_endpoint: bool,
+    /// Link to objects at this URL, e.g. a CloudFront distribution or custom domain serving the
+    /// bucket, instead of the bucket's own. Keys, and where files are uploaded, stay the same
+    #[structopt(long, value_name = "URL", parse(try_from_str = parse_base_url))]
+    public_url: Option<String>,
+    /// Put objects in this folder of the bucket, e.g. books/candide
+    #[structopt(long, default_value = "")]
+    prefix: String,
+}
+
+impl S3Args {
+    /// `--region`, or the region `--provider` defaults to.
+    fn region(&self) -> Option<&str> {
+        self.region
+            .as_deref()
+            .or_else(|| self.provider.and_then(|provider| provider.default_region))
+    }
+
+    /// The bucket and the region it's in, which every command reaching the bucket needs.
+    fn location(&self) -> Result<(&str, &str), String> {
+        match (&self.bucket, self.region()) {
+            (Some(bucket), Some(region)) => Ok((bucket, region)),
+            (None, None) => Err("--bucket and --region are required".to_owned()),
+            (None, Some(_)) => Err("--bucket is required".to_owned()),
+            (Some(_), None) => Err("--region is required".to_owned()),
+        }
+    }
+
+    fn uploader(
+        &self,
+        options: upload::UploadOptions,
+    ) -> Result<upload::S3Uploader, upload::UploadError> {
+        let (bucket, region) = self.location().map_err(upload::UploadError::invalid)?;
+        let endpoint = self
+            .endpoint(region)
+            .map_err(upload::UploadError::invalid)?;
+        let path_style = match self.provider {
+            _ if self.path_style => true,
+            _ if self.virtual_hosted_style => false,
+            Some(provider) => provider.path_style,
+            None => self.endpoint_url.is_some(),
+        };
+        let config = upload::ClientConfig {
+            endpoint,
+            path_style,
+            legacy_endpoint: self.legacy_endpoint,
+            public_url: self.public_url.clone(),
+            prefix: self.prefix.clone(),
+            provider: self.provider,
+            ..Default::default()
+        };
+        let mut uploader = upload::S3Uploader::new(region, bucket, config, options)?;
+        uploader.set_cancel(cancel::current());
+        Ok(uploader)
+    }
+
+    /// The S3 API to use instead of AWS's own for `region`: `--endpoint-url`, or the one
+    /// `--provider` gives.
+    fn endpoint(&self, region: &str) -> Result<Option<String>, String> {
+        let provider = self.provider;
+        if self.account_id.is_some() && !provider.is_some_and(|p| p.takes_account_id()) {
+            return Err("--account-id is only for --provider r2".to_owned());
+        }
+        if self.legacy_endpoint && provider.is_some_and(|p| p.endpoint != upload::Endpoint::Aws) {
+            return Err("--legacy-endpoint is only for AWS's own endpoint".to_owned());
+        }
+        match (&self.endpoint_url, provider) {
+            (Some(endpoint_url), _) => Ok(Some(endpoint_url.clone())),
+            (None, Some(provider)) => provider.endpoint_url(region, self.account_id.as_deref()),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct UploadArgs {
+    /// Leave interrupted multipart uploads that don't match any file being sent. Only uploads
+    /// directly in the podcast's folder are ever aborted
+    #[structopt(long)]
+    keep_orphans: bool,
+    /// After uploading, check that a media file can be fetched with an HTTP range request
+    #[structopt(long)]
+    verify_ranges: bool,
+    /// Upload files larger than this many MiB in parts
+    #[structopt(long, default_value = "100")]
+    multipart_threshold: u64,
+    /// Size in MiB of each part of a multipart upload (at least 5)
+    #[structopt(long, default_value = "8", parse(try_from_str = parse_part_size))]
+    part_size: u64,
+    /// Maximum number of files to upload at once
+    #[structopt(long, default_value = "4", value_name = "N")]
+    concurrency: usize,
+    /// Don't show progress bars while uploading
+    #[structopt(short, long)]
+    quiet: bool,
+    /// Print what would be created and uploaded without changing anything in S3
+    #[structopt(long)]
+    dry_run: bool,
+    /// If another run is publishing to the same bucket, wait for it to finish instead of failing
+    #[structopt(long)]
+    wait: bool,
+    /// How to make uploaded objects public: a bucket-wide read policy, a public-read ACL on each
+    /// object (for buckets with other, private content), or none. Defaults to bucket-policy, or
+    /// whatever --provider supports
+    #[structopt(long, possible_values = upload::PublicMode::VALUES)]
+    public_mode: Option<upload::PublicMode>,
+    /// If the bucket's Block Public Access settings would stop --public-mode working, turn them
+    /// off instead of failing
+    #[structopt(long)]
+    allow_public: bool,
+    /// Don't create the bucket; it must already exist, e.g. because it's managed with Terraform
+    #[structopt(long)]
+    no_create_bucket: bool,
+    /// Don't change the bucket's policy or Block Public Access settings
+    #[structopt(long)]
+    no_policy: bool,
+    /// Shorthand for --no-create-bucket --no-policy, for buckets managed elsewhere
+    #[structopt(long)]
+    existing_bucket: bool,
+    /// Keep the bucket private and link to objects with presigned URLs, which expire. Overrides
+    /// --public-mode
+    #[structopt(long)]
+    private: bool,
+    /// How long presigned URLs last with --private, e.g. 12h or 7d (at most 7d)
+    #[structopt(long, default_value = "7d", parse(try_from_str = human::parse_duration))]
+    expires: Duration,
+    /// Have S3 encrypt every uploaded object with keys it manages (aes256) or with AWS KMS
+    /// (aws:kms)
+    #[structopt(long, possible_values = upload::Sse::VALUES)]
+    sse: Option<upload::Sse>,
+    /// The KMS key to encrypt with, e.g. its ARN, instead of the account's default. Needs
+    /// --sse aws:kms
+    #[structopt(long, value_name = "KEY_ID", requires = "sse")]
+    sse_kms_key_id: Option<String>,
+    /// Storage class for episodes: STANDARD, STANDARD_IA, ONEZONE_IA or INTELLIGENT_TIERING
+    #[structopt(long, value_name = "CLASS")]
+    storage_class: Option<upload::StorageClass>,
+    /// Storage class for feeds and images, which are fetched on every poll. Defaults to STANDARD
+    #[structopt(long, value_name = "CLASS")]
+    feed_storage_class: Option<upload::StorageClass>,
+    /// Cache-Control header for feeds, which podcast apps poll. Empty for none
+    #[structopt(long, default_value = upload::FEED_CACHE_CONTROL, value_name = "VALUE")]
+    feed_cache_control: String,
+    /// Cache-Control header for episodes, images and other files, which don't change once
+    /// published. Empty for none
+    #[structopt(long, default_value = upload::MEDIA_CACHE_CONTROL, value_name = "VALUE")]
+    media_cache_control: String,
+    /// The order to upload files in: smallest first (size), largest first, or as given
+    /// (input). Feeds are always uploaded last
+    #[structopt(long, default_value = "size", possible_values = plan::UploadOrder::VALUES)]
+    upload_order: plan::UploadOrder,
+    /// After uploading, delete the objects in the bucket (under --prefix) that weren't part of
+    /// this run, e.g. episodes no longer in the feed. Objects in subfolders are kept
+    #[structopt(long)]
+    prune: bool,
+    /// Upload every file, even those already published unchanged, e.g. so that a new
+    /// Cache-Control header, storage class, encryption or ACL reaches them
+    #[structopt(long)]
+    force: bool,
+    /// Have S3 deliver the bucket's access logs to LOG-BUCKET, under the podcast bucket's name
+    /// or the given prefix, for `sloop stats --from-logs`
+    #[structopt(long, value_name = "LOG-BUCKET[/PREFIX]")]
+    enable_access_logs: Option<upload::LogTarget>,
+    /// Print sizes in bytes and durations in seconds instead of e.g. `1.4 GiB` and `1h 23m`
+    #[structopt(long)]
+    raw_numbers: bool,
+}
+
+impl UploadArgs {
+    /// The upload options for the bucket, which is with `provider` if given.
+    fn options(&self, provider: Option<upload::Provider>) -> upload::UploadOptions {
+        upload::UploadOptions {
+            keep_orphans: self.keep_orphans,
+            multipart_threshold: self.multipart_threshold * MIB,
+            part_size: self.part_size * MIB,
+            concurrency: self.concurrency,
+            dry_run: self.dry_run,
+            numbers: self.numbers(),
+            public_mode: match (self.private, self.public_mode, provider) {
+                (true, _, _) => upload::PublicMode::None,
+                (false, Some(public_mode), _) => public_mode,
+                (false, None, Some(provider)) => provider.default_public_mode(),
+                (false, None, None) => upload::PublicMode::BucketPolicy,
+            },
+            allow_public: self.allow_public,
+            skip_create_bucket: self.no_create_bucket || self.existing_bucket,
+            skip_policy: self.no_policy || self.existing_bucket,
+            presign: if self.private {
+                Some(self.expires)
+            } else {
+                None
+            },
+            strict: warning::is_strict(),
+            sse: self.sse,
+            sse_kms_key_id: self.sse_kms_key_id.clone(),
+            storage_class: self.storage_class,
+            feed_storage_class: self.feed_storage_class,
+            feed_cache_control: self.feed_cache_control.clone(),
+            media_cache_control: self.media_cache_control.clone(),
+            prune: self.prune,
+            force: self.force,
+            upload_order: self.upload_order,
+            access_logs: self.enable_access_logs.clone(),
+        }
+    }
+
+    fn numbers(&self) -> human::Numbers {
+        human::Numbers {
+            raw: self.raw_numbers,
+        }
+    }
+
+    /// Reminds the user to run `sloop resign` before presigned URLs expire.
+    fn warn_of_expiry(&self) {
+        if self.private && !self.dry_run {
+            eprintln!(
+                "These URLs expire in {}; run `sloop resign` with the same options before then",
+                human::format_duration(self.expires)
+            );
+        }
+    }
+
+    /// The log and progress reporter for an upload run from the terminal.
+    fn reporters(&self) -> (log::Log, Arc<dyn progress::Progress>) {
+        if self.quiet || self.dry_run {
+            return (log::stdout(), Arc::new(progress::NoProgress));
+        }
+        let progress = Arc::new(progress::TerminalProgress::new(self.numbers()));
+        (progress.log(), progress)
+    }
+}
+
+/// Locks out other runs publishing to the same bucket and prefix until the returned lock is
+/// dropped.
+fn lock_bucket(
+    paths: &paths::Paths,
+    s3_args: &S3Args,
+    wait: bool,
+) -> Result<lock::RunLock, Failure> {
+    let (bucket, _) = s3_args.location().map_err(Failure::invalid)?;
+    let podcast = match s3_args.prefix.trim_matches('/') {
+        "" => bucket.to_owned(),
+        prefix => format!("{}/{}", bucket, prefix),
+    };
+    lock_podcast(paths, &podcast, wait)
+}
+
+/// Locks out other runs publishing `podcast` until the returned lock is dropped.
+fn lock_podcast(paths: &paths::Paths, podcast: &str, wait: bool) -> Result<lock::RunLock, Failure> {
+    let path = paths.podcast_state_dir(podcast).join(lock::LOCK_FILE_NAME);
+    lock::RunLock::acquire(&path, wait).map_err(Failure::failed)
+}
+
+const MIB: u64 = 1024 * 1024;
+
+fn parse_part_size(value: &str) -> Result<u64, String> {
+    let size: u64 = value.parse().map_err(|e| format!("{}", e))?;
+    // S3 rejects parts smaller than 5 MiB, other than the last.
+    if size < 5 {
+        return Err("part size must be at least 5 MiB".to_owned());
+    }
+    Ok(size)
+}
+
+fn parse_start_date(value: &str) -> Result<chrono::NaiveDate, String> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a date like 2024-03-01", value))
+}
+
+fn parse_time_of_day(value: &str) -> Result<chrono::NaiveTime, String> {
+    chrono::NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| format!("{} is not a time of day like 06:00", value))
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let interval = human::parse_duration(value)?;
+    match feed::interval_duration(interval) {
+        Some(_) => Ok(interval),
+        None => Err(format!(
+            "{} is too long an interval to date episodes by",
+            value
+        )),
+    }
+}
+
+fn parse_season(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(season) if season > 0 => Ok(season),
+        _ => Err(format!(
+            "{} is not a season number; seasons start at 1",
+            value
+        )),
+    }
+}
+
+fn parse_max_items(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(max_items) if max_items > 0 => Ok(max_items),
+        _ => Err(format!(
+            "{} is not a number of items; feeds need at least 1",
+            value
+        )),
+    }
+}
+
+fn parse_base_url(value: &str) -> Result<String, String> {
+    let url = feed::parse_link(value)?;
+    Ok(url.trim_end_matches('/').to_owned())
+}
+
+fn read_description(path: &OsStr) -> Result<String, OsString> {
+    fs::read_to_string(path)
+        .map(|text| text.trim_end().to_owned())
+        .map_err(|e| format!("failed to read {}: {}", Path::new(path).display(), e).into())
+}
+
+// Where files are published: to the bucket, or to a directory a web server serves.
+#[derive(Debug, StructOpt)]
+struct BackendArgs {
+    /// Where to publish: s3 uploads to --bucket; local copies the files into --output-dir, and
+    /// sftp uploads them to --remote-dir on --host, for a web server to serve at --public-url
+    #[structopt(long, default_value = "s3", possible_values = upload::Backend::VALUES)]
+    backend: upload::Backend,
+    /// With --backend local, the directory to copy the feeds and files into, under any --prefix
+    #[structopt(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+    /// With --backend sftp, the server to upload to, connected to as ssh would: with the
+    /// settings ~/.ssh/config gives the host and keys from ssh-agent
+    #[structopt(long)]
+    host: Option<String>,
+    /// With --backend sftp, the SSH port, if not the one ~/.ssh/config gives or 22
+    #[structopt(long)]
+    port: Option<u16>,
+    /// With --backend sftp, who to log in as, if not the user ~/.ssh/config gives or yours
+    #[structopt(long)]
+    user: Option<String>,
+    /// With --backend sftp, the directory on --host to upload the feeds and files into, under
+    /// any --prefix, created if missing. Relative to the login directory unless absolute
+    #[structopt(long, value_name = "DIR")]
+    remote_dir: Option<String>,
+}
+
+impl BackendArgs {
+    /// Checks only the options of the backend in use were given.
+    fn check_options(&self) -> Result<(), Failure> {
+        let backend_options = [
+            (
+                upload::Backend::Local,
+                "output-dir",
+                self.output_dir.is_some(),
+            ),
+            (upload::Backend::Sftp, "host", self.host.is_some()),
+            (upload::Backend::Sftp, "port", self.port.is_some()),
+            (upload::Backend::Sftp, "user", self.user.is_some()),
+            (
+                upload::Backend::Sftp,
+                "remote-dir",
+                self.remote_dir.is_some(),
+            ),
+        ];
+        for (backend, option, given) in backend_options.iter() {
+            if *given && *backend != self.backend {
+                return Err(Failure::invalid(format!(
+                    "--{} is only for --backend {}",
+                    option,
+                    backend.name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks `--backend local` or `sftp` has a directory to publish files to and the URL it's
+    /// served at, as `--public-url`.
+    fn check_target(&self, s3_args: &S3Args) -> Result<(), Failure> {
+        let backend = self.backend.name();
+        let dir = match self.backend {
+            upload::Backend::Local => "--output-dir",
+            _ => "--remote-dir",
+        };
+        let missing = match self.backend {
+            upload::Backend::Local if self.output_dir.is_none() => {
+                Some("--output-dir, the directory to copy the files into")
+            }
+            upload::Backend::Sftp if self.host.is_none() => Some("--host, the server to upload to"),
+            upload::Backend::Sftp if self.remote_dir.is_none() => {
+                Some("--remote-dir, the directory on --host to upload the files into")
+            }
+            _ => None,
+        };
+        let problem = if let Some(missing) = missing {
+            format!("--backend {} needs {}", backend, missing)
+        } else if s3_args.public_url.is_none() {
+            format!(
+                "--backend {} needs --public-url, the URL {} is served at",
+                backend, dir
+            )
+        } else {
+            return Ok(());
+        };
+        Err(Failure::invalid(problem))
+    }
+
+    /// Where `--backend sftp` uploads to, once `--host` and `--remote-dir` are given.
+    fn sftp_target(&self) -> Option<upload::SftpTarget> {
+        Some(upload::SftpTarget {
+            host: self.host.clone()?,
+            port: self.port,
+            user: self.user.clone(),
+            remote_dir: self.remote_dir.clone()?,
+        })
+    }
+
+    /// What files are published through: the bucket's uploader, or with `--backend local` one
+    /// copying into `--output-dir`, or with `--backend sftp` one uploading to `--host`.
+    fn uploader(
+        &self,
+        s3_args: &S3Args,
+        upload_args: &UploadArgs,
+    ) -> Result<Box<dyn Uploader>, upload::UploadError> {
+        if let (upload::Backend::Sftp, Some(target), Some(public_url)) =
+            (self.backend, self.sftp_target(), &s3_args.public_url)
+        {
+            return Ok(Box::new(upload::SftpUploader::new(
+                target,
+                public_url,
+                &s3_args.prefix,
+                upload_args.dry_run,
+                upload_args.force,
+                upload_args.numbers(),
+            )));
+        }
+        match (self.backend, &self.output_dir, &s3_args.public_url) {
+            (upload::Backend::Local, Some(output_dir), Some(public_url)) => {
+                Ok(Box::new(upload::LocalUploader::new(
+                    output_dir,
+                    public_url,
+                    &s3_args.prefix,
+                    upload_args.dry_run,
+                    upload_args.force,
+                    upload_args.numbers(),
+                )))
+            }
+            _ => Ok(Box::new(
+                s3_args.uploader(upload_args.options(s3_args.provider))?,
+            )),
+        }
+    }
+
+    /// Locks out other runs publishing to the same bucket and prefix, or directory, until the
+    /// returned lock is dropped.
+    fn lock(
+        &self,
+        paths: &paths::Paths,
+        s3_args: &S3Args,
+        wait: bool,
+    ) -> Result<lock::RunLock, Failure> {
+        if let (upload::Backend::Sftp, Some(target)) = (self.backend, self.sftp_target()) {
+            let podcast = format!("{}:{}", target.host, target.remote_dir);
+            return lock_podcast(paths, &podcast, wait);
+        }
+        match (self.backend, &self.output_dir) {
+            (upload::Backend::Local, Some(output_dir)) => {
+                let output_dir =
+                    env::current_dir()
+                        .map(|dir| dir.join(output_dir))
+                        .map_err(|e| {
+                            Failure::failed(format!(
+                                "Failed to find {}: {}",
+                                output_dir.display(),
+                                e
+                            ))
+                        })?;
+                lock_podcast(paths, &output_dir.display().to_string(), wait)
+            }
+            _ => lock_bucket(paths, s3_args, wait),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct FeedArgs {
+    /// Required unless the manifest gives one
+    #[structopt(long, required_unless = "manifest")]
+    title: Option<String>,
+    /// Short description shown in podcast apps' list views
+    #[structopt(long)]
+    subtitle: Option<String>,
+    /// Who makes the podcast, shown by podcast apps as itunes:author
+    #[structopt(long)]
+    author: Option<String>,
+    /// What the podcast is about. Defaults to the subtitle, or else the title
+    #[structopt(long)]
+    description: Option<String>,
+    /// File to read the description from, for text too long for the command line
+    #[structopt(
+        long,
+        value_name = "PATH",
+        parse(try_from_os_str = read_description),
+        conflicts_with = "description"
+    )]
+    description_file: Option<String>,
+    /// Language code of the podcast, e.g. en-AU [default: en]
+    #[structopt(long)]
+    language: Option<String>,
+    /// The podcast's web site. Defaults to the URL of the folder the files are published to
+    #[structopt(long, value_name = "URL", parse(try_from_str = feed::parse_link))]
+    link: Option<String>,
+    /// Apple Podcasts category, e.g. "Arts" or "Arts:Books" for a subcategory. Repeat for more
+    /// than one
+    #[structopt(long, number_of_values = 1)]
+    category: Vec<category::Category>,
+    #[structopt(long)]
+    image: Option<PathBuf>,
+    /// Mark the podcast as containing explicit content
+    #[structopt(long)]
+    explicit: bool,
+    /// Let Apple Podcasts and other directories list the podcast, by leaving out the
+    /// itunes:block tag that otherwise keeps it private
+    #[structopt(long)]
+    listed: bool,
+    /// Mark episodes whose file names match this glob as explicit
+    #[structopt(long, number_of_values = 1)]
+    explicit_files: Vec<glob::Pattern>,
+    /// Date only the newest N episodes within the last week so apps announce just those as new
+    #[structopt(long, value_name = "N")]
+    mark_new: Option<usize>,
+    /// Keep only the newest N episodes, by date, in the feed, dropping the oldest with --append.
+    /// Their files are still uploaded, so links to them keep working
+    #[structopt(long, value_name = "N", parse(try_from_str = parse_max_items))]
+    max_items: Option<usize>,
+    /// Date the first episode at midnight UTC on this day instead of today, so the dates stay
+    /// the same from run to run
+    #[structopt(long, value_name = "YYYY-MM-DD", parse(try_from_str = parse_start_date))]
+    start_date: Option<chrono::NaiveDate>,
+    /// Date each episode this long before the one above it, e.g. 12h, 2d or 1w. 0 dates them
+    /// all the same
+    #[structopt(long, default_value = "1d", parse(try_from_str = parse_interval))]
+    interval: Duration,
+    /// newest-first dates the first file the start date and each after it earlier;
+    /// oldest-first dates the last file the start date, so apps that sort by date play the
+    /// files in order. Either way the feed lists them in the order given
+    #[structopt(long, default_value = "newest-first", possible_values = feed::DateOrder::VALUES)]
+    order: feed::DateOrder,
+    /// Date episodes by the schedule of --start-date, --interval and --order, or by when each
+    /// file was last modified
+    #[structopt(long, default_value = "schedule", possible_values = feed::PubDateSource::VALUES)]
+    pub_date_from: feed::PubDateSource,
+    /// Write dates in this time zone, e.g. Australia/Sydney, or at this offset from UTC, e.g.
+    /// +10:00, instead of in UTC
+    #[structopt(long, value_name = "ZONE")]
+    timezone: Option<timezone::Timezone>,
+    /// Date episodes at this local time, e.g. 06:00, in the --timezone, instead of at midnight
+    /// UTC
+    #[structopt(long, value_name = "HH:MM", parse(try_from_str = parse_time_of_day))]
+    time_of_day: Option<chrono::NaiveTime>,
+    /// Write this as the feed's lastBuildDate instead of now, e.g. 2024-03-01T06:00:00Z, and
+    /// start the schedule on its day unless --start-date is given, so that runs on the same
+    /// files write the same feed
+    #[structopt(long, value_name = "DATE", parse(try_from_str = human::parse_date_time))]
+    build_date: Option<chrono::DateTime<chrono::Utc>>,
+    /// Title episodes after their file names, with underscores as spaces, or after the titles
+    /// in their ID3 or MP4 tags, falling back to their names
+    #[structopt(long, default_value = "name", possible_values = feed::TitleSource::VALUES)]
+    titles_from: feed::TitleSource,
+    /// Leave the track number out of titles made from file names, so
+    /// "03 - The Council of Elrond.mp3" is titled "The Council of Elrond"
+    #[structopt(long)]
+    strip_track_numbers: bool,
+    /// Make each episode's title from this, e.g. "{feed_title}, Part {n:02}". {title} is the
+    /// title from --titles-from, {stem} the file name without its extension, {total} the number
+    /// of episodes, {feed_title} the --title, and n, as in {n:02}, the episode's position
+    #[structopt(long, value_name = "TEMPLATE", default_value = "{title}")]
+    title_template: feed::TitleTemplate,
+    /// Describe each episode with the text in a file beside it with the same name and a .txt
+    /// or .md extension, e.g. Chapter_1.txt for Chapter_1.mp3
+    #[structopt(long, default_value = "none", possible_values = feed::DescriptionSource::VALUES)]
+    descriptions_from: feed::DescriptionSource,
+    /// Link each episode to a web page, e.g. https://example.com/{slug}. {slug}, {index} and
+    /// {name} are replaced by the episode's title, position and file name
+    #[structopt(long, value_name = "TEMPLATE")]
+    episode_link_template: Option<feed::LinkTemplate>,
+    /// When the files are in more than one directory, e.g. Disc 1/ and Disc 2/, make each
+    /// directory a season, numbered in order of name, and publish its files in a folder of the
+    /// same name
+    #[structopt(long)]
+    seasons_from_dirs: bool,
+    /// Start each title with its season's directory name, e.g. "Disc 1: Track 1"
+    #[structopt(long, requires = "seasons-from-dirs")]
+    season_titles: bool,
+    /// Put every episode in season N and number them in order
+    #[structopt(
+        long,
+        value_name = "N",
+        conflicts_with = "seasons-from-dirs",
+        parse(try_from_str = parse_season)
+    )]
+    season: Option<usize>,
+    /// Tell podcast apps to list episodes newest first (episodic) or from the first (serial).
+    /// Serial feeds number their episodes in order
+    #[structopt(long, possible_values = feed::PodcastType::VALUES)]
+    podcast_type: Option<feed::PodcastType>,
+    /// Publish the cover art embedded in each file as art/<file name>.jpg, e.g.
+    /// art/Chapter_1.mp3.jpg, and give its episode that image. Episodes without art show the
+    /// podcast's --image
+    #[structopt(long)]
+    extract_art: bool,
+    /// Give the file NAME this guid instead of one derived from its URL, e.g. to keep the GUIDs
+    /// of a feed published elsewhere. With --seasons-from-dirs, NAME includes the season's
+    /// folder, e.g. "Disc 1/track.mp3". Repeat for each file
+    #[structopt(long, number_of_values = 1, value_name = "NAME=GUID")]
+    guid: Vec<String>,
+    #[structopt(flatten)]
+    s3_args: S3Args,
+    /// Write a feed linking to the files at this URL, e.g. https://example.com/podcast, instead
+    /// of in a bucket, so no AWS settings are needed. The files are published there by other
+    /// means, so this can't be used with --upload
+    #[structopt(long, value_name = "URL", parse(try_from_str = parse_base_url))]
+    base_url: Option<String>,
+    #[structopt(flatten)]
+    backend_args: BackendArgs,
+    /// File to write the feed to. Repeat to also write other formats, chosen by extension: .json
+    /// for JSON Feed and anything else for RSS
+    #[structopt(short, long, number_of_values = 1, required = true)]
+    out: Vec<PathBuf>,
+    /// Name to publish each --out as, in the same order, under any --prefix. This decides the
+    /// feed's URL. Defaults to feed.xml for RSS and feed.json for JSON Feed, whatever the local
+    /// file is called
+    #[structopt(long, number_of_values = 1, value_name = "NAME")]
+    feed_key: Vec<String>,
+    /// Fail instead of creating missing directories for the output file
+    #[structopt(long)]
+    no_create_dirs: bool,
+    /// Keep the items of the existing RSS feed, read from the first RSS --out or else downloaded
+    /// from the bucket, as they are and only add episodes for files it doesn't link to yet
+    #[structopt(long)]
+    append: bool,
+    /// With --append, add files as new episodes even when they're ones the feed links to
+    /// under another name, rather than pointing the old items at their new names
+    #[structopt(long, requires = "append")]
+    no_rename_detection: bool,
+    /// Upload the feed even if it has no episodes
+    #[structopt(long)]
+    allow_empty: bool,
+    /// Skip checking the generated feed for problems before uploading it
+    #[structopt(long)]
+    no_self_check: bool,
+    /// After uploading, check that the first episode can be fetched from the URL in the feed
+    #[structopt(long)]
+    verify_feed: bool,
+    /// After uploading, invalidate the feeds, and the image if it changed, in this CloudFront
+    /// distribution, so listeners aren't served stale cached copies
+    #[structopt(long, value_name = "ID")]
+    cloudfront_distribution_id: Option<String>,
+    /// Start the CloudFront invalidation without waiting for it to complete
+    #[structopt(long, requires = "cloudfront-distribution-id")]
+    no_wait: bool,
+    /// Print the media files in feed order, with their keys and titles, before publishing
+    #[structopt(short, long)]
+    verbose: bool,
+    /// Skip files modified less than this many seconds ago, which may still be being written
+    #[structopt(long, value_name = "SECONDS", default_value = "30")]
+    settle_time: u64,
+    /// Publish every file given, even those that look unfinished: partial downloads (.part,
+    /// .partial, .tmp, .crdownload), empty files and files modified within --settle-time
+    #[structopt(long)]
+    include_unsettled: bool,
+    /// Put the files in order by path before publishing them: none keeps the order given;
+    /// natural compares numbers by value and ignores case, so Chapter 2 comes before Chapter 10
+    #[structopt(long, default_value = "none", possible_values = feed::FileSort::VALUES)]
+    sort: feed::FileSort,
+    /// Take only files with these extensions from the directories given, instead of those
+    /// with a known audio type
+    #[structopt(long, use_delimiter = true, value_name = "EXT")]
+    include_ext: Vec<String>,
+    /// Skip, with a warning, files that can't be published, e.g. those without an extension
+    /// or whose names aren't UTF-8, instead of stopping the run
+    #[structopt(long)]
+    skip_invalid: bool,
+    /// Run COMMAND with the shell at STAGE: pre_generate, post_generate (given the feed files
+    /// as arguments), pre_upload or post_upload (given a JSON report on stdin). Failing pre_
+    /// hooks stop the run. Repeat for more
+    #[structopt(long, number_of_values = 1, value_name = "STAGE=COMMAND")]
+    hook: Vec<hook::Hook>,
+    /// Stop hooks still running after this long, e.g. 90s or 5m
+    #[structopt(long, default_value = "5m", parse(try_from_str = human::parse_duration))]
+    hook_timeout: Duration,
+    /// Read the podcast's details, and each episode's title, description, date and whether
+    /// it's explicit, from this TOML file. What it gives overrides these options
+    #[structopt(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+    /// The manifest's episodes, once `apply_manifest` has read it.
+    #[structopt(skip)]
+    manifest_episodes: Option<manifest::Manifest>,
+    /// Make the feed from the audio files already in the bucket under --prefix, e.g. uploaded
+    /// by other tools, instead of from files given, and upload just the feed and any --image
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "files",
+            "manifest",
+            "extract-art",
+            "seasons-from-dirs",
+            "strip-track-numbers",
+            "guid",
+        ]
+    )]
+    from_bucket: bool,
+    #[structopt(flatten)]
+    upload_args: UploadArgs,
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
+}
+
+impl FeedArgs {
+    /// Reads `--manifest`, if given, replacing the options its channel gives and keeping its
+    /// episodes for `inputs`.
+    fn apply_manifest(&mut self) -> Result<(), Failure> {
+        let path = match &self.manifest {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut manifest = manifest::Manifest::read(path).map_err(Failure::invalid)?;
+        if let Some(categories) = manifest.categories().map_err(Failure::invalid)? {
+            self.category = categories;
+        }
+        let channel = std::mem::take(&mut manifest.channel);
+        if channel.description.is_some() {
+            self.description = channel.description;
+            self.description_file = None;
+        }
+        self.title = channel.title.or(self.title.take());
+        self.author = channel.author.or(self.author.take());
+        self.image = channel.image.or(self.image.take());
+        if self.title.is_none() {
+            return Err(Failure::invalid(format!(
+                "The podcast needs a title: pass --title or give one in the [channel] of {}",
+                path.display()
+            )));
+        }
+        self.manifest_episodes = Some(manifest);
+        Ok(())
+    }
+
+    /// The podcast's title, which `--title` or the manifest gives.
+    fn feed_title(&self) -> &str {
+        self.title.as_deref().unwrap_or_default()
+    }
+
+    /// Checks the feed has somewhere for its URLs to point: the bucket, which `publishing` needs,
+    /// or else `--base-url`, or with `--backend local` or `sftp` the directory the files are
+    /// published to.
+    fn check_target(&self, publishing: bool) -> Result<(), Failure> {
+        self.backend_args.check_options()?;
+        if self.backend_args.backend != upload::Backend::S3 {
+            return self.check_served_target();
+        }
+        let problem = match &self.base_url {
+            Some(_) if publishing => {
+                "--base-url writes the feed without a bucket, so can't be used with --upload, \
+                 --plan, --dry-run, resign or daemon"
+            }
+            Some(_) if self.from_bucket => {
+                "--from-bucket lists the bucket, so needs --bucket and --region rather than \
+                 --base-url"
+            }
+            Some(_) if self.s3_args.public_url.is_some() => {
+                "--base-url and --public-url both say where the files are served, so give just \
+                 one"
+            }
+            Some(_) if self.upload_args.private => {
+                "--private links to the bucket with presigned URLs, so can't be used with \
+                 --base-url"
+            }
+            Some(_) => return Ok(()),
+            None if publishing => {
+                return self
+                    .s3_args
+                    .location()
+                    .map(|_| ())
+                    .map_err(Failure::invalid)
+            }
+            None => match (&self.s3_args.bucket, self.s3_args.region()) {
+                (Some(_), Some(_)) => return Ok(()),
+                (None, None) => {
+                    "Pass --bucket and --region for the feed to link to the bucket, or \
+                     --base-url to write it without one"
+                }
+                (None, Some(_)) => "--bucket is required, or --base-url instead of --region",
+                (Some(_), None) => "--region is required, or --base-url instead of --bucket",
+            },
+        };
+        Err(Failure::invalid(problem.to_owned()))
+    }
+
+    /// Checks `--backend local` or `sftp` has a directory to publish files to and the URL it's
+    /// served at, and isn't given options that need a bucket.
+    fn check_served_target(&self) -> Result<(), Failure> {
+        let backend = self.backend_args.backend.name();
+        if self.base_url.is_some() {
+            return Err(Failure::invalid(format!(
+                "--backend {} links to --public-url, so can't be used with --base-url",
+                backend
+            )));
+        }
+        self.backend_args.check_target(&self.s3_args)?;
+        let problem = if self.from_bucket {
+            format!(
+                "--from-bucket lists the bucket, so can't be used with --backend {}",
+                backend
+            )
+        } else if self.upload_args.private {
+            format!(
+                "--private links to the bucket with presigned URLs, so can't be used with \
+                 --backend {}",
+                backend
+            )
+        } else {
+            return Ok(());
+        };
+        Err(Failure::invalid(problem))
+    }
+
+    /// What publishing goes through: the bucket's uploader, or with `--backend local` one
+    /// copying into `--output-dir`, or with `--backend sftp` one uploading to `--host`.
+    /// `check_target` must have passed.
+    fn uploader(&self) -> Result<Box<dyn Uploader>, Failure> {
+        self.backend_args
+            .uploader(&self.s3_args, &self.upload_args)
+            .map_err(|e| upload_failure("", e))
+    }
+
+    /// Locks out other runs publishing to the same bucket and prefix, or directory, until the
+    /// returned lock is dropped.
+    fn lock(&self, paths: &paths::Paths) -> Result<lock::RunLock, Failure> {
+        self.backend_args
+            .lock(paths, &self.s3_args, self.upload_args.wait)
+    }
+
+    /// The media files in feed order, with any hosted media the manifest lists among them, as
+    /// `--list-files` prints them, and with `--extract-art` the directory their art was written
+    /// to, which has to be kept until the art is uploaded as it's removed when dropped.
+    fn inputs(&self) -> Result<(Vec<feed::Entry>, Option<output::TempDir>), Failure> {
+        let mut files = self.settled_files()?;
+        self.sort.sort(&mut files);
+        if let Some(manifest) = &self.manifest_episodes {
+            files = manifest.files(files).map_err(Failure::invalid)?;
+        }
+        let mut inputs = feed::resolve_inputs(&files, &self.s3_args.prefix, self.skip_invalid)
+            .map_err(Failure::invalid)?;
+        if self.strip_track_numbers {
+            feed::strip_track_numbers(&mut inputs);
+        }
+        if self.seasons_from_dirs {
+            feed::assign_seasons(&mut inputs, self.season_titles);
+        }
+        feed::check_keys(&inputs).map_err(Failure::invalid)?;
+        feed::assign_guids(&mut inputs, &self.guid).map_err(Failure::invalid)?;
+        if let Some(manifest) = &self.manifest_episodes {
+            manifest.apply(&mut inputs);
+        }
+        let art_dir = match self.extract_art {
+            true => {
+                let dir = output::TempDir::create("art").map_err(|e| {
+                    Failure::failed(format!("Failed to create a directory for art: {}", e))
+                })?;
+                feed::extract_art(&mut inputs, dir.path()).map_err(Failure::failed)?;
+                Some(dir)
+            }
+            false => None,
+        };
+        let entries = match &self.manifest_episodes {
+            Some(manifest) => manifest.entries(inputs),
+            None => inputs.into_iter().map(feed::Entry::File).collect(),
+        };
+        Ok((entries, art_dir))
+    }
+
+    /// With `--from-bucket`, the audio files already in the bucket under the prefix, in
+    /// `--sort` order, or else by key.
+    fn bucket_files(&self) -> Result<Vec<feed::RemoteFile>, Failure> {
+        let uploader = self
+            .s3_args
+            .uploader(self.upload_args.options(self.s3_args.provider))
+            .map_err(|e| upload_failure("", e))?;
+        let objects = uploader.list().map_err(|e| upload_failure("", e))?;
+        let files = feed::remote_files(objects, &uploader.folder(), &self.include_ext, self.sort);
+        let count = files.len();
+        log::message(&format!(
+            "Found {} {} in the bucket",
+            count,
+            if count == 1 { "file" } else { "files" }
+        ));
+        Ok(files)
+    }
+
+    /// Writes the feeds for the files given, or with `--from-bucket` for those in the bucket.
+    /// With `--base-url` they link to the files there, without reaching the bucket.
+    fn write_feeds(&self) -> Result<(), Failure> {
+        if let Some(base_url) = &self.base_url {
+            let urls = urls::ObjectUrls::public(base_url);
+            let (inputs, _art_dir) = self.inputs()?;
+            return self.generate(&inputs, urls, None).map(|_| ());
+        }
+        match self.from_bucket {
+            true => self.write_feed(&self.bucket_files()?),
+            false => self.write_feed(&self.inputs()?.0),
+        }
+        .map(|_| ())
+    }
+
+    /// Writes the feed files for `inputs`, returning the uploader their URLs point at and the episodes in them.
+    fn write_feed<M: feed::MediaFileLike + Clone>(
+        &self,
+        inputs: &[M],
+    ) -> Result<(Box<dyn Uploader>, Vec<feed::Episode>), Failure> {
+        let feed_keys = self.feed_keys()?;
+        let mut uploader = self.uploader()?;
+        for (out, key) in self.out.iter().zip(&feed_keys) {
+            uploader.upload_as(out, key);
+        }
+        for input in inputs {
+            if let (Some(path), Some(name)) = (input.path(), input.name()) {
+                uploader.upload_as(path, name);
+            }
+        }
+        for art in inputs.iter().filter_map(|input| input.art()) {
+            uploader.upload_as(&art.path, &art.name);
+        }
+        let urls = uploader.object_urls();
+        let episodes = self.generate(inputs, urls, Some(uploader.as_mut()))?;
+        Ok((uploader, episodes))
+    }
+
+    /// Writes the feed files for `inputs`, linking to them with `urls`, and returns the
+    /// episodes in them. Any `uploader` publishing them is told the lengths to expect, and has
+    /// the feed `--append` adds to if there's no local copy.
+    fn generate<M: feed::MediaFileLike + Clone>(
+        &self,
+        inputs: &[M],
+        urls: urls::ObjectUrls,
+        mut uploader: Option<&mut dyn Uploader>,
+    ) -> Result<Vec<feed::Episode>, Failure> {
+        let feed_urls: Vec<String> = self.feed_keys()?.iter().map(|key| urls.url(key)).collect();
+        let hooks = self.hooks();
+        let hook_env = self.hook_env(&feed_urls[0], inputs.len());
+        hooks
+            .run(Stage::PreGenerate, &hook_env, &[], None)
+            .map_err(Failure::invalid)?;
+        let feed = self.generator(urls);
+        let mut existing = match self.append {
+            true => self.existing_feed(uploader.as_deref())?,
+            false => None,
+        };
+        // Published files are compared with the renamed ones where the backend can tell
+        // without downloading them; elsewhere their lengths and durations are.
+        let same_contents = |path: &Path, name: &str| match uploader.as_deref() {
+            Some(uploader) => uploader.same_contents(path, name).map_err(|e| e.message),
+            None => Ok(None),
+        };
+        let same_contents: Option<&feed::SameContents> = match self.no_rename_detection {
+            true => None,
+            false => Some(&same_contents),
+        };
+        let mut episodes = match &mut existing {
+            Some(existing) => feed.new_episodes(inputs.to_vec(), existing, same_contents),
+            None => feed.episodes(inputs.to_vec()),
+        }
+        .map_err(|e| match feed.cancel.is_cancelled() {
+            true => Failure::cancelled(),
+            false => Failure::invalid(format!("Failed to create feed: {}", e)),
+        })?;
+        for (from, to) in existing.iter().flat_map(|existing| existing.renamed()) {
+            log::message(&format!(
+                "Pointing the item for {} at its new name, {}",
+                from, to
+            ));
+        }
+        let trimmed = match &mut existing {
+            Some(existing) => feed.trim_appended(existing, &mut episodes),
+            None => feed.trim(&mut episodes),
+        };
+        if trimmed > 0 {
+            let items = if trimmed == 1 { "item" } else { "items" };
+            log::message(&format!(
+                "Trimmed the {} oldest {} from the feed, keeping the newest {}",
+                trimmed,
+                items,
+                feed.max_items.unwrap_or_default()
+            ));
+        }
+        // Existing items keep the lengths they were published with, so only new ones are checked.
+        if let Some(uploader) = &mut uploader {
+            for (path, length) in feed.enclosure_lengths(inputs, &episodes) {
+                uploader.expect_length(path, length);
+            }
+        }
+        for (out, feed_url) in self.out.iter().zip(feed_urls) {
+            let format = feed::FeedFormat::from_path(out);
+            output::PartialFile::create(out, !self.no_create_dirs)
+                .and_then(|mut file| {
+                    match &existing {
+                        Some(existing) => feed.write_appended(
+                            existing,
+                            &episodes,
+                            format,
+                            Some(&feed_url),
+                            &mut file,
+                        ),
+                        None => feed.write(&episodes, format, Some(&feed_url), &mut file),
+                    }?;
+                    file.finish()
+                })
+                .map_err(|e| {
+                    Failure::failed(format!("Failed to create feed {}: {}", out.display(), e))
+                })?;
+        }
+        let episodes = match existing {
+            Some(existing) => [existing.episodes(), episodes].concat(),
+            None => episodes,
+        };
+        let feed_files: Vec<OsString> = self.out.iter().map(|out| out.into()).collect();
+        hooks
+            .run(Stage::PostGenerate, &hook_env, &feed_files, None)
+            .map_err(Failure::invalid)?;
+        Ok(episodes)
+    }
+
+    fn hooks(&self) -> hook::Hooks {
+        hook::Hooks {
+            hooks: self.hook.clone(),
+            timeout: self.hook_timeout,
+        }
+    }
+
+    /// The environment hooks run with: where the podcast is published and how many files it
+    /// has.
+    fn hook_env(&self, feed_url: &str, files: usize) -> Vec<(&'static str, String)> {
+        vec![
+            ("SLOOP_FEED_URL", feed_url.to_owned()),
+            (
+                "SLOOP_BUCKET",
+                self.s3_args.bucket.clone().unwrap_or_default(),
+            ),
+            ("SLOOP_PREFIX", self.s3_args.prefix.clone()),
+            ("SLOOP_FILES", files.to_string()),
+        ]
+    }
+
+    /// The RSS feed `--append` adds to: the first RSS `--out` if it exists locally, or else
+    /// the copy `uploader` published from it, or `None` if there's neither and the feed starts
+    /// afresh.
+    fn existing_feed(
+        &self,
+        uploader: Option<&dyn Uploader>,
+    ) -> Result<Option<feed::ExistingFeed>, Failure> {
+        let out = match self
+            .out
+            .iter()
+            .find(|out| feed::FeedFormat::from_path(out) == feed::FeedFormat::Rss)
+        {
+            Some(out) => out,
+            None => {
+                return Err(Failure::invalid(
+                    "--append needs an RSS --out to add to".to_owned(),
+                ))
+            }
+        };
+        let contents = match fs::read(out) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match uploader
+                .map(|uploader| uploader.download(out))
+                .transpose()
+                .map_err(|e| upload_failure("Failed to download the existing feed: ", e))?
+                .flatten()
+            {
+                Some(contents) => contents,
+                None => return Ok(None),
+            },
+            Err(err) => {
+                return Err(Failure::failed(format!(
+                    "Failed to read {}: {}",
+                    out.display(),
+                    err
+                )))
+            }
+        };
+        feed::ExistingFeed::parse(&contents)
+            .map(Some)
+            .map_err(|e| Failure::invalid(format!("Failed to read feed {}: {}", out.display(), e)))
+    }
+
+    /// The name each `--out` is published as: its `--feed-key`, or `feed.xml` or `feed.json`
+    /// by format, so renaming the local file doesn't move the feed.
+    fn feed_keys(&self) -> Result<Vec<String>, Failure> {
+        if self.feed_key.len() > self.out.len() {
+            return Err(Failure::invalid(format!(
+                "--feed-key was given {} times but there are only {} --out files",
+                self.feed_key.len(),
+                self.out.len()
+            )));
+        }
+        let mut keys: Vec<String> = Vec::new();
+        for (i, out) in self.out.iter().enumerate() {
+            let key = match self.feed_key.get(i) {
+                Some(key) => key.clone(),
+                None => match feed::FeedFormat::from_path(out) {
+                    feed::FeedFormat::Rss => "feed.xml".to_owned(),
+                    feed::FeedFormat::Json => "feed.json".to_owned(),
+                },
+            };
+            if key.is_empty() || key.contains('/') {
+                return Err(Failure::invalid(format!(
+                    "Feed key {:?} must be a file name; use --prefix for folders",
+                    key
+                )));
+            }
+            if keys.contains(&key) {
+                return Err(Failure::invalid(format!(
+                    "More than one --out would be published as {}; give each its own --feed-key",
+                    key
+                )));
+            }
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    fn generator(&self, urls: urls::ObjectUrls) -> feed::FeedGenerator {
+        feed::FeedGenerator {
+            title: self.feed_title().to_owned(),
+            subtitle: self.subtitle.clone(),
+            author: self.author.clone(),
+            description: self.description.clone().or(self.description_file.clone()),
+            language: self.language.clone(),
+            link: self.link.clone(),
+            categories: self.category.clone(),
+            urls,
+            image: self.image.clone().map(|path| feed::Image { path }),
+            explicit: if self.explicit { Some(true) } else { None },
+            listed: self.listed,
+            explicit_files: self.explicit_files.clone(),
+            mark_new: self.mark_new,
+            max_items: self.max_items,
+            start_date: self.start_date,
+            interval: Some(self.interval),
+            order: self.order,
+            pub_date_from: self.pub_date_from,
+            timezone: self.timezone.clone(),
+            time_of_day: self.time_of_day,
+            build_date: self.build_date,
+            titles_from: self.titles_from,
+            title_template: self.title_template.clone(),
+            descriptions_from: self.descriptions_from,
+            podcast_type: self.podcast_type,
+            season: self.season,
+            episode_link: self.episode_link_template.clone(),
+            cancel: cancel::current(),
+            skip_invalid: self.skip_invalid,
+        }
+    }
+
+    /// The files given, with directories expanded into the audio files in them, less any that
+    /// look like they're still being written, unless `--include-unsettled` is given. Each file
+    /// left out is reported.
+    fn settled_files(&self) -> Result<Vec<PathBuf>, Failure> {
+        let files = discover_files(&self.files, &self.include_ext, |path| {
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            extension.is_some_and(|extension| mime::audio_type(extension).is_some())
+        })?;
+        if self.include_unsettled {
+            return Ok(files);
+        }
+        let settle_time = Duration::from_secs(self.settle_time);
+        let now = SystemTime::now();
+        Ok(files
+            .into_iter()
+            .filter(
+                |path| match feed::unsettled_reason(path, settle_time, now) {
+                    Some(reason) => {
+                        warning::warn(&format!("skipping {}: {}", path.display(), reason));
+                        false
+                    }
+                    None => true,
+                },
+            )
+            .collect())
+    }
+
+    /// The title of each of `inputs`, as the feed gives them.
+    fn titles(&self, inputs: &[feed::Entry]) -> Vec<String> {
+        feed::episode_titles(
+            inputs,
+            self.titles_from,
+            &self.title_template,
+            self.feed_title(),
+        )
+    }
+
+    /// The image, the art extracted from `inputs` and their local media files, which are
+    /// uploaded before the feeds.
+    fn upload_files<M: feed::MediaFileLike>(&self, inputs: &[M]) -> Vec<PathBuf> {
+        let mut upload_files = Vec::new();
+        if let Some(image) = &self.image {
+            upload_files.push(image.clone());
+        }
+        upload_files.extend(
+            inputs
+                .iter()
+                .filter_map(|input| Some(input.art()?.path.clone())),
+        );
+        upload_files.extend(
+            inputs
+                .iter()
+                .filter_map(|input| Some(input.path()?.to_path_buf())),
+        );
+        upload_files
+    }
+
+    /// Writes the feeds and works out what publishing them would do, without changing the bucket.
+    fn plan(&self) -> Result<plan::Plan, Failure> {
+        match self.from_bucket {
+            true => self.plan_files(&self.bucket_files()?),
+            false => self.plan_files(&self.inputs()?.0),
+        }
+    }
+
+    /// The plan for `inputs`, which are the files given or those in the bucket.
+    fn plan_files<M: feed::MediaFileLike + Clone>(
+        &self,
+        inputs: &[M],
+    ) -> Result<plan::Plan, Failure> {
+        let (uploader, episodes) = self.write_feed(inputs)?;
+        let objects = uploader
+            .plan(&self.out, &self.upload_files(inputs))
+            .map_err(|e| upload_failure("Failed to plan upload: ", e))?;
+        Ok(plan::Plan {
+            target: uploader.base_url(),
+            channel: self
+                .generator(uploader.object_urls())
+                .channel_summary(&episodes),
+            objects,
+        })
+    }
+
+    /// Writes the feeds and uploads them along with their image and media files, returning the
+    /// URLs of the published feeds.
+    fn publish(
+        &self,
+        log: log::Log,
+        progress: Arc<dyn progress::Progress>,
+    ) -> Result<Published, Failure> {
+        self.publish_files(log, progress, true)
+    }
+
+    /// Writes a `--private` feed with freshly signed URLs and uploads just the feeds, assuming
+    /// the image and media files were published before.
+    fn resign(
+        &self,
+        log: log::Log,
+        progress: Arc<dyn progress::Progress>,
+    ) -> Result<Published, Failure> {
+        if !self.upload_args.private {
+            return Err(Failure::invalid(
+                "Only feeds published with --private have URLs to re-sign".to_owned(),
+            ));
+        }
+        if self.upload_args.prune {
+            return Err(Failure::invalid(
+                "--prune can't be used with resign, which doesn't upload the episodes".to_owned(),
+            ));
+        }
+        self.publish_files(log, progress, false)
+    }
+
+    /// Writes the feeds and uploads them, after the image and media files if `upload_media`.
+    fn publish_files(
+        &self,
+        log: log::Log,
+        progress: Arc<dyn progress::Progress>,
+        upload_media: bool,
+    ) -> Result<Published, Failure> {
+        if self.from_bucket {
+            let files = self.bucket_files()?;
+            let upload_files = match upload_media {
+                true => self.upload_files(&files),
+                false => Vec::new(),
+            };
+            return self.publish_feed(log, progress, &files, upload_files);
+        }
+        let (inputs, _art_dir) = self.inputs()?;
+        let upload_files = match upload_media {
+            true => self.upload_files(&inputs),
+            false => Vec::new(),
+        };
+        if self.verbose {
+            log("Publishing these files, in feed order:");
+            for line in feed::list_inputs(&inputs, &self.titles(&inputs)).lines() {
+                log(&format!("  {}", line));
+            }
+        }
+        self.publish_feed(log, progress, &inputs, upload_files)
+    }
+
+    /// Writes the feeds for `inputs` and uploads them, after `upload_files`.
+    fn publish_feed<M: feed::MediaFileLike + Clone>(
+        &self,
+        log: log::Log,
+        progress: Arc<dyn progress::Progress>,
+        inputs: &[M],
+        upload_files: Vec<PathBuf>,
+    ) -> Result<Published, Failure> {
+        let (mut uploader, episodes) = self.write_feed(inputs)?;
+        uploader.set_log(Arc::clone(&log));
+        uploader.set_progress(progress);
+        // The RSS check covers JSON Feed outputs too, since every format is written from the same
+        // episodes.
+        let rss_out = self
+            .out
+            .iter()
+            .find(|out| feed::FeedFormat::from_path(out) == feed::FeedFormat::Rss);
+        if let (false, Some(rss_out)) = (self.no_self_check, rss_out) {
+            let hosted: Vec<&str> = inputs.iter().filter_map(|input| input.url()).collect();
+            let problems = match File::open(rss_out) {
+                Ok(file) => check::check_feed(
+                    BufReader::new(file),
+                    &uploader.base_url(),
+                    &hosted,
+                    self.allow_empty,
+                ),
+                Err(e) => vec![format!("feed could not be read: {}", e)],
+            };
+            if !problems.is_empty() {
+                let mut message = "Refusing to upload feed:".to_owned();
+                for problem in problems {
+                    message.push_str(&format!("\n  {}", problem));
+                }
+                return Err(Failure::invalid(message));
+            }
+        }
+        let http: Option<&dyn http::HttpGet> = if self.upload_args.verify_ranges {
+            Some(&http::HyperClient)
+        } else {
+            None
+        };
+        let hooks = self.hooks();
+        let mut hook_env = self.hook_env(&uploader.url_for_file(&self.out[0]), inputs.len());
+        if !self.upload_args.dry_run {
+            hooks
+                .run(Stage::PreUpload, &hook_env, &[], None)
+                .map_err(Failure::invalid)?;
+        }
+        let summary = uploader
+            .publish(&self.out, upload_files, http)
+            .map_err(|e| upload_failure("Upload error: ", e))?;
+        let published = Published {
+            outcome: summary_outcome(&summary),
+            feed_urls: self.feed_urls(uploader.as_ref()),
+        };
+        if self.upload_args.dry_run {
+            self.invalidate(uploader.as_ref(), &summary, &log);
+            log("Dry run complete; nothing was uploaded");
+            return Ok(published);
+        }
+        log(&format!(
+            "Upload complete: {}",
+            summary.describe(self.upload_args.numbers())
+        ));
+        self.invalidate(uploader.as_ref(), &summary, &log);
+        let report = json!({
+            "outcome": published.outcome.name(),
+            "feed_urls": published.feed_urls,
+            "episodes": episodes.len(),
+            "uploaded": summary.files,
+            "bytes": summary.bytes,
+            "unchanged": summary.unchanged,
+            "deleted": summary.deleted,
+        });
+        hook_env.extend([
+            ("SLOOP_OUTCOME", published.outcome.name().to_owned()),
+            ("SLOOP_EPISODES", episodes.len().to_string()),
+            ("SLOOP_UPLOADED", summary.files.to_string()),
+            ("SLOOP_UNCHANGED", summary.unchanged.to_string()),
+            ("SLOOP_DELETED", summary.deleted.to_string()),
+        ]);
+        hooks
+            .run(
+                Stage::PostUpload,
+                &hook_env,
+                &[],
+                Some(&format!("{}\n", report)),
+            )
+            .map_err(Failure::invalid)?;
+        if let (true, Some(episode)) = (self.verify_feed, episodes.first()) {
+            check::verify_enclosure(&http::HyperClient, &episode.url).map_err(Failure::failed)?;
+        }
+        Ok(published)
+    }
+
+    /// With `--cloudfront-distribution-id`, invalidates the feeds and image that `summary` sent.
+    /// Failing to only warns, since they're uploaded either way.
+    fn invalidate(&self, uploader: &dyn Uploader, summary: &upload::UploadSummary, log: &log::Log) {
+        let distribution_id = match &self.cloudfront_distribution_id {
+            Some(distribution_id) => distribution_id,
+            None => return,
+        };
+        let cached: Vec<String> = self
+            .out
+            .iter()
+            .chain(&self.image)
+            .map(|path| uploader.key(path))
+            .collect();
+        let paths: Vec<String> = summary
+            .keys
+            .iter()
+            .filter(|key| cached.contains(key))
+            .map(|key| cdn::path_for_key(self.s3_args.public_url.as_deref(), key))
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        if self.upload_args.dry_run {
+            log(&format!(
+                "Would invalidate {} in CloudFront distribution {}",
+                paths.join(", "),
+                distribution_id
+            ));
+            return;
+        }
+        let invalidator = cdn::Invalidator::new(distribution_id, !self.no_wait, Arc::clone(log));
+        if let Err(err) = invalidator.invalidate(&paths) {
+            warning::warn_in(
+                log,
+                &format!(
+                    "failed to invalidate {} in CloudFront distribution {}: {}",
+                    paths.join(", "),
+                    distribution_id,
+                    err
+                ),
+            );
+        }
+    }
+
+    fn feed_urls(&self, uploader: &dyn Uploader) -> Vec<String> {
+        self.out
+            .iter()
+            .map(|out| uploader.url_for_file(out))
+            .collect()
+    }
+}
+
+/// `files` with each directory replaced by the files under it with one of `include_ext`, or, if
+/// none are given, that `known` accepts, telling the user how many each held.
+fn discover_files(
+    files: &[PathBuf],
+    include_ext: &[String],
+    known: impl Fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>, Failure> {
+    let wanted = |path: &Path| match include_ext.is_empty() {
+        true => known(path),
+        false => discover::has_extension(path, include_ext),
+    };
+    discover::expand(files, wanted, &mut |dir, count| {
+        let files = if count == 1 { "file" } else { "files" };
+        log::message(&format!("Found {} {} in {}", count, files, dir.display()))
+    })
+    .map_err(Failure::failed)
+}
+
+/// Uploads `files` as they are, without a feed.
+fn upload_files(
+    s3_args: &S3Args,
+    upload_args: &UploadArgs,
+    backend_args: &BackendArgs,
+    files: Vec<PathBuf>,
+) -> Result<Outcome, upload::UploadError> {
+    let mut uploader = backend_args.uploader(s3_args, upload_args)?;
+    let (log, progress) = upload_args.reporters();
+    uploader.set_log(Arc::clone(&log));
+    uploader.set_progress(progress);
+    let range_check = files.first().cloned();
+    let summary = uploader.upload(files)?;
+    if upload_args.dry_run {
+        log("Dry run complete; nothing was uploaded");
+        return Ok(summary_outcome(&summary));
+    }
+    log(&format!(
+        "Upload complete: {}",
+        summary.describe(upload_args.numbers())
+    ));
+    if let (Some(path), true) = (range_check, upload_args.verify_ranges) {
+        uploader.verify_range(&http::HyperClient, &path)?;
+    }
+    Ok(summary_outcome(&summary))
+}
+
+/// Whether a successful upload sent or deleted anything, or would have in a dry run.
+fn summary_outcome(summary: &upload::UploadSummary) -> Outcome {
+    if summary.files > 0 || summary.deleted > 0 {
+        Outcome::Published
+    } else {
+        Outcome::Unchanged
+    }
+}
+
+/// Reports an upload error, prefixed by `context`, with the outcome its stage calls for.
+fn upload_failure(context: &str, err: upload::UploadError) -> Failure {
+    if err.outcome() == Outcome::Cancelled {
+        return Failure::cancelled();
+    }
+    // The explanation of a taken bucket name stands on its own, whatever step ran into it.
+    let context = match err.kind {
+        upload::UploadErrorKind::BucketNameTaken => "",
+        upload::UploadErrorKind::Other => context,
+    };
+    Failure {
+        outcome: err.outcome(),
+        message: format!("{}{}", context, err),
+    }
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(about = "audiobook to podcast tool")]
-enum Opt {
+struct Opt {
+    /// Read configuration from this file instead of the default location
+    #[structopt(long, global = true, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Keep state and journal files in this directory instead of the default location
+    #[structopt(long, global = true, parse(from_os_str))]
+    state_dir: Option<PathBuf>,
+    /// Use the settings of this podcast from the config file, e.g. its title and bucket.
+    /// Options given on the command line take precedence
+    #[structopt(long, global = true, value_name = "NAME")]
+    podcast: Option<String>,
+    /// Fail the run if it warns about anything, listing the warnings at the end. Publishing
+    /// without --prefix into a bucket whose root already has another podcast's feeds or media
+    /// fails before uploading
+    #[structopt(long, global = true)]
+    strict: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
     Feed {
+        #[structopt(flatten)]
+        feed_args: FeedArgs,
         #[structopt(long)]
-        title: String,
-        #[structopt(long)]
-        image: Option<PathBuf>,
-        #[structopt(long)]
-        region: String,
+        upload: bool,
+        /// Print what publishing would create, update or skip, in a stable format that can be
+        /// diffed between runs, without changing anything in S3
         #[structopt(long)]
-        bucket: String,
-        #[structopt(short, long)]
-        out: PathBuf,
+        plan: bool,
+        /// Print the media files in feed order, one per line as "number, path, key and title"
+        /// separated by tabs. Used alone, nothing else is done
         #[structopt(long)]
-        upload: bool,
+        list_files: bool,
+    },
+    Upload {
+        #[structopt(flatten)]
+        s3_args: S3Args,
+        #[structopt(flatten)]
+        upload_args: UploadArgs,
+        #[structopt(flatten)]
+        backend_args: BackendArgs,
+        /// Take only files with these extensions from the directories given, instead of any
+        /// sloop knows the type of
+        #[structopt(long, use_delimiter = true, value_name = "EXT")]
+        include_ext: Vec<String>,
         #[structopt(parse(from_os_str))]
         files: Vec<PathBuf>,
     },
-    Upload {
+    /// Re-sign the URLs in a --private feed and upload just the feed, before the old URLs
+    /// expire. Takes the same options as feed
+    Resign {
+        #[structopt(flatten)]
+        feed_args: FeedArgs,
+    },
+    /// Remove an episode: upload the feed without its item, then delete its object
+    Remove {
+        #[structopt(flatten)]
+        s3_args: S3Args,
+        #[structopt(flatten)]
+        upload_args: UploadArgs,
+        /// The RSS feed to remove the episode from, under any --prefix
+        #[structopt(long, default_value = "feed.xml", value_name = "NAME")]
+        feed_key: String,
+        /// Delete the episode's object even if the feed has no item linking to it
         #[structopt(long)]
-        region: String,
+        ignore_missing: bool,
+        /// The episode's file name, or its key
+        episode: String,
+    },
+    /// Serve an HTTP API that regenerates and publishes the feed on request
+    Daemon {
+        /// Address to listen on
+        #[structopt(long, default_value = "127.0.0.1:8787")]
+        listen: SocketAddr,
+        /// Token clients must send in the X-Sloop-Token header, or keyring:SERVICE/NAME to read
+        /// it from the OS keychain
+        #[structopt(long, env = "SLOOP_DAEMON_TOKEN", hide_env_values = true)]
+        token: String,
+        #[structopt(flatten)]
+        feed_args: FeedArgs,
+    },
+    /// Print sloop's version and the commit it was built from
+    Version {
+        /// Print the version, git hash and enabled features as JSON
         #[structopt(long)]
-        bucket: String,
-        #[structopt(parse(from_os_str))]
-        files: Vec<PathBuf>,
+        json: bool,
+    },
+    /// Print where sloop looks for configuration and keeps state, including that of the
+    /// --podcast given
+    Paths,
+    /// List the objects in the bucket, under any --prefix, with their sizes and when they were
+    /// last modified
+    List {
+        #[structopt(flatten)]
+        s3_args: S3Args,
+        /// Print the objects as JSON, with sizes in bytes
+        #[structopt(long)]
+        json: bool,
+        /// Print sizes in bytes instead of e.g. `1.4 GiB`
+        #[structopt(long)]
+        raw_numbers: bool,
+    },
+    /// List the podcasts in the config files, one per line as "name, bucket/prefix and title"
+    /// separated by tabs
+    ListPodcasts,
+    /// Print how often each episode was downloaded
+    Stats {
+        #[structopt(flatten)]
+        s3_args: S3Args,
+        /// Count the requests in the S3 access logs the bucket delivers, as set up with
+        /// --enable-access-logs
+        #[structopt(long)]
+        from_logs: bool,
+        /// Print sizes in bytes instead of e.g. `1.4 GiB`
+        #[structopt(long)]
+        raw_numbers: bool,
+    },
+    /// Keep secrets in the OS keychain, for config values and SLOOP_DAEMON_TOKEN to refer to
+    /// as keyring:SERVICE/NAME
+    Secret {
+        #[structopt(subcommand)]
+        action: SecretAction,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum SecretAction {
+    /// Store the secret read from stdin, replacing any already there
+    Set {
+        /// Where to keep it, e.g. sloop/webhook
+        #[structopt(value_name = "SERVICE/NAME")]
+        entry: secret::Entry,
+    },
+    /// Print a secret
+    Get {
+        #[structopt(value_name = "SERVICE/NAME")]
+        entry: secret::Entry,
+    },
+    /// Remove a secret, exiting 3 if there was none
+    Delete {
+        #[structopt(value_name = "SERVICE/NAME")]
+        entry: secret::Entry,
     },
 }
 
 fn main() {
-    let opt = Opt::from_args();
-    match opt {
-        Opt::Feed {
-            title,
-            image,
-            region,
-            bucket,
-            out,
+    let args = with_profile(env::args_os().collect()).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(Outcome::Invalid.exit_code());
+    });
+    let opt = Opt::from_iter_safe(args).unwrap_or_else(|err| match err.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => err.exit(),
+        _ => {
+            eprintln!("{}", err.message);
+            process::exit(Outcome::Invalid.exit_code());
+        }
+    });
+    // The daemon serves run after run, so Ctrl-C still stops it outright.
+    if !matches!(opt.command, Command::Daemon { .. }) {
+        cancel::cancel_on_interrupt();
+    }
+    if opt.strict {
+        warning::set_strict();
+    }
+    let outcome = run(opt).and_then(check_warnings).unwrap_or_else(|failure| {
+        eprintln!("{}", failure);
+        failure.outcome
+    });
+    process::exit(outcome.exit_code());
+}
+
+/// With `--strict`, turns a run that warned into a failure listing the warnings.
+fn check_warnings<T>(done: T) -> Result<T, Failure> {
+    let warnings = warning::take();
+    if warnings.is_empty() {
+        return Ok(done);
+    }
+    Err(Failure::invalid(format!(
+        "Failing because of --strict, as the run warned:\n  {}",
+        warnings.join("\n  ")
+    )))
+}
+
+/// Adds the options that the podcast named by `--podcast` sets in the config files to `args`,
+/// unless given there already.
+fn with_profile(args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let name = match config::find_option(&args, "--podcast") {
+        Some(name) => name,
+        None => return Ok(args),
+    };
+    let config_file = config::find_option(&args, "--config").map(PathBuf::from);
+    let required = config_file.is_some();
+    let config = config::Config::load(&paths::Paths::resolve(config_file, None), required)?;
+    let profile = config.podcast(&name.to_string_lossy())?;
+    config::apply_profile(args, profile, &secret::Keychain)
+}
+
+/// Runs a command, returning how it went, which decides the exit code. Locks are released before
+/// returning.
+fn run(opt: Opt) -> Result<Outcome, Failure> {
+    let config_required = opt.config.is_some();
+    let paths = paths::Paths::resolve(opt.config, opt.state_dir);
+    let load_config = || config::Config::load(&paths, config_required).map_err(Failure::invalid);
+    match opt.command {
+        Command::Feed {
+            mut feed_args,
             upload,
-            files,
+            plan,
+            list_files,
         } => {
-            let uploader = upload::S3Uploader::new(&region, &bucket).unwrap();
-            let feed = feed::FeedGenerator {
-                title,
-                base_url: uploader.base_url(),
-                image: image.clone().map(|path| feed::Image { path }),
-            };
-            let media_files = files.iter().map(|path| feed::MediaFile { path }).collect();
-            if let Err(e) = feed.generate_for_files(media_files, File::create(&out).unwrap()) {
-                eprintln!("Failed to create feed: {}", e);
-                process::exit(1);
-            }
-            if upload {
-                let feed_url = uploader.url_for_file(&out);
-                let mut upload_files = vec![out];
-                if let Some(image) = &image {
-                    upload_files.push(image.clone());
+            feed_args.apply_manifest()?;
+            let publishing = upload || plan || feed_args.upload_args.dry_run;
+            if list_files {
+                if feed_args.from_bucket {
+                    return Err(Failure::invalid(
+                        "--list-files lists local files, so can't be used with --from-bucket"
+                            .to_owned(),
+                    ));
                 }
-                upload_files.extend(files);
-                match uploader.upload(upload_files) {
-                    Ok(_) => {
-                        eprintln!("Upload complete");
-                        eprintln!("Podcast available at {}", feed_url);
-                        process::exit(0);
-                    }
-                    Err(e) => {
-                        eprintln!("Upload error: {}", e.message);
-                        process::exit(1);
-                    }
+                let (inputs, _art_dir) = feed_args.inputs()?;
+                print!("{}", feed::list_inputs(&inputs, &feed_args.titles(&inputs)));
+                if !publishing {
+                    return Ok(Outcome::Published);
                 }
             }
+            feed_args.check_target(publishing)?;
+            if feed_args.base_url.is_some() {
+                feed_args.write_feeds()?;
+                return Ok(Outcome::Published);
+            }
+            let _lock = feed_args.lock(&paths)?;
+            if plan {
+                let plan = feed_args.plan()?;
+                print!("{}", plan);
+                return Ok(if plan.has_changes() {
+                    Outcome::Published
+                } else {
+                    Outcome::Unchanged
+                });
+            }
+            if !publishing {
+                feed_args.write_feeds()?;
+                return Ok(Outcome::Published);
+            }
+            let (log, progress) = feed_args.upload_args.reporters();
+            let published = feed_args.publish(log, progress)?;
+            let available = if feed_args.upload_args.dry_run {
+                "would be available"
+            } else {
+                "available"
+            };
+            for feed_url in published.feed_urls {
+                eprintln!("Podcast {} at {}", available, feed_url);
+            }
+            feed_args.upload_args.warn_of_expiry();
+            Ok(published.outcome)
+        }
+        Command::Resign { mut feed_args } => {
+            feed_args.apply_manifest()?;
+            feed_args.check_target(true)?;
+            let _lock = feed_args.lock(&paths)?;
+            let (log, progress) = feed_args.upload_args.reporters();
+            let published = feed_args.resign(log, progress)?;
+            for feed_url in published.feed_urls {
+                eprintln!("Podcast available at {}", feed_url);
+            }
+            feed_args.upload_args.warn_of_expiry();
+            Ok(published.outcome)
         }
-        Opt::Upload {
-            region,
-            bucket,
+        Command::Upload {
+            s3_args,
+            upload_args,
+            backend_args,
+            include_ext,
             files,
         } => {
-            let uploader = upload::S3Uploader::new(&region, &bucket).unwrap();
-            uploader.upload(files).unwrap();
+            backend_args.check_options()?;
+            if backend_args.backend != upload::Backend::S3 {
+                backend_args.check_target(&s3_args)?;
+                if upload_args.private {
+                    return Err(Failure::invalid(format!(
+                        "--private is for buckets that aren't public, so can't be used with \
+                         --backend {}",
+                        backend_args.backend.name()
+                    )));
+                }
+            }
+            let files = discover_files(&files, &include_ext, |path| {
+                mime::content_type(path).is_some()
+            })?;
+            let _lock = backend_args.lock(&paths, &s3_args, upload_args.wait)?;
+            upload_files(&s3_args, &upload_args, &backend_args, files)
+                .map_err(|e| upload_failure("Upload error: ", e))
+        }
+        Command::Remove {
+            s3_args,
+            upload_args,
+            feed_key,
+            ignore_missing,
+            episode,
+        } => {
+            let _lock = lock_bucket(&paths, &s3_args, upload_args.wait)?;
+            let mut uploader = s3_args
+                .uploader(upload_args.options(s3_args.provider))
+                .map_err(|e| upload_failure("", e))?;
+            let (log, progress) = upload_args.reporters();
+            uploader.set_log(Arc::clone(&log));
+            uploader.set_progress(progress);
+            uploader
+                .remove(&episode, &feed_key, ignore_missing)
+                .map_err(|e| upload_failure("Failed to remove episode: ", e))?;
+            if upload_args.dry_run {
+                log("Dry run complete; nothing was changed");
+            }
+            Ok(Outcome::Published)
+        }
+        Command::List {
+            s3_args,
+            json,
+            raw_numbers,
+        } => {
+            let objects = s3_args
+                .uploader(upload::UploadOptions::default())
+                .and_then(|uploader| uploader.list())
+                .map_err(|e| upload_failure("", e))?;
+            if json {
+                println!("{}", listing::to_json(&objects));
+            } else {
+                print!(
+                    "{}",
+                    listing::format(&objects, human::Numbers { raw: raw_numbers })
+                );
+            }
+            Ok(Outcome::Published)
+        }
+        Command::Stats {
+            s3_args,
+            from_logs,
+            raw_numbers,
+        } => {
+            if !from_logs {
+                return Err(Failure::invalid(
+                    "sloop stats needs --from-logs; S3 access logs are the only source of \
+                     statistics"
+                        .to_owned(),
+                ));
+            }
+            let tally = s3_args
+                .uploader(upload::UploadOptions::default())
+                .and_then(|uploader| uploader.access_log_stats())
+                .map_err(|e| upload_failure("", e))?;
+            if tally.unreadable_lines > 0 {
+                warning::warn(&format!(
+                    "skipped {} lines that aren't S3 access log lines",
+                    tally.unreadable_lines
+                ));
+            }
+            print!(
+                "{}",
+                stats::format(&tally.episodes(), human::Numbers { raw: raw_numbers })
+            );
+            Ok(Outcome::Published)
+        }
+        Command::Daemon {
+            listen,
+            token,
+            mut feed_args,
+        } => {
+            feed_args.apply_manifest()?;
+            feed_args.check_target(true)?;
+            let token = secret::resolve(&token, &secret::Keychain).map_err(Failure::invalid)?;
+            if token.is_empty() {
+                return Err(Failure::invalid(
+                    "The daemon token must not be empty".to_owned(),
+                ));
+            }
+            let listener = TcpListener::bind(listen)
+                .map_err(|e| Failure::failed(format!("Failed to listen on {}: {}", listen, e)))?;
+            eprintln!("Listening on {}", listen);
+            let daemon = daemon::Daemon::new(token, move |log| {
+                let _lock = feed_args.lock(&paths)?;
+                feed_args
+                    .publish(log, Arc::new(progress::NoProgress))
+                    .and_then(check_warnings)
+            });
+            daemon
+                .serve(listener)
+                .map_err(|e| Failure::failed(format!("Daemon stopped: {}", e)))?;
+            Ok(Outcome::Published)
+        }
+        Command::Version { json } => {
+            if json {
+                println!("{}", version::to_json());
+            } else {
+                println!("sloop {}", version::describe());
+            }
+            Ok(Outcome::Published)
+        }
+        Command::Paths => {
+            let describe = |path: &std::path::Path| {
+                let status = if path.exists() { "" } else { " (not found)" };
+                format!("{}{}", path.display(), status)
+            };
+            println!("Config file:         {}", describe(&paths.config_file));
+            println!(
+                "Project config file: {}",
+                describe(&paths.project_config_file)
+            );
+            println!("State directory:     {}", describe(&paths.state_dir));
+            if let Some(podcast) = opt.podcast {
+                if let Some(location) = load_config()?
+                    .podcast(&podcast)
+                    .map_err(Failure::invalid)?
+                    .location()
+                {
+                    let dir = paths.podcast_state_dir(&location);
+                    println!("Podcast state:       {}", describe(&dir));
+                }
+            }
+            Ok(Outcome::Published)
+        }
+        Command::Secret { action } => manage_secret(action, &secret::Keychain),
+        Command::ListPodcasts => {
+            for (name, profile) in load_config()?.podcasts {
+                println!(
+                    "{}\t{}\t{}",
+                    name,
+                    profile.location().unwrap_or_default(),
+                    profile.title.unwrap_or_default()
+                );
+            }
+            Ok(Outcome::Published)
         }
+    }
+}
+
+/// Runs a `secret` subcommand against `store`.
+fn manage_secret(
+    action: SecretAction,
+    store: &dyn secret::SecretStore,
+) -> Result<Outcome, Failure> {
+    let failed = |entry: &secret::Entry, err: String| {
+        Failure::failed(format!(
+            "Failed to reach the keychain for {}: {}",
+            entry, err
+        ))
     };
+    match action {
+        SecretAction::Set { entry } => {
+            let mut input = String::new();
+            io::Read::read_to_string(&mut io::stdin(), &mut input)
+                .map_err(|e| Failure::failed(format!("Failed to read the secret: {}", e)))?;
+            let secret = input.strip_suffix('\n').unwrap_or(&input);
+            let secret = secret.strip_suffix('\r').unwrap_or(secret);
+            if secret.is_empty() {
+                return Err(Failure::invalid(format!(
+                    "No secret given for {} on stdin",
+                    entry
+                )));
+            }
+            store
+                .set(&entry, secret)
+                .map_err(|err| failed(&entry, err))?;
+            Ok(Outcome::Published)
+        }
+        SecretAction::Get { entry } => {
+            match store.get(&entry).map_err(|err| failed(&entry, err))? {
+                Some(secret) => {
+                    println!("{}", secret);
+                    Ok(Outcome::Published)
+                }
+                None => Err(Failure::invalid(format!(
+                    "No secret {} in the keychain",
+                    entry
+                ))),
+            }
+        }
+        SecretAction::Delete { entry } => {
+            match store.delete(&entry).map_err(|err| failed(&entry, err))? {
+                true => Ok(Outcome::Published),
+                false => {
+                    eprintln!("No secret {} in the keychain", entry);
+                    Ok(Outcome::Unchanged)
+                }
+            }
+        }
+    }
 }
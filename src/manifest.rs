@@ -0,0 +1,608 @@
+//! A TOML file describing a podcast and its episodes in more detail than options can: each
+//! episode's title, description, date and whether it's explicit, in the order they're listed.
+//! What it gives overrides the command line and what sloop would derive from the files.
+//! Episodes hosted elsewhere can be listed by URL among the files.
+
+use crate::category::Category;
+use crate::feed::{self, Entry, Given, Hosted, Input, MediaFileLike};
+use crate::human;
+use crate::mime;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A manifest as written, with paths relative to it.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct File {
+    /// Reject files given on the command line that aren't listed, instead of adding them after
+    /// those that are.
+    #[serde(default)]
+    strict: bool,
+    #[serde(default)]
+    channel: Channel,
+    #[serde(default)]
+    episode: Vec<EpisodeEntry>,
+}
+
+/// The podcast's details, each replacing the option of the same name.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Channel {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<PathBuf>,
+    pub categories: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct EpisodeEntry {
+    file: Option<PathBuf>,
+    /// Where media hosted elsewhere is, instead of a file.
+    url: Option<String>,
+    /// The hosted media's size in bytes.
+    length: Option<u64>,
+    /// The hosted media's MIME type, if its URL's extension doesn't tell it.
+    #[serde(rename = "type")]
+    mime_type: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    /// A TOML date or date-time, or a string holding one.
+    pub_date: Option<toml::Value>,
+    explicit: Option<bool>,
+}
+
+/// A manifest read and checked, with paths relative to the working directory.
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub strict: bool,
+    pub channel: Channel,
+    /// Each episode's media and what the manifest says about it, in feed order.
+    pub episodes: Vec<(Media, Given)>,
+    path: PathBuf,
+}
+
+/// What an episode of a manifest is of.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Media {
+    File(PathBuf),
+    Hosted(Hosted),
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        Self::parse(&text, path)
+            .map_err(|err| format!("Failed to read the manifest {}: {}", path.display(), err))
+    }
+
+    /// Parses `text`, the manifest at `path`, failing at unknown keys with where they are.
+    fn parse(text: &str, path: &Path) -> Result<Self, String> {
+        let file: File = toml::from_str(text).map_err(|err| err.to_string())?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut channel = file.channel;
+        channel.image = channel.image.map(|image| dir.join(image));
+        let mut episodes: Vec<(Media, Given)> = Vec::new();
+        for entry in file.episode {
+            let name = match (&entry.file, &entry.url) {
+                (Some(file), None) => file.display().to_string(),
+                (None, Some(url)) => url.clone(),
+                _ => return Err("each [[episode]] needs either a file or a url".to_owned()),
+            };
+            let pub_date = match &entry.pub_date {
+                Some(date) => Some(
+                    parse_pub_date(date)
+                        .map_err(|err| format!("the episode for {}: {}", name, err))?,
+                ),
+                None => None,
+            };
+            let given = Given {
+                title: entry.title,
+                description: entry.description,
+                pub_date,
+                explicit: entry.explicit,
+            };
+            let media = match (entry.file, entry.url) {
+                (Some(file), _) => {
+                    if entry.length.is_some() || entry.mime_type.is_some() {
+                        return Err(format!(
+                            "the episode for {}: length and type are only for episodes given \
+                             by url; a file's are told from the file",
+                            name
+                        ));
+                    }
+                    Media::File(dir.join(file))
+                }
+                (None, url) => Media::Hosted(
+                    hosted(
+                        url.unwrap_or_default(),
+                        entry.length,
+                        entry.mime_type,
+                        &given,
+                    )
+                    .map_err(|err| format!("the episode for {}: {}", name, err))?,
+                ),
+            };
+            if episodes
+                .iter()
+                .any(|(listed, _)| same_media(listed, &media))
+            {
+                return Err(format!("{} is listed more than once", name));
+            }
+            episodes.push((media, given));
+        }
+        Ok(Manifest {
+            strict: file.strict,
+            channel,
+            episodes,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The channel's categories, checked as `--category` checks them.
+    pub fn categories(&self) -> Result<Option<Vec<Category>>, String> {
+        match &self.channel.categories {
+            Some(categories) => categories
+                .iter()
+                .map(|category| {
+                    category
+                        .parse()
+                        .map_err(|err| format!("The manifest {}: {}", self.path.display(), err))
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The files of the feed in order: those the manifest lists and then those in `given`
+    /// that it doesn't, unless it's strict, when those fail.
+    pub fn files(&self, given: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+        let mut files: Vec<PathBuf> = self.listed_files().cloned().collect();
+        for file in given {
+            if self.listed_files().any(|listed| same_file(listed, &file)) {
+                continue;
+            }
+            if self.strict {
+                return Err(format!(
+                    "{} isn't in the manifest {}, which is strict; add an [[episode]] for it",
+                    file.display(),
+                    self.path.display()
+                ));
+            }
+            files.push(file);
+        }
+        Ok(files)
+    }
+
+    /// Gives each of `inputs` what the manifest says about its file.
+    pub fn apply(&self, inputs: &mut [Input]) {
+        for input in inputs {
+            if let Some((_, given)) = self.episodes.iter().find(|(media, _)| match media {
+                Media::File(file) => same_file(file, &input.path),
+                Media::Hosted(_) => false,
+            }) {
+                input.given = given.clone();
+            }
+        }
+    }
+
+    /// The episodes in feed order: `inputs`, which follow `files`, with the hosted media the
+    /// manifest lists among them in their places.
+    pub fn entries(&self, inputs: Vec<Input>) -> Vec<Entry> {
+        let mut inputs: Vec<Option<Input>> = inputs.into_iter().map(Some).collect();
+        let mut entries = Vec::new();
+        for (media, _) in &self.episodes {
+            match media {
+                Media::File(file) => {
+                    let input = inputs
+                        .iter_mut()
+                        .find(|input| {
+                            input
+                                .as_ref()
+                                .is_some_and(|input| same_file(file, &input.path))
+                        })
+                        .and_then(Option::take);
+                    // Files left out as unsettled or invalid have no input.
+                    entries.extend(input.map(Entry::File));
+                }
+                Media::Hosted(hosted) => entries.push(Entry::Hosted(hosted.clone())),
+            }
+        }
+        entries.extend(inputs.into_iter().flatten().map(Entry::File));
+        entries
+    }
+
+    fn listed_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.episodes.iter().filter_map(|(media, _)| match media {
+            Media::File(file) => Some(file),
+            Media::Hosted(_) => None,
+        })
+    }
+}
+
+/// Media hosted at `url`, which must be absolute and have a `length`, as podcast apps need an
+/// enclosure's length, and a type, unless its extension tells it.
+fn hosted(
+    url: String,
+    length: Option<u64>,
+    mime_type: Option<String>,
+    given: &Given,
+) -> Result<Hosted, String> {
+    feed::parse_link(&url)?;
+    let length = length.ok_or(
+        "its url needs a length, the media's size in bytes, which podcast apps need to \
+         download it",
+    )?;
+    let mut hosted = Hosted {
+        url,
+        length,
+        mime_type: String::new(),
+        given: given.clone(),
+    };
+    hosted.mime_type = match mime_type {
+        Some(mime_type) => mime_type,
+        None => hosted
+            .extension()
+            .and_then(mime::audio_type)
+            .ok_or("its url needs a type, e.g. audio/mpeg")?
+            .to_owned(),
+    };
+    Ok(hosted)
+}
+
+fn same_media(a: &Media, b: &Media) -> bool {
+    match (a, b) {
+        (Media::File(a), Media::File(b)) => same_file(a, b),
+        (Media::Hosted(a), Media::Hosted(b)) => a.url == b.url,
+        _ => false,
+    }
+}
+
+/// Whether `a` and `b` are the same file, however they're written.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// A TOML date or date and time, or a string holding one.
+fn parse_pub_date(value: &toml::Value) -> Result<DateTime<Utc>, String> {
+    let text = match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Datetime(date) => date.to_string(),
+        other => other.to_string(),
+    };
+    human::parse_date_time(&text).map_err(|err| format!("pub_date {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::{self, FeedGenerator, MediaFileLike, TitleSource};
+    use crate::urls::ObjectUrls;
+    use chrono::NaiveDate;
+
+    const MANIFEST: &str = r#"
+strict = false
+
+[channel]
+title = "Candide"
+author = "Voltaire"
+categories = ["Arts:Books"]
+image = "cover.jpg"
+
+[[episode]]
+file = "Chapter_2.mp3"
+title = "How Candide Found Himself Among the Bulgarians"
+description = "Press-ganged & <flogged>."
+pub_date = 2024-03-02T08:30:00+10:00
+explicit = true
+
+[[episode]]
+file = "Chapter_1.mp3"
+pub_date = "2024-03-01"
+"#;
+
+    fn manifest(text: &str) -> Result<Manifest, String> {
+        Manifest::parse(text, Path::new("test_fixtures/plan/episodes.toml"))
+    }
+
+    fn date(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_channels_and_episodes_relative_to_the_manifest() {
+        let manifest = manifest(MANIFEST).unwrap();
+        assert_eq!(manifest.channel.title.as_deref(), Some("Candide"));
+        assert_eq!(manifest.channel.author.as_deref(), Some("Voltaire"));
+        assert_eq!(
+            manifest.channel.image,
+            Some(PathBuf::from("test_fixtures/plan/cover.jpg"))
+        );
+        assert_eq!(
+            manifest.categories().unwrap(),
+            Some(vec!["Arts:Books".parse().unwrap()])
+        );
+        let media: Vec<&Media> = manifest.episodes.iter().map(|(media, _)| media).collect();
+        assert_eq!(
+            media,
+            [
+                &Media::File("test_fixtures/plan/Chapter_2.mp3".into()),
+                &Media::File("test_fixtures/plan/Chapter_1.mp3".into())
+            ]
+        );
+        assert_eq!(
+            manifest.episodes[0].1,
+            Given {
+                title: Some("How Candide Found Himself Among the Bulgarians".to_owned()),
+                description: Some("Press-ganged & <flogged>.".to_owned()),
+                pub_date: Some(date("2024-03-01T22:30:00Z")),
+                explicit: Some(true),
+            }
+        );
+        assert_eq!(
+            manifest.episodes[1].1.pub_date,
+            Some(date("2024-03-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn empty_manifests_change_nothing() {
+        let manifest = manifest("").unwrap();
+        assert_eq!(manifest.channel, Channel::default());
+        assert_eq!(manifest.categories(), Ok(None));
+        let given = vec![PathBuf::from("Chapter_1.mp3")];
+        assert_eq!(manifest.files(given.clone()), Ok(given));
+    }
+
+    #[test]
+    fn unknown_keys_fail_naming_their_line() {
+        let err = manifest(
+            "[channel]\ntitle = \"Candide\"\n\n[[episode]]\nfile = \"a.mp3\"\ntitel = \"A\"\n",
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown field `titel`"), "{}", err);
+        assert!(err.contains("line 4"), "{}", err);
+        assert!(manifest("[chanel]\n")
+            .unwrap_err()
+            .contains("unknown field `chanel`"));
+    }
+
+    #[test]
+    fn rejects_bad_dates_categories_and_repeats() {
+        assert_eq!(
+            manifest("[[episode]]\nfile = \"a.mp3\"\npub_date = \"March 1\"\n").unwrap_err(),
+            "the episode for a.mp3: pub_date March 1 is not a date like 2024-03-01 or \
+             2024-03-01T06:00:00+10:00"
+        );
+        assert_eq!(
+            manifest("[[episode]]\nfile = \"a.mp3\"\n[[episode]]\nfile = \"a.mp3\"\n").unwrap_err(),
+            "a.mp3 is listed more than once"
+        );
+        let manifest = manifest("[channel]\ncategories = [\"Knitting\"]\n").unwrap();
+        assert!(manifest
+            .categories()
+            .unwrap_err()
+            .starts_with("The manifest test_fixtures/plan/episodes.toml: "));
+    }
+
+    #[test]
+    fn hosted_episodes_need_an_absolute_url_and_a_length() {
+        let listed = manifest(
+            "[[episode]]\nurl = \"https://archive.org/download/candide/Chapter_3.mp3\"\n\
+             length = 1234\n\
+             [[episode]]\nurl = \"https://archive.org/download/candide/chapter-4\"\n\
+             length = 5678\ntype = \"audio/ogg\"\n",
+        )
+        .unwrap();
+        let hosted: Vec<(&str, u64, &str)> = listed
+            .episodes
+            .iter()
+            .map(|(media, _)| match media {
+                Media::Hosted(hosted) => (
+                    hosted.url.as_str(),
+                    hosted.length,
+                    hosted.mime_type.as_str(),
+                ),
+                Media::File(file) => panic!("{} isn't hosted", file.display()),
+            })
+            .collect();
+        assert_eq!(
+            hosted,
+            [
+                (
+                    "https://archive.org/download/candide/Chapter_3.mp3",
+                    1234,
+                    "audio/mpeg"
+                ),
+                (
+                    "https://archive.org/download/candide/chapter-4",
+                    5678,
+                    "audio/ogg"
+                ),
+            ]
+        );
+
+        let url = "[[episode]]\nurl = \"https://archive.org/download/candide/Chapter_3.mp3\"\n";
+        assert_eq!(
+            manifest(url).unwrap_err(),
+            "the episode for https://archive.org/download/candide/Chapter_3.mp3: its url needs a \
+             length, the media's size in bytes, which podcast apps need to download it"
+        );
+        assert_eq!(
+            manifest("[[episode]]\nurl = \"https://archive.org/candide\"\nlength = 1\n")
+                .unwrap_err(),
+            "the episode for https://archive.org/candide: its url needs a type, e.g. audio/mpeg"
+        );
+        assert_eq!(
+            manifest("[[episode]]\nurl = \"Chapter_3.mp3\"\nlength = 1\n").unwrap_err(),
+            "the episode for Chapter_3.mp3: Chapter_3.mp3 is not an absolute http(s) URL"
+        );
+        assert_eq!(
+            manifest("[[episode]]\nfile = \"a.mp3\"\nlength = 1\n").unwrap_err(),
+            "the episode for a.mp3: length and type are only for episodes given by url; a \
+             file's are told from the file"
+        );
+        assert_eq!(
+            manifest("[[episode]]\ntitle = \"A\"\n").unwrap_err(),
+            "each [[episode]] needs either a file or a url"
+        );
+        assert_eq!(
+            manifest(&format!("{}length = 1\n{}length = 1\n", url, url)).unwrap_err(),
+            "https://archive.org/download/candide/Chapter_3.mp3 is listed more than once"
+        );
+    }
+
+    #[test]
+    fn hosted_episodes_are_linked_to_among_the_files() {
+        let manifest = manifest(
+            "[[episode]]\nfile = \"Chapter_1.mp3\"\n\
+             [[episode]]\nurl = \"https://archive.org/download/candide/Chapter_2.mp3?x=1\"\n\
+             length = 1234\ntitle = \"Chapter Two\"\n",
+        )
+        .unwrap();
+        let given = vec![PathBuf::from("test_fixtures/dir1/file1.mp3")];
+        let files = manifest.files(given).unwrap();
+        let inputs = feed::resolve_inputs(&files, "", false).unwrap();
+        let entries = manifest.entries(inputs);
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            ..Default::default()
+        };
+        let episodes = generator.episodes(entries.clone()).unwrap();
+        let enclosures: Vec<(&str, &str, u64, &str)> = episodes
+            .iter()
+            .map(|episode| {
+                (
+                    episode.title.as_str(),
+                    episode.url.as_str(),
+                    episode.length,
+                    episode.mime_type.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            enclosures,
+            [
+                (
+                    "Chapter 1",
+                    "https://eg.test/Chapter_1.mp3",
+                    12,
+                    "audio/mpeg"
+                ),
+                (
+                    "Chapter Two",
+                    "https://archive.org/download/candide/Chapter_2.mp3?x=1",
+                    1234,
+                    "audio/mpeg"
+                ),
+                ("file1", "https://eg.test/file1.mp3", 6, "audio/mpeg"),
+            ]
+        );
+        assert_eq!(
+            episodes[1].guid.as_deref(),
+            Some("https://archive.org/download/candide/Chapter_2.mp3?x=1")
+        );
+        // Only the local files are uploaded, and their lengths checked.
+        let paths: Vec<Option<&Path>> = entries.iter().map(|entry| entry.path()).collect();
+        assert_eq!(
+            paths,
+            [
+                Some(Path::new("test_fixtures/plan/Chapter_1.mp3")),
+                None,
+                Some(Path::new("test_fixtures/dir1/file1.mp3")),
+            ]
+        );
+        assert_eq!(generator.enclosure_lengths(&entries, &episodes).len(), 2);
+    }
+
+    #[test]
+    fn files_not_in_the_manifest_follow_it_unless_its_strict() {
+        let mut manifest = manifest(MANIFEST).unwrap();
+        let given = vec![
+            PathBuf::from("test_fixtures/plan/Chapter_1.mp3"),
+            PathBuf::from("test_fixtures/dir1/file1.mp3"),
+        ];
+        assert_eq!(
+            manifest.files(given.clone()).unwrap(),
+            [
+                PathBuf::from("test_fixtures/plan/Chapter_2.mp3"),
+                PathBuf::from("test_fixtures/plan/Chapter_1.mp3"),
+                PathBuf::from("test_fixtures/dir1/file1.mp3"),
+            ]
+        );
+        manifest.strict = true;
+        assert_eq!(
+            manifest.files(given),
+            Err("test_fixtures/dir1/file1.mp3 isn't in the manifest \
+                 test_fixtures/plan/episodes.toml, which is strict; add an [[episode]] for it"
+                .to_owned())
+        );
+    }
+
+    #[test]
+    fn what_the_manifest_gives_overrides_the_generator() {
+        let manifest = manifest(MANIFEST).unwrap();
+        let given = vec![
+            PathBuf::from("./test_fixtures/plan/Chapter_1.mp3"),
+            PathBuf::from("test_fixtures/dir1/file1.mp3"),
+        ];
+        let files = manifest.files(given).unwrap();
+        let mut inputs = feed::resolve_inputs(&files, "", false).unwrap();
+        manifest.apply(&mut inputs);
+        let generator = FeedGenerator {
+            title: "Candide".to_owned(),
+            urls: ObjectUrls::public("https://eg.test"),
+            start_date: NaiveDate::from_ymd_opt(2024, 6, 1),
+            titles_from: TitleSource::Tags,
+            title_template: "{n}. {title}".parse().unwrap(),
+            ..Default::default()
+        };
+        let episodes = generator.episodes(inputs.clone()).unwrap();
+        let summary: Vec<_> = episodes
+            .iter()
+            .map(|episode| {
+                (
+                    episode.title.as_str(),
+                    episode.description.as_deref(),
+                    episode.pub_date.to_rfc3339(),
+                    episode.explicit,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "How Candide Found Himself Among the Bulgarians",
+                    Some("Press-ganged & <flogged>."),
+                    "2024-03-01T22:30:00+00:00".to_owned(),
+                    Some(true),
+                ),
+                (
+                    "2. Chapter 1",
+                    None,
+                    "2024-03-01T00:00:00+00:00".to_owned(),
+                    None
+                ),
+                (
+                    "3. file1",
+                    None,
+                    "2024-05-30T00:00:00+00:00".to_owned(),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(inputs[1].name(), Some("Chapter_1.mp3"));
+    }
+}
@@ -0,0 +1,277 @@
+//! What sloop reads from inside media files: how long they play, their tags, chapters and
+//! artwork. Each container format has its own extractors, kept in a [`Registry`] that tools
+//! using sloop can add their own to.
+
+use crate::{duration, tags};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+/// Everything an extractor could read from a media file. Formats, and extractors, that don't
+/// record something leave it out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaMetadata {
+    pub duration: Option<Duration>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The file's place on its album or disc, e.g. 3 for a track tagged `3/12`.
+    pub track: Option<u32>,
+    pub artwork: Option<Artwork>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// A picture embedded in a media file, e.g. its cover.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artwork {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A chapter of a media file, from `start` until the next chapter or the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+/// The kind of file media is stored in, which decides the extractors that can read it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Mp3,
+    /// MP4 and its relatives, e.g. `.m4a` and `.m4b` audiobooks.
+    Mp4,
+    Ogg,
+    Flac,
+}
+
+impl Container {
+    /// The container of the file at `path`, told from its first bytes or, failing that, its
+    /// extension.
+    pub fn detect(path: &Path) -> Option<Container> {
+        let mut header = [0; 12];
+        let len = File::open(path)
+            .and_then(|mut file| file.read(&mut header))
+            .unwrap_or(0);
+        Self::from_header(&header[..len]).or_else(|| Self::from_extension(path))
+    }
+
+    fn from_header(header: &[u8]) -> Option<Container> {
+        match header {
+            [b'I', b'D', b'3', ..] => Some(Container::Mp3),
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(Container::Mp3),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(Container::Mp4),
+            [b'O', b'g', b'g', b'S', ..] => Some(Container::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(Container::Flac),
+            _ => None,
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Container> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "mp3" => Some(Container::Mp3),
+            "m4a" | "m4b" | "mp4" => Some(Container::Mp4),
+            "ogg" | "oga" | "opus" => Some(Container::Ogg),
+            "flac" => Some(Container::Flac),
+            _ => None,
+        }
+    }
+}
+
+/// Reads metadata from files of the containers it's registered for.
+pub trait MetadataExtractor: Send + Sync {
+    fn extract(&self, path: &Path) -> Result<MediaMetadata, String>;
+}
+
+/// The extractors for each container. Extracting tries those for the file's container in turn,
+/// the most recently registered first, until one succeeds.
+pub struct Registry {
+    extractors: Vec<(Container, Box<dyn MetadataExtractor>)>,
+}
+
+impl Registry {
+    /// A registry without any extractors, not even sloop's own.
+    pub fn empty() -> Self {
+        Registry {
+            extractors: Vec::new(),
+        }
+    }
+
+    /// Adds `extractor` for files of `container`, to be tried before those registered already,
+    /// so that it can replace sloop's own and fall back to them when it fails.
+    pub fn register<E: MetadataExtractor + 'static>(&mut self, container: Container, extractor: E) {
+        self.extractors.push((container, Box::new(extractor)));
+    }
+
+    /// Reads the metadata of the file at `path` with the first of its container's extractors to
+    /// succeed. Fails with every extractor's error if none do, or if none can read the file.
+    pub fn extract(&self, path: &Path) -> Result<MediaMetadata, String> {
+        let container = Container::detect(path);
+        let mut errors = Vec::new();
+        for (_, extractor) in self
+            .extractors
+            .iter()
+            .rev()
+            .filter(|(registered, _)| Some(*registered) == container)
+        {
+            match extractor.extract(path) {
+                Ok(metadata) => return Ok(metadata),
+                Err(err) => errors.push(err),
+            }
+        }
+        if errors.is_empty() {
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            return Err(format!("reading .{} files isn't supported", extension));
+        }
+        Err(errors.join("; "))
+    }
+}
+
+/// sloop's own extractors.
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry.register(
+            Container::Mp3,
+            FileExtractor {
+                duration: duration::mp3,
+                tags: tags::id3,
+            },
+        );
+        registry.register(
+            Container::Mp4,
+            FileExtractor {
+                duration: duration::mp4,
+                tags: tags::mp4,
+            },
+        );
+        registry
+    }
+}
+
+/// Reads the duration and tags, with functions for one container. Files whose tags can't be
+/// read still have a duration, so only failing to read that fails.
+struct FileExtractor {
+    duration: fn(&Path) -> Result<Duration, String>,
+    tags: fn(&Path) -> Result<MediaMetadata, String>,
+}
+
+impl MetadataExtractor for FileExtractor {
+    fn extract(&self, path: &Path) -> Result<MediaMetadata, String> {
+        let duration = (self.duration)(path)?;
+        if duration.is_zero() {
+            return Err("no audio found".to_owned());
+        }
+        Ok(MediaMetadata {
+            duration: Some(duration),
+            ..(self.tags)(path).unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Gives `title`, or fails if it's `None`, counting its calls.
+    struct Fixed {
+        title: Option<&'static str>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Fixed {
+        fn new(title: Option<&'static str>) -> (Self, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let extractor = Fixed {
+                title,
+                calls: calls.clone(),
+            };
+            (extractor, calls)
+        }
+    }
+
+    impl MetadataExtractor for Fixed {
+        fn extract(&self, _path: &Path) -> Result<MediaMetadata, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.title {
+                Some(title) => Ok(MediaMetadata {
+                    title: Some(title.to_owned()),
+                    ..Default::default()
+                }),
+                None => Err("unreadable".to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn detects_containers_by_content_then_extension() {
+        assert_eq!(Container::from_header(b"ID3\x04\0"), Some(Container::Mp3));
+        assert_eq!(
+            Container::from_header(&[0xFF, 0xFB, 0x90]),
+            Some(Container::Mp3)
+        );
+        assert_eq!(
+            Container::from_header(b"\0\0\0\x20ftypM4B "),
+            Some(Container::Mp4)
+        );
+        assert_eq!(Container::from_header(b"OggS\0\x02"), Some(Container::Ogg));
+        assert_eq!(Container::from_header(b"fLaC\0\0"), Some(Container::Flac));
+        assert_eq!(Container::from_header(b"episode one\n"), None);
+        assert_eq!(
+            Container::detect(Path::new("test_fixtures/dir1/file1.mp3")),
+            Some(Container::Mp3)
+        );
+        assert_eq!(Container::detect(Path::new("cover.jpg")), None);
+    }
+
+    #[test]
+    fn later_extractors_are_tried_first_falling_back_on_failure() {
+        let path = Path::new("episode.mp3");
+        let mut registry = Registry::empty();
+        let (first, first_calls) = Fixed::new(Some("first"));
+        let (failing, failing_calls) = Fixed::new(None);
+        let (other, other_calls) = Fixed::new(Some("other container"));
+        registry.register(Container::Mp3, first);
+        registry.register(Container::Mp4, other);
+        registry.register(Container::Mp3, failing);
+        assert_eq!(registry.extract(path).unwrap().title.unwrap(), "first");
+        assert_eq!(failing_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(other_calls.load(Ordering::SeqCst), 0);
+
+        let (overriding, _) = Fixed::new(Some("overriding"));
+        registry.register(Container::Mp3, overriding);
+        assert_eq!(registry.extract(path).unwrap().title.unwrap(), "overriding");
+    }
+
+    #[test]
+    fn fails_with_every_error_when_no_extractor_succeeds() {
+        let mut registry = Registry::empty();
+        assert_eq!(
+            registry.extract(Path::new("episode.mp3")),
+            Err("reading .mp3 files isn't supported".to_owned())
+        );
+        registry.register(Container::Mp3, Fixed::new(None).0);
+        registry.register(Container::Mp3, Fixed::new(None).0);
+        assert_eq!(
+            registry.extract(Path::new("episode.mp3")),
+            Err("unreadable; unreadable".to_owned())
+        );
+    }
+
+    #[test]
+    fn files_without_audio_have_no_metadata() {
+        let registry = Registry::default();
+        assert!(registry
+            .extract(Path::new("test_fixtures/dir1/file1.mp3"))
+            .is_err());
+        assert_eq!(
+            registry.extract(Path::new("episode.ogg")),
+            Err("reading .ogg files isn't supported".to_owned())
+        );
+    }
+}
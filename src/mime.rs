@@ -0,0 +1,87 @@
+use std::path::Path;
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// The extensions of the audio files sloop publishes, with the MIME type of each.
+const AUDIO_TYPES: &[(&str, &str)] = &[
+    ("aac", "audio/aac"),
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("m4b", "audio/mp4"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "audio/mp4"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("wav", "audio/wav"),
+    ("webm", "audio/webm"),
+];
+
+/// MIME type of an audio file with the given extension, as used for feed enclosures.
+pub fn audio_type(extension: &str) -> Option<&'static str> {
+    AUDIO_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, mime_type)| *mime_type)
+}
+
+/// The extensions `audio_type` knows, for telling users what they can publish.
+pub fn audio_extensions() -> Vec<&'static str> {
+    AUDIO_TYPES
+        .iter()
+        .map(|(extension, _)| *extension)
+        .collect()
+}
+
+/// Content type to store an uploaded file with. Audio files get the same type as their feed
+/// enclosure so that the object header and the feed always agree.
+pub fn content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?;
+    audio_type(extension).or_else(|| match extension.to_ascii_lowercase().as_str() {
+        "xml" | "rss" => Some("application/rss+xml"),
+        "json" => Some("application/feed+json"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_audio_extensions() {
+        assert_eq!(audio_type("mp3"), Some("audio/mpeg"));
+        assert_eq!(audio_type("M4B"), Some("audio/mp4"));
+        assert_eq!(audio_type("ogg"), Some("audio/ogg"));
+        assert_eq!(audio_type("oga"), Some("audio/ogg"));
+        assert_eq!(audio_type("opus"), Some("audio/opus"));
+        assert_eq!(audio_type("flac"), Some("audio/flac"));
+        assert_eq!(audio_type("wav"), Some("audio/wav"));
+        assert_eq!(audio_type("webm"), Some("audio/webm"));
+        assert_eq!(audio_type("txt"), None);
+    }
+
+    #[test]
+    fn maps_feed_and_image_extensions() {
+        assert_eq!(
+            content_type(Path::new("out/feed.xml")),
+            Some("application/rss+xml")
+        );
+        assert_eq!(
+            content_type(Path::new("out/feed.json")),
+            Some("application/feed+json")
+        );
+        assert_eq!(content_type(Path::new("cover.JPG")), Some("image/jpeg"));
+        assert_eq!(content_type(Path::new("cover.jpeg")), Some("image/jpeg"));
+        assert_eq!(content_type(Path::new("cover.png")), Some("image/png"));
+        assert_eq!(content_type(Path::new("ch1.mp3")), Some("audio/mpeg"));
+    }
+
+    #[test]
+    fn returns_none_for_unknown_extensions() {
+        assert_eq!(content_type(Path::new("notes.txt")), None);
+        assert_eq!(content_type(Path::new("README")), None);
+    }
+}
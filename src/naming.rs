@@ -0,0 +1,291 @@
+//! How sloop names things: the S3 key each file is uploaded to, the public URL of each key and the
+//! title and slug of each episode. The feed, the uploader and the command line all go through
+//! these functions, so other tools can use them to predict exactly what sloop will publish.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::path::Path;
+
+/// Everything but ASCII letters, digits, `.` and `_` is percent-encoded in URLs.
+const ESCAPE_CHAR_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'.').remove(b'_');
+
+/// Percent-encodes a file name, or any other single URL path segment.
+///
+/// ```
+/// use sloop::naming::escape_file_name;
+///
+/// assert_eq!(escape_file_name("Chapter 1 (Intro).mp3"), "Chapter%201%20%28Intro%29.mp3");
+/// ```
+pub fn escape_file_name(name: &str) -> String {
+    utf8_percent_encode(name, ESCAPE_CHAR_SET).to_string()
+}
+
+/// The S3 key a file is uploaded to, from which its public URL is also derived, so that feed
+/// enclosures always point at the object that was uploaded.
+///
+/// ```
+/// use sloop::naming::ObjectKey;
+/// use std::path::Path;
+///
+/// let key = ObjectKey::from_path("books/candide", Path::new("Chapter 1 & 2.mp3"));
+/// assert_eq!(key.key(), "books/candide/Chapter 1 & 2.mp3");
+/// assert_eq!(
+///     key.url("https://candide.s3.ap-southeast-2.amazonaws.com"),
+///     "https://candide.s3.ap-southeast-2.amazonaws.com/books/candide/Chapter%201%20%26%202.mp3"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectKey(String);
+
+impl ObjectKey {
+    /// The key for a file named `name` under `prefix`. Leading and trailing slashes on the prefix
+    /// are ignored, and an empty prefix means the bucket root.
+    pub fn new(prefix: &str, name: &str) -> Self {
+        match prefix.trim_matches('/') {
+            "" => Self(name.to_owned()),
+            prefix => Self(format!("{}/{}", prefix, name)),
+        }
+    }
+
+    /// The key for `path`'s file name under `prefix`.
+    ///
+    /// # Panics
+    ///
+    /// If `path` has no file name or it isn't valid UTF-8.
+    pub fn from_path(prefix: &str, path: &Path) -> Self {
+        Self::new(prefix, path.file_name().unwrap().to_str().unwrap())
+    }
+
+    /// The unescaped key, as sent to S3.
+    pub fn key(&self) -> &str {
+        &self.0
+    }
+
+    /// The object's public URL under `bucket_url`; see [`url_for_key`].
+    pub fn url(&self, bucket_url: &str) -> String {
+        url_for_key(bucket_url, &self.0)
+    }
+}
+
+/// The S3 key a file is uploaded to: its file name under `prefix`. Leading and trailing slashes
+/// on the prefix are ignored, and an empty prefix means the bucket root. The key is not escaped.
+///
+/// ```
+/// use sloop::naming::key_for_path;
+/// use std::path::Path;
+///
+/// let path = Path::new("/home/me/Candide/Chapter 1.mp3");
+/// assert_eq!(key_for_path("books/candide/", path), "books/candide/Chapter 1.mp3");
+/// assert_eq!(key_for_path("", path), "Chapter 1.mp3");
+/// ```
+///
+/// # Panics
+///
+/// If `path` has no file name or it isn't valid UTF-8.
+pub fn key_for_path(prefix: &str, path: &Path) -> String {
+    ObjectKey::from_path(prefix, path).0
+}
+
+/// The public URL of `key` under `bucket_url`, escaping each segment of the key. Empty segments
+/// are skipped, so a prefix (with or without slashes) can be passed as the key to get the URL of
+/// the folder it names.
+///
+/// ```
+/// use sloop::naming::url_for_key;
+///
+/// let bucket_url = "https://candide.s3.ap-southeast-2.amazonaws.com";
+/// assert_eq!(
+///     url_for_key(bucket_url, "books/candide/Chapter 1.mp3"),
+///     "https://candide.s3.ap-southeast-2.amazonaws.com/books/candide/Chapter%201.mp3"
+/// );
+/// ```
+pub fn url_for_key(bucket_url: &str, key: &str) -> String {
+    let mut url = bucket_url.trim_end_matches('/').to_owned();
+    for segment in key.split('/').filter(|segment| !segment.is_empty()) {
+        url.push('/');
+        url.push_str(&escape_file_name(segment));
+    }
+    url
+}
+
+/// An episode's title, derived from its file name without the extension.
+///
+/// ```
+/// use sloop::naming::title_from_stem;
+///
+/// assert_eq!(title_from_stem("Chapter_01_The_Garden"), "Chapter 01 The Garden");
+/// ```
+pub fn title_from_stem(stem: &str) -> String {
+    stem.replace('_', " ")
+}
+
+/// `stem` without the track number it starts with, e.g. from a CD rip: up to three digits and
+/// then a run of spaces, dashes, dots and underscores. Stems that are only a number, start with
+/// a longer one such as a year, or have a number with a decimal point are left alone.
+///
+/// ```
+/// use sloop::naming::strip_track_number;
+///
+/// assert_eq!(strip_track_number("03 - The Council of Elrond"), "The Council of Elrond");
+/// assert_eq!(strip_track_number("1984 Chapter 1"), "1984 Chapter 1");
+/// ```
+pub fn strip_track_number(stem: &str) -> &str {
+    let digits = stem.len() - stem.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || digits > 3 {
+        return stem;
+    }
+    let separated = &stem[digits..];
+    let rest = separated.trim_start_matches([' ', '-', '.', '_']);
+    let separators = &separated[..separated.len() - rest.len()];
+    let decimal = separators == "." && rest.starts_with(|c: char| c.is_ascii_digit());
+    if separators.is_empty() || rest.is_empty() || decimal {
+        return stem;
+    }
+    rest
+}
+
+/// A URL-friendly version of a title: lower-cased, with each run of anything but letters and
+/// digits replaced by a single hyphen.
+///
+/// ```
+/// use sloop::naming::slug_from_title;
+///
+/// assert_eq!(slug_from_title("Chapter 1: The Garden!"), "chapter-1-the-garden");
+/// ```
+pub fn slug_from_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_track_number_leaves_titles_that_start_with_numbers() {
+        let stems = [
+            ("03 - The Council of Elrond", "The Council of Elrond"),
+            ("03_The_Council_of_Elrond", "The_Council_of_Elrond"),
+            ("1. A Long-expected Party", "A Long-expected Party"),
+            ("001-Prologue", "Prologue"),
+            ("12 . - _ Riddles in the Dark", "Riddles in the Dark"),
+            ("7", "7"),
+            ("07 - ", "07 - "),
+            ("1984 Chapter 1", "1984 Chapter 1"),
+            ("2001_A_Space_Odyssey", "2001_A_Space_Odyssey"),
+            ("3rd Interlude", "3rd Interlude"),
+            ("1.5 Interlude", "1.5 Interlude"),
+            ("01 - 02 Duet", "02 Duet"),
+            ("Chapter 03", "Chapter 03"),
+            ("", ""),
+        ];
+        for (stem, stripped) in stems.iter() {
+            assert_eq!(strip_track_number(stem), *stripped, "stripping {:?}", stem);
+        }
+    }
+
+    #[test]
+    fn escape_file_name_keeps_only_safe_characters() {
+        assert_eq!(escape_file_name("file_1.mp3"), "file_1.mp3");
+        assert_eq!(
+            escape_file_name("a b+c&d#e?.mp3"),
+            "a%20b%2Bc%26d%23e%3F.mp3"
+        );
+        assert_eq!(escape_file_name("a-b~c.mp3"), "a%2Db%7Ec.mp3");
+        assert_eq!(escape_file_name("a/b"), "a%2Fb");
+        assert_eq!(escape_file_name("Café.mp3"), "Caf%C3%A9.mp3");
+    }
+
+    #[test]
+    fn key_for_path_normalises_prefix() {
+        let path = Path::new("dir/file.mp3");
+        for prefix in &["books/a", "books/a/", "/books/a/", "//books/a//"] {
+            assert_eq!(
+                key_for_path(prefix, path),
+                "books/a/file.mp3",
+                "{:?}",
+                prefix
+            );
+        }
+        assert_eq!(key_for_path("/", path), "file.mp3");
+    }
+
+    #[test]
+    fn key_for_path_leaves_names_unescaped() {
+        let path = Path::new("My Book/Chapter 1 (Intro).mp3");
+        assert_eq!(
+            key_for_path("books/My Book", path),
+            "books/My Book/Chapter 1 (Intro).mp3"
+        );
+    }
+
+    #[test]
+    fn url_for_key_escapes_each_segment() {
+        assert_eq!(
+            url_for_key("https://eg.test", "books/My Book/Chapter 1.mp3"),
+            "https://eg.test/books/My%20Book/Chapter%201.mp3"
+        );
+        assert_eq!(
+            url_for_key("https://eg.test/", "/books/a/"),
+            "https://eg.test/books/a"
+        );
+        assert_eq!(url_for_key("https://eg.test", ""), "https://eg.test");
+    }
+
+    #[test]
+    fn url_for_key_matches_key_for_path() {
+        let path = Path::new("Chapter 1.mp3");
+        assert_eq!(
+            url_for_key("https://eg.test", &key_for_path("books/a/", path)),
+            format!(
+                "{}/{}",
+                url_for_key("https://eg.test", "books/a"),
+                escape_file_name("Chapter 1.mp3")
+            )
+        );
+    }
+
+    #[test]
+    fn object_key_url_decodes_to_key() {
+        let names = [
+            "Chapter 1.mp3",
+            "1+1=2.mp3",
+            "Chapter 1 & 2.mp3",
+            "Track #3.mp3",
+            "Café – Épisode 1.mp3",
+            "第一章.mp3",
+        ];
+        for name in &names {
+            let key = ObjectKey::from_path("books/My Book", Path::new(name));
+            assert_eq!(key.key(), format!("books/My Book/{}", name));
+            let url = key.url("https://eg.test");
+            let path = url.strip_prefix("https://eg.test/").unwrap();
+            assert!(
+                !path.contains(|c: char| " +&#".contains(c) || !c.is_ascii()),
+                "{}",
+                url
+            );
+            let decoded = percent_encoding::percent_decode_str(path)
+                .decode_utf8()
+                .unwrap();
+            assert_eq!(decoded, key.key());
+        }
+    }
+
+    #[test]
+    fn title_from_stem_replaces_underscores() {
+        assert_eq!(title_from_stem("file1"), "file1");
+        assert_eq!(title_from_stem("__a_b"), "  a b");
+        assert_eq!(title_from_stem("a-b c"), "a-b c");
+    }
+
+    #[test]
+    fn slug_from_title_collapses_separators() {
+        assert_eq!(slug_from_title("Chapter_1 (Intro)"), "chapter-1-intro");
+        assert_eq!(slug_from_title("  Ünïcode Title  "), "ünïcode-title");
+        assert_eq!(slug_from_title("!!!"), "");
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// How a run ended. Each outcome has its own exit code, so scripts such as cron wrappers can
+/// tell "nothing to do" from "published" from "failed" without parsing output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Changes were published, or with `--dry-run` or `--plan` would be. Commands that don't
+    /// publish anything also end this way when they succeed.
+    Published,
+    /// The run succeeded but the bucket already had every file, so nothing was uploaded.
+    Unchanged,
+    /// The arguments, files or feed were rejected before any request was sent.
+    Invalid,
+    /// Uploading failed after some objects in the bucket had already been changed.
+    PartialUpload,
+    /// Any other failure.
+    Failed,
+    /// The run was stopped by Ctrl-C before it finished.
+    Cancelled,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Published => 0,
+            Outcome::Unchanged => 3,
+            Outcome::Invalid => 4,
+            Outcome::PartialUpload => 5,
+            Outcome::Failed => 1,
+            // As for a shell command killed by SIGINT.
+            Outcome::Cancelled => 130,
+        }
+    }
+
+    /// The name used for the outcome in JSON output, e.g. `partial_upload`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Published => "published",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Invalid => "invalid",
+            Outcome::PartialUpload => "partial_upload",
+            Outcome::Failed => "failed",
+            Outcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// What a successful publish did.
+#[derive(Debug, PartialEq)]
+pub struct Published {
+    /// Either `Published` or `Unchanged`.
+    pub outcome: Outcome,
+    pub feed_urls: Vec<String>,
+}
+
+/// Why a run failed, with the outcome that decides its exit code.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub outcome: Outcome,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn invalid(message: String) -> Self {
+        Self {
+            outcome: Outcome::Invalid,
+            message,
+        }
+    }
+
+    pub fn failed(message: String) -> Self {
+        Self {
+            outcome: Outcome::Failed,
+            message,
+        }
+    }
+
+    pub fn cancelled() -> Self {
+        Self {
+            outcome: Outcome::Cancelled,
+            message: "Cancelled".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_outcome_has_its_own_exit_code() {
+        let outcomes = [
+            Outcome::Published,
+            Outcome::Unchanged,
+            Outcome::Invalid,
+            Outcome::PartialUpload,
+            Outcome::Failed,
+            Outcome::Cancelled,
+        ];
+        let codes: Vec<i32> = outcomes.iter().map(|outcome| outcome.exit_code()).collect();
+        assert_eq!(codes, [0, 3, 4, 5, 1, 130]);
+        let names: Vec<&str> = outcomes.iter().map(|outcome| outcome.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "published",
+                "unchanged",
+                "invalid",
+                "partial_upload",
+                "failed",
+                "cancelled"
+            ]
+        );
+    }
+}
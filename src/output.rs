@@ -0,0 +1,225 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Creates a file that sloop writes output to, first creating any missing parent directories
+/// when `create_dirs` is set. Errors name the path that couldn't be created.
+pub fn create_file(path: &Path, create_dirs: bool) -> Result<File, Error> {
+    let with_path = |err: Error| Error::new(err.kind(), format!("{}: {}", path.display(), err));
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            if !create_dirs {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "{}: directory {} does not exist",
+                        path.display(),
+                        parent.display()
+                    ),
+                ));
+            }
+            fs::create_dir_all(parent).map_err(with_path)?;
+        }
+    }
+    File::create(path).map_err(with_path)
+}
+
+/// An output file written beside its final path with a `.partial` suffix and only moved into
+/// place by `finish`. Dropping it unfinished, when a run fails or is cancelled, removes it, so
+/// the previous output is left as it was rather than half-written.
+pub struct PartialFile {
+    path: PathBuf,
+    partial: PathBuf,
+    file: File,
+    finished: bool,
+}
+
+impl PartialFile {
+    /// Starts writing `path`, creating missing parent directories as `create_file` does.
+    pub fn create(path: &Path, create_dirs: bool) -> Result<Self, Error> {
+        let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".partial");
+        let partial = path.with_file_name(name);
+        let file = create_file(&partial, create_dirs)?;
+        Ok(Self {
+            path: path.to_owned(),
+            partial,
+            file,
+            finished: false,
+        })
+    }
+
+    /// Moves the written file into place, replacing any file at its path.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.file.flush()?;
+        fs::rename(&self.partial, &self.path)
+            .map_err(|err| Error::new(err.kind(), format!("{}: {}", self.path.display(), err)))?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// A directory of its own under the system's temporary directory for files a run writes out on
+/// its way to publishing them, removed with everything in it when dropped, whether the run
+/// finished, failed or was cancelled. It's created fresh, with a name no other run can guess,
+/// and on Unix only its owner can use it, so nothing planted in the temporary directory beforehand,
+/// such as a symlink, can redirect what's written to it.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a directory named after `label`, e.g. `sloop-art-4242-0-1731660000123456789`.
+    pub fn create(label: &str) -> Result<Self, Error> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos());
+            let path = std::env::temp_dir().join(format!(
+                "sloop-{}-{}-{}-{}",
+                label,
+                process::id(),
+                CREATED.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            // Unlike `create_dir_all`, fails if anything, even a dangling symlink, is already
+            // there, in which case another name is tried.
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => {
+                    return Err(Error::new(
+                        err.kind(),
+                        format!("{}: {}", path.display(), err),
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sloop-output-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn creates_missing_parent_directories() {
+        let dir = temp_dir("create");
+        let path = dir.join("podcasts/dune/feed.xml");
+        create_file(&path, true).unwrap();
+        assert!(path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn partial_files_replace_the_output_once_finished() {
+        let dir = temp_dir("partial");
+        let path = dir.join("feed.xml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "old").unwrap();
+        let mut file = PartialFile::create(&path, false).unwrap();
+        file.write_all(b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        file.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unfinished_partial_files_are_removed() {
+        let dir = temp_dir("unfinished");
+        let path = dir.join("feed.xml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "old").unwrap();
+        let mut file = PartialFile::create(&path, false).unwrap();
+        file.write_all(b"ne").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn temp_dirs_are_new_and_removed_when_dropped() {
+        let dir = TempDir::create("output-test").unwrap();
+        let other = TempDir::create("output-test").unwrap();
+        assert_ne!(dir.path(), other.path());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let path = dir.path().to_owned();
+        fs::create_dir_all(path.join("art")).unwrap();
+        fs::write(path.join("art/Chapter_1.mp3.jpg"), "art").unwrap();
+        drop(dir);
+        assert!(!path.exists());
+        assert!(other.path().exists());
+    }
+
+    #[test]
+    fn reports_missing_directory_when_not_creating() {
+        let dir = temp_dir("no-create");
+        let path = dir.join("podcasts/feed.xml");
+        let err = create_file(&path, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().contains(&path.display().to_string()));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn includes_path_in_other_errors() {
+        let dir = temp_dir("error");
+        fs::create_dir_all(&dir).unwrap();
+        let err = create_file(&dir, true).unwrap_err();
+        assert!(err.to_string().starts_with(&dir.display().to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
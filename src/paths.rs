@@ -0,0 +1,219 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+const APP_NAME: &str = "sloop";
+const CONFIG_FILE_NAME: &str = "config.toml";
+pub const PROJECT_CONFIG_FILE_NAME: &str = "sloop.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    Unix,
+    MacOs,
+    Windows,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Unix
+        }
+    }
+}
+
+/// Locations of the files sloop reads and writes between runs.
+#[derive(Debug, PartialEq)]
+pub struct Paths {
+    /// User-wide configuration, e.g. `$XDG_CONFIG_HOME/sloop/config.toml`.
+    pub config_file: PathBuf,
+    /// Per-project configuration in the working directory, which overrides `config_file`.
+    pub project_config_file: PathBuf,
+    /// Directory holding a subdirectory of state and journal files for each podcast.
+    pub state_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolves paths for the current platform and environment, applying any command-line
+    /// overrides.
+    pub fn resolve(config_file: Option<PathBuf>, state_dir: Option<PathBuf>) -> Self {
+        let cwd = env::current_dir().unwrap_or_default();
+        Self::resolve_with(
+            Platform::current(),
+            |name| env::var_os(name).map(PathBuf::from),
+            &cwd,
+            config_file,
+            state_dir,
+        )
+    }
+
+    pub fn resolve_with<E: Fn(&str) -> Option<PathBuf>>(
+        platform: Platform,
+        env: E,
+        cwd: &Path,
+        config_file: Option<PathBuf>,
+        state_dir: Option<PathBuf>,
+    ) -> Self {
+        let var = |name: &str| env(name).filter(|path| path.is_absolute());
+        let home = || env("HOME").unwrap_or_default();
+        let (config_dir, state_base) = match platform {
+            Platform::Windows => {
+                let roaming = env("APPDATA").unwrap_or_else(|| home().join("AppData\\Roaming"));
+                let local = env("LOCALAPPDATA").unwrap_or_else(|| home().join("AppData\\Local"));
+                (roaming.join(APP_NAME), local.join(APP_NAME).join("state"))
+            }
+            Platform::MacOs => {
+                let support = home().join("Library/Application Support").join(APP_NAME);
+                (
+                    var("XDG_CONFIG_HOME")
+                        .map(|dir| dir.join(APP_NAME))
+                        .unwrap_or_else(|| support.clone()),
+                    var("XDG_STATE_HOME")
+                        .map(|dir| dir.join(APP_NAME))
+                        .unwrap_or_else(|| support.join("state")),
+                )
+            }
+            Platform::Unix => (
+                var("XDG_CONFIG_HOME")
+                    .unwrap_or_else(|| home().join(".config"))
+                    .join(APP_NAME),
+                var("XDG_STATE_HOME")
+                    .unwrap_or_else(|| home().join(".local/state"))
+                    .join(APP_NAME),
+            ),
+        };
+        Self {
+            config_file: config_file.unwrap_or_else(|| config_dir.join(CONFIG_FILE_NAME)),
+            project_config_file: cwd.join(PROJECT_CONFIG_FILE_NAME),
+            state_dir: state_dir.unwrap_or(state_base),
+        }
+    }
+
+    /// Directory for one podcast's state and journal files.
+    pub fn podcast_state_dir(&self, podcast: &str) -> PathBuf {
+        let name: String = podcast
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' => '_',
+                c => c,
+            })
+            .collect();
+        self.state_dir.join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(platform: Platform, vars: &[(&str, &str)]) -> Paths {
+        let vars: HashMap<String, PathBuf> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), PathBuf::from(value)))
+            .collect();
+        Paths::resolve_with(
+            platform,
+            |name| vars.get(name).cloned(),
+            Path::new("/project"),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn uses_xdg_directories() {
+        let paths = resolve(
+            Platform::Unix,
+            &[
+                ("HOME", "/home/user"),
+                ("XDG_CONFIG_HOME", "/xdg/config"),
+                ("XDG_STATE_HOME", "/xdg/state"),
+            ],
+        );
+        assert_eq!(
+            paths.config_file,
+            PathBuf::from("/xdg/config/sloop/config.toml")
+        );
+        assert_eq!(paths.state_dir, PathBuf::from("/xdg/state/sloop"));
+        assert_eq!(
+            paths.project_config_file,
+            PathBuf::from("/project/sloop.toml")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_home_when_xdg_unset() {
+        let paths = resolve(Platform::Unix, &[("HOME", "/home/user")]);
+        assert_eq!(
+            paths.config_file,
+            PathBuf::from("/home/user/.config/sloop/config.toml")
+        );
+        assert_eq!(
+            paths.state_dir,
+            PathBuf::from("/home/user/.local/state/sloop")
+        );
+    }
+
+    #[test]
+    fn ignores_relative_xdg_directories() {
+        let paths = resolve(
+            Platform::Unix,
+            &[("HOME", "/home/user"), ("XDG_CONFIG_HOME", "relative")],
+        );
+        assert_eq!(
+            paths.config_file,
+            PathBuf::from("/home/user/.config/sloop/config.toml")
+        );
+    }
+
+    #[test]
+    fn uses_application_support_on_macos() {
+        let paths = resolve(Platform::MacOs, &[("HOME", "/Users/user")]);
+        assert_eq!(
+            paths.config_file,
+            PathBuf::from("/Users/user/Library/Application Support/sloop/config.toml")
+        );
+        assert_eq!(
+            paths.state_dir,
+            PathBuf::from("/Users/user/Library/Application Support/sloop/state")
+        );
+    }
+
+    #[test]
+    fn uses_appdata_on_windows() {
+        let paths = resolve(
+            Platform::Windows,
+            &[("APPDATA", "/roaming"), ("LOCALAPPDATA", "/local")],
+        );
+        assert_eq!(
+            paths.config_file,
+            PathBuf::from("/roaming/sloop/config.toml")
+        );
+        assert_eq!(paths.state_dir, PathBuf::from("/local/sloop/state"));
+    }
+
+    #[test]
+    fn command_line_overrides_take_precedence() {
+        let paths = Paths::resolve_with(
+            Platform::Unix,
+            |_| Some(PathBuf::from("/xdg")),
+            Path::new("/project"),
+            Some(PathBuf::from("custom.toml")),
+            Some(PathBuf::from("/custom/state")),
+        );
+        assert_eq!(paths.config_file, PathBuf::from("custom.toml"));
+        assert_eq!(paths.state_dir, PathBuf::from("/custom/state"));
+    }
+
+    #[test]
+    fn podcast_state_dir_is_a_single_subdirectory() {
+        let paths = resolve(Platform::Unix, &[("XDG_STATE_HOME", "/state")]);
+        assert_eq!(
+            paths.podcast_state_dir("books/dune"),
+            PathBuf::from("/state/sloop/books_dune")
+        );
+    }
+}
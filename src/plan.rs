@@ -0,0 +1,237 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What publishing does with one object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// No object has the key yet.
+    Create,
+    /// The object differs from the local file.
+    Update,
+    /// The object already matches the local file, so it isn't uploaded again.
+    Skip,
+    /// No local file has the key any more, and `--prune` is set.
+    Delete,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Skip => "skip",
+            Action::Delete => "delete",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedObject {
+    pub key: String,
+    pub action: Action,
+    pub size: u64,
+    pub content_type: String,
+}
+
+/// The order files are sent in. Feeds always go last, after the episodes they link to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadOrder {
+    /// As given on the command line.
+    Input,
+    /// Smallest first, so a flaky connection secures as many episodes as it can before a large
+    /// one.
+    #[default]
+    Size,
+    LargestFirst,
+}
+
+impl UploadOrder {
+    pub const VALUES: &'static [&'static str] = &["input", "size", "largest-first"];
+}
+
+impl FromStr for UploadOrder {
+    type Err = String;
+
+    fn from_str(order: &str) -> Result<Self, Self::Err> {
+        match order {
+            "input" => Ok(UploadOrder::Input),
+            "size" => Ok(UploadOrder::Size),
+            "largest-first" => Ok(UploadOrder::LargestFirst),
+            _ => Err(format!(
+                "{} is not one of {}",
+                order,
+                UploadOrder::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// The indices of `objects` in the order to upload them: feeds last, in their given order, and
+/// everything else as `order` says. Objects of the same size keep their given order.
+pub fn upload_order(objects: &[PlannedObject], order: UploadOrder) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..objects.len()).collect();
+    indices.sort_by_key(|&i| {
+        let object = &objects[i];
+        let size = match order {
+            _ if is_feed(object) => 0,
+            UploadOrder::Input => 0,
+            UploadOrder::Size => object.size as i128,
+            UploadOrder::LargestFirst => -(object.size as i128),
+        };
+        (is_feed(object), size)
+    });
+    indices
+}
+
+fn is_feed(object: &PlannedObject) -> bool {
+    object.content_type == "application/rss+xml" || object.content_type == "application/feed+json"
+}
+
+/// Everything a publish would do, as printed by `--plan`. The output is sorted and contains no
+/// timestamps, so plans from different runs can be committed and diffed.
+#[derive(Debug, PartialEq)]
+pub struct Plan {
+    /// Where the objects go, e.g. the bucket URL.
+    pub target: String,
+    /// Channel fields and episodes of the feed being published, in feed order.
+    pub channel: Vec<(&'static str, String)>,
+    pub objects: Vec<PlannedObject>,
+}
+
+impl Plan {
+    /// Whether publishing would create or update any object.
+    pub fn has_changes(&self) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.action != Action::Skip)
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "target {}", self.target)?;
+        writeln!(f)?;
+        writeln!(f, "feed")?;
+        for (name, value) in &self.channel {
+            writeln!(f, "  {:<9} {}", name, value)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "objects")?;
+        let mut objects: Vec<&PlannedObject> = self.objects.iter().collect();
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        for object in objects {
+            writeln!(
+                f,
+                "  {:<6} {} ({} B, {})",
+                object.action, object.key, object.size, object.content_type
+            )?;
+        }
+        let count = |action| {
+            self.objects
+                .iter()
+                .filter(|object| object.action == action)
+                .count()
+        };
+        writeln!(f)?;
+        write!(
+            f,
+            "{} to create, {} to update, {} unchanged",
+            count(Action::Create),
+            count(Action::Update),
+            count(Action::Skip)
+        )?;
+        match count(Action::Delete) {
+            0 => writeln!(f),
+            deletes => writeln!(f, ", {} to delete", deletes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str, size: u64, content_type: &str) -> PlannedObject {
+        PlannedObject {
+            key: key.to_owned(),
+            action: Action::Create,
+            size,
+            content_type: content_type.to_owned(),
+        }
+    }
+
+    fn objects() -> Vec<PlannedObject> {
+        vec![
+            object("feed.xml", 1, "application/rss+xml"),
+            object("Omnibus.mp3", 900, "audio/mpeg"),
+            object("Chapter_1.mp3", 30, "audio/mpeg"),
+            object("feed.json", 2, "application/feed+json"),
+            object("cover.jpg", 30, "image/jpeg"),
+            object("Chapter_2.mp3", 20, "audio/mpeg"),
+        ]
+    }
+
+    fn ordered_keys(order: UploadOrder) -> Vec<String> {
+        let objects = objects();
+        upload_order(&objects, order)
+            .into_iter()
+            .map(|i| objects[i].key.clone())
+            .collect()
+    }
+
+    #[test]
+    fn uploads_smallest_first_by_default_with_feeds_last() {
+        assert_eq!(
+            ordered_keys(UploadOrder::default()),
+            vec![
+                "Chapter_2.mp3",
+                "Chapter_1.mp3",
+                "cover.jpg",
+                "Omnibus.mp3",
+                "feed.xml",
+                "feed.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn uploads_largest_first() {
+        assert_eq!(
+            ordered_keys(UploadOrder::LargestFirst),
+            vec![
+                "Omnibus.mp3",
+                "Chapter_1.mp3",
+                "cover.jpg",
+                "Chapter_2.mp3",
+                "feed.xml",
+                "feed.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn uploads_in_input_order_with_feeds_last() {
+        assert_eq!(
+            ordered_keys(UploadOrder::Input),
+            vec![
+                "Omnibus.mp3",
+                "Chapter_1.mp3",
+                "cover.jpg",
+                "Chapter_2.mp3",
+                "feed.xml",
+                "feed.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_upload_orders() {
+        for value in UploadOrder::VALUES {
+            assert!(value.parse::<UploadOrder>().is_ok(), "{}", value);
+        }
+        assert_eq!(
+            "smallest".parse::<UploadOrder>(),
+            Err("smallest is not one of input, size, largest-first".to_owned())
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::human::Numbers;
+use crate::log::Log;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Receives upload progress, e.g. to draw progress bars. Calls come from every upload thread, so
+/// several files may be in progress at once.
+pub trait Progress: Send + Sync {
+    /// `key` has started uploading. `number` counts files from 1 up to `total_files`.
+    fn start_file(&self, _key: &str, _number: usize, _total_files: usize, _len: u64) {}
+
+    /// Another `bytes` of `key` have been sent.
+    fn advance(&self, _key: &str, _bytes: u64) {}
+
+    /// `key` has finished uploading, successfully or not.
+    fn finish_file(&self, _key: &str) {}
+}
+
+/// Ignores progress, e.g. for `--quiet` or when there's no terminal to draw on.
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// Draws a bar for each file being uploaded under an overall "file N of M" line on stderr.
+pub struct TerminalProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    bars: Mutex<HashMap<String, ProgressBar>>,
+    numbers: Numbers,
+}
+
+impl TerminalProgress {
+    pub fn new(numbers: Numbers) -> Self {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let overall = multi.add(ProgressBar::new_spinner());
+        overall.set_style(ProgressStyle::with_template("{msg}").unwrap());
+        Self {
+            multi,
+            overall,
+            bars: Mutex::new(HashMap::new()),
+            numbers,
+        }
+    }
+
+    /// A log that prints above the bars instead of through them.
+    pub fn log(self: &Arc<Self>) -> Log {
+        let progress = Arc::clone(self);
+        Arc::new(move |line| {
+            // Bars aren't drawn when stderr isn't a terminal, and neither is anything printed
+            // through them.
+            if progress.multi.is_hidden() || progress.multi.println(line).is_err() {
+                println!("{}", line);
+            }
+        })
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn start_file(&self, key: &str, number: usize, total_files: usize, len: u64) {
+        self.overall
+            .set_message(format!("Uploading file {} of {}", number, total_files));
+        let bar = self.multi.add(ProgressBar::new(len));
+        let numbers = self.numbers;
+        bar.set_style(
+            ProgressStyle::with_template("{msg:30!} [{bar:30}] {sent}/{size} {rate}")
+                .unwrap()
+                .with_key(
+                    "sent",
+                    move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                        let _ = w.write_str(&numbers.bytes(state.pos()));
+                    },
+                )
+                .with_key(
+                    "size",
+                    move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                        let _ = w.write_str(&numbers.bytes(state.len().unwrap_or(0)));
+                    },
+                )
+                .with_key(
+                    "rate",
+                    move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                        let _ = write!(w, "{}/s", numbers.bytes(state.per_sec() as u64));
+                    },
+                )
+                .progress_chars("=> "),
+        );
+        bar.set_message(key.to_owned());
+        self.bars.lock().unwrap().insert(key.to_owned(), bar);
+    }
+
+    fn advance(&self, key: &str, bytes: u64) {
+        if let Some(bar) = self.bars.lock().unwrap().get(key) {
+            bar.inc(bytes);
+        }
+    }
+
+    fn finish_file(&self, key: &str) {
+        if let Some(bar) = self.bars.lock().unwrap().remove(key) {
+            bar.finish_and_clear();
+            self.multi.remove(&bar);
+        }
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        self.overall.finish_and_clear();
+    }
+}
@@ -0,0 +1,359 @@
+//! Secrets kept in the OS keychain instead of in config files or the environment, where they
+//! are referred to as `keyring:SERVICE/NAME`, e.g. `keyring:sloop/webhook`.
+
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+
+/// What a value starts with to refer to a secret instead of being one.
+pub const SCHEME: &str = "keyring:";
+
+/// Where a secret is kept in the keychain: the service it belongs to and its name there.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entry {
+    pub service: String,
+    pub name: String,
+}
+
+impl FromStr for Entry {
+    type Err = String;
+
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        match entry.split_once('/') {
+            Some((service, name)) if !service.is_empty() && !name.is_empty() => Ok(Entry {
+                service: service.to_owned(),
+                name: name.to_owned(),
+            }),
+            _ => Err(format!(
+                "{:?} must be SERVICE/NAME, e.g. sloop/webhook",
+                entry
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.service, self.name)
+    }
+}
+
+/// Somewhere secrets are kept, the OS keychain outside of tests.
+pub trait SecretStore {
+    /// The secret kept at `entry`, or `None` if there's none.
+    fn get(&self, entry: &Entry) -> Result<Option<String>, String>;
+    /// Keeps `secret` at `entry`, replacing any there.
+    fn set(&self, entry: &Entry, secret: &str) -> Result<(), String>;
+    /// Removes the secret at `entry`, returning whether there was one.
+    fn delete(&self, entry: &Entry) -> Result<bool, String>;
+}
+
+/// `value` itself or, if it's written `keyring:SERVICE/NAME`, the secret it refers to. Fails
+/// naming the entry if there's no such secret.
+pub fn resolve(value: &str, store: &dyn SecretStore) -> Result<String, String> {
+    let entry: Entry = match value.strip_prefix(SCHEME) {
+        Some(entry) => entry.parse()?,
+        None => return Ok(value.to_owned()),
+    };
+    match store.get(&entry) {
+        Ok(Some(secret)) => Ok(secret),
+        Ok(None) => Err(format!(
+            "No secret {} in the keychain, which {}{} refers to; add it with `sloop secret set {}`",
+            entry, SCHEME, entry, entry
+        )),
+        Err(err) => Err(format!(
+            "Failed to read the secret {} from the keychain: {}",
+            entry, err
+        )),
+    }
+}
+
+/// The OS keychain, used through its command-line tool: `security` on macOS and `secret-tool`
+/// for the Secret Service elsewhere.
+pub struct Keychain;
+
+#[cfg(target_os = "macos")]
+impl SecretStore for Keychain {
+    fn get(&self, entry: &Entry) -> Result<Option<String>, String> {
+        let output = tool(
+            "security",
+            &[
+                "find-generic-password",
+                "-s",
+                &entry.service,
+                "-a",
+                &entry.name,
+                "-w",
+            ],
+            None,
+        )?;
+        match output.status.code() {
+            Some(0) => Ok(Some(secret_from(&output.stdout))),
+            Some(NOT_FOUND) => Ok(None),
+            _ => Err(failure("security", &output)),
+        }
+    }
+
+    /// The secret is given on stdin, as a command to `security -i`, so that it never appears
+    /// among the process's arguments, where other users could see it.
+    fn set(&self, entry: &Entry, secret: &str) -> Result<(), String> {
+        let output = tool("security", &["-i"], Some(&add_command(entry, secret)))?;
+        // `security -i` exits 0 even when a command fails, saying why on stderr.
+        match output.status.success() && output.stderr.is_empty() {
+            true => Ok(()),
+            false => Err(failure("security", &output)),
+        }
+    }
+
+    fn delete(&self, entry: &Entry) -> Result<bool, String> {
+        let output = tool(
+            "security",
+            &[
+                "delete-generic-password",
+                "-s",
+                &entry.service,
+                "-a",
+                &entry.name,
+            ],
+            None,
+        )?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(NOT_FOUND) => Ok(false),
+            _ => Err(failure("security", &output)),
+        }
+    }
+}
+
+/// The `security -i` command keeping `secret` at `entry`, replacing any there. The secret is
+/// given in hex, so nothing in it needs quoting.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn add_command(entry: &Entry, secret: &str) -> String {
+    let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+    let hex: String = secret.bytes().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "add-generic-password -U -s {} -a {} -X {}\n",
+        quote(&entry.service),
+        quote(&entry.name),
+        hex
+    )
+}
+
+/// `security`'s exit code for a missing item.
+#[cfg(target_os = "macos")]
+const NOT_FOUND: i32 = 44;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl SecretStore for Keychain {
+    fn get(&self, entry: &Entry) -> Result<Option<String>, String> {
+        let attributes = attributes(entry);
+        let output = tool(
+            "secret-tool",
+            &[&["lookup"], &attributes[..]].concat(),
+            None,
+        )?;
+        // secret-tool exits 1 without a word when there's no such secret.
+        match output.status.success() {
+            true => Ok(Some(secret_from(&output.stdout))),
+            false if output.stderr.is_empty() => Ok(None),
+            false => Err(failure("secret-tool", &output)),
+        }
+    }
+
+    fn set(&self, entry: &Entry, secret: &str) -> Result<(), String> {
+        let label = format!("--label=sloop: {}", entry);
+        let attributes = attributes(entry);
+        let args = [&["store", label.as_str()], &attributes[..]].concat();
+        let output = tool("secret-tool", &args, Some(secret))?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(failure("secret-tool", &output)),
+        }
+    }
+
+    fn delete(&self, entry: &Entry) -> Result<bool, String> {
+        if self.get(entry)?.is_none() {
+            return Ok(false);
+        }
+        let attributes = attributes(entry);
+        let output = tool("secret-tool", &[&["clear"], &attributes[..]].concat(), None)?;
+        match output.status.success() {
+            true => Ok(true),
+            false => Err(failure("secret-tool", &output)),
+        }
+    }
+}
+
+/// The attributes secret-tool finds `entry` by, named as other keyring libraries name them.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn attributes(entry: &Entry) -> [&str; 4] {
+    ["service", &entry.service, "username", &entry.name]
+}
+
+#[cfg(not(unix))]
+impl SecretStore for Keychain {
+    fn get(&self, _entry: &Entry) -> Result<Option<String>, String> {
+        Err(UNSUPPORTED.to_owned())
+    }
+
+    fn set(&self, _entry: &Entry, _secret: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_owned())
+    }
+
+    fn delete(&self, _entry: &Entry) -> Result<bool, String> {
+        Err(UNSUPPORTED.to_owned())
+    }
+}
+
+#[cfg(not(unix))]
+const UNSUPPORTED: &str = "sloop can't use the Windows Credential Manager, or any keychain \
+                           but those of macOS and the Secret Service, yet; give the value itself \
+                           in the config file or environment instead";
+
+/// Runs the keychain's `program`, writing `stdin` to it if given.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn tool(program: &str, args: &[&str], stdin: Option<&str>) -> Result<Output, String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            format!(
+                "couldn't run {}, which reaches the keychain: {}",
+                program, err
+            )
+        })?;
+    if let (Some(mut pipe), Some(input)) = (child.stdin.take(), stdin) {
+        pipe.write_all(input.as_bytes())
+            .map_err(|err| format!("couldn't give {} the secret: {}", program, err))?;
+    }
+    child
+        .wait_with_output()
+        .map_err(|err| format!("{} failed: {}", program, err))
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn secret_from(stdout: &[u8]) -> String {
+    let secret = String::from_utf8_lossy(stdout);
+    secret.strip_suffix('\n').unwrap_or(&secret).to_owned()
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn failure(program: &str, output: &Output) -> String {
+    format!(
+        "{} failed ({}): {}",
+        program,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// A store kept in memory, standing in for the keychain in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore(std::sync::Mutex<std::collections::BTreeMap<Entry, String>>);
+
+#[cfg(test)]
+impl MemoryStore {
+    pub fn with(secrets: &[(&str, &str)]) -> Self {
+        let store = MemoryStore::default();
+        for (entry, secret) in secrets {
+            store.set(&entry.parse().unwrap(), secret).unwrap();
+        }
+        store
+    }
+}
+
+#[cfg(test)]
+impl SecretStore for MemoryStore {
+    fn get(&self, entry: &Entry) -> Result<Option<String>, String> {
+        Ok(self.0.lock().unwrap().get(entry).cloned())
+    }
+
+    fn set(&self, entry: &Entry, secret: &str) -> Result<(), String> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(entry.clone(), secret.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, entry: &Entry) -> Result<bool, String> {
+        Ok(self.0.lock().unwrap().remove(entry).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entries() {
+        assert_eq!(
+            "sloop/webhook".parse(),
+            Ok(Entry {
+                service: "sloop".to_owned(),
+                name: "webhook".to_owned()
+            })
+        );
+        assert_eq!(
+            "webhook".parse::<Entry>(),
+            Err("\"webhook\" must be SERVICE/NAME, e.g. sloop/webhook".to_owned())
+        );
+        assert!("sloop/".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn resolves_references_and_keeps_plain_values() {
+        let store = MemoryStore::with(&[("sloop/webhook", "https://hooks.eg.test/T0k3n")]);
+        assert_eq!(
+            resolve("keyring:sloop/webhook", &store),
+            Ok("https://hooks.eg.test/T0k3n".to_owned())
+        );
+        assert_eq!(
+            resolve("https://hooks.eg.test/plain", &store),
+            Ok("https://hooks.eg.test/plain".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_secrets_name_their_entry() {
+        assert_eq!(
+            resolve("keyring:sloop/daemon", &MemoryStore::default()),
+            Err(
+                "No secret sloop/daemon in the keychain, which keyring:sloop/daemon refers to; \
+                 add it with `sloop secret set sloop/daemon`"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            resolve("keyring:daemon", &MemoryStore::default()),
+            Err("\"daemon\" must be SERVICE/NAME, e.g. sloop/webhook".to_owned())
+        );
+    }
+
+    #[test]
+    fn macos_secrets_are_given_on_stdin_in_hex() {
+        let entry = "sloop \"x\"/web\\hook".parse().unwrap();
+        assert_eq!(
+            add_command(&entry, "T0k3n \"quoted\"\n"),
+            "add-generic-password -U -s \"sloop \\\"x\\\"\" -a \"web\\\\hook\" \
+             -X 54306b336e202271756f746564220a\n"
+        );
+    }
+
+    #[test]
+    fn memory_store_deletes_what_it_has() {
+        let store = MemoryStore::with(&[("sloop/proxy", "hunter2")]);
+        let entry = "sloop/proxy".parse().unwrap();
+        assert_eq!(store.delete(&entry), Ok(true));
+        assert_eq!(store.delete(&entry), Ok(false));
+        assert_eq!(store.get(&entry), Ok(None));
+    }
+}
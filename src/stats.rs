@@ -0,0 +1,320 @@
+use crate::human::Numbers;
+use crate::mime;
+use percent_encoding::percent_decode_str;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// The fields of an S3 server access log line that the statistics need. Lines have many more,
+/// and S3 adds new ones at the end from time to time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub bucket: String,
+    /// e.g. `REST.GET.OBJECT`.
+    pub operation: String,
+    /// The object key, decoded, or `None` for requests that don't name one.
+    pub key: Option<String>,
+    pub status: Option<u16>,
+    pub bytes_sent: u64,
+}
+
+/// Index of each field in a log line, as documented by AWS.
+const BUCKET: usize = 1;
+const OPERATION: usize = 6;
+const KEY: usize = 7;
+const STATUS: usize = 9;
+const BYTES_SENT: usize = 11;
+
+/// Parses one line of an S3 server access log, or returns `None` if it has too few fields to
+/// be one.
+pub fn parse_line(line: &str) -> Option<LogRecord> {
+    let fields = split_fields(line);
+    if fields.len() <= BYTES_SENT {
+        return None;
+    }
+    let value = |field: &str| Some(field.to_owned()).filter(|field| field != "-");
+    Some(LogRecord {
+        bucket: fields[BUCKET].to_owned(),
+        operation: fields[OPERATION].to_owned(),
+        key: value(fields[KEY])
+            .map(|key| percent_decode_str(&key).decode_utf8_lossy().into_owned()),
+        status: fields[STATUS].parse().ok(),
+        bytes_sent: fields[BYTES_SENT].parse().unwrap_or(0),
+    })
+}
+
+/// Splits a log line on spaces, keeping `[bracketed]` times and `"quoted"` fields, which
+/// contain spaces, whole. Quotes inside quoted fields are escaped with a backslash.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let end = match rest.as_bytes()[0] {
+            b'[' => rest.find(']').map(|end| end + 1),
+            b'"' => {
+                let mut escaped = false;
+                rest.char_indices().skip(1).find_map(|(i, c)| match c {
+                    _ if escaped => {
+                        escaped = false;
+                        None
+                    }
+                    '\\' => {
+                        escaped = true;
+                        None
+                    }
+                    '"' => Some(i + 1),
+                    _ => None,
+                })
+            }
+            _ => rest.find(' '),
+        }
+        .unwrap_or(rest.len());
+        let field = &rest[..end];
+        fields.push(
+            field
+                .strip_prefix('"')
+                .and_then(|field| field.strip_suffix('"'))
+                .unwrap_or(field),
+        );
+        rest = rest[end..].trim_start();
+    }
+    fields
+}
+
+/// How often one episode was fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeStats {
+    pub key: String,
+    /// Successful GET requests. Podcast apps often fetch an episode in several ranges, so this
+    /// is more than the number of listeners.
+    pub gets: u64,
+    pub bytes_sent: u64,
+}
+
+/// Tallies the GET requests for the episodes in one podcast's folder from the lines of its
+/// bucket's access logs.
+#[derive(Debug)]
+pub struct Tally {
+    bucket: String,
+    /// The folder the podcast's objects are in, ending in `/`, or empty for the bucket root.
+    folder: String,
+    episodes: BTreeMap<String, EpisodeStats>,
+    /// Lines that weren't access log lines and were skipped.
+    pub unreadable_lines: usize,
+}
+
+impl Tally {
+    /// Starts a tally of requests to `bucket` for episodes directly in `folder`, with each of
+    /// `keys` listed even if it's never fetched.
+    pub fn new(bucket: &str, folder: &str, keys: &[String]) -> Self {
+        let mut tally = Self {
+            bucket: bucket.to_owned(),
+            folder: folder.to_owned(),
+            episodes: BTreeMap::new(),
+            unreadable_lines: 0,
+        };
+        for key in keys {
+            if tally.is_episode(key) {
+                tally.episode(key);
+            }
+        }
+        tally
+    }
+
+    /// Adds the requests in the log file `log`.
+    pub fn add_log(&mut self, log: &str) {
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            match parse_line(line) {
+                Some(record) => self.add(&record),
+                None => self.unreadable_lines += 1,
+            }
+        }
+    }
+
+    fn add(&mut self, record: &LogRecord) {
+        let key = match &record.key {
+            Some(key) => key,
+            None => return,
+        };
+        let succeeded = record
+            .status
+            .is_some_and(|status| (200..300).contains(&status));
+        if record.bucket != self.bucket
+            || record.operation != "REST.GET.OBJECT"
+            || !succeeded
+            || !self.is_episode(key)
+        {
+            return;
+        }
+        let episode = self.episode(key);
+        episode.gets += 1;
+        episode.bytes_sent += record.bytes_sent;
+    }
+
+    fn episode(&mut self, key: &str) -> &mut EpisodeStats {
+        self.episodes
+            .entry(key.to_owned())
+            .or_insert_with(|| EpisodeStats {
+                key: key.to_owned(),
+                gets: 0,
+                bytes_sent: 0,
+            })
+    }
+
+    /// Whether `key` is a media file directly in the podcast's folder.
+    fn is_episode(&self, key: &str) -> bool {
+        key.strip_prefix(self.folder.as_str())
+            .is_some_and(|name| !name.contains('/'))
+            && Path::new(key)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(mime::audio_type)
+                .is_some()
+    }
+
+    /// The episodes, sorted by key.
+    pub fn episodes(self) -> Vec<EpisodeStats> {
+        self.episodes.into_values().collect()
+    }
+}
+
+/// The episodes as a table, one per line, with the number of GET requests and bytes sent.
+pub fn format(episodes: &[EpisodeStats], numbers: Numbers) -> String {
+    let rows: Vec<(String, String, &str)> = episodes
+        .iter()
+        .map(|episode| {
+            (
+                episode.gets.to_string(),
+                numbers.bytes(episode.bytes_sent),
+                episode.key.as_str(),
+            )
+        })
+        .collect();
+    let gets_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0).max(4);
+    let bytes_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0).max(4);
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "{:>gets_width$}  {:>bytes_width$}  Episode",
+        "GETs",
+        "Sent",
+        gets_width = gets_width,
+        bytes_width = bytes_width
+    );
+    for (gets, bytes, key) in rows {
+        let _ = writeln!(
+            output,
+            "{:>gets_width$}  {:>bytes_width$}  {}",
+            gets,
+            bytes,
+            key,
+            gets_width = gets_width,
+            bytes_width = bytes_width
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from a bucket's access logs, with the IDs shortened.
+    const LOG: &str = r#"79a59df900b9 candide [06/Feb/2026:00:00:38 +0000] 192.0.2.3 - 3E57427F3EXAMPLE REST.GET.OBJECT books/Chapter%201.mp3 "GET /books/Chapter%201.mp3 HTTP/1.1" 206 - 1048576 3000000 57 12 "-" "AppleCoreMedia/1.0.0.21E230 (iPhone; U; CPU OS 17_4 like Mac OS X; en_au)" - Ijb9tBH0Yc= SigV4 ECDHE-RSA-AES128-GCM-SHA256 - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2 - -
+79a59df900b9 candide [06/Feb/2026:00:00:39 +0000] 192.0.2.3 - 3E57427F3EXAMPLF REST.GET.OBJECT books/Chapter%201.mp3 "GET /books/Chapter%201.mp3 HTTP/1.1" 206 - 1951424 3000000 80 15 "-" "AppleCoreMedia/1.0.0.21E230 (iPhone; U; CPU OS 17_4 like Mac OS X; en_au)" - Ijb9tBH0Yd= SigV4 ECDHE-RSA-AES128-GCM-SHA256 - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2 - - Yes future-column
+79a59df900b9 candide [06/Feb/2026:00:01:02 +0000] 198.51.100.7 - 3E57427F3EXAMPLG REST.GET.OBJECT books/feed.xml "GET /books/feed.xml HTTP/1.1" 200 - 4821 4821 20 19 "-" "Overcast/3.0 (+http://overcast.fm/; \"quoted\" bot)" - Ijb9tBH0Ye= SigV4 - - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2
+79a59df900b9 candide [06/Feb/2026:00:01:10 +0000] 198.51.100.7 - 3E57427F3EXAMPLH REST.GET.OBJECT books/Chapter_2.mp3 "GET /books/Chapter_2.mp3 HTTP/1.1" 403 AccessDenied 243 - 9 - "-" "curl/8.4.0" - Ijb9tBH0Yf= SigV4 - - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2
+79a59df900b9 candide [06/Feb/2026:00:01:30 +0000] 198.51.100.7 - 3E57427F3EXAMPLI REST.HEAD.OBJECT books/Chapter_2.mp3 "HEAD /books/Chapter_2.mp3 HTTP/1.1" 200 - - 2000000 9 - "-" "curl/8.4.0" - Ijb9tBH0Yg= SigV4 - - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2
+79a59df900b9 other [06/Feb/2026:00:02:00 +0000] 203.0.113.9 - 3E57427F3EXAMPLJ REST.GET.OBJECT books/Chapter_2.mp3 "GET /books/Chapter_2.mp3 HTTP/1.1" 200 - 2000000 2000000 30 10 "-" "curl/8.4.0" - Ijb9tBH0Yh= SigV4 - - other.s3.ap-southeast-2.amazonaws.com TLSv1.2
+79a59df900b9 candide [06/Feb/2026:00:03:00 +0000] 203.0.113.9 - 3E57427F3EXAMPLK REST.GET.OBJECT books/extras/Interview.mp3 "GET /books/extras/Interview.mp3 HTTP/1.1" 200 - 500 500 30 10 "-" "curl/8.4.0" - Ijb9tBH0Yi= SigV4 - - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2
+79a59df900b9 candide [06/Feb/2026:00:04:00 +0000] 203.0.113.9 - 3E57427F3EXAMPLL REST.GET.BUCKET - "GET /?list-type=2&prefix=books%2F HTTP/1.1" 200 - 1024 - 30 10 "-" "aws-cli/2.15.0" - Ijb9tBH0Yj= SigV4 - - candide.s3.ap-southeast-2.amazonaws.com TLSv1.2
+"#;
+
+    #[test]
+    fn parses_quoted_fields_and_ignores_trailing_columns() {
+        let lines: Vec<&str> = LOG.lines().collect();
+        assert_eq!(
+            parse_line(lines[1]),
+            Some(LogRecord {
+                bucket: "candide".to_owned(),
+                operation: "REST.GET.OBJECT".to_owned(),
+                key: Some("books/Chapter 1.mp3".to_owned()),
+                status: Some(206),
+                bytes_sent: 1_951_424,
+            })
+        );
+        let feed = parse_line(lines[2]).unwrap();
+        assert_eq!(feed.key.as_deref(), Some("books/feed.xml"));
+        assert_eq!(feed.bytes_sent, 4821);
+        let head = parse_line(lines[4]).unwrap();
+        assert_eq!(head.bytes_sent, 0);
+        assert_eq!(parse_line(lines[7]).unwrap().key, None);
+    }
+
+    #[test]
+    fn splits_fields_on_spaces_outside_brackets_and_quotes() {
+        assert_eq!(
+            split_fields(r#"a [1 2] "b \"c\" d" e "#),
+            vec!["a", "[1 2]", r#"b \"c\" d"#, "e"]
+        );
+        assert_eq!(
+            split_fields(r#"a "unterminated"#),
+            vec!["a", "\"unterminated"]
+        );
+    }
+
+    #[test]
+    fn rejects_lines_with_too_few_fields() {
+        assert_eq!(parse_line("not a log line"), None);
+    }
+
+    #[test]
+    fn tallies_successful_gets_of_episodes_in_the_folder() {
+        let keys = vec![
+            "books/Chapter 1.mp3".to_owned(),
+            "books/Chapter_3.mp3".to_owned(),
+            "books/cover.jpg".to_owned(),
+        ];
+        let mut tally = Tally::new("candide", "books/", &keys);
+        tally.add_log(LOG);
+        tally.add_log("garbage\n\n");
+        assert_eq!(tally.unreadable_lines, 1);
+        assert_eq!(
+            tally.episodes(),
+            vec![
+                EpisodeStats {
+                    key: "books/Chapter 1.mp3".to_owned(),
+                    gets: 2,
+                    bytes_sent: 3_000_000,
+                },
+                EpisodeStats {
+                    key: "books/Chapter_3.mp3".to_owned(),
+                    gets: 0,
+                    bytes_sent: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_a_table_with_a_header() {
+        let episodes = vec![
+            EpisodeStats {
+                key: "books/Chapter 1.mp3".to_owned(),
+                gets: 12,
+                bytes_sent: 3_000_000,
+            },
+            EpisodeStats {
+                key: "books/Chapter_3.mp3".to_owned(),
+                gets: 0,
+                bytes_sent: 0,
+            },
+        ];
+        assert_eq!(
+            format(&episodes, Numbers::default()),
+            "GETs     Sent  Episode\n  \
+               12  2.9 MiB  books/Chapter 1.mp3\n   \
+                0      0 B  books/Chapter_3.mp3\n"
+        );
+    }
+}
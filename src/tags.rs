@@ -0,0 +1,442 @@
+//! Reads the tags media files carry about themselves: ID3v2 at the start of MP3s and the iTunes
+//! `ilst` box of MP4s. Only the text and cover art sloop uses are read; other frames and items
+//! are skipped.
+
+use crate::duration::find_box;
+use crate::metadata::{Artwork, MediaMetadata};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The title, artist, album, track number and cover art in the ID3v2 tag at the start of the MP3 file at
+/// `path`, left out if there's no tag.
+pub fn id3(path: &Path) -> Result<MediaMetadata, String> {
+    let mut file = File::open(path).map_err(|err| err.to_string())?;
+    let mut header = [0; 10];
+    match file.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(Default::default()),
+        Err(err) => return Err(err.to_string()),
+    }
+    if &header[..3] != b"ID3" {
+        return Ok(Default::default());
+    }
+    let mut tag = vec![0; syncsafe(&header[6..10]) as usize];
+    file.read_exact(&mut tag)
+        .map_err(|err| format!("ID3 tag cut short: {}", err))?;
+    parse_id3(&header, tag)
+}
+
+/// Reads the text and picture frames of an ID3v2.2, 2.3 or 2.4 tag with the 10-byte `header`.
+fn parse_id3(header: &[u8], mut tag: Vec<u8>) -> Result<MediaMetadata, String> {
+    let (version, flags) = (header[3], header[5]);
+    if !(2..=4).contains(&version) {
+        return Err(format!("ID3v2.{} tags aren't supported", version));
+    }
+    // Before 2.4 the whole tag is unsynchronised, rather than each frame.
+    if flags & 0x80 != 0 && version < 4 {
+        tag = resynchronise(&tag);
+    }
+    let mut position = 0;
+    if flags & 0x40 != 0 && version > 2 {
+        let size = tag.get(..4).ok_or("ID3 extended header cut short")?;
+        position = match version {
+            3 => u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize + 4,
+            _ => syncsafe(size) as usize,
+        };
+    }
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut metadata = MediaMetadata::default();
+    let mut front_cover = false;
+    while position + header_len <= tag.len() && tag[position] != 0 {
+        let frame = &tag[position..position + header_len];
+        let size = match version {
+            2 => u32::from_be_bytes([0, frame[3], frame[4], frame[5]]),
+            3 => u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            _ => syncsafe(&frame[4..8]),
+        } as usize;
+        let start = position + header_len;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= tag.len())
+            .ok_or("ID3 frame runs past the end of the tag")?;
+        position = end;
+        let id = &frame[..id_len];
+        let mut data = tag[start..end].to_vec();
+        if version == 4 {
+            let format = frame[9];
+            // Compressed or encrypted frames hold nothing sloop can read.
+            if format & 0x0C != 0 {
+                continue;
+            }
+            if format & 0x02 != 0 {
+                data = resynchronise(&data);
+            }
+            if format & 0x01 != 0 {
+                data = data.get(4..).unwrap_or_default().to_vec();
+            }
+        } else if version == 3 && frame[9] & 0xC0 != 0 {
+            continue;
+        }
+        let field = match id {
+            b"TIT2" | b"TT2" => &mut metadata.title,
+            b"TPE1" | b"TP1" => &mut metadata.artist,
+            b"TALB" | b"TAL" => &mut metadata.album,
+            b"TRCK" | b"TRK" => {
+                metadata.track = text(&data).and_then(|track| track_number(&track));
+                continue;
+            }
+            // The front cover wins over any other picture, and otherwise the first one does.
+            b"APIC" | b"PIC" if !front_cover => {
+                if let Some((picture_type, artwork)) = picture(&data, version) {
+                    front_cover = picture_type == FRONT_COVER;
+                    metadata.artwork = Some(artwork);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        *field = text(&data);
+    }
+    Ok(metadata)
+}
+
+/// The picture type ID3 gives a front cover.
+const FRONT_COVER: u8 = 3;
+
+/// The type and image of an `APIC` frame, or a `PIC` frame in ID3v2.2, which names its format
+/// in three letters rather than a MIME type. Pictures that are links rather than images, or
+/// aren't JPEG or PNG, are left out.
+fn picture(data: &[u8], version: u8) -> Option<(u8, Artwork)> {
+    let (&encoding, rest) = data.split_first()?;
+    let (mime_type, rest) = match version {
+        2 => {
+            let mime_type = match rest.get(..3)? {
+                b"JPG" => "image/jpeg",
+                b"PNG" => "image/png",
+                _ => return None,
+            };
+            (mime_type.to_owned(), &rest[3..])
+        }
+        _ => {
+            let end = rest.iter().position(|&byte| byte == 0)?;
+            let mime_type = String::from_utf8_lossy(&rest[..end]).to_ascii_lowercase();
+            (mime_type, &rest[end + 1..])
+        }
+    };
+    let mime_type = match mime_type.as_str() {
+        "image/jpeg" | "image/jpg" | "jpg" => "image/jpeg",
+        "image/png" | "png" => "image/png",
+        _ => return None,
+    };
+    let (&picture_type, description) = rest.split_first()?;
+    // The description ends in a null, two bytes wide in UTF-16, and the image follows.
+    let image = match encoding {
+        1 | 2 => {
+            let end = description
+                .chunks_exact(2)
+                .position(|pair| pair == [0, 0])?;
+            &description[end * 2 + 2..]
+        }
+        _ => {
+            let end = description.iter().position(|&byte| byte == 0)?;
+            &description[end + 1..]
+        }
+    };
+    if image.is_empty() {
+        return None;
+    }
+    Some((
+        picture_type,
+        Artwork {
+            mime_type: mime_type.to_owned(),
+            data: image.to_vec(),
+        },
+    ))
+}
+
+/// The 28-bit number ID3 writes in four bytes of seven bits each, so it has no `0xFF` bytes.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, byte| (size << 7) | u32::from(byte & 0x7F))
+}
+
+/// Undoes unsynchronisation, which follows each `0xFF` with a `0x00` so players don't take the
+/// tag for audio.
+fn resynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if !(byte == 0 && i > 0 && data[i - 1] == 0xFF) {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+/// The first value of a text frame, in whichever of ID3's encodings it names, or `None` if
+/// it's empty.
+fn text(data: &[u8]) -> Option<String> {
+    let (&encoding, bytes) = data.split_first()?;
+    let text = match encoding {
+        0 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+        1 => match bytes {
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+            _ => utf16(bytes, u16::from_be_bytes),
+        },
+        2 => utf16(bytes, u16::from_be_bytes),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    let first = text.split('\0').next().unwrap_or_default().trim();
+    Some(first.to_owned()).filter(|first| !first.is_empty())
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The track in `3` or `3/12`.
+fn track_number(track: &str) -> Option<u32> {
+    track.split('/').next()?.trim().parse().ok()
+}
+
+/// The title, artist, album, track number and cover art in the iTunes metadata of the MP4 file at `path`
+/// (`.m4a`, `.m4b`, `.mp4`), left out if it has none.
+pub fn mp4(path: &Path) -> Result<MediaMetadata, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    mp4_tags(&mut BufReader::new(file))
+}
+
+/// Reads the items of the `ilst` box in `moov/udta/meta`.
+fn mp4_tags<R: Read + Seek>(reader: &mut R) -> Result<MediaMetadata, String> {
+    let mut end = None;
+    for kind in [b"moov", b"udta", b"meta", b"ilst"].iter() {
+        end = match find_box(reader, kind, end)? {
+            Some(end) => Some(end),
+            None => return Ok(Default::default()),
+        };
+        // `meta` is a full box, with a version and flags before the boxes inside it.
+        if *kind == b"meta" {
+            reader
+                .seek(SeekFrom::Current(4))
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    let ilst = reader.stream_position().map_err(|err| err.to_string())?;
+    // Each value comes with its type, e.g. 13 for a JPEG and 14 for a PNG.
+    let mut item = |kind: &[u8; 4]| -> Result<Option<(u32, Vec<u8>)>, String> {
+        reader
+            .seek(SeekFrom::Start(ilst))
+            .map_err(|err| err.to_string())?;
+        let item_end = match find_box(reader, kind, end)? {
+            Some(item_end) => item_end,
+            None => return Ok(None),
+        };
+        let data_end = match find_box(reader, b"data", Some(item_end))? {
+            Some(data_end) => data_end,
+            None => return Ok(None),
+        };
+        // The data's type and locale come before the value.
+        let mut type_and_locale = [0; 8];
+        reader
+            .read_exact(&mut type_and_locale)
+            .map_err(|err| err.to_string())?;
+        let data_type = u32::from_be_bytes([
+            0,
+            type_and_locale[1],
+            type_and_locale[2],
+            type_and_locale[3],
+        ]);
+        let start = reader.stream_position().map_err(|err| err.to_string())?;
+        let mut value = vec![0; data_end.saturating_sub(start) as usize];
+        reader
+            .read_exact(&mut value)
+            .map_err(|err| err.to_string())?;
+        Ok(Some((data_type, value)))
+    };
+    let text = |value: Option<(u32, Vec<u8>)>| {
+        let value = String::from_utf8_lossy(&value?.1).trim().to_owned();
+        Some(value).filter(|value| !value.is_empty())
+    };
+    Ok(MediaMetadata {
+        title: text(item(b"\xA9nam")?),
+        artist: text(item(b"\xA9ART")?),
+        album: text(item(b"\xA9alb")?),
+        // Two reserved bytes, then the track and the number of tracks.
+        track: item(b"trkn")?
+            .and_then(|(_, value)| {
+                Some(u32::from(u16::from_be_bytes([
+                    *value.get(2)?,
+                    *value.get(3)?,
+                ])))
+            })
+            .filter(|&track| track > 0),
+        artwork: item(b"covr")?.and_then(|(data_type, data)| {
+            let mime_type = match data_type {
+                13 => "image/jpeg",
+                14 => "image/png",
+                _ => return None,
+            };
+            Some(Artwork {
+                mime_type: mime_type.to_owned(),
+                data,
+            })
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn header(version: u8, flags: u8) -> Vec<u8> {
+        vec![b'I', b'D', b'3', version, 0, flags, 0, 0, 0, 0]
+    }
+
+    /// An ID3v2.3 or 2.4 frame holding `data`.
+    fn frame(version: u8, id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        let size = data.len() as u32;
+        match version {
+            3 => frame.extend_from_slice(&size.to_be_bytes()),
+            _ => frame.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7F) as u8)),
+        }
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn reads_text_frames_in_each_encoding() {
+        let mut tag = frame(3, b"TIT2", b"\0Chapter One\0");
+        tag.extend(frame(3, b"TPE1", b"\x01\xFF\xFEV\0o\0l\0t\0a\0i\0r\0e\0"));
+        tag.extend(frame(3, b"TALB", b"\x02\0C\0a\0n\0d\0i\0d\0e"));
+        tag.extend(frame(3, b"TRCK", b"\x003/30"));
+        tag.extend(frame(3, b"COMM", b"\0eng\0not read"));
+        tag.extend_from_slice(&[0; 16]);
+        let metadata = parse_id3(&header(3, 0), tag).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Chapter One"));
+        assert_eq!(metadata.artist.as_deref(), Some("Voltaire"));
+        assert_eq!(metadata.album.as_deref(), Some("Candide"));
+        assert_eq!(metadata.track, Some(3));
+
+        let tag = frame(4, b"TIT2", "\x03Chapitre deux\0Chapter Two".as_bytes());
+        let metadata = parse_id3(&header(4, 0), tag).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Chapitre deux"));
+    }
+
+    #[test]
+    fn reads_id3v2_2_and_unsynchronised_tags() {
+        let mut tag = b"TT2\0\0\x07\0Part 1".to_vec();
+        tag.extend_from_slice(b"TRK\0\0\x03\x0012");
+        let metadata = parse_id3(&header(2, 0), tag).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Part 1"));
+        assert_eq!(metadata.track, Some(12));
+
+        // Latin-1 ÿ is 0xFF, so unsynchronisation follows it with a 0x00.
+        let mut tag = frame(3, b"TIT2", b"\0Ha\xFF");
+        tag.push(0);
+        let metadata = parse_id3(&header(3, 0x80), tag).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Haÿ"));
+    }
+
+    #[test]
+    fn reads_the_front_cover_of_those_pictured() {
+        let mut tag = frame(3, b"APIC", b"\0image/png\0\x04back\0PNG back");
+        tag.extend(frame(
+            3,
+            b"APIC",
+            b"\x01image/jpeg\0\x03\xFF\xFEC\0\0\0JPEG front",
+        ));
+        tag.extend(frame(3, b"APIC", b"\0image/jpeg\0\x03\0JPEG second front"));
+        let metadata = parse_id3(&header(3, 0), tag).unwrap();
+        assert_eq!(
+            metadata.artwork,
+            Some(Artwork {
+                mime_type: "image/jpeg".to_owned(),
+                data: b"JPEG front".to_vec(),
+            })
+        );
+
+        let tag = b"PIC\0\0\x0F\0PNG\x00\0PNG image".to_vec();
+        let artwork = parse_id3(&header(2, 0), tag).unwrap().artwork.unwrap();
+        assert_eq!(artwork.mime_type, "image/png");
+        assert_eq!(artwork.data, b"PNG image");
+
+        let links = frame(4, b"APIC", b"\0-->\0\x03\0https://eg.test/cover.jpg");
+        assert_eq!(parse_id3(&header(4, 0), links).unwrap().artwork, None);
+    }
+
+    #[test]
+    fn empty_and_broken_tags() {
+        let tag = frame(3, b"TIT2", b"\0  \0");
+        assert_eq!(parse_id3(&header(3, 0), tag).unwrap().title, None);
+        let mut tag = frame(3, b"TIT2", b"\0Chapter One");
+        tag.truncate(tag.len() - 3);
+        assert_eq!(
+            parse_id3(&header(3, 0), tag),
+            Err("ID3 frame runs past the end of the tag".to_owned())
+        );
+        assert_eq!(
+            parse_id3(&header(5, 0), Vec::new()),
+            Err("ID3v2.5 tags aren't supported".to_owned())
+        );
+    }
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    fn item(kind: &[u8; 4], value: &[u8]) -> Vec<u8> {
+        let data = [&[0, 0, 0, 1, 0, 0, 0, 0], value].concat();
+        mp4_box(kind, &mp4_box(b"data", &data))
+    }
+
+    #[test]
+    fn reads_itunes_metadata_from_mp4s() {
+        let mut ilst = item(b"\xA9ART", b"Voltaire");
+        ilst.extend(item(b"trkn", &[0, 0, 0, 7, 0, 30, 0, 0]));
+        ilst.extend(item(b"\xA9nam", "Chapitre sept".as_bytes()));
+        ilst.extend(mp4_box(
+            b"covr",
+            &mp4_box(b"data", b"\0\0\0\x0D\0\0\0\0JPEG cover"),
+        ));
+        let meta = [
+            &[0, 0, 0, 0][..],
+            &mp4_box(b"hdlr", &[0; 25]),
+            &mp4_box(b"ilst", &ilst),
+        ]
+        .concat();
+        let moov = [
+            mp4_box(b"mvhd", &[0; 100]),
+            mp4_box(b"udta", &mp4_box(b"meta", &meta)),
+        ]
+        .concat();
+        let file = [mp4_box(b"ftyp", b"M4B \0\0\0\0"), mp4_box(b"moov", &moov)].concat();
+        let metadata = mp4_tags(&mut Cursor::new(file)).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Chapitre sept"));
+        assert_eq!(metadata.artist.as_deref(), Some("Voltaire"));
+        assert_eq!(metadata.album, None);
+        assert_eq!(metadata.track, Some(7));
+        assert_eq!(
+            metadata.artwork,
+            Some(Artwork {
+                mime_type: "image/jpeg".to_owned(),
+                data: b"JPEG cover".to_vec(),
+            })
+        );
+
+        let untagged = [mp4_box(b"ftyp", b"M4B \0\0\0\0"), mp4_box(b"moov", &[])].concat();
+        assert_eq!(mp4_tags(&mut Cursor::new(untagged)), Ok(Default::default()));
+    }
+}
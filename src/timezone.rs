@@ -0,0 +1,542 @@
+//! Time zones dates are written in: a fixed offset from UTC, or a zone from the system's time
+//! zone database, e.g. `Australia/Sydney`, whose offset changes with daylight saving.
+
+use crate::human;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Where the time zone database is looked for unless `TZDIR` says otherwise.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Timezone {
+    Fixed(FixedOffset),
+    /// A named zone, with its offsets read from the database.
+    Zone {
+        name: String,
+        rules: Arc<ZoneRules>,
+    },
+}
+
+impl Timezone {
+    /// The offset from UTC at `date`.
+    pub fn offset_at(&self, date: DateTime<Utc>) -> FixedOffset {
+        match self {
+            Timezone::Fixed(offset) => *offset,
+            Timezone::Zone { rules, .. } => rules.offset_at(date.timestamp()),
+        }
+    }
+
+    /// When it's `local` in this zone. A time skipped when clocks go forward is taken as the
+    /// same time of day before they did, so it ends up an hour later, and a time that happens
+    /// twice when they go back is taken as the first.
+    pub fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        let local_seconds = local.and_utc().timestamp();
+        let day = 24 * 3600;
+        let before = self.offset_at(utc(local_seconds - day)).local_minus_utc() as i64;
+        let after = self.offset_at(utc(local_seconds + day)).local_minus_utc() as i64;
+        let mut candidates = vec![local_seconds - before, local_seconds - after];
+        candidates.sort_unstable();
+        candidates
+            .into_iter()
+            .find(|&seconds| {
+                self.offset_at(utc(seconds)).local_minus_utc() as i64 == local_seconds - seconds
+            })
+            .map(utc)
+            .unwrap_or_else(|| utc(local_seconds - before))
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    /// An offset like `+10:00`, or the name of a zone like `Australia/Sydney`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(offset) = human::parse_utc_offset(value) {
+            return Ok(Timezone::Fixed(offset));
+        }
+        let invalid = || {
+            format!(
+                "{} is not an offset from UTC like +10:00 or a time zone like Australia/Sydney",
+                value
+            )
+        };
+        let relative = Path::new(value)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if value.is_empty() || !relative {
+            return Err(invalid());
+        }
+        let dir = env::var_os("TZDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(ZONEINFO));
+        let data = fs::read(dir.join(value)).map_err(|_| invalid())?;
+        let rules = ZoneRules::parse(&data).map_err(|err| format!("{}: {}", value, err))?;
+        Ok(Timezone::Zone {
+            name: value.to_owned(),
+            rules: Arc::new(rules),
+        })
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timezone::Fixed(offset) => write!(f, "{}", offset),
+            Timezone::Zone { name, .. } => f.write_str(name),
+        }
+    }
+}
+
+fn utc(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(seconds, 0).single().unwrap_or_default()
+}
+
+/// The offsets of a zone as a TZif file records them: from each transition until the next, and
+/// after the last, by the POSIX rule in its footer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneRules {
+    /// When each offset takes effect, in seconds since the epoch, earliest first.
+    transitions: Vec<(i64, i32)>,
+    /// The offset before the first transition.
+    initial: i32,
+    footer: Option<PosixRule>,
+}
+
+impl ZoneRules {
+    /// Reads a TZif file, using its 64-bit data if it has any.
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { data, position: 0 };
+        let header = reader.header()?;
+        if header.version == 0 {
+            return reader.body(&header, 4);
+        }
+        reader.skip(header.body_len(4))?;
+        let header = reader.header()?;
+        let mut rules = reader.body(&header, 8)?;
+        let footer = String::from_utf8_lossy(reader.rest());
+        let footer = footer.trim_matches('\n');
+        if !footer.is_empty() {
+            rules.footer = Some(PosixRule::parse(footer)?);
+        }
+        Ok(rules)
+    }
+
+    fn offset_at(&self, seconds: i64) -> FixedOffset {
+        let offset = match self.transitions.iter().rposition(|&(at, _)| at <= seconds) {
+            Some(last) if last + 1 == self.transitions.len() => match &self.footer {
+                Some(footer) => footer.offset_at(seconds),
+                None => self.transitions[last].1,
+            },
+            Some(i) => self.transitions[i].1,
+            None if self.transitions.is_empty() => match &self.footer {
+                Some(footer) => footer.offset_at(seconds),
+                None => self.initial,
+            },
+            None => self.initial,
+        };
+        FixedOffset::east_opt(offset).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+struct Header {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl Header {
+    /// The length of the data after the header, with times `time_size` bytes long.
+    fn body_len(&self, time_size: usize) -> usize {
+        self.timecnt * (time_size + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or("the time zone file is cut short")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.take(len).map(|_| ())
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn header(&mut self) -> Result<Header, String> {
+        if self.take(4)? != b"TZif" {
+            return Err("not a time zone file".to_owned());
+        }
+        let version = self.take(1)?[0];
+        self.skip(15)?;
+        Ok(Header {
+            version,
+            isutcnt: self.count()?,
+            isstdcnt: self.count()?,
+            leapcnt: self.count()?,
+            timecnt: self.count()?,
+            typecnt: self.count()?,
+            charcnt: self.count()?,
+        })
+    }
+
+    fn body(&mut self, header: &Header, time_size: usize) -> Result<ZoneRules, String> {
+        let times = self.take(header.timecnt * time_size)?;
+        let indices = self.take(header.timecnt)?;
+        let types: Vec<i32> = self
+            .take(header.typecnt * 6)?
+            .chunks_exact(6)
+            .map(|info| i32::from_be_bytes([info[0], info[1], info[2], info[3]]))
+            .collect();
+        self.skip(header.charcnt + header.leapcnt * (time_size + 4))?;
+        self.skip(header.isstdcnt + header.isutcnt)?;
+        let transitions = times
+            .chunks_exact(time_size)
+            .zip(indices)
+            .map(|(time, &index)| {
+                let at = match time_size {
+                    4 => i64::from(i32::from_be_bytes([time[0], time[1], time[2], time[3]])),
+                    _ => i64::from_be_bytes([
+                        time[0], time[1], time[2], time[3], time[4], time[5], time[6], time[7],
+                    ]),
+                };
+                let offset = *types
+                    .get(index as usize)
+                    .ok_or("the time zone file names a type it doesn't have")?;
+                Ok((at, offset))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ZoneRules {
+            transitions,
+            initial: types.first().copied().unwrap_or(0),
+            footer: None,
+        })
+    }
+}
+
+/// A POSIX `TZ` rule like `AEST-10AEDT,M10.1.0,M4.1.0/3`: a standard offset and, optionally,
+/// a daylight saving offset with the days and times it starts and ends.
+#[derive(Debug, Clone, PartialEq)]
+struct PosixRule {
+    standard: i32,
+    daylight: Option<(i32, Transition, Transition)>,
+}
+
+/// The day of the year a change happens on, and the local time of day it happens at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Transition {
+    day: TransitionDay,
+    time: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransitionDay {
+    /// `Mm.w.d`: day `d` (0 is Sunday) of week `w` of month `m`, where week 5 is the last.
+    MonthWeekDay(u32, u32, u32),
+    /// `Jn`: day `n` from 1, never counting 29 February.
+    Julian(u32),
+    /// `n`: day `n` from 0, counting 29 February in leap years.
+    Zero(u32),
+}
+
+impl PosixRule {
+    fn parse(rule: &str) -> Result<Self, String> {
+        let invalid = || format!("{} isn't a time zone rule sloop understands", rule);
+        let rest = after_name(rule).ok_or_else(invalid)?;
+        // POSIX offsets are west of UTC, the opposite of the usual sign.
+        let (standard, rest) = hms(rest).ok_or_else(invalid)?;
+        let standard = -standard;
+        if rest.is_empty() {
+            return Ok(PosixRule {
+                standard,
+                daylight: None,
+            });
+        }
+        let mut rest = after_name(rest).ok_or_else(invalid)?;
+        let daylight = match rest.starts_with(',') || rest.is_empty() {
+            true => standard + 3600,
+            false => {
+                let (offset, after) = hms(rest).ok_or_else(invalid)?;
+                rest = after;
+                -offset
+            }
+        };
+        // Without rules, US rules are assumed, as POSIX suggests.
+        let rest = match rest {
+            "" => ",M3.2.0,M11.1.0",
+            rest => rest,
+        };
+        let mut rules = rest.strip_prefix(',').ok_or_else(invalid)?.split(',');
+        let mut transition = || -> Result<Transition, String> {
+            let rule = rules.next().ok_or_else(invalid)?;
+            let (day, time) = match rule.split_once('/') {
+                Some((day, time)) => match hms(time) {
+                    Some((time, "")) => (day, time),
+                    _ => return Err(invalid()),
+                },
+                None => (rule, 2 * 3600),
+            };
+            let day = if let Some(date) = day.strip_prefix('M') {
+                let parts: Vec<u32> = date
+                    .split('.')
+                    .map(|part| part.parse().map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?;
+                match parts[..] {
+                    [month @ 1..=12, week @ 1..=5, weekday @ 0..=6] => {
+                        TransitionDay::MonthWeekDay(month, week, weekday)
+                    }
+                    _ => return Err(invalid()),
+                }
+            } else if let Some(day) = day.strip_prefix('J') {
+                TransitionDay::Julian(day.parse().map_err(|_| invalid())?)
+            } else {
+                TransitionDay::Zero(day.parse().map_err(|_| invalid())?)
+            };
+            Ok(Transition { day, time })
+        };
+        let (start, end) = (transition()?, transition()?);
+        Ok(PosixRule {
+            standard,
+            daylight: Some((daylight, start, end)),
+        })
+    }
+
+    fn offset_at(&self, seconds: i64) -> i32 {
+        let (daylight, start, end) = match self.daylight {
+            Some(daylight) => daylight,
+            None => return self.standard,
+        };
+        let year = utc(seconds + i64::from(self.standard)).year();
+        // Daylight saving starts at a standard time and ends at a daylight saving one.
+        let starts = start.at(year) - i64::from(self.standard);
+        let ends = end.at(year) - i64::from(daylight);
+        let in_daylight = match starts < ends {
+            true => starts <= seconds && seconds < ends,
+            // In the southern hemisphere, daylight saving spans the new year.
+            false => !(ends <= seconds && seconds < starts),
+        };
+        match in_daylight {
+            true => daylight,
+            false => self.standard,
+        }
+    }
+}
+
+impl Transition {
+    /// When the change happens in `year`, as local seconds since the epoch.
+    fn at(self, year: i32) -> i64 {
+        let date = match self.day {
+            TransitionDay::MonthWeekDay(month, week, weekday) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+                let offset = (weekday + 7 - first.weekday().num_days_from_sunday()) % 7;
+                let mut date = first + Duration::days(i64::from(offset + (week - 1) * 7));
+                while date.month() != month {
+                    date -= Duration::days(7);
+                }
+                date
+            }
+            TransitionDay::Julian(day) => {
+                let date =
+                    NaiveDate::from_yo_opt(year, 1).unwrap() + Duration::days(i64::from(day) - 1);
+                let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                match leap && date.ordinal() >= 60 {
+                    true => date + Duration::days(1),
+                    false => date,
+                }
+            }
+            TransitionDay::Zero(day) => {
+                NaiveDate::from_yo_opt(year, 1).unwrap() + Duration::days(i64::from(day))
+            }
+        };
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() + i64::from(self.time)
+    }
+}
+
+/// The text after the zone abbreviation `text` starts with, e.g. `AEST` or `<+0530>`, which
+/// must be at least three letters.
+fn after_name(text: &str) -> Option<&str> {
+    match text.strip_prefix('<') {
+        Some(quoted) => Some(&quoted[quoted.find('>')? + 1..]),
+        None => {
+            let end = text
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(text.len());
+            (end >= 3).then(|| &text[end..])
+        }
+    }
+}
+
+/// A signed `hh[:mm[:ss]]` at the start of `text`, in seconds, and the text after it.
+fn hms(text: &str) -> Option<(i32, &str)> {
+    let (sign, text) = match text.as_bytes().first()? {
+        b'-' => (-1, &text[1..]),
+        b'+' => (1, &text[1..]),
+        _ => (1, text),
+    };
+    let end = text
+        .find(|c: char| !c.is_ascii_digit() && c != ':')
+        .unwrap_or(text.len());
+    let mut seconds = 0;
+    for (index, part) in text[..end].split(':').enumerate() {
+        let value: i32 = part.parse().ok()?;
+        seconds += value * [3600, 60, 1].get(index)?;
+    }
+    Some((sign * seconds, &text[end..]))
+}
+
+/// Sydney, from a TZif file with one transition, to its standard time in 2000, and its rule
+/// after, so tests don't depend on the system's database.
+#[cfg(test)]
+pub(crate) fn sydney() -> Timezone {
+    let mut data = b"TZif2".to_vec();
+    data.extend_from_slice(&[0; 15]);
+    // No v1 data, then the v2 header with one transition and two types.
+    data.extend_from_slice(&[0; 24]);
+    data.extend_from_slice(b"TZif2");
+    data.extend_from_slice(&[0; 15]);
+    for count in [0u32, 0, 0, 1, 2, 4] {
+        data.extend_from_slice(&count.to_be_bytes());
+    }
+    data.extend_from_slice(&946_684_800i64.to_be_bytes());
+    data.push(1);
+    data.extend_from_slice(&36_000i32.to_be_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&36_000i32.to_be_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(b"LMT\0");
+    data.extend_from_slice(b"\nAEST-10AEDT,M10.1.0,M4.1.0/3\n");
+    Timezone::Zone {
+        name: "Australia/Sydney".to_owned(),
+        rules: Arc::new(ZoneRules::parse(&data).unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn offset(timezone: &Timezone, date: &str) -> i32 {
+        timezone.offset_at(at(date)).local_minus_utc() / 3600
+    }
+
+    #[test]
+    fn offsets_and_names_are_both_time_zones() {
+        assert_eq!(
+            "+10:00".parse(),
+            Ok(Timezone::Fixed(FixedOffset::east_opt(10 * 3600).unwrap()))
+        );
+        assert_eq!(
+            "Mars/Olympus_Mons".parse::<Timezone>(),
+            Err(
+                "Mars/Olympus_Mons is not an offset from UTC like +10:00 or a time zone like \
+                 Australia/Sydney"
+                    .to_owned()
+            )
+        );
+        assert!("../../etc/passwd".parse::<Timezone>().is_err());
+        assert!("/etc/localtime".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn posix_rules_follow_daylight_saving_either_side_of_the_equator() {
+        let sydney = PosixRule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        let hours = |rule: &PosixRule, date| rule.offset_at(at(date).timestamp()) / 3600;
+        assert_eq!(hours(&sydney, "2030-01-15T00:00:00Z"), 11);
+        assert_eq!(hours(&sydney, "2030-07-15T00:00:00Z"), 10);
+        // Clocks go back at 3am daylight time on the first Sunday of April, 7 April 2030.
+        assert_eq!(hours(&sydney, "2030-04-06T15:59:59Z"), 11);
+        assert_eq!(hours(&sydney, "2030-04-06T16:00:00Z"), 10);
+
+        let new_york = PosixRule::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(hours(&new_york, "2030-01-15T00:00:00Z"), -5);
+        assert_eq!(hours(&new_york, "2030-07-15T00:00:00Z"), -4);
+        // Clocks go forward at 2am on the second Sunday of March, 10 March 2030.
+        assert_eq!(hours(&new_york, "2030-03-10T06:59:59Z"), -5);
+        assert_eq!(hours(&new_york, "2030-03-10T07:00:00Z"), -4);
+
+        let fixed = PosixRule::parse("<+0530>-5:30").unwrap();
+        assert_eq!(fixed.offset_at(0), 5 * 3600 + 30 * 60);
+        assert!(PosixRule::parse("X1").is_err());
+    }
+
+    #[test]
+    fn zones_follow_their_transitions_and_then_their_rule() {
+        let sydney = sydney();
+        assert_eq!(offset(&sydney, "1999-01-15T00:00:00Z"), 10);
+        assert_eq!(offset(&sydney, "2024-01-15T00:00:00Z"), 11);
+        assert_eq!(offset(&sydney, "2024-07-15T00:00:00Z"), 10);
+    }
+
+    #[test]
+    fn local_times_are_found_across_daylight_saving_changes() {
+        let sydney = sydney();
+        let local = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(
+            sydney.to_utc(local("2024-03-01 06:00")),
+            at("2024-02-29T19:00:00Z")
+        );
+        assert_eq!(
+            sydney.to_utc(local("2024-06-01 06:00")),
+            at("2024-05-31T20:00:00Z")
+        );
+        // 2:30am doesn't happen on 6 October 2024, when clocks go from 2am to 3am.
+        assert_eq!(
+            sydney.to_utc(local("2024-10-06 02:30")),
+            at("2024-10-05T16:30:00Z")
+        );
+        // 2:30am happens twice on 7 April 2024; the first is in daylight time.
+        assert_eq!(
+            sydney.to_utc(local("2024-04-07 02:30")),
+            at("2024-04-06T15:30:00Z")
+        );
+    }
+
+    #[test]
+    fn reads_the_systems_zones_if_it_has_them() {
+        if !Path::new(ZONEINFO).join("Australia/Sydney").exists() || env::var_os("TZDIR").is_some()
+        {
+            return;
+        }
+        let sydney: Timezone = "Australia/Sydney".parse().unwrap();
+        assert_eq!(sydney.to_string(), "Australia/Sydney");
+        assert_eq!(offset(&sydney, "2024-01-15T00:00:00Z"), 11);
+        assert_eq!(offset(&sydney, "2024-07-15T00:00:00Z"), 10);
+    }
+}
@@ -1,309 +1,6113 @@
+use crate::cancel::CancelToken;
+use crate::feed;
+use crate::http::HttpGet;
+use crate::human::{self, Numbers};
+use crate::listing::ListedObject;
+use crate::log::{self, Log};
+use crate::mime;
+use crate::outcome::Outcome;
+use crate::plan::{self, Action, PlannedObject, UploadOrder};
+use crate::progress::{NoProgress, Progress};
+use crate::stats::Tally;
+use crate::urls::{ObjectUrls, Presigner, MAX_PRESIGNED_EXPIRY};
+use crate::version;
+use crate::warning;
+use base64::engine::general_purpose::STANDARD as base64;
 use base64::Engine;
-use base64::engine::general_purpose::{STANDARD as base64};
-use rusoto_core::{Region, RusotoError};
-use rusoto_s3::CreateBucketError::BucketAlreadyOwnedByYou;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Async, Poll, Stream};
+use rusoto_core::credential::{
+    AwsCredentials, ChainProvider, ProvideAwsCredentials, StaticProvider,
+};
+use rusoto_core::{ByteStream, HttpClient, Region, RusotoError};
+use rusoto_s3::CreateBucketError::{BucketAlreadyExists, BucketAlreadyOwnedByYou};
 use rusoto_s3::{
-    CreateBucketConfiguration, CreateBucketRequest, DeletePublicAccessBlockRequest,
-    PutBucketPolicyRequest, PutObjectRequest, S3Client, S3,
+    AbortMultipartUploadRequest, BucketLoggingStatus, CompleteMultipartUploadRequest,
+    CompletedMultipartUpload, CompletedPart, CreateBucketConfiguration, CreateBucketRequest,
+    CreateMultipartUploadRequest, Delete, DeleteObjectRequest, DeleteObjectsRequest,
+    GetBucketLoggingRequest, GetObjectError, GetObjectRequest, GetPublicAccessBlockRequest,
+    HeadObjectRequest, ListMultipartUploadsRequest, ListObjectsV2Error, ListObjectsV2Request,
+    ListPartsRequest, LoggingEnabled, MultipartUpload, ObjectIdentifier, Part,
+    PutBucketLoggingRequest, PutBucketPolicyRequest, PutObjectRequest, PutPublicAccessBlockRequest,
+    S3Client, UploadPartRequest, S3,
 };
 use serde_json::json;
+use sloop::naming::{self, ObjectKey};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::current_thread::Runtime;
+
+mod layout;
+mod local;
+mod provider;
+mod sftp;
+
+pub use local::LocalUploader;
+pub use provider::{Endpoint, Provider};
+pub use sftp::{SftpTarget, SftpUploader};
+
+const MULTIPART_THRESHOLD: u64 = 100 * 1024 * 1024;
+const PART_SIZE: u64 = 8 * 1024 * 1024;
+const CONCURRENCY: usize = 4;
+/// Feeds change whenever episodes are added, and podcast apps poll them, so caches such as
+/// CloudFront should only keep them briefly.
+pub const FEED_CACHE_CONTROL: &str = "max-age=300";
+/// Episodes and artwork don't change once published.
+pub const MEDIA_CACHE_CONTROL: &str = "max-age=31536000, immutable";
+/// Regions where S3 rejects a bucket location constraint naming the region itself, because it's
+/// the default.
+const OMIT_LOCATION_CONSTRAINT: &[&str] = &["us-east-1"];
+/// Size of the reads used to stream a file's body to S3.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// The most keys S3 deletes in one `DeleteObjects` request.
+const MAX_KEYS_PER_DELETE: usize = 1000;
+/// S3 rejects longer bucket names.
+const MAX_BUCKET_NAME_LEN: usize = 63;
+
+pub struct S3Uploader {
+    client: Box<dyn S3 + Send + Sync>,
+    region: String,
+    bucket_name: String,
+    endpoint: Option<String>,
+    path_style: bool,
+    legacy_endpoint: bool,
+    /// Where the bucket's objects are served from instead of the bucket, e.g. a CDN, without a
+    /// trailing slash.
+    public_url: Option<String>,
+    /// Prepended to every key, without a trailing slash; empty for the bucket root.
+    prefix: String,
+    check_credentials: bool,
+    /// Resolved up front when presigning, which needs the secret key.
+    credentials: Option<AwsCredentials>,
+    /// Names to upload files as instead of their own, such as a feed written to `feed-v2.xml`
+    /// but published as `feed.xml`.
+    names: HashMap<PathBuf, String>,
+    /// The enclosure length the feeds give each media file, which must still be its size when
+    /// the feeds are uploaded.
+    enclosure_lengths: Vec<(PathBuf, u64)>,
+    options: UploadOptions,
+    log: Log,
+    progress: Arc<dyn Progress>,
+    /// Checked between files, parts and chunks; uploads stop once it's cancelled.
+    cancel: CancelToken,
+}
+
+/// How to reach the S3 API. The defaults talk to AWS using credentials from the environment.
+#[derive(Debug, Default)]
+pub struct ClientConfig {
+    /// Base URL of an S3-compatible API to use instead of AWS, e.g. `http://localhost:9000`.
+    pub endpoint: Option<String>,
+    /// Access key ID and secret access key to use instead of the default provider chain.
+    pub credentials: Option<(String, String)>,
+    /// Address objects as `{endpoint}/{bucket}/{key}` rather than `{bucket}.{endpoint}/{key}`.
+    pub path_style: bool,
+    /// Address AWS as `s3-{region}.amazonaws.com`, the dash-style endpoint older feeds embed,
+    /// rather than `s3.{region}.amazonaws.com`. Newer regions only resolve the latter.
+    pub legacy_endpoint: bool,
+    /// URL that serves the bucket's objects by key, e.g. a CloudFront distribution, for feeds
+    /// to link to instead of the bucket. Uploads still go to the bucket.
+    pub public_url: Option<String>,
+    /// Folder within the bucket to put objects in, e.g. `books/candide`. Leading and trailing
+    /// slashes are ignored.
+    pub prefix: String,
+    /// The S3-compatible service the endpoint belongs to, if it's one sloop knows, to check the
+    /// options against what it supports.
+    pub provider: Option<Provider>,
+}
+
+#[derive(Debug)]
+pub struct UploadOptions {
+    pub keep_orphans: bool,
+    pub multipart_threshold: u64,
+    pub part_size: u64,
+    /// Maximum number of files to upload at once.
+    pub concurrency: usize,
+    /// Build every request but log it instead of sending it.
+    pub dry_run: bool,
+    /// How sizes are written in log lines.
+    pub numbers: Numbers,
+    pub public_mode: PublicMode,
+    /// Turn off the bucket's Block Public Access settings that would stop `public_mode` working,
+    /// rather than failing.
+    pub allow_public: bool,
+    /// Assume the bucket exists rather than creating it, e.g. when it's managed with Terraform.
+    pub skip_create_bucket: bool,
+    /// Leave the bucket's policy and Block Public Access settings alone. Objects still get ACLs
+    /// in `object-acl` mode.
+    pub skip_policy: bool,
+    /// Link to objects with presigned URLs lasting this long, instead of public URLs. At most
+    /// `MAX_PRESIGNED_EXPIRY`.
+    pub presign: Option<Duration>,
+    /// Fail instead of warning when the bucket looks like it's shared with another podcast.
+    pub strict: bool,
+    /// How S3 encrypts every uploaded object at rest, for bucket policies that require it.
+    pub sse: Option<Sse>,
+    /// The KMS key to encrypt with when `sse` is `Sse::Kms`, instead of the account's default.
+    pub sse_kms_key_id: Option<String>,
+    /// The storage class for episodes, which are rarely fetched once downloaded. S3's default,
+    /// STANDARD, when `None`.
+    pub storage_class: Option<StorageClass>,
+    /// The storage class for feeds, images and other files, which are fetched on every poll.
+    pub feed_storage_class: Option<StorageClass>,
+    /// The `Cache-Control` header for feeds; none if empty.
+    pub feed_cache_control: String,
+    /// The `Cache-Control` header for everything else, e.g. episodes and artwork; none if empty.
+    pub media_cache_control: String,
+    /// After uploading, delete the objects in the podcast's folder that this run didn't
+    /// publish, e.g. episodes dropped from the feed.
+    pub prune: bool,
+    /// Upload every file, even those the bucket already has, e.g. to give objects already
+    /// published a new `Cache-Control` header or storage class.
+    pub force: bool,
+    /// The order to send the files that need uploading in.
+    pub upload_order: UploadOrder,
+    /// Have S3 deliver the bucket's server access logs here, for `sloop stats`.
+    pub access_logs: Option<LogTarget>,
+}
+
+/// Server-side encryption, sent as each object's `x-amz-server-side-encryption` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sse {
+    /// Keys managed by S3.
+    Aes256,
+    /// Keys managed by AWS KMS.
+    Kms,
+}
+
+impl Sse {
+    pub const VALUES: &'static [&'static str] = &["aes256", "aws:kms"];
+
+    /// The header value S3 expects.
+    fn header(self) -> String {
+        match self {
+            Sse::Aes256 => "AES256".to_owned(),
+            Sse::Kms => "aws:kms".to_owned(),
+        }
+    }
+}
+
+/// The S3 storage classes that serve objects straight away, so podcast apps can play them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Standard,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+}
+
+impl StorageClass {
+    pub const VALUES: &'static [&'static str] = &[
+        "STANDARD",
+        "STANDARD_IA",
+        "ONEZONE_IA",
+        "INTELLIGENT_TIERING",
+    ];
+
+    fn name(self) -> String {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::StandardIa => "STANDARD_IA",
+            StorageClass::OnezoneIa => "ONEZONE_IA",
+            StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+        }
+        .to_owned()
+    }
+}
+
+impl FromStr for StorageClass {
+    type Err = String;
+
+    fn from_str(class: &str) -> Result<Self, Self::Err> {
+        match class {
+            "STANDARD" => Ok(StorageClass::Standard),
+            "STANDARD_IA" => Ok(StorageClass::StandardIa),
+            "ONEZONE_IA" => Ok(StorageClass::OnezoneIa),
+            "INTELLIGENT_TIERING" => Ok(StorageClass::IntelligentTiering),
+            "GLACIER" | "DEEP_ARCHIVE" => Err(format!(
+                "{} objects must be restored before they can be downloaded, so podcast apps \
+                 couldn't play them; use one of {}",
+                class,
+                StorageClass::VALUES.join(", ")
+            )),
+            _ => Err(format!(
+                "{} is not one of {}",
+                class,
+                StorageClass::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+impl FromStr for Sse {
+    type Err = String;
+
+    fn from_str(sse: &str) -> Result<Self, Self::Err> {
+        match sse {
+            "aes256" => Ok(Sse::Aes256),
+            "aws:kms" => Ok(Sse::Kms),
+            _ => Err(format!("{} is not one of {}", sse, Sse::VALUES.join(", "))),
+        }
+    }
+}
+
+/// Where `sloop feed` publishes a podcast's files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// An S3 bucket, or a bucket of an S3-compatible service.
+    S3,
+    /// A directory on this machine that a web server serves, e.g. nginx.
+    Local,
+    /// A directory on a web host reached over SFTP, e.g. shared hosting.
+    Sftp,
+}
+
+impl Backend {
+    pub const VALUES: &'static [&'static str] = &["s3", "local", "sftp"];
+
+    /// What `--backend` calls the backend.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::S3 => "s3",
+            Backend::Local => "local",
+            Backend::Sftp => "sftp",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend {
+            "s3" => Ok(Backend::S3),
+            "local" => Ok(Backend::Local),
+            "sftp" => Ok(Backend::Sftp),
+            _ => Err(format!(
+                "{} is not one of {}",
+                backend,
+                Backend::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// How uploaded objects are made readable by podcast apps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PublicMode {
+    /// A bucket policy lets anyone read every object in the bucket.
+    BucketPolicy,
+    /// Each uploaded object gets a `public-read` ACL, leaving other objects in the bucket private.
+    ObjectAcl,
+    /// Objects are left as the bucket's settings make them, e.g. for a bucket behind CloudFront.
+    None,
+}
+
+impl PublicMode {
+    pub const VALUES: &'static [&'static str] = &["bucket-policy", "object-acl", "none"];
+
+    /// What `--public-mode` calls the mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            PublicMode::BucketPolicy => "bucket-policy",
+            PublicMode::ObjectAcl => "object-acl",
+            PublicMode::None => "none",
+        }
+    }
+
+    /// The canned ACL for each uploaded object.
+    fn object_acl(self) -> Option<String> {
+        match self {
+            PublicMode::ObjectAcl => Some("public-read".to_owned()),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PublicMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "bucket-policy" => Ok(PublicMode::BucketPolicy),
+            "object-acl" => Ok(PublicMode::ObjectAcl),
+            "none" => Ok(PublicMode::None),
+            _ => Err(format!(
+                "{} is not one of {}",
+                mode,
+                PublicMode::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Where S3 delivers a bucket's server access logs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogTarget {
+    pub bucket: String,
+    /// Starts the key of every log object. The podcast bucket's name and a `/` if `None`, so
+    /// one log bucket can take the logs of several.
+    pub prefix: Option<String>,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    /// Parses `bucket` or `bucket/prefix`.
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        let (bucket, prefix) = match target.split_once('/') {
+            Some((bucket, prefix)) => (bucket, Some(prefix.to_owned())),
+            None => (target, None),
+        };
+        if bucket.is_empty() {
+            return Err(format!(
+                "{:?} doesn't name a bucket, e.g. my-logs or my-logs/podcasts/",
+                target
+            ));
+        }
+        Ok(Self {
+            bucket: bucket.to_owned(),
+            prefix,
+        })
+    }
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            keep_orphans: false,
+            multipart_threshold: MULTIPART_THRESHOLD,
+            part_size: PART_SIZE,
+            concurrency: CONCURRENCY,
+            dry_run: false,
+            numbers: Numbers::default(),
+            public_mode: PublicMode::BucketPolicy,
+            allow_public: false,
+            skip_create_bucket: false,
+            skip_policy: false,
+            presign: None,
+            strict: false,
+            sse: None,
+            sse_kms_key_id: None,
+            storage_class: None,
+            feed_storage_class: None,
+            feed_cache_control: FEED_CACHE_CONTROL.to_owned(),
+            media_cache_control: MEDIA_CACHE_CONTROL.to_owned(),
+            prune: false,
+            force: false,
+            upload_order: UploadOrder::default(),
+            access_logs: None,
+        }
+    }
+}
+
+/// What a successful run sent.
+#[derive(Debug, PartialEq)]
+pub struct UploadSummary {
+    pub files: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Files not sent because the bucket already had them.
+    pub unchanged: usize,
+    /// Objects deleted by `prune`.
+    pub deleted: usize,
+    /// Keys of the files sent, or that a dry run would have sent, with any feeds last.
+    pub keys: Vec<String>,
+}
+
+impl UploadSummary {
+    /// E.g. `12 files, 1.2 GiB in 3m 12s (3 unchanged, 2 deleted)`.
+    pub fn describe(&self, numbers: Numbers) -> String {
+        let mut description = format!(
+            "{} file{}, {} in {}",
+            self.files,
+            if self.files == 1 { "" } else { "s" },
+            numbers.bytes(self.bytes),
+            numbers.duration(self.elapsed)
+        );
+        let mut notes = Vec::new();
+        if self.unchanged > 0 {
+            notes.push(format!("{} unchanged", self.unchanged));
+        }
+        if self.deleted > 0 {
+            notes.push(format!("{} deleted", self.deleted));
+        }
+        if !notes.is_empty() {
+            description.push_str(&format!(" ({})", notes.join(", ")));
+        }
+        description
+    }
+}
+
+/// Publishes a podcast's feeds and files under keys laid out as in the bucket, so feeds link to
+/// them the same way wherever they're published. `sloop feed` only uses this, whichever
+/// `Backend` is chosen.
+pub trait Uploader {
+    /// The URL objects are served under, including any prefix.
+    fn base_url(&self) -> String;
+
+    /// The URL `file` is served at once published.
+    fn url_for_file(&self, file: &Path) -> String;
+
+    /// How feeds should link to the published objects.
+    fn object_urls(&self) -> ObjectUrls {
+        ObjectUrls::public(&self.base_url())
+    }
+
+    /// The key `path` is published as: its file name, or the name given by `upload_as`, under
+    /// the prefix.
+    fn key(&self, path: &Path) -> String;
+
+    /// Publishes `path` as `name`, under the prefix, rather than under its own file name.
+    fn upload_as(&mut self, path: &Path, name: &str);
+
+    /// Records that the feeds give `path` an enclosure length of `length` bytes. Publishing
+    /// fails rather than publish feeds whose lengths no longer match the files.
+    fn expect_length(&mut self, path: &Path, length: u64);
+
+    /// Sends progress lines to `log` instead of stdout.
+    fn set_log(&mut self, log: Log);
+
+    /// Reports bytes sent to `progress` as files are published.
+    fn set_progress(&mut self, progress: Arc<dyn Progress>);
+
+    /// Publishes `files` as they are, skipping those already published unchanged.
+    fn upload(&self, files: Vec<PathBuf>) -> Result<UploadSummary, UploadError>;
+
+    /// What `publish` would do with each of `feeds` and `files`, sorted by key, without
+    /// changing anything.
+    fn plan(&self, feeds: &[PathBuf], files: &[PathBuf])
+        -> Result<Vec<PlannedObject>, UploadError>;
+
+    /// Publishes `files`, then, once they're all in place, `feeds`, so a failure never leaves
+    /// feeds linking to missing files. The first audio file is checked with a range request
+    /// first if `http` is given.
+    fn publish(
+        &self,
+        feeds: &[PathBuf],
+        files: Vec<PathBuf>,
+        http: Option<&dyn HttpGet>,
+    ) -> Result<UploadSummary, UploadError>;
+
+    /// The published copy of `path`, or `None` if it hasn't been published.
+    fn download(&self, path: &Path) -> Result<Option<Vec<u8>>, UploadError>;
+
+    /// Whether `path` holds the same bytes as the published object `name`, under the prefix,
+    /// or `None` if that can't be told without downloading it, as when it isn't published.
+    fn same_contents(&self, path: &Path, name: &str) -> Result<Option<bool>, UploadError>;
+
+    /// Requests the first KB of the published `file` from the URL it's served at and checks
+    /// that the server honours the range, which podcast apps rely on for seeking.
+    fn verify_range(&self, http: &dyn HttpGet, file: &Path) -> Result<(), UploadError> {
+        check_range(http, &self.url_for_file(file), &self.key(file))
+    }
+}
+
+/// An object already in the bucket, as listed.
+#[derive(Debug)]
+struct ExistingObject {
+    size: u64,
+    e_tag: String,
+}
+
+#[derive(Debug)]
+pub struct UploadError {
+    pub message: String,
+    /// The local file being uploaded when the error happened, if it concerns a single file.
+    pub path: Option<PathBuf>,
+    /// Identifies the failed S3 request, for when AWS support is needed to explain an error.
+    pub request_ids: Option<RequestIds>,
+    /// What went wrong, for the errors callers may want to handle differently.
+    pub kind: UploadErrorKind,
+    /// How far the run got before the error.
+    outcome: Outcome,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadErrorKind {
+    /// Another AWS account already owns a bucket with the name, which is global to S3.
+    BucketNameTaken,
+    Other,
+}
+
+/// The `x-amz-request-id` and `x-amz-id-2` headers of an S3 response.
+#[derive(Debug, PartialEq)]
+pub struct RequestIds {
+    pub request_id: String,
+    pub host_id: Option<String>,
+}
+
+impl UploadError {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            path: None,
+            request_ids: None,
+            kind: UploadErrorKind::Other,
+            outcome: Outcome::Failed,
+            source: None,
+        }
+    }
+
+    /// An error found while checking the files or settings, before sending any request.
+    pub fn invalid(message: String) -> Self {
+        Self {
+            outcome: Outcome::Invalid,
+            ..Self::new(message)
+        }
+    }
+
+    fn cancelled() -> Self {
+        Self {
+            outcome: Outcome::Cancelled,
+            ..Self::new("Cancelled".to_owned())
+        }
+    }
+
+    /// Wraps a failed read of a local file, e.g. `UploadError::io("open", path, err)`.
+    fn io(action: &str, path: &Path, err: io::Error) -> Self {
+        Self {
+            message: format!("Failed to {} {}: {}", action, path.display(), err),
+            path: Some(path.to_owned()),
+            request_ids: None,
+            kind: UploadErrorKind::Other,
+            outcome: Outcome::Failed,
+            source: Some(Box::new(err)),
+        }
+    }
+
+    /// Wraps a failed S3 call, keeping the request IDs if S3 responded.
+    fn from_rusoto<E: Error + Send + Sync + 'static>(context: String, err: RusotoError<E>) -> Self {
+        let (request_ids, explanation) = match &err {
+            RusotoError::Unknown(response) => {
+                let header = |name| response.headers.get(name).cloned();
+                let request_ids = header("x-amz-request-id").map(|request_id| RequestIds {
+                    request_id,
+                    host_id: header("x-amz-id-2"),
+                });
+                let body = response.body_as_str();
+                let explanation = error_element(body, "Code").map(|code| {
+                    explain_error_code(code, header("date").as_deref(), Utc::now()).unwrap_or_else(
+                        || match error_element(body, "Message") {
+                            Some(message) => format!("{} ({})", code, message),
+                            None => code.to_owned(),
+                        },
+                    )
+                });
+                (request_ids, explanation)
+            }
+            _ => (None, None),
+        };
+        let message = match explanation {
+            Some(explanation) => format!("{}: {}", context, explanation),
+            None => format!("{}: {}", context, err),
+        };
+        Self {
+            message,
+            path: None,
+            request_ids,
+            kind: UploadErrorKind::Other,
+            outcome: Outcome::Failed,
+            source: Some(Box::new(err)),
+        }
+    }
+
+    /// Records the file being uploaded, unless a more specific one is already known.
+    fn with_path(mut self, path: &Path) -> Self {
+        self.path.get_or_insert_with(|| path.to_owned());
+        self
+    }
+
+    /// Records that `files` files had been uploaded before the error, so the bucket was left
+    /// partly changed.
+    fn after_uploading(mut self, files: usize) -> Self {
+        if files > 0 && self.outcome != Outcome::Cancelled {
+            self.outcome = Outcome::PartialUpload;
+        }
+        self
+    }
+
+    /// How far the run got: rejected before sending anything, failed after changing some
+    /// objects, or neither.
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(ids) = &self.request_ids {
+            write!(f, " (request ID: {}", ids.request_id)?;
+            if let Some(host_id) = &ids.host_id {
+                write!(f, ", host ID: {}", host_id)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for UploadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn Error + 'static))
+    }
+}
+
+impl S3Uploader {
+    pub fn new(
+        region: &str,
+        bucket_name: &str,
+        config: ClientConfig,
+        options: UploadOptions,
+    ) -> Result<Self, UploadError> {
+        if options.sse_kms_key_id.is_some() && options.sse != Some(Sse::Kms) {
+            return Err(UploadError::invalid(
+                "A KMS key ID can only be given with aws:kms encryption".to_owned(),
+            ));
+        }
+        if options.presign.is_some() && config.public_url.is_some() {
+            return Err(UploadError::invalid(
+                "Presigned URLs are signed for the bucket, so can't be served from a public URL"
+                    .to_owned(),
+            ));
+        }
+        if let Some(public_url) = &config.public_url {
+            check_public_url(public_url, bucket_name, region).map_err(UploadError::invalid)?;
+        }
+        if let Some(provider) = &config.provider {
+            provider
+                .check(
+                    options.public_mode,
+                    config.public_url.is_some(),
+                    options.presign.is_some(),
+                )
+                .map_err(UploadError::invalid)?;
+        }
+        let endpoint = config
+            .endpoint
+            .map(|endpoint| endpoint.trim_end_matches('/').to_owned());
+        // S3-compatible services use their own region names (e.g. `auto` for Cloudflare R2), so
+        // only AWS regions are validated.
+        let rusoto_region = match &endpoint {
+            Some(_) if region.is_empty() => {
+                return Err(UploadError::invalid("Region must not be empty".to_owned()))
+            }
+            Some(endpoint) => Region::Custom {
+                name: region.to_owned(),
+                endpoint: endpoint.clone(),
+            },
+            None => Region::from_str(region)
+                .map_err(|_| UploadError::invalid(format!("Invalid region: {}", region)))?,
+        };
+        // Custom endpoints may allow anonymous access, so only insist on credentials for AWS.
+        let check_credentials = endpoint.is_none() && config.credentials.is_none();
+        let credentials = match (options.presign, &config.credentials) {
+            (Some(expiry), _) if expiry > MAX_PRESIGNED_EXPIRY => {
+                return Err(UploadError::invalid(format!(
+                    "Presigned URLs can last at most {} (--expires 7d)",
+                    human::format_duration(MAX_PRESIGNED_EXPIRY)
+                )))
+            }
+            (Some(_), Some((access_key, secret_key))) => Some(AwsCredentials::new(
+                access_key.clone(),
+                secret_key.clone(),
+                None,
+                None,
+            )),
+            (Some(_), None) => {
+                let mut provider = ChainProvider::new();
+                provider.set_timeout(Duration::from_secs(2));
+                Some(resolve_credentials(&provider)?)
+            }
+            (None, _) => None,
+        };
+        let client = match config.credentials {
+            Some((access_key, secret_key)) => {
+                let http_client = HttpClient::new().map_err(|err| {
+                    UploadError::new(format!("Failed to create HTTP client: {}", err))
+                })?;
+                let credentials = StaticProvider::new_minimal(access_key, secret_key);
+                S3Client::new_with(http_client, credentials, rusoto_region)
+            }
+            None => S3Client::new(rusoto_region),
+        };
+        Ok(Self {
+            client: Box::new(client),
+            region: region.to_owned(),
+            bucket_name: bucket_name.to_owned(),
+            endpoint,
+            path_style: config.path_style,
+            legacy_endpoint: config.legacy_endpoint,
+            public_url: config
+                .public_url
+                .map(|url| url.trim_end_matches('/').to_owned()),
+            prefix: config.prefix.trim_matches('/').to_owned(),
+            check_credentials,
+            credentials,
+            names: HashMap::new(),
+            enclosure_lengths: Vec::new(),
+            options,
+            log: log::stdout(),
+            progress: Arc::new(NoProgress),
+            cancel: CancelToken::default(),
+        })
+    }
+
+    /// Stops uploading, hashing and planning at the next chunk once `cancel` is cancelled.
+    /// Multipart uploads cut short are left to resume on the next run.
+    pub fn set_cancel(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    fn bucket_url(&self) -> String {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None if self.path_style => format!("https://{}", self.aws_host()),
+            None => return format!("https://{}.{}", self.bucket_name, self.aws_host()),
+        };
+        if self.path_style {
+            return format!("{}/{}", endpoint, self.bucket_name);
+        }
+        match endpoint.find("://") {
+            Some(i) => format!(
+                "{}{}.{}",
+                &endpoint[..i + 3],
+                self.bucket_name,
+                &endpoint[i + 3..]
+            ),
+            None => format!("{}.{}", self.bucket_name, endpoint),
+        }
+    }
+
+    fn aws_host(&self) -> String {
+        if self.legacy_endpoint {
+            format!("s3-{}.amazonaws.com", self.region)
+        } else if self.region == "us-east-1" {
+            // The global endpoint, which routes to us-east-1.
+            "s3.amazonaws.com".to_owned()
+        } else {
+            format!("s3.{}.amazonaws.com", self.region)
+        }
+    }
+
+    /// Requests the first KB of an uploaded file from its public URL and checks that the server
+    /// honours the range, which podcast apps rely on for seeking.
+    pub fn verify_range<H: HttpGet + ?Sized>(
+        &self,
+        http: &H,
+        file: &Path,
+    ) -> Result<(), UploadError> {
+        check_range(http, &self.url_for_file(file), self.name(file))
+    }
+
+    /// Removes the episode published as `name` (a file name in the podcast's folder, or its
+    /// full key): uploads the RSS feed `feed_name` without the item linking to it, then deletes
+    /// the object, so the feed never links to a missing file. Fails if the feed has no such
+    /// item, unless `ignore_missing` is set, in which case the feed is left alone and the
+    /// object deleted anyway. A dry run still downloads the feed, to check the item is there.
+    pub fn remove(
+        &self,
+        name: &str,
+        feed_name: &str,
+        ignore_missing: bool,
+    ) -> Result<(), UploadError> {
+        let folder = ObjectKey::new(&self.prefix, "").key().to_owned();
+        let name = name.strip_prefix(folder.as_str()).unwrap_or(name);
+        for name in &[name, feed_name] {
+            if name.is_empty() || name.contains('/') {
+                return Err(UploadError::invalid(format!(
+                    "{} isn't a file in {}",
+                    name,
+                    self.base_url()
+                )));
+            }
+        }
+        let episode_key = ObjectKey::new(&self.prefix, name).key().to_owned();
+        let feed_key = ObjectKey::new(&self.prefix, feed_name).key().to_owned();
+        let feed = self.get_object(&feed_key)?;
+        let enclosure_url = ObjectKey::new("", name).url(&self.object_urls().base_url());
+        let edited = feed::remove_item(&feed, &enclosure_url).map_err(|err| {
+            UploadError::new(format!("Failed to read feed {}: {}", feed_key, err))
+        })?;
+        let mut uploaded = 0;
+        match edited {
+            Some(edited) => {
+                // Written under the feed's own name, so it's uploaded with the same key and
+                // headers as when it was published.
+                let dir = env::temp_dir().join(format!("sloop-remove-{}", process::id()));
+                let path = dir.join(feed_name);
+                let result = fs::create_dir_all(&dir)
+                    .and_then(|_| fs::write(&path, edited))
+                    .map_err(|err| UploadError::io("write", &path, err))
+                    .and_then(|_| self.upload_file(&path, None, Some(&feed_metadata()), 1, 1));
+                self.progress.finish_file(&feed_key);
+                let _ = fs::remove_dir_all(&dir);
+                result?;
+                uploaded = 1;
+            }
+            None if ignore_missing => (self.log)(&format!(
+                "{} has no episode linking to {}; leaving it unchanged",
+                feed_key, enclosure_url
+            )),
+            None => {
+                return Err(UploadError::invalid(format!(
+                    "{} has no episode linking to {}; pass --ignore-missing to delete {} anyway",
+                    feed_key, enclosure_url, episode_key
+                )))
+            }
+        }
+        if self.options.dry_run {
+            (self.log)(&format!("Would delete {}", episode_key));
+            return Ok(());
+        }
+        (self.log)(&format!("Deleting {}", episode_key));
+        let request = DeleteObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: episode_key.clone(),
+            ..Default::default()
+        };
+        self.client.delete_object(request).sync().map_err(|err| {
+            UploadError::from_rusoto(format!("Failed to delete {}", episode_key), err)
+                .after_uploading(uploaded)
+        })?;
+        Ok(())
+    }
+
+    /// The contents of the object at `key`.
+    fn get_object(&self, key: &str) -> Result<Vec<u8>, UploadError> {
+        self.fetch_object(&self.bucket_name, key)?.ok_or_else(|| {
+            UploadError::new(format!(
+                "There is no {} in bucket {}",
+                key, self.bucket_name
+            ))
+        })
+    }
+
+    fn fetch_object(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>, UploadError> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+        let output = match self.client.get_object(request).sync() {
+            Ok(output) => output,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+            Err(err) => {
+                return Err(UploadError::from_rusoto(
+                    format!("Failed to download {}", key),
+                    err,
+                ))
+            }
+        };
+        let mut body = Vec::new();
+        if let Some(stream) = output.body {
+            stream
+                .into_blocking_read()
+                .read_to_end(&mut body)
+                .map_err(|err| UploadError::new(format!("Failed to download {}: {}", key, err)))?;
+        }
+        Ok(Some(body))
+    }
+
+    /// With `prune`, deletes the objects in the podcast's folder whose keys aren't in `keep`, as
+    /// listed in `existing`, logging each, and returns how many were deleted. `changed` is the
+    /// number of files already uploaded by the run, for the error's outcome.
+    fn prune(
+        &self,
+        keep: &HashSet<String>,
+        existing: &HashMap<String, ExistingObject>,
+        changed: usize,
+    ) -> Result<usize, UploadError> {
+        if !self.options.prune {
+            return Ok(0);
+        }
+        if self.options.dry_run {
+            (self.log)(&format!(
+                "Would delete any other objects in {}",
+                self.base_url()
+            ));
+            return Ok(0);
+        }
+        let stale = self.stale_keys(keep, existing);
+        for (i, batch) in stale.chunks(MAX_KEYS_PER_DELETE).enumerate() {
+            let deleted = i * MAX_KEYS_PER_DELETE;
+            for key in batch {
+                (self.log)(&format!("Deleting {}", key));
+            }
+            let request = DeleteObjectsRequest {
+                bucket: self.bucket_name.clone(),
+                delete: Delete {
+                    objects: batch
+                        .iter()
+                        .map(|key| ObjectIdentifier {
+                            key: key.clone(),
+                            version_id: None,
+                        })
+                        .collect(),
+                    quiet: Some(true),
+                },
+                ..Default::default()
+            };
+            let output = self.client.delete_objects(request).sync().map_err(|err| {
+                UploadError::from_rusoto("Failed to delete objects".to_owned(), err)
+                    .after_uploading(changed + deleted)
+            })?;
+            if let Some(error) = output.errors.unwrap_or_default().into_iter().next() {
+                return Err(UploadError::new(format!(
+                    "Failed to delete {}: {}",
+                    error.key.unwrap_or_default(),
+                    error.message.unwrap_or_default()
+                ))
+                .after_uploading(changed + deleted + batch.len()));
+            }
+        }
+        Ok(stale.len())
+    }
+
+    /// The keys of the objects directly in the podcast's folder that aren't in `keep`, sorted.
+    /// Objects in subfolders may belong to podcasts published with a longer prefix, so are
+    /// never included.
+    fn stale_keys(
+        &self,
+        keep: &HashSet<String>,
+        existing: &HashMap<String, ExistingObject>,
+    ) -> Vec<String> {
+        let mut stale: Vec<String> = existing
+            .keys()
+            .filter(|key| self.in_folder(key))
+            .filter(|key| !keep.contains(*key))
+            .cloned()
+            .collect();
+        stale.sort_unstable();
+        stale
+    }
+
+    /// Whether `key` is directly in the podcast's folder, rather than outside it or in a
+    /// subfolder.
+    fn in_folder(&self, key: &str) -> bool {
+        let folder = ObjectKey::new(&self.prefix, "");
+        key.strip_prefix(folder.key())
+            .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+    }
+
+    /// Without a prefix, warns if the bucket root already has feeds or media that `files` doesn't
+    /// include, as when another podcast was published there: the two would share keys and mix
+    /// episodes. Refuses instead with `strict`. Uses the listing already fetched for skipping
+    /// unchanged files, so costs no extra requests.
+    fn check_shared_root(
+        &self,
+        files: &[PathBuf],
+        existing: &HashMap<String, ExistingObject>,
+    ) -> Result<(), UploadError> {
+        if !self.prefix.is_empty() {
+            return Ok(());
+        }
+        let ours: HashSet<String> = files.iter().map(|path| self.key(path)).collect();
+        let mut foreign: Vec<&str> = existing
+            .keys()
+            .map(|key| key.as_str())
+            .filter(|key| !key.contains('/') && !ours.contains(*key))
+            .filter(|key| is_feed_or_media(key))
+            .collect();
+        if foreign.is_empty() {
+            return Ok(());
+        }
+        foreign.sort_unstable();
+        let listed = match foreign.len() {
+            n if n > 3 => format!("{} and {} more", foreign[..3].join(", "), n - 3),
+            _ => foreign.join(", "),
+        };
+        let message = format!(
+            "the root of bucket {} already has feeds or media that this run isn't publishing \
+             ({}). If another podcast lives there, publish each with its own --prefix",
+            self.bucket_name, listed
+        );
+        if self.options.strict {
+            return Err(UploadError::new(format!(
+                "Refusing to publish: {}",
+                message
+            )));
+        }
+        warning::warn_in(&self.log, &message);
+        Ok(())
+    }
+
+    /// Warns about each feed that isn't in the bucket yet while another feed of the same format
+    /// is in its folder, as happens when `--feed-key` or `--out` changes: subscribers to the old
+    /// URL won't get new episodes unless the old feed sends them to the new one. Episodes in the
+    /// folder that `all_files` doesn't include suggest the other feed is another podcast's
+    /// instead, which `check_shared_root` warns about.
+    fn warn_of_moved_feeds(
+        &self,
+        feeds: &[PathBuf],
+        all_files: &[PathBuf],
+        existing: &HashMap<String, ExistingObject>,
+    ) {
+        let folder = ObjectKey::new(&self.prefix, "").key().to_owned();
+        let ours: HashSet<String> = all_files.iter().map(|path| self.key(path)).collect();
+        // Other objects directly in the folder, by name.
+        let others: Vec<&str> = existing
+            .keys()
+            .filter(|key| !ours.contains(*key))
+            .filter_map(|key| key.strip_prefix(folder.as_str()))
+            .filter(|name| !name.contains('/'))
+            .collect();
+        let foreign_episodes = others.iter().any(|name| {
+            mime::content_type(Path::new(name)).is_some_and(|t| t.starts_with("audio/"))
+        });
+        if foreign_episodes {
+            return;
+        }
+        for feed in feeds {
+            let key = self.key(feed);
+            if existing.contains_key(&key) {
+                continue;
+            }
+            let format = mime::content_type(feed);
+            let mut old_names: Vec<&str> = others
+                .iter()
+                .filter(|name| mime::content_type(Path::new(name)) == format)
+                .copied()
+                .collect();
+            old_names.sort_unstable();
+            for old_name in old_names {
+                warning::warn_in(
+                    &self.log,
+                    &format!(
+                        "publishing the feed as {}, but {} is already in the bucket. If \
+                     podcast apps subscribe to {}, add \
+                     <itunes:new-feed-url>{}</itunes:new-feed-url> to that feed to move them to \
+                     the new URL",
+                        key,
+                        ObjectKey::new(&self.prefix, old_name).key(),
+                        self.object_urls().url(old_name),
+                        self.url_for_file(feed)
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Splits off the files the bucket already has, logging each, and returns the rest, in
+    /// `upload_order`, with the number skipped. Uses the same decision as `plan`.
+    fn changed_files(
+        &self,
+        files: Vec<PathBuf>,
+        existing: &HashMap<String, ExistingObject>,
+    ) -> Result<(Vec<PathBuf>, usize), UploadError> {
+        let mut changed = Vec::new();
+        let mut objects = Vec::new();
+        let mut unchanged = 0;
+        for path in files {
+            self.check_cancelled()?;
+            let object = self.plan_object(&path, existing)?;
+            if object.action == Action::Skip {
+                (self.log)(&format!("Skipping {}, which is unchanged", object.key));
+                unchanged += 1;
+            } else {
+                changed.push(path);
+                objects.push(object);
+            }
+        }
+        let changed = plan::upload_order(&objects, self.options.upload_order)
+            .into_iter()
+            .map(|i| changed[i].clone())
+            .collect();
+        Ok((changed, unchanged))
+    }
+
+    /// Decides whether `path` needs uploading by comparing it with the object at its key. S3's
+    /// ETag for an object sloop uploaded is predictable from the file and the part size, so
+    /// unchanged files are found without downloading anything. With `force`, every file is.
+    fn plan_object(
+        &self,
+        path: &Path,
+        existing: &HashMap<String, ExistingObject>,
+    ) -> Result<PlannedObject, UploadError> {
+        let key = self.key(path);
+        let size = file_len(path)?;
+        let action = match existing.get(&key) {
+            None => Action::Create,
+            Some(_) if self.options.force => Action::Update,
+            Some(object) => match self.matches_object(path, &key, size, object)? {
+                true => Action::Skip,
+                false => Action::Update,
+            },
+        };
+        Ok(PlannedObject {
+            key,
+            action,
+            size,
+            content_type: content_type(path),
+        })
+    }
+
+    /// Whether `path`, of `size` bytes, holds what `object`, at `key`, does, from its size and
+    /// ETag. S3 gives objects it encrypts with SSE-KMS an ETag that isn't derived from their
+    /// contents, so with `Sse::Kms` the ETag sloop stored in their metadata when uploading them
+    /// is compared instead, which takes a HEAD request for each object of the same size.
+    fn matches_object(
+        &self,
+        path: &Path,
+        key: &str,
+        size: u64,
+        object: &ExistingObject,
+    ) -> Result<bool, UploadError> {
+        if object.size != size {
+            return Ok(false);
+        }
+        let e_tag = self.file_e_tag(path, size)?;
+        let published = match self.options.sse {
+            Some(Sse::Kms) => match self.stored_e_tag(key)? {
+                Some(stored) => stored,
+                // Uploaded before sloop recorded it, or by another tool.
+                None => return Ok(false),
+            },
+            _ => object.e_tag.clone(),
+        };
+        Ok(published.trim_matches('"').eq_ignore_ascii_case(&e_tag))
+    }
+
+    /// The ETag S3 gives `path`, of `len` bytes, when sloop uploads it without SSE-KMS.
+    fn file_e_tag(&self, path: &Path, len: u64) -> Result<String, UploadError> {
+        let mut file = fs::File::open(path).map_err(|err| UploadError::io("open", path, err))?;
+        self.expected_e_tag(&mut file, len)
+            .map_err(|err| self.read_error(path, err))
+    }
+
+    /// The ETag recorded in the metadata of the object at `key` as it was uploaded with
+    /// SSE-KMS, if it has one.
+    fn stored_e_tag(&self, key: &str) -> Result<Option<String>, UploadError> {
+        let request = HeadObjectRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+        let output = self.client.head_object(request).sync().map_err(|err| {
+            UploadError::from_rusoto(format!("Failed to read the metadata of {}", key), err)
+        })?;
+        Ok(output
+            .metadata
+            .and_then(|mut metadata| metadata.remove(E_TAG_METADATA)))
+    }
+
+    /// The user metadata to upload `path`, of `len` bytes, with: `metadata`, and with SSE-KMS
+    /// the ETag the object would have without it, for `matches_object` to compare on later runs.
+    fn object_metadata(
+        &self,
+        path: &Path,
+        len: u64,
+        metadata: Option<&HashMap<String, String>>,
+    ) -> Result<Option<HashMap<String, String>>, UploadError> {
+        if self.options.sse != Some(Sse::Kms) {
+            return Ok(metadata.cloned());
+        }
+        let mut metadata = metadata.cloned().unwrap_or_default();
+        metadata.insert(E_TAG_METADATA.to_owned(), self.file_e_tag(path, len)?);
+        Ok(Some(metadata))
+    }
+
+    /// The ETag S3 gives an object with these contents when sloop uploads it: the MD5 of the
+    /// contents, or for a multipart upload the MD5 of the parts' MD5s and the number of parts.
+    fn expected_e_tag<R: Read>(&self, reader: &mut R, len: u64) -> io::Result<String> {
+        if len <= self.options.multipart_threshold {
+            return Ok(format!("{:x}", file_md5(reader, &self.cancel)?));
+        }
+        let mut digests = Vec::new();
+        let mut parts = 0;
+        loop {
+            self.cancel.check()?;
+            let mut body = Vec::new();
+            (&mut *reader)
+                .take(self.options.part_size)
+                .read_to_end(&mut body)?;
+            if body.is_empty() {
+                break;
+            }
+            digests.extend_from_slice(&md5::compute(&body).0);
+            parts += 1;
+        }
+        Ok(format!("{:x}-{}", md5::compute(&digests), parts))
+    }
+
+    /// The objects under the prefix, to compare local files against. A dry run sends no requests,
+    /// so compares against nothing.
+    fn existing_objects_to_compare(&self) -> Result<HashMap<String, ExistingObject>, UploadError> {
+        if self.options.dry_run {
+            return Ok(HashMap::new());
+        }
+        self.existing_objects()
+    }
+
+    fn existing_objects(&self) -> Result<HashMap<String, ExistingObject>, UploadError> {
+        let objects = self
+            .list_objects("Failed to list objects")?
+            .unwrap_or_default();
+        let mut existing = HashMap::new();
+        for object in objects {
+            if let (Some(key), Some(size), Some(e_tag)) = (object.key, object.size, object.e_tag) {
+                let size = size as u64;
+                existing.insert(key, ExistingObject { size, e_tag });
+            }
+        }
+        Ok(existing)
+    }
+
+    /// The objects under the prefix, sorted by key, as `sloop list` shows them.
+    pub fn list(&self) -> Result<Vec<ListedObject>, UploadError> {
+        let context = format!("Failed to list bucket {}", self.bucket_name);
+        let objects = self
+            .list_objects(&context)?
+            .ok_or_else(|| UploadError::new(format!("{}: the bucket doesn't exist", context)))?;
+        let mut listed: Vec<ListedObject> = objects
+            .into_iter()
+            .filter_map(|object| {
+                let key = object.key?;
+                let last_modified = object
+                    .last_modified
+                    .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                    .map(|date| date.with_timezone(&Utc));
+                let feed = is_feed(Path::new(&key));
+                Some(ListedObject {
+                    size: object.size.unwrap_or(0) as u64,
+                    last_modified,
+                    feed,
+                    key,
+                })
+            })
+            .collect();
+        listed.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(listed)
+    }
+
+    /// The start of every key under the prefix, e.g. `books/Dune/`, which is empty without one.
+    pub fn folder(&self) -> String {
+        ObjectKey::new(&self.prefix, "").key().to_owned()
+    }
+
+    /// Tallies the requests for the podcast's episodes in the access logs S3 delivers for the
+    /// bucket, downloading every log object. Episodes that were never fetched are included.
+    pub fn access_log_stats(&self) -> Result<Tally, UploadError> {
+        let request = GetBucketLoggingRequest {
+            bucket: self.bucket_name.clone(),
+        };
+        let target = self
+            .client
+            .get_bucket_logging(request)
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto(
+                    format!("Failed to get the logging settings of {}", self.bucket_name),
+                    err,
+                )
+            })?
+            .logging_enabled
+            .ok_or_else(|| {
+                UploadError::invalid(format!(
+                    "Bucket {} doesn't deliver access logs; publish with --enable-access-logs \
+                     LOG-BUCKET and wait for requests to be logged",
+                    self.bucket_name
+                ))
+            })?;
+        let keys: Vec<String> = self.list()?.into_iter().map(|object| object.key).collect();
+        let folder = self.folder();
+        let mut tally = Tally::new(&self.bucket_name, &folder, &keys);
+        let context = format!("Failed to list access logs in {}", target.target_bucket);
+        let logs = self
+            .list_objects_in(
+                &target.target_bucket,
+                Some(target.target_prefix.clone()).filter(|prefix| !prefix.is_empty()),
+                &context,
+            )?
+            .ok_or_else(|| UploadError::new(format!("{}: the bucket doesn't exist", context)))?;
+        for key in logs.into_iter().filter_map(|log| log.key) {
+            if let Some(log) = self.fetch_object(&target.target_bucket, &key)? {
+                tally.add_log(&String::from_utf8_lossy(&log));
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Every object under the prefix, following continuation tokens through each page of
+    /// results, or `None` if the bucket doesn't exist. Errors start with `context`.
+    fn list_objects(&self, context: &str) -> Result<Option<Vec<rusoto_s3::Object>>, UploadError> {
+        let prefix = Some(format!("{}/", self.prefix)).filter(|_| !self.prefix.is_empty());
+        self.list_objects_in(&self.bucket_name, prefix, context)
+    }
+
+    /// Like `list_objects`, but for any bucket and prefix.
+    fn list_objects_in(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        context: &str,
+    ) -> Result<Option<Vec<rusoto_s3::Object>>, UploadError> {
+        let mut objects = Vec::new();
+        let mut request = ListObjectsV2Request {
+            bucket: bucket.to_owned(),
+            prefix,
+            ..Default::default()
+        };
+        loop {
+            let output = match self.client.list_objects_v2(request.clone()).sync() {
+                Ok(output) => output,
+                Err(RusotoError::Service(ListObjectsV2Error::NoSuchBucket(_))) => return Ok(None),
+                Err(err) => return Err(UploadError::from_rusoto(context.to_owned(), err)),
+            };
+            objects.extend(output.contents.unwrap_or_default());
+            match output.next_continuation_token {
+                Some(token) if output.is_truncated == Some(true) => {
+                    request.continuation_token = Some(token)
+                }
+                _ => return Ok(Some(objects)),
+            }
+        }
+    }
+
+    fn prepare_bucket(&self) -> Result<(), UploadError> {
+        if self.check_credentials && !self.options.dry_run {
+            let mut provider = ChainProvider::new();
+            provider.set_timeout(Duration::from_secs(2));
+            check_credentials(&provider)?;
+        }
+        if !self.options.skip_create_bucket {
+            self.create_bucket()?;
+        }
+        if !self.options.skip_policy {
+            self.make_bucket_public()?;
+        }
+        if let Some(target) = &self.options.access_logs {
+            self.enable_access_logs(target)?;
+        }
+        Ok(())
+    }
+
+    fn enable_access_logs(&self, target: &LogTarget) -> Result<(), UploadError> {
+        let prefix = target
+            .prefix
+            .clone()
+            .unwrap_or_else(|| format!("{}/", self.bucket_name));
+        if self.options.dry_run {
+            (self.log)(&format!(
+                "Would deliver access logs for {} to s3://{}/{}",
+                self.bucket_name, target.bucket, prefix
+            ));
+            return Ok(());
+        }
+        let request = PutBucketLoggingRequest {
+            bucket: self.bucket_name.clone(),
+            bucket_logging_status: BucketLoggingStatus {
+                logging_enabled: Some(LoggingEnabled {
+                    target_bucket: target.bucket.clone(),
+                    target_prefix: prefix,
+                    target_grants: None,
+                }),
+            },
+            ..Default::default()
+        };
+        self.client
+            .put_bucket_logging(request)
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto(
+                    format!(
+                        "Failed to deliver access logs for {} to {}; the log bucket must let S3 \
+                         write to it",
+                        self.bucket_name, target.bucket
+                    ),
+                    err,
+                )
+            })
+    }
+
+    fn create_bucket(&self) -> Result<(), UploadError> {
+        // MinIO and similar services reject location constraints they don't know, but AWS (and
+        // servers emulating it) require one, so with a custom endpoint the constraint is only
+        // sent if the server asks for it.
+        let with_constraint = self.endpoint.is_none();
+        let request = self.create_bucket_request(with_constraint);
+        if self.options.dry_run {
+            (self.log)(&format!(
+                "Would create bucket {} in {}",
+                request.bucket, self.region
+            ));
+            return Ok(());
+        }
+        let result = match self.client.create_bucket(request).sync() {
+            Err(RusotoError::Unknown(response))
+                if !with_constraint
+                    && error_element(response.body_as_str(), "Code")
+                        == Some("IllegalLocationConstraintException") =>
+            {
+                self.client
+                    .create_bucket(self.create_bucket_request(true))
+                    .sync()
+            }
+            result => result,
+        };
+        match result {
+            Ok(_) | Err(RusotoError::Service(BucketAlreadyOwnedByYou(_))) => Ok(()),
+            Err(err @ RusotoError::Service(BucketAlreadyExists(_))) => Err(UploadError {
+                message: bucket_name_taken(&self.bucket_name, Utc::now().timestamp_subsec_nanos()),
+                kind: UploadErrorKind::BucketNameTaken,
+                ..UploadError::from_rusoto(String::new(), err)
+            }),
+            Err(err) => Err(UploadError::from_rusoto(
+                "Failed to create bucket".to_owned(),
+                err,
+            )),
+        }
+    }
+
+    fn create_bucket_request(&self, with_constraint: bool) -> CreateBucketRequest {
+        CreateBucketRequest {
+            bucket: self.bucket_name.clone(),
+            create_bucket_configuration: Some(CreateBucketConfiguration {
+                location_constraint: Some(self.region.clone()),
+            })
+            .filter(|_| with_constraint && !OMIT_LOCATION_CONSTRAINT.contains(&&*self.region)),
+            ..Default::default()
+        }
+    }
+
+    /// Lets public ACLs and policies take effect and, in `bucket-policy` mode, applies a policy
+    /// making every object readable.
+    fn make_bucket_public(&self) -> Result<(), UploadError> {
+        if self.options.public_mode == PublicMode::None {
+            return Ok(());
+        }
+        if self.options.dry_run {
+            if self.options.public_mode == PublicMode::BucketPolicy {
+                (self.log)(&format!(
+                    "Would check Block Public Access on {} and apply a public read policy:\n{}",
+                    self.bucket_name,
+                    self.public_policy()
+                ));
+            } else {
+                (self.log)(&format!(
+                    "Would check Block Public Access on {} and upload objects with a public-read \
+                     ACL",
+                    self.bucket_name
+                ));
+            }
+            return Ok(());
+        }
+        self.allow_public_access()?;
+        if self.options.public_mode == PublicMode::ObjectAcl {
+            return Ok(());
+        }
+        let policy = self.public_policy();
+        let policy_request = PutBucketPolicyRequest {
+            bucket: self.bucket_name.to_owned(),
+            policy,
+            ..Default::default()
+        };
+        self.client
+            .put_bucket_policy(policy_request)
+            .sync()
+            .map_err(|err| {
+                let denied = rusoto_error_code(&err) == Some("AccessDenied");
+                let mut err =
+                    UploadError::from_rusoto("Failed to set bucket policy".to_owned(), err);
+                if denied {
+                    // The bucket's own settings were checked above, so this is usually the
+                    // account-wide setting, which sloop leaves alone.
+                    err.message.push_str(
+                        ". If Block Public Access is on for the whole account, turn off \
+                         BlockPublicPolicy in the S3 console, or pass --public-mode none",
+                    );
+                }
+                err
+            })
+    }
+
+    /// Turns off the bucket's Block Public Access settings that would stop the public mode taking
+    /// effect, leaving the others as they are. Without `allow_public`, explains what's blocking
+    /// instead.
+    fn allow_public_access(&self) -> Result<(), UploadError> {
+        let request = GetPublicAccessBlockRequest {
+            bucket: self.bucket_name.to_owned(),
+        };
+        let config = match self.client.get_public_access_block(request).sync() {
+            Ok(output) => output.public_access_block_configuration.unwrap_or_default(),
+            // Services without Block Public Access, e.g. MinIO, have nothing blocking.
+            Err(err)
+                if matches!(
+                    rusoto_error_code(&err),
+                    Some("NoSuchPublicAccessBlockConfiguration") | Some("NotImplemented")
+                ) =>
+            {
+                return Ok(())
+            }
+            Err(err) => {
+                return Err(UploadError::from_rusoto(
+                    "Failed to get public access block".to_owned(),
+                    err,
+                ))
+            }
+        };
+        let mut allowed = config.clone();
+        let settings = match self.options.public_mode {
+            PublicMode::BucketPolicy => [
+                ("BlockPublicPolicy", &mut allowed.block_public_policy),
+                (
+                    "RestrictPublicBuckets",
+                    &mut allowed.restrict_public_buckets,
+                ),
+            ],
+            PublicMode::ObjectAcl => [
+                ("BlockPublicAcls", &mut allowed.block_public_acls),
+                ("IgnorePublicAcls", &mut allowed.ignore_public_acls),
+            ],
+            PublicMode::None => return Ok(()),
+        };
+        let mut blocking = vec![];
+        for (name, setting) in settings {
+            if *setting == Some(true) {
+                blocking.push(name);
+                *setting = Some(false);
+            }
+        }
+        if blocking.is_empty() {
+            return Ok(());
+        }
+        let blocking = blocking.join(" and ");
+        if !self.options.allow_public {
+            return Err(UploadError::new(format!(
+                "Block Public Access is on for bucket {} ({}), so podcast apps couldn't download \
+                 its episodes. Pass --allow-public to let sloop turn this off, or pass \
+                 --public-mode none to manage access yourself",
+                self.bucket_name, blocking
+            )));
+        }
+        (self.log)(&format!(
+            "Turning off {} for bucket {}",
+            blocking, self.bucket_name
+        ));
+        self.client
+            .put_public_access_block(PutPublicAccessBlockRequest {
+                bucket: self.bucket_name.to_owned(),
+                public_access_block_configuration: allowed,
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto("Failed to update public access block".to_owned(), err)
+            })
+    }
+
+    /// A bucket policy letting anyone read the bucket's objects, which podcast apps need.
+    fn public_policy(&self) -> String {
+        json!({
+            "Version": "2012-10-17",
+            "Statement": [{
+                "Sid": "AddPerm",
+                "Effect": "Allow",
+                "Principal": "*",
+                "Action": ["s3:GetObject"],
+                "Resource": [format!("arn:aws:s3:::{}/*", &self.bucket_name)],
+            }]
+        })
+        .to_string()
+    }
+
+    /// Finds multipart uploads left behind by previous runs. Uploads for files that are about to
+    /// be sent again are returned so they can be resumed; others directly in the podcast's folder
+    /// are aborted unless `keep_orphans` is set. Like `stale_keys`, uploads outside the folder or
+    /// in its subfolders are left alone, as they may be another podcast's or another tool's.
+    fn interrupted_uploads(
+        &self,
+        files: &[PathBuf],
+    ) -> Result<HashMap<String, MultipartUpload>, UploadError> {
+        if self.options.dry_run {
+            return Ok(HashMap::new());
+        }
+        let mut keys = Vec::new();
+        for path in files {
+            if self.is_multipart(path)? {
+                keys.push(self.key(path));
+            }
+        }
+        let mut resumable: HashMap<String, MultipartUpload> = HashMap::new();
+        let mut orphans = Vec::new();
+        for upload in self.list_multipart_uploads()? {
+            let key = match &upload.key {
+                Some(key) if keys.contains(key) => key.clone(),
+                Some(key) if self.in_folder(key) => {
+                    orphans.push(upload);
+                    continue;
+                }
+                _ => continue,
+            };
+            match resumable.remove(&key) {
+                Some(existing) if existing.initiated >= upload.initiated => {
+                    orphans.push(upload);
+                    resumable.insert(key, existing);
+                }
+                Some(existing) => {
+                    orphans.push(existing);
+                    resumable.insert(key, upload);
+                }
+                None => {
+                    resumable.insert(key, upload);
+                }
+            }
+        }
+        if !self.options.keep_orphans {
+            for orphan in orphans {
+                self.abort_multipart_upload(&orphan)?;
+            }
+        }
+        Ok(resumable)
+    }
+
+    /// The object key for `path`: its file name under the prefix.
+    fn keys(&self, paths: &[PathBuf]) -> HashSet<String> {
+        paths.iter().map(|path| self.key(path)).collect()
+    }
+
+    fn object_key(&self, path: &Path) -> ObjectKey {
+        ObjectKey::new(&self.prefix, self.name(path))
+    }
+
+    /// Episodes get `storage_class` and everything else, fetched more often, gets
+    /// `feed_storage_class`.
+    fn storage_class(&self, path: &Path) -> Option<String> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match mime::audio_type(extension) {
+            Some(_) => self.options.storage_class,
+            None => self.options.feed_storage_class,
+        }
+        .map(StorageClass::name)
+    }
+
+    /// Feeds, going by their content type, get `feed_cache_control` and everything else gets
+    /// `media_cache_control`.
+    fn cache_control(&self, path: &Path) -> Option<String> {
+        let cache_control = if is_feed(path) {
+            &self.options.feed_cache_control
+        } else {
+            &self.options.media_cache_control
+        };
+        Some(cache_control.clone()).filter(|cache_control| !cache_control.is_empty())
+    }
+
+    /// The name `path` is uploaded as, within the prefix.
+    fn name<'a>(&'a self, path: &'a Path) -> &'a str {
+        match self.names.get(path) {
+            Some(name) => name,
+            None => file_name(path),
+        }
+    }
+
+    fn is_multipart(&self, path: &Path) -> Result<bool, UploadError> {
+        Ok(file_len(path)? > self.options.multipart_threshold)
+    }
+
+    fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, UploadError> {
+        let mut uploads = Vec::new();
+        // Only narrows the listing; `interrupted_uploads` decides which are ours.
+        let mut request = ListMultipartUploadsRequest {
+            bucket: self.bucket_name.clone(),
+            prefix: Some(format!("{}/", self.prefix)).filter(|_| !self.prefix.is_empty()),
+            ..Default::default()
+        };
+        loop {
+            let output = self
+                .client
+                .list_multipart_uploads(request.clone())
+                .sync()
+                .map_err(|err| {
+                    UploadError::from_rusoto("Failed to list multipart uploads".to_owned(), err)
+                })?;
+            uploads.extend(output.uploads.unwrap_or_default());
+            if output.is_truncated != Some(true) {
+                return Ok(uploads);
+            }
+            request.key_marker = output.next_key_marker;
+            request.upload_id_marker = output.next_upload_id_marker;
+        }
+    }
+
+    fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<Part>, UploadError> {
+        let mut parts = Vec::new();
+        let mut request = ListPartsRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            upload_id: upload_id.to_owned(),
+            ..Default::default()
+        };
+        loop {
+            let output = self
+                .client
+                .list_parts(request.clone())
+                .sync()
+                .map_err(|err| {
+                    UploadError::from_rusoto(format!("Failed to list parts of {}", key), err)
+                })?;
+            parts.extend(output.parts.unwrap_or_default());
+            if output.is_truncated != Some(true) {
+                return Ok(parts);
+            }
+            request.part_number_marker = output.next_part_number_marker;
+        }
+    }
+
+    fn abort_multipart_upload(&self, upload: &MultipartUpload) -> Result<(), UploadError> {
+        let key = upload.key.clone().unwrap_or_default();
+        (self.log)(&format!("Aborting orphaned upload of {}", &key));
+        self.client
+            .abort_multipart_upload(AbortMultipartUploadRequest {
+                bucket: self.bucket_name.clone(),
+                key,
+                upload_id: upload.upload_id.clone().unwrap_or_default(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto("Failed to abort multipart upload".to_owned(), err)
+            })?;
+        Ok(())
+    }
+
+    /// Uploads files on up to `concurrency` threads, returning the number of bytes sent. After a
+    /// failure no new uploads are started, but those in progress are allowed to finish; the error
+    /// then names every file that failed. Files are numbered for progress reports out of
+    /// `total_files`.
+    fn upload_files(
+        &self,
+        files: Vec<PathBuf>,
+        interrupted: HashMap<String, MultipartUpload>,
+        total_files: usize,
+    ) -> Result<u64, UploadError> {
+        let queue = Mutex::new(files.into_iter().enumerate());
+        let interrupted = Mutex::new(interrupted);
+        let bytes = Mutex::new(0);
+        let uploaded = Mutex::new(0);
+        let failures = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..self.options.concurrency.max(1) {
+                scope.spawn(|| loop {
+                    if !failures.lock().unwrap().is_empty() || self.cancel.is_cancelled() {
+                        return;
+                    }
+                    let (i, path) = match queue.lock().unwrap().next() {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    let key = self.key(&path);
+                    let upload = interrupted.lock().unwrap().remove(&key);
+                    let result = self.upload_file(&path, upload, None, i + 1, total_files);
+                    self.progress.finish_file(&key);
+                    match result {
+                        Ok(sent) => {
+                            *bytes.lock().unwrap() += sent;
+                            *uploaded.lock().unwrap() += 1;
+                        }
+                        Err(err) => failures.lock().unwrap().push(err.with_path(&path)),
+                    }
+                });
+            }
+        });
+        // Uploads cut short by cancelling fail too, but only the cancelling is worth reporting.
+        self.check_cancelled()?;
+        let mut failures = failures.into_inner().unwrap();
+        let uploaded = uploaded.into_inner().unwrap();
+        match failures.len() {
+            0 => Ok(bytes.into_inner().unwrap()),
+            1 => Err(failures.remove(0).after_uploading(uploaded)),
+            count => {
+                let mut message = format!("{} files failed to upload:", count);
+                for failure in failures {
+                    message.push_str(&format!("\n  {}", failure));
+                }
+                Err(UploadError::new(message).after_uploading(uploaded))
+            }
+        }
+    }
+
+    /// Uploads one file with the given user metadata, returning its size.
+    fn upload_file(
+        &self,
+        path: &Path,
+        interrupted: Option<MultipartUpload>,
+        metadata: Option<&HashMap<String, String>>,
+        number: usize,
+        total_files: usize,
+    ) -> Result<u64, UploadError> {
+        let key = &self.key(path);
+        let len = file_len(path)?;
+        let metadata = self.object_metadata(path, len, metadata)?;
+        let metadata = metadata.as_ref();
+        self.progress.start_file(key, number, total_files, len);
+        if len > self.options.multipart_threshold {
+            self.upload_multipart(path, interrupted, metadata)?;
+            return Ok(len);
+        }
+        let mut file = fs::File::open(path).map_err(|err| UploadError::io("open", path, err))?;
+        let read_error = |err| self.read_error(path, err);
+        let content_length = file.metadata().map_err(read_error)?.len();
+        let content_md5 =
+            Some(base64.encode(file_md5(&mut file, &self.cancel).map_err(read_error)?.0));
+        file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+        // Objects are stored exactly as read, with no content encoding, so that podcast apps
+        // can seek within them using range requests.
+        let request = PutObjectRequest {
+            content_length: Some(content_length as i64),
+            body: Some(ByteStream::new(FileStream {
+                file,
+                key: key.to_owned(),
+                progress: Arc::clone(&self.progress),
+                cancel: self.cancel.clone(),
+            })),
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            content_md5,
+            content_type: Some(content_type(path)),
+            metadata: metadata.cloned(),
+            acl: self.options.public_mode.object_acl(),
+            server_side_encryption: self.options.sse.map(Sse::header),
+            ssekms_key_id: self.options.sse_kms_key_id.clone(),
+            storage_class: self.storage_class(path),
+            cache_control: self.cache_control(path),
+            ..Default::default()
+        };
+        if self.options.dry_run {
+            (self.log)(&format!(
+                "Would upload {} ({}, {})",
+                request.key,
+                self.options.numbers.bytes(content_length),
+                request.content_type.as_deref().unwrap_or_default()
+            ));
+            return Ok(content_length);
+        }
+        (self.log)(&format!("Uploading {}", key));
+        self.client.put_object(request).sync().map_err(|err| {
+            self.check_cancelled().err().unwrap_or_else(|| {
+                UploadError::from_rusoto(format!("Failed to upload {}", key), err)
+            })
+        })?;
+        Ok(content_length)
+    }
+
+    /// Fails once the run has been cancelled.
+    fn check_cancelled(&self) -> Result<(), UploadError> {
+        match self.cancel.is_cancelled() {
+            true => Err(UploadError::cancelled()),
+            false => Ok(()),
+        }
+    }
+
+    /// A failed read of `path`, unless the run was cancelled, which is then the reason.
+    fn read_error(&self, path: &Path, err: io::Error) -> UploadError {
+        self.check_cancelled()
+            .err()
+            .unwrap_or_else(|| UploadError::io("read", path, err))
+    }
+
+    fn upload_multipart(
+        &self,
+        path: &Path,
+        interrupted: Option<MultipartUpload>,
+        metadata: Option<&HashMap<String, String>>,
+    ) -> Result<(), UploadError> {
+        let key = &self.key(path);
+        let mut file = fs::File::open(path).map_err(|err| UploadError::io("open", path, err))?;
+        let read_error = |err| self.read_error(path, err);
+        let (upload_id, mut parts) = match interrupted.and_then(|upload| upload.upload_id) {
+            Some(upload_id) => {
+                let uploaded = self.list_parts(key, &upload_id)?;
+                let parts = reusable_parts(&uploaded, self.options.part_size, &mut file)
+                    .map_err(read_error)?;
+                (self.log)(&format!(
+                    "Resuming upload of {} from part {}",
+                    key,
+                    parts.len() + 1
+                ));
+                (upload_id, parts)
+            }
+            None => match self.create_multipart_upload(path, metadata)? {
+                Some(upload_id) => {
+                    (self.log)(&format!("Uploading {}", key));
+                    (upload_id, Vec::new())
+                }
+                None => return Ok(()),
+            },
+        };
+        let resume_from = parts.len() as u64 * self.options.part_size;
+        self.progress.advance(key, resume_from);
+        file.seek(SeekFrom::Start(resume_from))
+            .map_err(read_error)?;
+        loop {
+            self.check_cancelled()?;
+            let mut body = Vec::new();
+            (&mut file)
+                .take(self.options.part_size)
+                .read_to_end(&mut body)
+                .map_err(read_error)?;
+            if body.is_empty() && !parts.is_empty() {
+                break;
+            }
+            let part_number = parts.len() as i64 + 1;
+            let part_len = body.len() as u64;
+            let request = UploadPartRequest {
+                content_md5: Some(base64.encode(md5::compute(&body).0)),
+                content_length: Some(body.len() as i64),
+                body: Some(body.into()),
+                bucket: self.bucket_name.clone(),
+                key: key.to_owned(),
+                upload_id: upload_id.clone(),
+                part_number,
+                ..Default::default()
+            };
+            let output = self.client.upload_part(request).sync().map_err(|err| {
+                UploadError::from_rusoto(
+                    format!(
+                        "Failed to upload part {} of {} (it will be resumed on the next run)",
+                        part_number, key
+                    ),
+                    err,
+                )
+            })?;
+            self.progress.advance(key, part_len);
+            parts.push(CompletedPart {
+                e_tag: output.e_tag,
+                part_number: Some(part_number),
+            });
+        }
+        self.client
+            .complete_multipart_upload(CompleteMultipartUploadRequest {
+                bucket: self.bucket_name.clone(),
+                key: key.to_owned(),
+                upload_id,
+                multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto(format!("Failed to complete upload of {}", key), err)
+            })?;
+        Ok(())
+    }
+
+    /// Starts a multipart upload and returns its ID, or `None` in a dry run.
+    fn create_multipart_upload(
+        &self,
+        path: &Path,
+        metadata: Option<&HashMap<String, String>>,
+    ) -> Result<Option<String>, UploadError> {
+        let key = &self.key(path);
+        let request = CreateMultipartUploadRequest {
+            bucket: self.bucket_name.clone(),
+            key: key.to_owned(),
+            content_type: Some(content_type(path)),
+            metadata: metadata.cloned(),
+            acl: self.options.public_mode.object_acl(),
+            server_side_encryption: self.options.sse.map(Sse::header),
+            ssekms_key_id: self.options.sse_kms_key_id.clone(),
+            storage_class: self.storage_class(path),
+            cache_control: self.cache_control(path),
+            ..Default::default()
+        };
+        if self.options.dry_run {
+            let len = file_len(path)?;
+            (self.log)(&format!(
+                "Would upload {} ({}, {}) in {} parts",
+                request.key,
+                self.options.numbers.bytes(len),
+                request.content_type.as_deref().unwrap_or_default(),
+                len.max(1).div_ceil(self.options.part_size)
+            ));
+            return Ok(None);
+        }
+        self.client
+            .create_multipart_upload(request)
+            .sync()
+            .map_err(|err| {
+                UploadError::from_rusoto(format!("Failed to start upload of {}", key), err)
+            })?
+            .upload_id
+            .map(Some)
+            .ok_or_else(|| UploadError::new(format!("No upload ID returned for {}", key)))
+    }
+}
+
+impl Uploader for S3Uploader {
+    /// The URL objects are served under, including any prefix: the bucket's own, or the public
+    /// URL in front of it.
+    fn base_url(&self) -> String {
+        match &self.public_url {
+            Some(public_url) => naming::url_for_key(public_url, &self.prefix),
+            None => naming::url_for_key(&self.bucket_url(), &self.prefix),
+        }
+    }
+
+    fn url_for_file(&self, file: &Path) -> String {
+        self.object_urls().url(self.name(file))
+    }
+
+    /// How feeds should link to the uploaded objects: presigned URLs if `presign` is set,
+    /// otherwise public ones.
+    fn object_urls(&self) -> ObjectUrls {
+        match (self.options.presign, &self.credentials) {
+            (Some(expires_in), Some(credentials)) => ObjectUrls::Presigned(Presigner {
+                bucket_url: self.bucket_url(),
+                prefix: self.prefix.clone(),
+                region: self.region.clone(),
+                credentials: credentials.clone(),
+                expires_in,
+            }),
+            _ => ObjectUrls::public(&self.base_url()),
+        }
+    }
+
+    /// The key `path` is uploaded as: its file name, or the name given by `upload_as`, under
+    /// the prefix.
+    fn key(&self, path: &Path) -> String {
+        self.object_key(path).key().to_owned()
+    }
+
+    /// Uploads `path` as `name`, under the prefix, rather than under its own file name.
+    fn upload_as(&mut self, path: &Path, name: &str) {
+        self.names.insert(path.to_path_buf(), name.to_owned());
+    }
+
+    /// Records that the feeds give `path` an enclosure length of `length` bytes. Publishing
+    /// fails rather than upload feeds whose lengths no longer match the files.
+    fn expect_length(&mut self, path: &Path, length: u64) {
+        self.enclosure_lengths.push((path.to_path_buf(), length));
+    }
+
+    /// Sends progress lines to `log` instead of stdout.
+    fn set_log(&mut self, log: Log) {
+        self.log = log;
+    }
+
+    /// Reports bytes sent to `progress` as files upload.
+    fn set_progress(&mut self, progress: Arc<dyn Progress>) {
+        self.progress = progress;
+    }
+
+    fn upload(&self, files: Vec<PathBuf>) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        check_files(&files)?;
+        self.prepare_bucket()?;
+        let existing = self.existing_objects_to_compare()?;
+        self.check_shared_root(&files, &existing)?;
+        let keys = self.keys(&files);
+        let (files, unchanged) = self.changed_files(files, &existing)?;
+        let sent = files.iter().map(|path| self.key(path)).collect();
+        let interrupted = self.interrupted_uploads(&files)?;
+        let total_files = files.len();
+        let bytes = self.upload_files(files, interrupted, total_files)?;
+        let deleted = self.prune(&keys, &existing, total_files)?;
+        Ok(UploadSummary {
+            files: total_files,
+            bytes,
+            elapsed: started.elapsed(),
+            unchanged,
+            deleted,
+            keys: sent,
+        })
+    }
+
+    /// What `publish` would do with each of `feeds` and `files`, sorted by key. Only reads from
+    /// the bucket, which needn't exist yet.
+    fn plan(
+        &self,
+        feeds: &[PathBuf],
+        files: &[PathBuf],
+    ) -> Result<Vec<PlannedObject>, UploadError> {
+        let mut all_files = files.to_vec();
+        all_files.extend(feeds.iter().cloned());
+        check_files(&all_files)?;
+        let existing = self.existing_objects()?;
+        let mut objects = all_files
+            .iter()
+            .map(|path| self.plan_object(path, &existing))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.options.prune {
+            for key in self.stale_keys(&self.keys(&all_files), &existing) {
+                objects.push(PlannedObject {
+                    action: Action::Delete,
+                    size: existing[&key].size,
+                    content_type: mime::content_type(Path::new(&key))
+                        .unwrap_or(mime::OCTET_STREAM)
+                        .to_owned(),
+                    key,
+                });
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    /// Uploads `files` (media and artwork), then checks that the first audio file can be fetched
+    /// with a range request if `http` is given, and only then uploads the feeds, in order. If
+    /// anything before the feeds fails, they aren't uploaded, so the previously published feeds
+    /// stay live instead of pointing at missing files.
+    fn publish(
+        &self,
+        feeds: &[PathBuf],
+        files: Vec<PathBuf>,
+        http: Option<&dyn HttpGet>,
+    ) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        let mut all_files = files.clone();
+        all_files.extend(feeds.iter().cloned());
+        check_files(&all_files)?;
+        check_enclosure_lengths(&self.enclosure_lengths)?;
+        self.prepare_bucket()?;
+        let existing = self.existing_objects_to_compare()?;
+        self.check_shared_root(&all_files, &existing)?;
+        self.warn_of_moved_feeds(feeds, &all_files, &existing);
+        let keys = self.keys(&all_files);
+        // The range check doesn't need the file to have been sent by this run.
+        let range_check = files
+            .iter()
+            .find(|path| {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                mime::audio_type(extension).is_some()
+            })
+            .cloned();
+        let (files, unchanged_files) = self.changed_files(files, &existing)?;
+        let (feeds, unchanged_feeds) = self.changed_files(feeds.to_vec(), &existing)?;
+        let mut all_files = files.clone();
+        all_files.extend(feeds.iter().cloned());
+        let sent = all_files.iter().map(|path| self.key(path)).collect();
+        let mut interrupted = self.interrupted_uploads(&all_files)?;
+        let feed_uploads: Vec<Option<MultipartUpload>> = feeds
+            .iter()
+            .map(|feed| interrupted.remove(&self.key(feed)))
+            .collect();
+        let total_files = files.len() + feeds.len();
+        let uploaded = files.len();
+        let mut bytes = self.upload_files(files, interrupted, total_files)?;
+        if let (Some(http), Some(path), false) = (http, range_check, self.options.dry_run) {
+            self.verify_range(http, &path)
+                .map_err(|err| err.after_uploading(uploaded))?;
+        }
+        self.check_cancelled()?;
+        // Files can change while others upload, and a feed with the wrong length for one stops
+        // some apps playing it.
+        check_enclosure_lengths(&self.enclosure_lengths)
+            .map_err(|err| err.after_uploading(uploaded))?;
+        let first_feed = total_files - feeds.len() + 1;
+        let feed_metadata = feed_metadata();
+        for (i, (feed, upload)) in feeds.iter().zip(feed_uploads).enumerate() {
+            let result = self.upload_file(
+                feed,
+                upload,
+                Some(&feed_metadata),
+                first_feed + i,
+                total_files,
+            );
+            self.progress.finish_file(&self.key(feed));
+            bytes += result.map_err(|err| err.with_path(feed).after_uploading(uploaded + i))?;
+        }
+        // Only once the feeds no longer link to them can stale objects go.
+        let deleted = self.prune(&keys, &existing, total_files)?;
+        Ok(UploadSummary {
+            files: total_files,
+            bytes,
+            elapsed: started.elapsed(),
+            unchanged: unchanged_files + unchanged_feeds,
+            deleted,
+            keys: sent,
+        })
+    }
+
+    /// The published copy of `path`, from the key it's uploaded to, or `None` if it hasn't been
+    /// published.
+    fn download(&self, path: &Path) -> Result<Option<Vec<u8>>, UploadError> {
+        self.fetch_object(&self.bucket_name, &self.key(path))
+    }
+
+    /// Compares ETags, as publishing does to skip unchanged files. A dry run sends no
+    /// requests, so can't tell.
+    fn same_contents(&self, path: &Path, name: &str) -> Result<Option<bool>, UploadError> {
+        if self.options.dry_run {
+            return Ok(None);
+        }
+        let key = ObjectKey::new(&self.prefix, name);
+        match self.existing_objects()?.get(key.key()) {
+            Some(object) => self
+                .matches_object(path, key.key(), file_len(path)?, object)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Checks that a public URL pointing straight at S3 is the URL of `bucket`, since feeds
+/// linking to another bucket, e.g. last year's, would link to files that aren't there. A URL in
+/// another region only gets a warning, as it may be `--region` that's wrong, which S3 will point
+/// out when uploading. URLs of CDNs and custom domains can't be checked, so pass.
+fn check_public_url(public_url: &str, bucket: &str, region: &str) -> Result<(), String> {
+    let (named_bucket, named_region) = match s3_bucket_of_url(public_url) {
+        Some(named) => named,
+        None => return Ok(()),
+    };
+    if named_bucket != bucket {
+        return Err(format!(
+            "--public-url {} is the URL of the bucket {}, but files are uploaded to {}; give the \
+             URL files are served from for {}",
+            public_url, named_bucket, bucket, bucket
+        ));
+    }
+    match named_region {
+        Some(named_region) if named_region != region => warning::warn(&format!(
+            "--public-url {} is in {}, but the bucket is in {}",
+            public_url, named_region, region
+        )),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The bucket an S3 URL names, virtual-hosted or path-style, and the region it names if any,
+/// e.g. `candide` and `eu-west-1` for `https://candide.s3.eu-west-1.amazonaws.com/books`.
+fn s3_bucket_of_url(url: &str) -> Option<(String, Option<String>)> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    let labels: Vec<&str> = host.strip_suffix(".amazonaws.com")?.split('.').collect();
+    let s3 = labels
+        .iter()
+        .position(|label| *label == "s3" || label.starts_with("s3-"))?;
+    let bucket = match labels[..s3].join(".") {
+        bucket if bucket.is_empty() => path.split('/').next().filter(|b| !b.is_empty())?.to_owned(),
+        bucket => bucket,
+    };
+    // The region follows the dash in `s3-eu-west-1` or `s3-website-eu-west-1`, or is the next
+    // label in `s3.eu-west-1`, `s3-website.eu-west-1` or `s3.dualstack.eu-west-1`.
+    let dashed = labels[s3]
+        .strip_prefix("s3-website-")
+        .or_else(|| labels[s3].strip_prefix("s3-"))
+        .filter(|region| *region != "website");
+    let region = dashed
+        .into_iter()
+        .chain(labels[s3 + 1..].iter().copied())
+        .find(|label| *label != "dualstack")
+        .map(str::to_owned);
+    Some((bucket, region))
+}
+
+/// Resolves credentials once up front so that a missing configuration is reported clearly
+/// rather than as a failure of whichever request happens to be sent first.
+fn check_credentials<P: ProvideAwsCredentials>(provider: &P) -> Result<(), UploadError> {
+    resolve_credentials(provider).map(|_| ())
+}
+
+fn resolve_credentials<P: ProvideAwsCredentials>(
+    provider: &P,
+) -> Result<AwsCredentials, UploadError> {
+    let no_credentials = |reason: String| {
+        UploadError::new(format!(
+            "No AWS credentials found ({}). Provide them in one of these ways:\n  \
+             - set the AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables\n  \
+             - run `aws configure` to create a shared credentials file (~/.aws/credentials)\n  \
+             - set AWS_PROFILE to use a named profile from the shared credentials file",
+            reason
+        ))
+    };
+    let mut runtime = Runtime::new().map_err(|err| no_credentials(err.to_string()))?;
+    runtime
+        .block_on(provider.credentials())
+        .map_err(|err| no_credentials(err.to_string()))
+}
+
+/// Reads a file in `CHUNK_SIZE` pieces as the request body is sent, so memory use stays the same
+/// whatever the size of the file.
+struct FileStream {
+    file: fs::File,
+    key: String,
+    progress: Arc<dyn Progress>,
+    cancel: CancelToken,
+}
+
+impl Stream for FileStream {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, io::Error> {
+        self.cancel.check()?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let len = self.file.read(&mut chunk)?;
+        if len == 0 {
+            return Ok(Async::Ready(None));
+        }
+        chunk.truncate(len);
+        self.progress.advance(&self.key, len as u64);
+        Ok(Async::Ready(Some(chunk.into())))
+    }
+}
+
+fn file_md5<R: Read>(reader: &mut R, cancel: &CancelToken) -> io::Result<md5::Digest> {
+    let mut context = md5::Context::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        cancel.check()?;
+        let len = reader.read(&mut chunk)?;
+        if len == 0 {
+            return Ok(context.compute());
+        }
+        context.consume(&chunk[..len]);
+    }
+}
+
+/// An element of an S3 error response body, e.g. its `Code` or `Message`.
+fn error_element<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&format!("</{}>", name))?;
+    Some(&body[start..end])
+}
+
+/// The `Code` of an error S3 responded with but rusoto doesn't model, e.g. `AccessDenied`.
+fn rusoto_error_code<E>(err: &RusotoError<E>) -> Option<&str> {
+    match err {
+        RusotoError::Unknown(response) => error_element(response.body_as_str(), "Code"),
+        _ => None,
+    }
+}
+
+/// Plain explanations for errors that new users find confusing. `date` is the response's `Date`
+/// header, used to estimate how far the local clock is off.
+fn explain_error_code(code: &str, date: Option<&str>, now: DateTime<Utc>) -> Option<String> {
+    match code {
+        "ExpiredToken" | "TokenRefreshRequired" => Some(
+            "your AWS session has expired — run `aws sso login` or refresh your credentials"
+                .to_owned(),
+        ),
+        "RequestTimeTooSkewed" => {
+            let skew = date
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|server_time| (now - server_time.with_timezone(&Utc)).num_minutes().abs());
+            Some(match skew {
+                Some(minutes) => format!(
+                    "your system clock is off by roughly {} minutes; correct it and try again",
+                    minutes
+                ),
+                None => "your system clock is off; correct it and try again".to_owned(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Fails, naming the files, if any media file's size differs from the enclosure length the
+/// feeds give it, which means it changed after the feeds were written. Only stats the files.
+fn check_enclosure_lengths(enclosure_lengths: &[(PathBuf, u64)]) -> Result<(), UploadError> {
+    let mut changed = Vec::new();
+    for (path, length) in enclosure_lengths {
+        let size = fs::metadata(path)
+            .map_err(|err| UploadError::io("read", path, err))?
+            .len();
+        if size != *length {
+            changed.push(format!(
+                "{} is {} bytes but the feed gives {}",
+                path.display(),
+                size,
+                length
+            ));
+        }
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+    Err(UploadError::invalid(format!(
+        "Refusing to upload the feed, as files changed after it was written:\n  {}\n\
+         Run sloop again to write the feed for the files as they are now",
+        changed.join("\n  ")
+    )))
+}
+
+/// Requests the first KB of `key` from `url` and checks that the server honours the range.
+fn check_range<H: HttpGet + ?Sized>(http: &H, url: &str, key: &str) -> Result<(), UploadError> {
+    let response = http
+        .get(url, &[("Range", "bytes=0-1023")])
+        .map_err(|err| UploadError::new(format!("Range request for {} failed: {}", key, err)))?;
+    if response != 206 {
+        return Err(UploadError::new(format!(
+            "Range request for {} returned status {} instead of 206",
+            key, response
+        )));
+    }
+    Ok(())
+}
+
+/// Publishes in the order `Uploader::publish` promises, for backends that send one file at a
+/// time with `send`: `files`, then a range check of the first audio file if `http` is given,
+/// then, if the enclosure lengths still hold, `feeds`.
+fn publish_in_order(
+    uploader: &dyn Uploader,
+    enclosure_lengths: &[(PathBuf, u64)],
+    feeds: &[PathBuf],
+    files: &[PathBuf],
+    http: Option<&dyn HttpGet>,
+    send: &dyn Fn(&[PathBuf]) -> Result<UploadSummary, UploadError>,
+) -> Result<UploadSummary, UploadError> {
+    let started = Instant::now();
+    let all_files = [files, feeds].concat();
+    check_files(&all_files)?;
+    check_enclosure_lengths(enclosure_lengths)?;
+    let summary = send(files)?;
+    let range_check = files.iter().find(|path| {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        mime::audio_type(extension).is_some()
+    });
+    if let (Some(http), Some(path)) = (http, range_check) {
+        uploader
+            .verify_range(http, path)
+            .map_err(|err| err.after_uploading(summary.files))?;
+    }
+    check_enclosure_lengths(enclosure_lengths).map_err(|err| err.after_uploading(summary.files))?;
+    let feeds = send(feeds).map_err(|err| err.after_uploading(summary.files))?;
+    Ok(UploadSummary {
+        files: summary.files + feeds.files,
+        bytes: summary.bytes + feeds.bytes,
+        elapsed: started.elapsed(),
+        unchanged: summary.unchanged + feeds.unchanged,
+        deleted: 0,
+        keys: [summary.keys, feeds.keys].concat(),
+    })
+}
+
+fn file_len(path: &Path) -> Result<u64, UploadError> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|err| UploadError::io("read", path, err))
+}
+
+/// The user metadata sloop records an object's ETag under when S3 encrypts it with SSE-KMS, as
+/// S3's own ETag for it then isn't the MD5 of its contents.
+const E_TAG_METADATA: &str = "sloop-etag";
+
+/// User metadata stored with each feed, recording which version of sloop published it. S3 serves
+/// it as the `x-amz-meta-sloop-version` header.
+fn feed_metadata() -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    metadata.insert("sloop-version".to_owned(), version::VERSION.to_owned());
+    metadata
+}
+
+/// Fails unless every path is a readable file with a UTF-8 file name to use in its key, so that
+/// problems with the files are found before anything is sent and deriving keys later can't
+/// panic.
+fn check_files(paths: &[PathBuf]) -> Result<(), UploadError> {
+    for path in paths {
+        if path.file_name().and_then(|name| name.to_str()).is_none() {
+            return Err(UploadError::invalid(format!(
+                "{} has no UTF-8 file name to upload it as",
+                path.display()
+            ))
+            .with_path(path));
+        }
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => {
+                return Err(
+                    UploadError::invalid(format!("{} is not a file", path.display()))
+                        .with_path(path),
+                )
+            }
+            Err(err) => {
+                return Err(UploadError {
+                    outcome: Outcome::Invalid,
+                    ..UploadError::io("read", path, err)
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Explains that `bucket` belongs to someone else, suggesting a name made unique by a suffix
+/// taken from `seed`.
+fn bucket_name_taken(bucket: &str, seed: u32) -> String {
+    let stem: String = bucket.chars().take(MAX_BUCKET_NAME_LEN - 7).collect();
+    format!(
+        "The bucket name {} is already taken. Bucket names are shared by every AWS account, \
+         so choose one less likely to be in use, e.g. {}-{:06x}. If the bucket is yours under \
+         another account, pass --no-create-bucket",
+        bucket,
+        stem.trim_end_matches(['-', '.']),
+        seed & 0xff_ffff
+    )
+}
+
+/// Whether `key` looks like a feed or an episode, going by its extension.
+fn is_feed_or_media(key: &str) -> bool {
+    let path = Path::new(key);
+    is_feed(path) || mime::content_type(path).is_some_and(|t| t.starts_with("audio/"))
+}
+
+/// Whether `path` is an RSS or JSON feed, going by its extension.
+fn is_feed(path: &Path) -> bool {
+    matches!(
+        mime::content_type(path),
+        Some("application/rss+xml") | Some("application/feed+json")
+    )
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().unwrap().to_str().unwrap()
+}
+
+/// Content type for an uploaded object. Without one S3 serves `binary/octet-stream`, which some
+/// podcast apps and browsers refuse to play or display.
+fn content_type(path: &Path) -> String {
+    mime::content_type(path)
+        .unwrap_or_else(|| {
+            warning::warn(&format!(
+                "unknown content type for {}, uploading as {}",
+                path.display(),
+                mime::OCTET_STREAM
+            ));
+            mime::OCTET_STREAM
+        })
+        .to_owned()
+}
+
+/// Compares the parts already uploaded for an interrupted multipart upload against the local
+/// file, returning the leading parts whose ETags match. Uploading resumes after the last of these.
+fn reusable_parts<R: Read>(
+    uploaded: &[Part],
+    part_size: u64,
+    file: &mut R,
+) -> std::io::Result<Vec<CompletedPart>> {
+    let mut uploaded: Vec<&Part> = uploaded.iter().collect();
+    uploaded.sort_by_key(|part| part.part_number);
+    let mut parts = Vec::new();
+    for (i, part) in uploaded.into_iter().enumerate() {
+        let part_number = i as i64 + 1;
+        if part.part_number != Some(part_number) {
+            break;
+        }
+        let mut body = Vec::new();
+        file.take(part_size).read_to_end(&mut body)?;
+        if body.is_empty() || part.size != Some(body.len() as i64) {
+            break;
+        }
+        let etag = format!("{:x}", md5::compute(&body));
+        match &part.e_tag {
+            Some(e_tag) if e_tag.trim_matches('"').eq_ignore_ascii_case(&etag) => {}
+            _ => break,
+        }
+        parts.push(CompletedPart {
+            e_tag: part.e_tag.clone(),
+            part_number: Some(part_number),
+        });
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    mod integration;
+    mod s3_mock;
+    pub(super) mod scratch;
+
+    use super::*;
+    use crate::assert_contains;
+    use rusoto_s3::CreateBucketError::BucketAlreadyExists;
+    use rusoto_s3::PublicAccessBlockConfiguration;
+    use serde::Deserialize;
+    use std::path::Path;
+    use std::sync::{Arc, Barrier};
+
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct BucketPolicy {
+        Version: String,
+        Statement: Vec<BucketPolicyStatement>,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct BucketPolicyStatement {
+        Sid: String,
+        Effect: String,
+        Principal: String,
+        Action: Vec<String>,
+        Resource: Vec<String>,
+    }
+
+    fn uploader(s3: s3_mock::S3Mock) -> S3Uploader {
+        uploader_with_options(s3, Default::default())
+    }
+
+    fn uploader_with_options(s3: s3_mock::S3Mock, options: UploadOptions) -> S3Uploader {
+        S3Uploader {
+            client: Box::new(s3),
+            region: String::from("region1"),
+            bucket_name: String::from("bucket1"),
+            endpoint: None,
+            path_style: false,
+            legacy_endpoint: false,
+            public_url: None,
+            prefix: String::new(),
+            check_credentials: false,
+            credentials: None,
+            names: HashMap::new(),
+            enclosure_lengths: Vec::new(),
+            options,
+            log: log::stdout(),
+            progress: Arc::new(NoProgress),
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Records every progress call as a line like "start feed.xml 6/6 7".
+    #[derive(Default)]
+    struct RecordingProgress {
+        events: Mutex<Vec<String>>,
+        sent: Mutex<HashMap<String, u64>>,
+    }
+
+    impl Progress for RecordingProgress {
+        fn start_file(&self, key: &str, number: usize, total_files: usize, len: u64) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("start {} {}/{} {}", key, number, total_files, len));
+        }
+
+        fn advance(&self, key: &str, bytes: u64) {
+            *self.sent.lock().unwrap().entry(key.to_owned()).or_default() += bytes;
+        }
+
+        fn finish_file(&self, key: &str) {
+            self.events.lock().unwrap().push(format!("finish {}", key));
+        }
+    }
+
+    fn multipart_options() -> UploadOptions {
+        UploadOptions {
+            multipart_threshold: 4,
+            part_size: 2,
+            ..Default::default()
+        }
+    }
+
+    /// Publishes a feed, image and episode with `options`, the episode in one request and the
+    /// others in parts, returning the key, encryption and KMS key ID each was uploaded with.
+    fn publish_encrypted(options: UploadOptions) -> Vec<(String, Option<String>, Option<String>)> {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let creates = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&puts),
+            create_multipart_upload_requests: Arc::clone(&creates),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            multipart_threshold: 12,
+            part_size: 6,
+            ..options
+        };
+        let files = vec![plan_fixture("cover.jpg"), plan_fixture("Chapter_1.mp3")];
+        uploader_with_options(s3, options)
+            .publish(&[plan_fixture("feed.xml")], files, None)
+            .unwrap();
+        let mut encryption: Vec<(String, Option<String>, Option<String>)> = puts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let sse = request.server_side_encryption.clone();
+                (request.key.clone(), sse, request.ssekms_key_id.clone())
+            })
+            .collect();
+        encryption.extend(creates.lock().unwrap().iter().map(|request| {
+            let sse = request.server_side_encryption.clone();
+            (request.key.clone(), sse, request.ssekms_key_id.clone())
+        }));
+        encryption.sort();
+        encryption
+    }
+
+    #[test]
+    fn objects_are_not_encrypted_by_default() {
+        let encryption = publish_encrypted(Default::default());
+        let keys: Vec<&str> = encryption.iter().map(|(key, ..)| key.as_str()).collect();
+        assert_eq!(keys, ["Chapter_1.mp3", "cover.jpg", "feed.xml"]);
+        for (key, sse, key_id) in &encryption {
+            assert_eq!((sse, key_id), (&None, &None), "{}", key);
+        }
+    }
+
+    #[test]
+    fn encrypts_every_object_with_aes256() {
+        let encryption = publish_encrypted(UploadOptions {
+            sse: Some(Sse::Aes256),
+            ..Default::default()
+        });
+        assert_eq!(encryption.len(), 3);
+        for (key, sse, key_id) in &encryption {
+            assert_eq!(sse.as_deref(), Some("AES256"), "{}", key);
+            assert_eq!(key_id, &None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn encrypts_every_object_with_the_kms_key() {
+        let arn = "arn:aws:kms:region1:123456789012:key/1234abcd";
+        let encryption = publish_encrypted(UploadOptions {
+            sse: Some(Sse::Kms),
+            sse_kms_key_id: Some(arn.to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(encryption.len(), 3);
+        for (key, sse, key_id) in &encryption {
+            assert_eq!(sse.as_deref(), Some("aws:kms"), "{}", key);
+            assert_eq!(key_id.as_deref(), Some(arn), "{}", key);
+        }
+    }
+
+    #[test]
+    fn new_rejects_a_kms_key_without_kms_encryption() {
+        let options = UploadOptions {
+            sse: Some(Sse::Aes256),
+            sse_kms_key_id: Some("key".to_owned()),
+            ..Default::default()
+        };
+        let err = S3Uploader::new("us-east-1", "bucket1", Default::default(), options)
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert_eq!(
+            err.message,
+            "A KMS key ID can only be given with aws:kms encryption"
+        );
+    }
+
+    /// Publishes a feed, image and two episodes with `options`, one episode in parts, returning
+    /// the storage class each object was uploaded with, by key.
+    fn publish_storage_classes(options: UploadOptions) -> Vec<(String, Option<String>)> {
+        publish_mixed_files(
+            options,
+            |put| put.storage_class.clone(),
+            |create| create.storage_class.clone(),
+        )
+    }
+
+    /// Publishes a feed, image and two episodes with `options`, one episode in parts, returning
+    /// `put_field` or `create_field` of the request that uploaded each object, by key.
+    fn publish_mixed_files(
+        options: UploadOptions,
+        put_field: impl Fn(&s3_mock::PutObjectData) -> Option<String>,
+        create_field: impl Fn(&CreateMultipartUploadRequest) -> Option<String>,
+    ) -> Vec<(String, Option<String>)> {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let creates = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&puts),
+            create_multipart_upload_requests: Arc::clone(&creates),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            multipart_threshold: 8,
+            part_size: 6,
+            ..options
+        };
+        let files = vec![
+            plan_fixture("cover.jpg"),
+            plan_fixture("Chapter_1.mp3"),
+            Path::new("test_fixtures/dir1/file1.mp3").to_path_buf(),
+        ];
+        uploader_with_options(s3, options)
+            .publish(&[plan_fixture("feed.xml")], files, None)
+            .unwrap();
+        let mut fields: Vec<(String, Option<String>)> = puts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| (request.key.clone(), put_field(request)))
+            .collect();
+        fields.extend(
+            creates
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| (request.key.clone(), create_field(request))),
+        );
+        fields.sort();
+        fields
+    }
+
+    fn publish_cache_controls(options: UploadOptions) -> Vec<(String, Option<String>)> {
+        publish_mixed_files(
+            options,
+            |put| put.cache_control.clone(),
+            |create| create.cache_control.clone(),
+        )
+    }
+
+    #[test]
+    fn feeds_are_cached_briefly_and_media_for_a_year() {
+        let feed = Some("max-age=300".to_owned());
+        let media = Some("max-age=31536000, immutable".to_owned());
+        assert_eq!(
+            publish_cache_controls(Default::default()),
+            [
+                ("Chapter_1.mp3".to_owned(), media.clone()),
+                ("cover.jpg".to_owned(), media.clone()),
+                ("feed.xml".to_owned(), feed),
+                ("file1.mp3".to_owned(), media),
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_control_can_be_overridden_or_left_out() {
+        let options = UploadOptions {
+            feed_cache_control: "no-cache".to_owned(),
+            media_cache_control: String::new(),
+            ..Default::default()
+        };
+        assert_eq!(
+            publish_cache_controls(options),
+            [
+                ("Chapter_1.mp3".to_owned(), None),
+                ("cover.jpg".to_owned(), None),
+                ("feed.xml".to_owned(), Some("no-cache".to_owned())),
+                ("file1.mp3".to_owned(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn storage_class_applies_to_episodes_only() {
+        let classes = publish_storage_classes(UploadOptions {
+            storage_class: Some(StorageClass::StandardIa),
+            ..Default::default()
+        });
+        let standard_ia = Some("STANDARD_IA".to_owned());
+        assert_eq!(
+            classes,
+            [
+                ("Chapter_1.mp3".to_owned(), standard_ia.clone()),
+                ("cover.jpg".to_owned(), None),
+                ("feed.xml".to_owned(), None),
+                ("file1.mp3".to_owned(), standard_ia),
+            ]
+        );
+    }
+
+    #[test]
+    fn feed_storage_class_applies_to_feeds_and_images() {
+        let classes = publish_storage_classes(UploadOptions {
+            storage_class: Some(StorageClass::IntelligentTiering),
+            feed_storage_class: Some(StorageClass::OnezoneIa),
+            ..Default::default()
+        });
+        let tiering = Some("INTELLIGENT_TIERING".to_owned());
+        let onezone_ia = Some("ONEZONE_IA".to_owned());
+        assert_eq!(
+            classes,
+            [
+                ("Chapter_1.mp3".to_owned(), tiering.clone()),
+                ("cover.jpg".to_owned(), onezone_ia.clone()),
+                ("feed.xml".to_owned(), onezone_ia),
+                ("file1.mp3".to_owned(), tiering),
+            ]
+        );
+    }
+
+    #[test]
+    fn storage_class_is_left_to_s3_by_default() {
+        let classes = publish_storage_classes(Default::default());
+        assert!(
+            classes.iter().all(|(_, class)| class.is_none()),
+            "{:?}",
+            classes
+        );
+    }
+
+    #[test]
+    fn rejects_storage_classes_that_cannot_be_played() {
+        assert_eq!(
+            "STANDARD_IA".parse::<StorageClass>(),
+            Ok(StorageClass::StandardIa)
+        );
+        let err = "GLACIER".parse::<StorageClass>().unwrap_err();
+        assert!(
+            err.starts_with("GLACIER objects must be restored before they can be downloaded"),
+            "{}",
+            err
+        );
+        assert!("DEEP_ARCHIVE".parse::<StorageClass>().is_err());
+        assert_eq!(
+            "standard".parse::<StorageClass>(),
+            Err(
+                "standard is not one of STANDARD, STANDARD_IA, ONEZONE_IA, INTELLIGENT_TIERING"
+                    .to_owned()
+            )
+        );
+    }
+
+    /// Publishes a small file and a multipart one in `mode`, returning the S3 calls made and the
+    /// ACLs of the put and multipart requests.
+    fn publish_in_public_mode(mode: PublicMode) -> (Vec<&'static str>, Vec<Option<String>>) {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let creates = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&puts),
+            create_multipart_upload_requests: Arc::clone(&creates),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let options = UploadOptions {
+            public_mode: mode,
+            ..multipart_options()
+        };
+        let files = vec![
+            plan_fixture("Chapter_1.mp3"),
+            Path::new("test_fixtures/dir1/file1.mp3").to_path_buf(),
+        ];
+        uploader_with_options(s3, options).upload(files).unwrap();
+        let mut acls: Vec<Option<String>> = puts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.acl.clone())
+            .collect();
+        acls.extend(
+            creates
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| request.acl.clone()),
+        );
+        let calls = calls.lock().unwrap().clone();
+        (calls, acls)
+    }
+
+    #[test]
+    fn bucket_policy_mode_makes_bucket_public() {
+        let (calls, acls) = publish_in_public_mode(PublicMode::BucketPolicy);
+        assert!(calls.contains(&"get_public_access_block"));
+        assert!(calls.contains(&"put_bucket_policy"));
+        assert_eq!(acls, vec![None, None]);
+    }
+
+    #[test]
+    fn object_acl_mode_makes_each_object_public_without_a_policy() {
+        let (calls, acls) = publish_in_public_mode(PublicMode::ObjectAcl);
+        assert!(calls.contains(&"get_public_access_block"));
+        assert!(!calls.contains(&"put_bucket_policy"), "{:?}", calls);
+        let public_read = Some("public-read".to_owned());
+        assert_eq!(acls, vec![public_read.clone(), public_read]);
+    }
+
+    #[test]
+    fn none_mode_leaves_access_alone() {
+        let (calls, acls) = publish_in_public_mode(PublicMode::None);
+        assert!(!calls.contains(&"get_public_access_block"));
+        assert!(!calls.contains(&"put_bucket_policy"));
+        assert_eq!(acls, vec![None, None]);
+    }
+
+    /// Block Public Access as AWS turns it on for new buckets.
+    fn all_public_access_blocked() -> PublicAccessBlockConfiguration {
+        PublicAccessBlockConfiguration {
+            block_public_acls: Some(true),
+            block_public_policy: Some(true),
+            ignore_public_acls: Some(true),
+            restrict_public_buckets: Some(true),
+        }
+    }
+
+    #[test]
+    fn explains_block_public_access_without_allow_public() {
+        let s3 = s3_mock::S3Mock {
+            public_access_block: Some(all_public_access_blocked()),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let err = uploader(s3).upload(vec![]).err().unwrap();
+        assert_eq!(
+            err.message,
+            "Block Public Access is on for bucket bucket1 (BlockPublicPolicy and \
+             RestrictPublicBuckets), so podcast apps couldn't download its episodes. Pass \
+             --allow-public to let sloop turn this off, or pass --public-mode none to manage \
+             access yourself"
+        );
+        let calls = calls.lock().unwrap();
+        assert!(!calls.contains(&"put_public_access_block"), "{:?}", calls);
+        assert!(!calls.contains(&"put_bucket_policy"), "{:?}", calls);
+    }
+
+    #[test]
+    fn allow_public_turns_off_only_the_settings_blocking_the_policy() {
+        let s3 = s3_mock::S3Mock {
+            public_access_block: Some(all_public_access_blocked()),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let requests = Arc::clone(&s3.put_public_access_block_requests);
+        let options = UploadOptions {
+            allow_public: true,
+            ..Default::default()
+        };
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].bucket, "bucket1");
+        assert_eq!(
+            requests[0].public_access_block_configuration,
+            PublicAccessBlockConfiguration {
+                block_public_policy: Some(false),
+                restrict_public_buckets: Some(false),
+                ..all_public_access_blocked()
+            }
+        );
+        let calls = calls.lock().unwrap();
+        let put_block = calls
+            .iter()
+            .position(|call| *call == "put_public_access_block");
+        let put_policy = calls.iter().position(|call| *call == "put_bucket_policy");
+        assert!(put_block < put_policy, "{:?}", calls);
+    }
+
+    #[test]
+    fn allow_public_turns_off_only_the_settings_blocking_object_acls() {
+        let s3 = s3_mock::S3Mock {
+            public_access_block: Some(all_public_access_blocked()),
+            ..Default::default()
+        };
+        let requests = Arc::clone(&s3.put_public_access_block_requests);
+        let options = UploadOptions {
+            public_mode: PublicMode::ObjectAcl,
+            allow_public: true,
+            ..Default::default()
+        };
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        assert_eq!(
+            requests.lock().unwrap()[0].public_access_block_configuration,
+            PublicAccessBlockConfiguration {
+                block_public_acls: Some(false),
+                ignore_public_acls: Some(false),
+                ..all_public_access_blocked()
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_block_public_access_alone_if_it_allows_the_mode() {
+        let s3 = s3_mock::S3Mock {
+            public_access_block: Some(PublicAccessBlockConfiguration {
+                block_public_policy: Some(false),
+                restrict_public_buckets: Some(false),
+                ..all_public_access_blocked()
+            }),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        uploader(s3).upload(vec![]).unwrap();
+        let calls = calls.lock().unwrap();
+        assert!(!calls.contains(&"put_public_access_block"), "{:?}", calls);
+        assert!(calls.contains(&"put_bucket_policy"), "{:?}", calls);
+    }
+
+    #[test]
+    fn hints_at_account_block_public_access_when_policy_is_denied() {
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_error: Some(s3_mock::ErrorResponse::new("AccessDenied")),
+            ..Default::default()
+        };
+        let err = uploader(s3).upload(vec![]).err().unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to set bucket policy: AccessDenied (Error message). If Block Public Access is \
+             on for the whole account, turn off BlockPublicPolicy in the S3 console, or pass \
+             --public-mode none"
+        );
+    }
+
+    #[test]
+    fn existing_bucket_options_skip_creating_and_configuring_the_bucket() {
+        let creates = Arc::new(Mutex::new(Vec::new()));
+        let policies = Arc::new(Mutex::new(Vec::new()));
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            create_bucket_requests: Arc::clone(&creates),
+            put_bucket_policy_requests: Arc::clone(&policies),
+            put_object_requests: Arc::clone(&puts),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let options = UploadOptions {
+            skip_create_bucket: true,
+            skip_policy: true,
+            ..Default::default()
+        };
+        let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+        uploader_with_options(s3, options).upload(files).unwrap();
+        assert_eq!(creates.lock().unwrap().len(), 0);
+        assert_eq!(policies.lock().unwrap().len(), 0);
+        assert_eq!(puts.lock().unwrap().len(), 1);
+        let calls = calls.lock().unwrap();
+        assert!(!calls.contains(&"get_public_access_block"), "{:?}", calls);
+    }
+
+    #[test]
+    fn skipping_bucket_creation_still_sets_the_policy() {
+        let s3: s3_mock::S3Mock = Default::default();
+        let calls = Arc::clone(&s3.calls);
+        let options = UploadOptions {
+            skip_create_bucket: true,
+            ..Default::default()
+        };
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        let calls = calls.lock().unwrap();
+        assert!(!calls.contains(&"create_bucket"), "{:?}", calls);
+        assert!(calls.contains(&"put_bucket_policy"), "{:?}", calls);
+    }
+
+    #[test]
+    fn skipping_the_policy_still_creates_the_bucket_and_sets_object_acls() {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&puts),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let options = UploadOptions {
+            public_mode: PublicMode::ObjectAcl,
+            skip_policy: true,
+            ..Default::default()
+        };
+        let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+        uploader_with_options(s3, options).upload(files).unwrap();
+        let calls = calls.lock().unwrap();
+        assert!(calls.contains(&"create_bucket"), "{:?}", calls);
+        assert!(!calls.contains(&"get_public_access_block"), "{:?}", calls);
+        assert!(!calls.contains(&"put_bucket_policy"), "{:?}", calls);
+        assert_eq!(puts.lock().unwrap()[0].acl.as_deref(), Some("public-read"));
+    }
+
+    #[test]
+    fn parses_public_modes() {
+        for value in PublicMode::VALUES {
+            assert!(value.parse::<PublicMode>().is_ok(), "{}", value);
+        }
+        assert_eq!(
+            "public".parse::<PublicMode>(),
+            Err("public is not one of bucket-policy, object-acl, none".to_owned())
+        );
+    }
+
+    fn part(part_number: i64, body: &[u8]) -> Part {
+        Part {
+            part_number: Some(part_number),
+            size: Some(body.len() as i64),
+            e_tag: Some(format!("\"{:x}\"", md5::compute(body))),
+            ..Default::default()
+        }
+    }
+
+    fn multipart_upload(key: &str, upload_id: &str) -> MultipartUpload {
+        MultipartUpload {
+            key: Some(key.to_owned()),
+            upload_id: Some(upload_id.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn creates_an_s3_bucket() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            create_bucket_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        uploader.upload(vec![]).unwrap();
+        let request = requests.lock().unwrap().first().unwrap().clone();
+        assert_eq!(request.bucket, "bucket1");
+        assert_eq!(
+            request
+                .create_bucket_configuration
+                .expect("bucket configuration not set")
+                .location_constraint,
+            Some(String::from("region1")),
+        );
+    }
+
+    #[test]
+    fn publishes_to_custom_endpoint() {
+        let dir =
+            std::env::temp_dir().join(format!("sloop-custom-endpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let feed = dir.join("feed.xml");
+        let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            create_bucket_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.endpoint = Some("http://localhost:9000".to_owned());
+        uploader.path_style = true;
+        let generator = crate::feed::FeedGenerator {
+            title: "Feed Title 1".to_owned(),
+            urls: uploader.object_urls(),
+            ..Default::default()
+        };
+        let media = crate::feed::resolve_inputs(&files, "", false).unwrap();
+        generator
+            .generate_for_files(media, fs::File::create(&feed).unwrap())
+            .unwrap();
+        uploader
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap();
+        assert_eq!(
+            uploader.url_for_file(&feed),
+            "http://localhost:9000/bucket1/feed.xml"
+        );
+        let xml = fs::read_to_string(&feed).unwrap();
+        assert_contains!(xml, "url=\"http://localhost:9000/bucket1/file1.mp3\"");
+        let request = requests.lock().unwrap().first().unwrap().clone();
+        assert!(request.create_bucket_configuration.is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sends_location_constraint_to_custom_endpoint_that_requires_it() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            create_bucket_requests: Arc::clone(&requests),
+            require_location_constraint: true,
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.endpoint = Some("http://localhost:4566".to_owned());
+        uploader.upload(vec![]).unwrap();
+        let constraints: Vec<Option<String>> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                r.create_bucket_configuration
+                    .clone()
+                    .and_then(|c| c.location_constraint)
+            })
+            .collect();
+        assert_eq!(constraints, vec![None, Some("region1".to_owned())]);
+    }
+
+    #[test]
+    fn omits_location_constraint_only_for_us_east_1() {
+        for region in &["us-east-1", "us-east-2", "eu-west-1", "ap-southeast-2"] {
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let s3 = s3_mock::S3Mock {
+                create_bucket_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            let mut uploader = uploader(s3);
+            uploader.region = region.to_string();
+            uploader.upload(vec![]).unwrap();
+            let constraint = requests.lock().unwrap()[0]
+                .create_bucket_configuration
+                .clone()
+                .and_then(|c| c.location_constraint);
+            let expected = match *region {
+                "us-east-1" => None,
+                _ => Some(region.to_string()),
+            };
+            assert_eq!(constraint, expected, "region {}", region);
+        }
+    }
+
+    #[test]
+    fn handles_bucket_already_owned_by_user() {
+        let s3 = s3_mock::S3Mock {
+            create_bucket_error: Some(BucketAlreadyOwnedByYou(String::new())),
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        uploader.upload(vec![]).unwrap();
+    }
+
+    #[test]
+    fn throws_for_bucket_already_exists() {
+        let s3 = s3_mock::S3Mock {
+            create_bucket_error: Some(BucketAlreadyExists(String::new())),
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        let err = uploader.upload(vec![]).unwrap_err();
+        assert_eq!(err.kind, UploadErrorKind::BucketNameTaken);
+        assert!(
+            err.message
+                .starts_with("The bucket name bucket1 is already taken."),
+            "{}",
+            err.message
+        );
+        assert_contains!(err.message, "shared by every AWS account");
+        assert_contains!(err.message, "e.g. bucket1-");
+        assert_contains!(err.message, "--no-create-bucket");
+    }
+
+    #[test]
+    fn suggests_a_valid_bucket_name_when_the_name_is_taken() {
+        assert_contains!(
+            bucket_name_taken("candide", 0x1a5e21f),
+            "e.g. candide-a5e21f."
+        );
+        let long = "a".repeat(60) + "-b";
+        let message = bucket_name_taken(&long, 0);
+        let suggested = message.split("e.g. ").nth(1).unwrap().split(". ").next();
+        assert_eq!(suggested.unwrap(), format!("{}-000000", "a".repeat(56)));
+    }
+
+    #[test]
+    fn sets_bucket_policy_to_public() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        uploader.upload(vec![]).unwrap();
+        let request = requests.lock().unwrap().first().unwrap().clone();
+        assert_eq!(request.bucket, "bucket1");
+        let policy: BucketPolicy = serde_json::from_str(&request.policy).unwrap();
+        assert_eq!(policy.Version, "2012-10-17");
+        let statement = &policy.Statement[0];
+        assert_eq!(statement.Sid, "AddPerm");
+        assert_eq!(statement.Effect, "Allow");
+        assert_eq!(statement.Principal, "*");
+        assert_eq!(statement.Action[0], "s3:GetObject");
+        assert_eq!(statement.Resource[0], "arn:aws:s3:::bucket1/*");
+    }
+
+    #[test]
+    fn returns_error_if_setting_bucket_policy_fails() {
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_error: Some(s3_mock::ErrorResponse::new("AccessDenied")),
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        assert!(uploader.upload(vec![]).is_err(), "expected error");
+    }
+
+    #[test]
+    fn explains_expired_credentials() {
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_error: Some(s3_mock::ErrorResponse::new("ExpiredToken")),
+            ..Default::default()
+        };
+        let err = uploader(s3).upload(vec![]).err().unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to set bucket policy: your AWS session has expired — run `aws sso login` or \
+             refresh your credentials"
+        );
+    }
+
+    #[test]
+    fn explains_clock_skew() {
+        let server_time = Utc::now() - chrono::Duration::minutes(30);
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_error: Some(s3_mock::ErrorResponse {
+                code: "RequestTimeTooSkewed",
+                date: Some(server_time.to_rfc2822()),
+            }),
+            ..Default::default()
+        };
+        let err = uploader(s3).upload(vec![]).err().unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to set bucket policy: your system clock is off by roughly 30 minutes; \
+             correct it and try again"
+        );
+    }
+
+    #[test]
+    fn explains_clock_skew_without_date() {
+        let now = DateTime::parse_from_rfc2822("Mon, 1 Jan 2024 12:00:00 +0000")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            explain_error_code("RequestTimeTooSkewed", None, now),
+            Some("your system clock is off; correct it and try again".to_owned())
+        );
+        let ahead = (now + chrono::Duration::minutes(90)).to_rfc2822();
+        assert_contains!(
+            explain_error_code("RequestTimeTooSkewed", Some(&ahead), now).unwrap(),
+            "roughly 90 minutes"
+        );
+        assert_eq!(explain_error_code("AccessDenied", None, now), None);
+    }
+
+    #[test]
+    fn reports_request_ids_of_failed_requests() {
+        let s3 = s3_mock::S3Mock {
+            put_bucket_policy_error: Some(s3_mock::ErrorResponse::new("AccessDenied")),
+            ..Default::default()
+        };
+        let err = uploader(s3).upload(vec![]).err().unwrap();
+        assert_eq!(
+            err.request_ids,
+            Some(RequestIds {
+                request_id: "request-id-1".to_owned(),
+                host_id: Some("host-id-1".to_owned()),
+            })
+        );
+        assert_contains!(err.to_string(), "Failed to set bucket policy");
+        assert_contains!(
+            err.to_string(),
+            "(request ID: request-id-1, host ID: host-id-1)"
+        );
+    }
+
+    #[test]
+    fn uploads_files_in_directory() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                put_object_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            let uploader = uploader(s3);
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader.upload(files).unwrap();
+        }
+        let requests = Arc::try_unwrap(requests).unwrap().into_inner().unwrap();
+        let request = requests.first().unwrap();
+        assert_eq!(request.bucket, String::from("bucket1"));
+        assert_eq!(request.key, String::from("file1.mp3"));
+        assert_eq!(request.body_len, 6);
+        assert_eq!(
+            request.content_md5,
+            Some("qzwQPf7mliTEhrdNPJDbZQ==".to_owned())
+        );
+    }
+
+    #[test]
+    fn base_url_returns_url_for_bucket() {
+        let s3: s3_mock::S3Mock = Default::default();
+        let uploader = uploader(s3);
+        assert_eq!(
+            uploader.base_url(),
+            "https://bucket1.s3.region1.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn constructs_url_for_file() {
+        let s3: s3_mock::S3Mock = Default::default();
+        let uploader = uploader(s3);
+        assert_eq!(
+            uploader.url_for_file(&PathBuf::from("/tmp/file1.txt")),
+            "https://bucket1.s3.region1.amazonaws.com/file1.txt"
+        );
+    }
+
+    #[test]
+    fn uploads_large_files_in_parts() {
+        let parts = Arc::new(Mutex::new(Vec::new()));
+        let completions = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                upload_part_requests: Arc::clone(&parts),
+                complete_multipart_upload_requests: Arc::clone(&completions),
+                ..Default::default()
+            };
+            let uploader = uploader_with_options(s3, multipart_options());
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader.upload(files).unwrap();
+        }
+        let parts = Arc::try_unwrap(parts).unwrap().into_inner().unwrap();
+        let bodies: Vec<&[u8]> = parts.iter().map(|p| p.body.as_slice()).collect();
+        assert_eq!(bodies, vec![&b"da"[..], b"ta", b"1\n"]);
+        assert_eq!(parts[2].part_number, 3);
+        assert_eq!(parts[0].key, "file1.mp3");
+        assert_eq!(parts[0].upload_id, "new-upload-id");
+        let completion = completions.lock().unwrap().first().unwrap().clone();
+        assert_eq!(completion.key, "file1.mp3");
+        let completed = completion.multipart_upload.unwrap().parts.unwrap();
+        assert_eq!(completed.len(), 3);
+        assert_eq!(
+            completed[2].e_tag,
+            Some(format!("\"{:x}\"", md5::compute(b"1\n")))
+        );
+    }
+
+    #[test]
+    fn resumes_interrupted_upload_from_first_missing_part() {
+        let parts = Arc::new(Mutex::new(Vec::new()));
+        let creations = Arc::new(Mutex::new(Vec::new()));
+        let completions = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                multipart_uploads: vec![multipart_upload("file1.mp3", "old-upload-id")],
+                parts: vec![("old-upload-id".to_owned(), vec![part(1, b"da")])]
+                    .into_iter()
+                    .collect(),
+                create_multipart_upload_requests: Arc::clone(&creations),
+                upload_part_requests: Arc::clone(&parts),
+                complete_multipart_upload_requests: Arc::clone(&completions),
+                ..Default::default()
+            };
+            let uploader = uploader_with_options(s3, multipart_options());
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader.upload(files).unwrap();
+        }
+        assert!(
+            creations.lock().unwrap().is_empty(),
+            "expected upload to be resumed"
+        );
+        let parts = Arc::try_unwrap(parts).unwrap().into_inner().unwrap();
+        let numbers: Vec<i64> = parts.iter().map(|p| p.part_number).collect();
+        assert_eq!(numbers, vec![2, 3]);
+        assert_eq!(parts[0].upload_id, "old-upload-id");
+        let completion = completions.lock().unwrap().first().unwrap().clone();
+        assert_eq!(completion.upload_id, "old-upload-id");
+        assert_eq!(completion.multipart_upload.unwrap().parts.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn aborts_orphaned_uploads() {
+        let aborts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            multipart_uploads: vec![multipart_upload("other.mp3", "orphan-id")],
+            abort_multipart_upload_requests: Arc::clone(&aborts),
+            ..Default::default()
+        };
+        uploader(s3).upload(vec![]).unwrap();
+        let abort = aborts.lock().unwrap().first().unwrap().clone();
+        assert_eq!(abort.key, "other.mp3");
+        assert_eq!(abort.upload_id, "orphan-id");
+    }
+
+    #[test]
+    fn keeps_orphaned_uploads_if_requested() {
+        let aborts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            multipart_uploads: vec![multipart_upload("other.mp3", "orphan-id")],
+            abort_multipart_upload_requests: Arc::clone(&aborts),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            keep_orphans: true,
+            ..Default::default()
+        };
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        assert!(aborts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reusable_parts_stops_at_first_mismatch() {
+        let uploaded = vec![part(1, b"da"), part(2, b"XX"), part(3, b"1\n")];
+        let mut file = std::io::Cursor::new(b"data1\n".to_vec());
+        let parts = reusable_parts(&uploaded, 2, &mut file).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].part_number, Some(1));
+    }
+
+    #[test]
+    fn reusable_parts_stops_at_gap_in_part_numbers() {
+        let uploaded = vec![part(1, b"da"), part(3, b"1\n")];
+        let mut file = std::io::Cursor::new(b"data1\n".to_vec());
+        let parts = reusable_parts(&uploaded, 2, &mut file).unwrap();
+        assert_eq!(parts.len(), 1);
+    }
+
+    #[test]
+    fn reusable_parts_ignores_parts_of_a_different_size() {
+        let uploaded = vec![part(1, b"dat")];
+        let mut file = std::io::Cursor::new(b"data1\n".to_vec());
+        let parts = reusable_parts(&uploaded, 2, &mut file).unwrap();
+        assert!(parts.is_empty());
+    }
+
+    #[test]
+    fn reusable_parts_accepts_all_matching_parts() {
+        let uploaded = vec![part(2, b"ta"), part(1, b"da"), part(3, b"1\n")];
+        let mut file = std::io::Cursor::new(b"data1\n".to_vec());
+        let parts = reusable_parts(&uploaded, 2, &mut file).unwrap();
+        let numbers: Vec<Option<i64>> = parts.iter().map(|p| p.part_number).collect();
+        assert_eq!(numbers, vec![Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn prefix_applies_to_keys_and_urls() {
+        for prefix in &["books/My Book", "books/My Book/", "/books/My Book/"] {
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let s3 = s3_mock::S3Mock {
+                put_object_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            let mut uploader = uploader(s3);
+            uploader.prefix = prefix.trim_matches('/').to_owned();
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader.upload(files).unwrap();
+            assert_eq!(
+                requests.lock().unwrap()[0].key,
+                "books/My Book/file1.mp3",
+                "prefix {:?}",
+                prefix
+            );
+            assert_eq!(
+                uploader.base_url(),
+                "https://bucket1.s3.region1.amazonaws.com/books/My%20Book"
+            );
+            assert_eq!(
+                uploader.url_for_file(Path::new("Chapter 1.mp3")),
+                "https://bucket1.s3.region1.amazonaws.com/books/My%20Book/Chapter%201.mp3"
+            );
+        }
+    }
+
+    #[test]
+    fn enclosure_urls_point_at_uploaded_keys() {
+        let dir = std::env::temp_dir().join(format!("sloop-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "Chapter 1 & 2.mp3",
+            "1+1.mp3",
+            "Track #3.mp3",
+            "Épisode 1.mp3",
+        ];
+        let paths: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+        for path in &paths {
+            fs::write(path, b"audio").unwrap();
+        }
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/My Book".to_owned();
+        let feed = crate::feed::FeedGenerator {
+            urls: uploader.object_urls(),
+            ..Default::default()
+        };
+        let media_files = crate::feed::resolve_inputs(&paths, "", false).unwrap();
+        let episodes = feed.episodes(media_files).unwrap();
+        uploader.upload(paths.clone()).unwrap();
+        let mut keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.key.clone())
+            .collect();
+        keys.sort();
+        for (path, episode) in paths.iter().zip(&episodes) {
+            assert_eq!(episode.url, uploader.url_for_file(path));
+            let escaped_key = episode
+                .url
+                .strip_prefix("https://bucket1.s3.region1.amazonaws.com/")
+                .unwrap();
+            let key = percent_encoding::percent_decode_str(escaped_key)
+                .decode_utf8()
+                .unwrap();
+            assert!(
+                keys.binary_search(&key.into_owned()).is_ok(),
+                "{}",
+                episode.url
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prefix_is_normalised_by_new() {
+        let config = ClientConfig {
+            prefix: "/books/candide/".to_owned(),
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("us-east-1", "bucket1", config, Default::default()).unwrap();
+        assert_eq!(uploader.prefix, "books/candide");
+    }
+
+    #[test]
+    fn prefix_limits_which_interrupted_uploads_are_aborted() {
+        let aborts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            multipart_uploads: vec![
+                multipart_upload("books/a/other.mp3", "ours"),
+                multipart_upload("books/b/other.mp3", "theirs"),
+            ],
+            abort_multipart_upload_requests: Arc::clone(&aborts),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/a".to_owned();
+        uploader.upload(vec![]).unwrap();
+        let aborted: Vec<String> = aborts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.upload_id.clone())
+            .collect();
+        assert_eq!(aborted, vec!["ours"]);
+    }
+
+    #[test]
+    fn leaves_interrupted_uploads_outside_the_folder_alone() {
+        for prefix in &["", "books"] {
+            let aborts = Arc::new(Mutex::new(Vec::new()));
+            let folder = ObjectKey::new(prefix, "").key().to_owned();
+            let s3 = s3_mock::S3Mock {
+                multipart_uploads: vec![
+                    multipart_upload(&format!("{}other.mp3", folder), "ours"),
+                    multipart_upload("other/other.mp3", "another podcast's"),
+                    multipart_upload(&format!("{}nested/other.mp3", folder), "a nested podcast's"),
+                ],
+                abort_multipart_upload_requests: Arc::clone(&aborts),
+                ..Default::default()
+            };
+            let mut uploader = uploader(s3);
+            uploader.prefix = prefix.to_string();
+            uploader.upload(vec![]).unwrap();
+            let aborted: Vec<String> = aborts
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| r.upload_id.clone())
+                .collect();
+            assert_eq!(aborted, vec!["ours"], "prefix {:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn base_url_uses_global_endpoint_for_us_east_1() {
+        let mut uploader = uploader(Default::default());
+        uploader.region = String::from("us-east-1");
+        assert_eq!(uploader.base_url(), "https://bucket1.s3.amazonaws.com");
+        uploader.path_style = true;
+        assert_eq!(uploader.base_url(), "https://s3.amazonaws.com/bucket1");
+    }
+
+    #[test]
+    fn base_url_supports_legacy_endpoint() {
+        let mut uploader = uploader(Default::default());
+        uploader.legacy_endpoint = true;
+        assert_eq!(
+            uploader.base_url(),
+            "https://bucket1.s3-region1.amazonaws.com"
+        );
+        assert_eq!(
+            uploader.url_for_file(&PathBuf::from("/tmp/file1.txt")),
+            "https://bucket1.s3-region1.amazonaws.com/file1.txt"
+        );
+        uploader.region = String::from("us-east-1");
+        assert_eq!(
+            uploader.base_url(),
+            "https://bucket1.s3-us-east-1.amazonaws.com"
+        );
+        uploader.path_style = true;
+        assert_eq!(
+            uploader.base_url(),
+            "https://s3-us-east-1.amazonaws.com/bucket1"
+        );
+    }
+
+    #[test]
+    fn base_url_uses_custom_endpoint() {
+        let mut uploader = uploader(Default::default());
+        uploader.endpoint = Some(String::from("http://localhost:9000"));
+        assert_eq!(uploader.base_url(), "http://bucket1.localhost:9000");
+        uploader.path_style = true;
+        assert_eq!(uploader.base_url(), "http://localhost:9000/bucket1");
+    }
+
+    #[test]
+    fn public_url_replaces_the_bucket_in_urls_but_not_keys() {
+        let config = ClientConfig {
+            public_url: Some(String::from("https://cdn.eg.test/books/hobbit/")),
+            prefix: String::from("audio book"),
+            ..Default::default()
+        };
+        let mut uploader =
+            S3Uploader::new("us-east-1", "bucket1", config, Default::default()).unwrap();
+        uploader.upload_as(Path::new("out/feed.xml"), "feed.xml");
+        assert_eq!(
+            uploader.base_url(),
+            "https://cdn.eg.test/books/hobbit/audio%20book"
+        );
+        assert_eq!(
+            uploader.url_for_file(Path::new("Chapter 1.mp3")),
+            "https://cdn.eg.test/books/hobbit/audio%20book/Chapter%201.mp3"
+        );
+        assert_eq!(
+            uploader.key(Path::new("out/feed.xml")),
+            "audio book/feed.xml"
+        );
+
+        uploader.public_url = Some(String::from("http://localhost:8080"));
+        assert_eq!(
+            uploader.url_for_file(Path::new("Chapter 1.mp3")),
+            "http://localhost:8080/audio%20book/Chapter%201.mp3"
+        );
+    }
+
+    #[test]
+    fn finds_the_bucket_an_s3_url_names() {
+        let named = s3_bucket_of_url;
+        let bucket = |bucket: &str, region: Option<&str>| {
+            Some((bucket.to_owned(), region.map(str::to_owned)))
+        };
+        assert_eq!(
+            named("https://candide.s3.eu-west-1.amazonaws.com/books"),
+            bucket("candide", Some("eu-west-1"))
+        );
+        assert_eq!(
+            named("https://old.candide.s3.amazonaws.com"),
+            bucket("old.candide", None)
+        );
+        assert_eq!(
+            named("http://candide.s3-website-us-west-2.amazonaws.com/"),
+            bucket("candide", Some("us-west-2"))
+        );
+        assert_eq!(
+            named("https://candide.s3-website.eu-west-1.amazonaws.com"),
+            bucket("candide", Some("eu-west-1"))
+        );
+        assert_eq!(
+            named("https://candide.s3-ap-southeast-2.amazonaws.com"),
+            bucket("candide", Some("ap-southeast-2"))
+        );
+        assert_eq!(
+            named("https://s3.dualstack.eu-west-1.amazonaws.com/candide/books"),
+            bucket("candide", Some("eu-west-1"))
+        );
+        assert_eq!(named("https://cdn.eg.test/books"), None);
+        assert_eq!(named("https://d111111abcdef8.cloudfront.net"), None);
+        assert_eq!(named("https://s3.amazonaws.com/"), None);
+    }
+
+    #[test]
+    fn public_urls_of_other_buckets_are_refused() {
+        let new = |public_url: &str| {
+            let config = ClientConfig {
+                public_url: Some(public_url.to_owned()),
+                ..Default::default()
+            };
+            S3Uploader::new("eu-west-1", "newbucket", config, Default::default())
+        };
+        let err = new("https://oldbucket.s3.amazonaws.com").err().unwrap();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert_eq!(
+            err.message,
+            "--public-url https://oldbucket.s3.amazonaws.com is the URL of the bucket oldbucket, \
+             but files are uploaded to newbucket; give the URL files are served from for newbucket"
+        );
+        assert!(new("https://s3.eu-west-1.amazonaws.com/oldbucket/books").is_err());
+        assert!(new("https://newbucket.s3.eu-west-1.amazonaws.com/books").is_ok());
+        assert!(new("https://podcast.eg.test").is_ok());
+    }
+
+    #[test]
+    fn public_url_cant_serve_presigned_urls() {
+        let config = ClientConfig {
+            public_url: Some(String::from("https://cdn.eg.test")),
+            credentials: Some((String::from("key1"), String::from("secret1"))),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            presign: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let err = S3Uploader::new("us-east-1", "bucket1", config, options)
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+    }
+
+    #[test]
+    fn base_url_supports_path_style_on_aws() {
+        let mut uploader = uploader(Default::default());
+        uploader.path_style = true;
+        assert_eq!(
+            uploader.base_url(),
+            "https://s3.region1.amazonaws.com/bucket1"
+        );
+    }
+
+    #[test]
+    fn new_accepts_injected_endpoint_and_credentials() {
+        let config = ClientConfig {
+            endpoint: Some(String::from("http://localhost:4566/")),
+            credentials: Some((String::from("key1"), String::from("secret1"))),
+            path_style: true,
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("us-east-1", "bucket1", config, Default::default()).unwrap();
+        assert_eq!(
+            uploader.url_for_file(Path::new("file1.mp3")),
+            "http://localhost:4566/bucket1/file1.mp3"
+        );
+    }
+
+    #[test]
+    fn new_accepts_any_region_with_custom_endpoint() {
+        let config = ClientConfig {
+            endpoint: Some(String::from("https://account1.r2.cloudflarestorage.com")),
+            path_style: true,
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("auto", "bucket1", config, Default::default()).unwrap();
+        assert_eq!(
+            uploader.base_url(),
+            "https://account1.r2.cloudflarestorage.com/bucket1"
+        );
+    }
+
+    #[test]
+    fn new_supports_minio_style_configuration() {
+        let config = ClientConfig {
+            endpoint: Some(String::from("http://localhost:9000")),
+            credentials: Some((String::from("minioadmin"), String::from("minioadmin"))),
+            path_style: true,
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("us-east-1", "bucket1", config, Default::default()).unwrap();
+        assert_eq!(uploader.base_url(), "http://localhost:9000/bucket1");
+    }
+
+    /// An uploader for `bucket1` as `--provider name` sets one up in `region`, sending its
+    /// requests to `s3` and publishing in the provider's default public mode.
+    fn provider_uploader(
+        name: &str,
+        region: &str,
+        public_url: Option<&str>,
+        s3: s3_mock::S3Mock,
+    ) -> S3Uploader {
+        let provider: Provider = name.parse().unwrap();
+        let endpoint = match provider.endpoint_url(region, Some("account1")) {
+            Ok(endpoint) => endpoint,
+            Err(_) => Some("http://localhost:9000".to_owned()),
+        };
+        let config = ClientConfig {
+            endpoint,
+            path_style: provider.path_style,
+            public_url: public_url.map(str::to_owned),
+            provider: Some(provider),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            public_mode: provider.default_public_mode(),
+            ..Default::default()
+        };
+        let mut uploader = S3Uploader::new(region, "bucket1", config, options).unwrap();
+        uploader.client = Box::new(s3);
+        uploader.check_credentials = false;
+        uploader
+    }
+
+    #[test]
+    fn providers_link_to_objects_where_they_serve_them() {
+        let cases = [
+            (
+                "aws",
+                "eu-west-1",
+                None,
+                "https://bucket1.s3.eu-west-1.amazonaws.com/Chapter%201.mp3",
+            ),
+            (
+                "r2",
+                "auto",
+                Some("https://pub-1.r2.dev"),
+                "https://pub-1.r2.dev/Chapter%201.mp3",
+            ),
+            (
+                "b2",
+                "us-west-004",
+                None,
+                "https://bucket1.s3.us-west-004.backblazeb2.com/Chapter%201.mp3",
+            ),
+            (
+                "spaces",
+                "nyc3",
+                None,
+                "https://bucket1.nyc3.digitaloceanspaces.com/Chapter%201.mp3",
+            ),
+            (
+                "minio",
+                "us-east-1",
+                None,
+                "http://localhost:9000/bucket1/Chapter%201.mp3",
+            ),
+        ];
+        for (name, region, public_url, url) in cases.iter() {
+            let uploader = provider_uploader(name, region, *public_url, Default::default());
+            assert_eq!(
+                uploader.url_for_file(Path::new("/audio/Chapter 1.mp3")),
+                *url,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn r2_presigns_urls_for_its_api() {
+        let provider: Provider = "r2".parse().unwrap();
+        let config = ClientConfig {
+            endpoint: provider.endpoint_url("auto", Some("account1")).unwrap(),
+            credentials: Some((String::from("key1"), String::from("secret1"))),
+            path_style: provider.path_style,
+            provider: Some(provider),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            public_mode: PublicMode::None,
+            presign: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("auto", "bucket1", config, options).unwrap();
+        let url = uploader.url_for_file(Path::new("/audio/file1.mp3"));
+        assert!(
+            url.starts_with("https://account1.r2.cloudflarestorage.com/bucket1/file1.mp3?"),
+            "{}",
+            url
+        );
+    }
+
+    #[test]
+    fn providers_refuse_what_they_dont_support() {
+        let provider: Provider = "r2".parse().unwrap();
+        let config = ClientConfig {
+            endpoint: provider.endpoint_url("auto", Some("account1")).unwrap(),
+            provider: Some(provider),
+            ..Default::default()
+        };
+        let err = S3Uploader::new("auto", "bucket1", config, Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert_eq!(
+            err.message,
+            "Cloudflare R2 doesn't support --public-mode bucket-policy; use none"
+        );
+    }
+
+    #[test]
+    fn providers_only_send_the_requests_they_support() {
+        // Name, region, whether the bucket policy is set, the ACL objects get, and the
+        // location constraint the bucket is created with.
+        let cases = [
+            ("aws", "eu-west-1", true, None, Some("eu-west-1")),
+            ("r2", "auto", false, None, None),
+            ("b2", "us-west-004", false, None, None),
+            ("spaces", "nyc3", false, Some("public-read"), None),
+            ("minio", "us-east-1", true, None, None),
+        ];
+        for (name, region, policy, acl, constraint) in cases.iter() {
+            let creates = Arc::new(Mutex::new(Vec::new()));
+            let puts = Arc::new(Mutex::new(Vec::new()));
+            let s3 = s3_mock::S3Mock {
+                create_bucket_requests: Arc::clone(&creates),
+                put_object_requests: Arc::clone(&puts),
+                get_public_access_block_error: Some(s3_mock::ErrorResponse::new("NotImplemented"))
+                    .filter(|_| *name == "minio"),
+                ..Default::default()
+            };
+            let calls = Arc::clone(&s3.calls);
+            let uploader = provider_uploader(name, region, Some("https://eg.test"), s3);
+            uploader
+                .upload(vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()])
+                .unwrap();
+            let calls = calls.lock().unwrap();
+            assert_eq!(calls.contains(&"put_bucket_policy"), *policy, "{}", name);
+            assert_eq!(
+                calls.contains(&"get_public_access_block"),
+                *policy || acl.is_some(),
+                "{}",
+                name
+            );
+            assert_eq!(puts.lock().unwrap()[0].acl.as_deref(), *acl, "{}", name);
+            let created = creates.lock().unwrap()[0].clone();
+            assert_eq!(
+                created
+                    .create_bucket_configuration
+                    .and_then(|config| config.location_constraint)
+                    .as_deref(),
+                *constraint,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn new_validates_region_without_custom_endpoint() {
+        let err = S3Uploader::new("auto", "bucket1", Default::default(), Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "Invalid region: auto");
+        let config = ClientConfig {
+            endpoint: Some(String::from("http://localhost:9000")),
+            ..Default::default()
+        };
+        let err = S3Uploader::new("", "bucket1", config, Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "Region must not be empty");
+    }
+
+    #[test]
+    fn new_presigns_urls_with_the_configured_credentials() {
+        let config = ClientConfig {
+            endpoint: Some(String::from("http://localhost:9000")),
+            credentials: Some((String::from("minioadmin"), String::from("minioadmin"))),
+            path_style: true,
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            presign: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("us-east-1", "bucket1", config, options).unwrap();
+        let url = uploader.url_for_file(Path::new("/tmp/file1.mp3"));
+        assert!(
+            url.starts_with("http://localhost:9000/bucket1/file1.mp3?"),
+            "{}",
+            url
+        );
+        assert!(url.contains("X-Amz-Credential=minioadmin%2F"), "{}", url);
+        assert!(url.contains("X-Amz-Expires=3600"), "{}", url);
+    }
+
+    #[test]
+    fn new_rejects_presigned_urls_lasting_over_7_days() {
+        let config = ClientConfig {
+            credentials: Some((String::from("key"), String::from("secret"))),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            presign: Some(MAX_PRESIGNED_EXPIRY + Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let err = S3Uploader::new("us-east-1", "bucket1", config, options)
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert!(err.message.contains("7d"), "{}", err.message);
+    }
+
+    struct MockHttp {
+        status: u16,
+        urls: Mutex<Vec<String>>,
+        headers: Mutex<Vec<String>>,
+    }
+
+    impl MockHttp {
+        fn new(status: u16) -> Self {
+            Self {
+                status,
+                urls: Mutex::new(Vec::new()),
+                headers: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl HttpGet for MockHttp {
+        fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<u16, String> {
+            self.urls.lock().unwrap().push(url.to_owned());
+            for (name, value) in headers {
+                self.headers
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {}", name, value));
+            }
+            Ok(self.status)
+        }
+    }
+
+    #[test]
+    fn uploads_with_explicit_length_and_no_content_encoding() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                put_object_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader(s3).upload(files).unwrap();
+        }
+        let request = requests.lock().unwrap().remove(0);
+        assert_eq!(request.content_length, Some(6));
+        assert_eq!(request.content_encoding, None);
+    }
+
+    #[test]
+    fn sends_progress_to_log() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader(Default::default());
+        let sink = Arc::clone(&lines);
+        uploader.set_log(Arc::new(move |line| {
+            sink.lock().unwrap().push(line.to_owned())
+        }));
+        let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+        uploader.upload(files).unwrap();
+        assert_eq!(*lines.lock().unwrap(), vec!["Uploading file1.mp3"]);
+    }
+
+    #[test]
+    fn streams_large_files_in_chunks() {
+        let path = std::env::temp_dir().join(format!("sloop-stream-{}.mp3", std::process::id()));
+        let len = 20 * CHUNK_SIZE as u64 + 1;
+        fs::File::create(&path).unwrap().set_len(len).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                put_object_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            uploader(s3).upload(vec![path.clone()]).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+        let request = requests.lock().unwrap().remove(0);
+        assert_eq!(request.content_length, Some(len as i64));
+        assert_eq!(request.body_len, len as usize);
+        assert_eq!(request.largest_chunk, CHUNK_SIZE);
+    }
+
+    fn temp_files(name: &str, count: usize) -> (PathBuf, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(format!("sloop-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let files = (0..count)
+            .map(|i| {
+                let path = dir.join(format!("file{}.mp3", i));
+                fs::write(&path, format!("data{}\n", i)).unwrap();
+                path
+            })
+            .collect();
+        (dir, files)
+    }
+
+    #[test]
+    fn uploads_each_file_once_regardless_of_concurrency() {
+        let (dir, files) = temp_files("concurrency", 20);
+        for concurrency in &[1, 3, 8, 50] {
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let s3 = s3_mock::S3Mock {
+                put_object_requests: Arc::clone(&requests),
+                ..Default::default()
+            };
+            let options = UploadOptions {
+                concurrency: *concurrency,
+                ..Default::default()
+            };
+            uploader_with_options(s3, options)
+                .upload(files.clone())
+                .unwrap();
+            let mut keys: Vec<String> = requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| r.key.clone())
+                .collect();
+            keys.sort();
+            let mut expected: Vec<String> = (0..20).map(|i| format!("file{}.mp3", i)).collect();
+            expected.sort();
+            assert_eq!(keys, expected, "concurrency {}", concurrency);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn names_missing_file_in_error() {
+        let path = Path::new("test_fixtures/dir1/missing.mp3");
+        let err = uploader(Default::default())
+            .upload(vec![path.to_path_buf()])
+            .err()
+            .unwrap();
+        assert!(
+            err.message
+                .starts_with("Failed to read test_fixtures/dir1/missing.mp3: "),
+            "{}",
+            err.message
+        );
+        assert_eq!(err.path.as_deref(), Some(path));
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn surfaces_s3_error_text_when_put_object_fails() {
+        let s3 = s3_mock::S3Mock {
+            put_object_errors: vec!["file1.mp3".to_owned()],
+            ..Default::default()
+        };
+        let path = Path::new("test_fixtures/dir1/file1.mp3");
+        let err = uploader(s3).upload(vec![path.to_path_buf()]).err().unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to upload file1.mp3: AccessDenied (Error message)"
+        );
+        assert_eq!(err.path.as_deref(), Some(path));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn rejects_paths_without_file_names() {
+        let s3: s3_mock::S3Mock = Default::default();
+        let calls = Arc::clone(&s3.calls);
+        let err = uploader(s3)
+            .upload(vec![PathBuf::from("test_fixtures/..")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.message,
+            "test_fixtures/.. has no UTF-8 file name to upload it as"
+        );
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_missing_files_before_sending_anything() {
+        let s3: s3_mock::S3Mock = Default::default();
+        let calls = Arc::clone(&s3.calls);
+        let err = uploader(s3)
+            .upload(vec![PathBuf::from("test_fixtures/missing.mp3")])
+            .err()
+            .unwrap();
+        assert!(
+            err.message
+                .starts_with("Failed to read test_fixtures/missing.mp3: "),
+            "{}",
+            err.message
+        );
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert!(calls.lock().unwrap().is_empty());
+        let err = uploader(s3_mock::S3Mock::default())
+            .upload(vec![PathBuf::from("test_fixtures/dir1")])
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "test_fixtures/dir1 is not a file");
+        assert_eq!(err.outcome(), Outcome::Invalid);
+    }
+
+    #[test]
+    fn failure_before_any_file_is_uploaded_is_not_partial() {
+        let s3 = s3_mock::S3Mock {
+            put_object_errors: vec!["file1.mp3".to_owned()],
+            ..Default::default()
+        };
+        let path = Path::new("test_fixtures/dir1/file1.mp3");
+        let err = uploader(s3).upload(vec![path.to_path_buf()]).err().unwrap();
+        assert_eq!(err.outcome(), Outcome::Failed);
+    }
+
+    #[test]
+    fn failure_after_uploading_some_files_is_partial() {
+        let (dir, files) = temp_files("partial", 2);
+        let s3 = s3_mock::S3Mock {
+            put_object_errors: vec!["file1.mp3".to_owned()],
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            concurrency: 1,
+            ..Default::default()
+        };
+        let err = uploader_with_options(s3, options)
+            .upload(files)
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::PartialUpload);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn feed_failure_after_uploading_media_is_partial() {
+        let (dir, files) = temp_files("partial-feed", 1);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let s3 = s3_mock::S3Mock {
+            put_object_errors: vec!["feed.xml".to_owned()],
+            ..Default::default()
+        };
+        let err = uploader(s3)
+            .publish(std::slice::from_ref(&feed), files, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.outcome(), Outcome::PartialUpload);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stops_starting_uploads_after_a_failure() {
+        let (dir, files) = temp_files("failure", 3);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            put_object_errors: vec!["file1.mp3".to_owned()],
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            concurrency: 1,
+            ..Default::default()
+        };
+        let err = uploader_with_options(s3, options)
+            .upload(files)
+            .err()
+            .unwrap();
+        assert!(err.message.starts_with("Failed to upload file1.mp3: "));
+        let keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.key.clone())
+            .collect();
+        assert_eq!(keys, vec!["file0.mp3", "file1.mp3"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_every_failed_file() {
+        let (dir, files) = temp_files("failures", 2);
+        let s3 = s3_mock::S3Mock {
+            put_object_errors: vec!["file0.mp3".to_owned(), "file1.mp3".to_owned()],
+            put_object_barrier: Some(Arc::new(Barrier::new(2))),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            concurrency: 2,
+            ..Default::default()
+        };
+        let err = uploader_with_options(s3, options)
+            .upload(files)
+            .err()
+            .unwrap();
+        assert!(err.message.starts_with("2 files failed to upload:"));
+        assert_contains!(err.message, "Failed to upload file0.mp3");
+        assert_contains!(err.message, "Failed to upload file1.mp3");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn publish_uploads_feed_last() {
+        let (dir, files) = temp_files("publish", 5);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        uploader(s3)
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap();
+        let keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.key.clone())
+            .collect();
+        assert_eq!(keys.len(), 6);
+        assert_eq!(keys.last().unwrap(), "feed.xml");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stamps_feed_with_sloop_version() {
+        let (dir, files) = temp_files("version", 1);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        uploader(s3)
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].key, "file0.mp3");
+        assert_eq!(requests[0].metadata, None);
+        assert_eq!(requests[1].key, "feed.xml");
+        let metadata = requests[1].metadata.as_ref().unwrap();
+        assert_eq!(metadata["sloop-version"], version::VERSION);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_progress_for_each_file() {
+        let (dir, files) = temp_files("progress", 2);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let progress = Arc::new(RecordingProgress::default());
+        let options = UploadOptions {
+            concurrency: 1,
+            ..Default::default()
+        };
+        let mut uploader = uploader_with_options(s3_mock::S3Mock::default(), options);
+        uploader.set_progress(Arc::clone(&progress) as Arc<dyn Progress>);
+        let summary = uploader
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap();
+        assert_eq!(
+            *progress.events.lock().unwrap(),
+            vec![
+                "start file0.mp3 1/3 6",
+                "finish file0.mp3",
+                "start file1.mp3 2/3 6",
+                "finish file1.mp3",
+                "start feed.xml 3/3 6",
+                "finish feed.xml",
+            ]
+        );
+        let sent = progress.sent.lock().unwrap();
+        assert_eq!(sent["file0.mp3"], 6);
+        assert_eq!(sent["file1.mp3"], 6);
+        assert_eq!(sent["feed.xml"], 6);
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.bytes, 18);
+        assert_eq!(summary.keys, ["file0.mp3", "file1.mp3", "feed.xml"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_resumed_parts_as_progress() {
+        let s3 = s3_mock::S3Mock {
+            multipart_uploads: vec![multipart_upload("file1.mp3", "old-upload-id")],
+            parts: vec![("old-upload-id".to_owned(), vec![part(1, b"da")])]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let progress = Arc::new(RecordingProgress::default());
+        let mut uploader = uploader_with_options(s3, multipart_options());
+        uploader.set_progress(Arc::clone(&progress) as Arc<dyn Progress>);
+        let path = Path::new("test_fixtures/dir1/file1.mp3");
+        let summary = uploader.upload(vec![path.to_path_buf()]).unwrap();
+        let len = fs::metadata(path).unwrap().len();
+        assert_eq!(progress.sent.lock().unwrap()["file1.mp3"], len);
+        assert_eq!(summary.bytes, len);
+    }
+
+    #[test]
+    fn summarises_upload() {
+        let summary = UploadSummary {
+            files: 12,
+            bytes: 1_288_490_189,
+            elapsed: Duration::from_secs(192),
+            unchanged: 0,
+            deleted: 0,
+            keys: Vec::new(),
+        };
+        assert_eq!(
+            summary.describe(Numbers::default()),
+            "12 files, 1.2 GiB in 3m 12s"
+        );
+        assert_eq!(
+            summary.describe(Numbers { raw: true }),
+            "12 files, 1288490189 B in 192s"
+        );
+        let summary = UploadSummary {
+            files: 1,
+            bytes: 512,
+            elapsed: Duration::from_secs(2),
+            unchanged: 3,
+            deleted: 0,
+            keys: Vec::new(),
+        };
+        assert_eq!(
+            summary.describe(Numbers::default()),
+            "1 file, 512 B in 2s (3 unchanged)"
+        );
+        let summary = UploadSummary {
+            deleted: 2,
+            ..summary
+        };
+        assert_eq!(
+            summary.describe(Numbers::default()),
+            "1 file, 512 B in 2s (3 unchanged, 2 deleted)"
+        );
+    }
+
+    fn object(key: &str, size: i64, e_tag: &str) -> rusoto_s3::Object {
+        rusoto_s3::Object {
+            key: Some(key.to_owned()),
+            size: Some(size),
+            e_tag: Some(format!("\"{}\"", e_tag)),
+            ..Default::default()
+        }
+    }
+
+    fn plan_fixture(name: &str) -> PathBuf {
+        Path::new("test_fixtures/plan").join(name)
+    }
+
+    #[test]
+    fn skips_files_the_bucket_already_has() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            objects: vec![
+                object("Chapter_1.mp3", 12, "4df09984294ac43bc2ae49200c02be5d"),
+                object("Chapter_2.mp3", 12, "00000000000000000000000000000000"),
+            ],
+            ..Default::default()
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader(s3);
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        let files = vec![
+            plan_fixture("Chapter_1.mp3"),
+            plan_fixture("Chapter_2.mp3"),
+            plan_fixture("cover.jpg"),
+        ];
+        let summary = uploader.upload(files).unwrap();
+        let mut keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.key.clone())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["Chapter_2.mp3", "cover.jpg"]);
+        assert_eq!((summary.files, summary.unchanged), (2, 1));
+        assert_eq!(summary.keys, vec!["Chapter_2.mp3", "cover.jpg"]);
+        assert_contains!(
+            lines.lock().unwrap().join("\n"),
+            "Skipping Chapter_1.mp3, which is unchanged"
+        );
+    }
+
+    #[test]
+    fn force_uploads_files_the_bucket_already_has() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            objects: vec![object(
+                "Chapter_1.mp3",
+                12,
+                "4df09984294ac43bc2ae49200c02be5d",
+            )],
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            force: true,
+            ..Default::default()
+        };
+        let uploader = uploader_with_options(s3, options);
+        let summary = uploader
+            .upload(vec![plan_fixture("Chapter_1.mp3")])
+            .unwrap();
+        assert_eq!(requests.lock().unwrap()[0].key, "Chapter_1.mp3");
+        assert_eq!((summary.files, summary.unchanged), (1, 0));
+    }
+
+    #[test]
+    fn compares_the_stored_e_tag_of_objects_encrypted_with_kms() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stored = |e_tag: &str| {
+            vec![(E_TAG_METADATA.to_owned(), e_tag.to_owned())]
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        };
+        // S3's own ETags for objects it encrypts with KMS never match.
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            objects: vec![
+                object("Chapter_1.mp3", 12, "11111111111111111111111111111111"),
+                object("Chapter_2.mp3", 12, "22222222222222222222222222222222"),
+                object("cover.jpg", 4, "33333333333333333333333333333333"),
+            ],
+            object_metadata: vec![
+                (
+                    "Chapter_1.mp3".to_owned(),
+                    stored("4df09984294ac43bc2ae49200c02be5d"),
+                ),
+                (
+                    "Chapter_2.mp3".to_owned(),
+                    stored("00000000000000000000000000000000"),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            sse: Some(Sse::Kms),
+            ..Default::default()
+        };
+        let files = vec![
+            plan_fixture("Chapter_1.mp3"),
+            plan_fixture("Chapter_2.mp3"),
+            plan_fixture("cover.jpg"),
+        ];
+        let summary = uploader_with_options(s3, options).upload(files).unwrap();
+        assert_eq!(summary.unchanged, 1);
+        let requests = requests.lock().unwrap();
+        let mut sent: Vec<(&str, &str)> = requests
+            .iter()
+            .map(|r| {
+                let metadata = r.metadata.as_ref().unwrap();
+                (r.key.as_str(), metadata[E_TAG_METADATA].as_str())
+            })
+            .collect();
+        sent.sort();
+        assert_eq!(
+            sent,
+            [
+                ("Chapter_2.mp3", "7e9392e2eb2c21d24e493ca8c9ee305c"),
+                ("cover.jpg", "995e93664766e2205d19ea51eec95355")
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_files_with_objects_of_other_names() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object("Chapter 1.mp3", 12, "4df09984294ac43bc2ae49200c02be5d"),
+                object("Chapter 2.mp3", 12, "00000000000000000000000000000000"),
+            ],
+            ..Default::default()
+        };
+        let uploader = uploader(s3);
+        let file = plan_fixture("Chapter_1.mp3");
+        let same = |name| uploader.same_contents(&file, name).unwrap();
+        assert_eq!(same("Chapter 1.mp3"), Some(true));
+        assert_eq!(same("Chapter 2.mp3"), Some(false));
+        assert_eq!(same("Chapter 3.mp3"), None);
+    }
+
+    #[test]
+    fn predicts_multipart_e_tags() {
+        let options = UploadOptions {
+            multipart_threshold: 4,
+            part_size: 2,
+            ..Default::default()
+        };
+        let uploader = uploader_with_options(Default::default(), options);
+        let digests: Vec<u8> = [&b"da"[..], b"ta", b"1\n"]
+            .iter()
+            .flat_map(|part| md5::compute(part).0)
+            .collect();
+        assert_eq!(
+            uploader.expected_e_tag(&mut &b"data1\n"[..], 6).unwrap(),
+            format!("{:x}-3", md5::compute(digests))
+        );
+        assert_eq!(
+            uploader.expected_e_tag(&mut &b"data"[..], 4).unwrap(),
+            format!("{:x}", md5::compute(b"data"))
+        );
+    }
+
+    #[test]
+    fn lists_existing_objects_across_pages_under_the_prefix() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object("books/a/1.mp3", 1, "e1"),
+                object("books/b/2.mp3", 2, "e2"),
+                object("books/a/3.mp3", 3, "e3"),
+                object("books/a/4.mp3", 4, "e4"),
+            ],
+            list_objects_page_size: 1,
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/a".to_owned();
+        let mut keys: Vec<String> = uploader.existing_objects().unwrap().into_keys().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["books/a/1.mp3", "books/a/3.mp3", "books/a/4.mp3"]
+        );
+    }
+
+    /// Publishes the plan fixtures without a prefix to a bucket holding `objects`, returning the
+    /// result, the warnings logged and the keys put.
+    fn publish_to_root(
+        objects: Vec<rusoto_s3::Object>,
+        strict: bool,
+    ) -> (Result<UploadSummary, UploadError>, Vec<String>, Vec<String>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            objects,
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            strict,
+            ..Default::default()
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader_with_options(s3, options);
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        let files = vec![plan_fixture("Chapter_1.mp3"), plan_fixture("Chapter_2.mp3")];
+        let result = uploader.publish(&[plan_fixture("feed.xml")], files, None);
+        let warnings = lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.starts_with("Warning:"))
+            .cloned()
+            .collect();
+        let keys = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.key.clone())
+            .collect();
+        (result, warnings, keys)
+    }
+
+    #[test]
+    fn publishing_to_a_clean_bucket_root_does_not_warn() {
+        let (result, warnings, _) = publish_to_root(Vec::new(), true);
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn republishing_the_same_feed_to_the_bucket_root_does_not_warn() {
+        let objects = vec![
+            object("Chapter_1.mp3", 12, "4df09984294ac43bc2ae49200c02be5d"),
+            object("Chapter_2.mp3", 1, "e2"),
+            object("feed.xml", 1, "e3"),
+            object("notes.txt", 1, "e4"),
+            object("books/Zadig/feed.xml", 1, "e5"),
+        ];
+        let (result, warnings, _) = publish_to_root(objects, true);
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn warns_when_another_feed_is_in_the_bucket_root() {
+        let objects = vec![
+            object("zadig.xml", 1, "e1"),
+            object("Zadig_1.mp3", 1, "e2"),
+            object("Chapter_1.mp3", 1, "e3"),
+        ];
+        let (result, warnings, keys) = publish_to_root(objects, false);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(
+            warnings,
+            [
+                "Warning: the root of bucket bucket1 already has feeds or media that this run \
+              isn't publishing (Zadig_1.mp3, zadig.xml). If another podcast lives there, publish \
+              each with its own --prefix"
+            ]
+        );
+        assert_eq!(keys.len(), 3);
+    }
+
+    #[test]
+    fn strict_refuses_to_publish_into_a_shared_bucket_root() {
+        let objects = (1..=5)
+            .map(|n| object(&format!("Zadig_{}.mp3", n), 1, "e"))
+            .collect();
+        let (result, warnings, keys) = publish_to_root(objects, true);
+        let err = result.unwrap_err();
+        assert!(
+            err.message.starts_with(
+                "Refusing to publish: the root of bucket bucket1 already has feeds or media \
+                 that this run isn't publishing (Zadig_1.mp3, Zadig_2.mp3, Zadig_3.mp3 and 2 \
+                 more)"
+            ),
+            "{}",
+            err.message
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert!(keys.is_empty(), "{:?}", keys);
+    }
+
+    #[test]
+    fn uploads_feeds_under_the_names_they_are_given() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Candide".to_owned();
+        let feed = plan_fixture("feed.xml");
+        uploader.upload_as(&feed, "candide.rss");
+        uploader
+            .publish(
+                std::slice::from_ref(&feed),
+                vec![plan_fixture("Chapter_1.mp3")],
+                None,
+            )
+            .unwrap();
+        let keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.key.clone())
+            .collect();
+        assert_eq!(
+            keys,
+            ["books/Candide/Chapter_1.mp3", "books/Candide/candide.rss"]
+        );
+        assert_eq!(
+            uploader.url_for_file(&feed),
+            "https://bucket1.s3.region1.amazonaws.com/books/Candide/candide.rss"
+        );
+    }
+
+    #[test]
+    fn warns_when_the_feed_moves_to_a_new_key() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object("books/Candide/Chapter_1.mp3", 1, "e1"),
+                object("books/Candide/podcast.xml", 1, "e2"),
+                object("books/Candide/cover.jpg", 1, "e3"),
+            ],
+            ..Default::default()
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Candide".to_owned();
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        let feed = plan_fixture("feed.xml");
+        uploader
+            .publish(
+                std::slice::from_ref(&feed),
+                vec![plan_fixture("Chapter_1.mp3")],
+                None,
+            )
+            .unwrap();
+        let warnings: Vec<String> = lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.starts_with("Warning:"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "Warning: publishing the feed as books/Candide/feed.xml, but \
+              books/Candide/podcast.xml is already in the bucket. If podcast apps subscribe to \
+              https://bucket1.s3.region1.amazonaws.com/books/Candide/podcast.xml, add \
+              <itunes:new-feed-url>https://bucket1.s3.region1.amazonaws.com/books/Candide/feed.xml\
+              </itunes:new-feed-url> to that feed to move them to the new URL"
+            ]
+        );
+    }
+
+    #[test]
+    fn another_podcasts_feed_is_not_taken_for_a_moved_feed() {
+        let objects = vec![object("zadig.xml", 1, "e1"), object("Zadig_1.mp3", 1, "e2")];
+        let (result, warnings, _) = publish_to_root(objects, false);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].contains("--prefix"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn lists_every_page_of_objects_under_the_prefix() {
+        let mut objects: Vec<rusoto_s3::Object> = (1..=5)
+            .rev()
+            .map(|i| object(&format!("books/Dune/Chapter_{}.mp3", i), 12, "e"))
+            .collect();
+        objects[0].last_modified = Some("2026-10-01T09:30:00.000Z".to_owned());
+        objects.push(object("books/Dune/feed.xml", 666, "e"));
+        objects.push(object("books/Zadig/feed.xml", 1, "e"));
+        let s3 = s3_mock::S3Mock {
+            objects,
+            list_objects_page_size: 2,
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Dune".to_owned();
+        let listed = uploader.list().unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["list_objects_v2"; 3]);
+        let keys: Vec<&str> = listed.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "books/Dune/Chapter_1.mp3",
+                "books/Dune/Chapter_2.mp3",
+                "books/Dune/Chapter_3.mp3",
+                "books/Dune/Chapter_4.mp3",
+                "books/Dune/Chapter_5.mp3",
+                "books/Dune/feed.xml",
+            ]
+        );
+        let feeds: Vec<bool> = listed.iter().map(|object| object.feed).collect();
+        assert_eq!(feeds, vec![false, false, false, false, false, true]);
+        assert_eq!(
+            listed[4].last_modified.unwrap().to_rfc3339(),
+            "2026-10-01T09:30:00+00:00"
+        );
+        assert_eq!(listed[5].size, 666);
+    }
+
+    #[test]
+    fn feeds_can_be_made_from_every_page_of_objects_in_the_bucket() {
+        let mut objects: Vec<rusoto_s3::Object> = (1..=5)
+            .map(|i| object(&format!("books/Dune/Chapter_{}.mp3", i), i, "e"))
+            .collect();
+        objects.push(object("books/Dune/cover.jpg", 1, "e"));
+        objects.push(object("books/Dune/feed.xml", 666, "e"));
+        let s3 = s3_mock::S3Mock {
+            objects,
+            list_objects_page_size: 2,
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Dune".to_owned();
+        let files = crate::feed::remote_files(
+            uploader.list().unwrap(),
+            &uploader.folder(),
+            &[],
+            crate::feed::FileSort::None,
+        );
+        assert_eq!(*calls.lock().unwrap(), vec!["list_objects_v2"; 4]);
+        let generator = crate::feed::FeedGenerator {
+            title: "Dune".to_owned(),
+            urls: uploader.object_urls(),
+            ..Default::default()
+        };
+        let enclosures: Vec<(String, u64)> = generator
+            .episodes(files)
+            .unwrap()
+            .into_iter()
+            .map(|episode| (episode.url, episode.length))
+            .collect();
+        let url = |n| {
+            format!(
+                "https://bucket1.s3.region1.amazonaws.com/books/Dune/Chapter_{}.mp3",
+                n
+            )
+        };
+        assert_eq!(enclosures, (1..=5).map(|n| (url(n), n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn listing_a_missing_bucket_fails() {
+        let s3 = s3_mock::S3Mock {
+            no_such_bucket: true,
+            ..Default::default()
+        };
+        let err = uploader(s3).list().unwrap_err();
+        assert!(
+            err.message == "Failed to list bucket bucket1: the bucket doesn't exist",
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn enables_access_logs_under_the_bucket_name_by_default() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_bucket_logging_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            access_logs: Some("logs1".parse().unwrap()),
+            ..Default::default()
+        };
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].bucket, "bucket1");
+        let target = requests[0]
+            .bucket_logging_status
+            .logging_enabled
+            .clone()
+            .unwrap();
+        assert_eq!(target.target_bucket, "logs1");
+        assert_eq!(target.target_prefix, "bucket1/");
+    }
+
+    #[test]
+    fn parses_log_targets() {
+        assert_eq!(
+            "logs1/podcasts/".parse::<LogTarget>(),
+            Ok(LogTarget {
+                bucket: "logs1".to_owned(),
+                prefix: Some("podcasts/".to_owned()),
+            })
+        );
+        assert!("/podcasts".parse::<LogTarget>().is_err());
+    }
+
+    #[test]
+    fn tallies_episode_requests_from_every_log_object() {
+        let line = |key: &str, bytes: u64| {
+            format!(
+                "owner1 bucket1 [06/Feb/2026:00:00:38 +0000] 192.0.2.3 - REQ1 REST.GET.OBJECT {} \
+                 \"GET /{} HTTP/1.1\" 200 - {} {} 57 12 \"-\" \"Overcast/3.0\" -\n",
+                key, key, bytes, bytes
+            )
+        };
+        let mut object_bodies = HashMap::new();
+        object_bodies.insert(
+            "logs/2026-02-06-00-00-00-A".to_owned(),
+            line("books/Chapter_1.mp3", 10).into_bytes(),
+        );
+        object_bodies.insert(
+            "logs/2026-02-06-01-00-00-B".to_owned(),
+            format!(
+                "{}{}",
+                line("books/Chapter_1.mp3", 5),
+                line("books/feed.xml", 1)
+            )
+            .into_bytes(),
+        );
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object("books/Chapter_1.mp3", 15, "e"),
+                object("books/Chapter_2.mp3", 15, "e"),
+                object("books/feed.xml", 1, "e"),
+                object("logs/2026-02-06-00-00-00-A", 1, "e"),
+                object("logs/2026-02-06-01-00-00-B", 1, "e"),
+            ],
+            object_bodies,
+            logging_enabled: Some(LoggingEnabled {
+                target_bucket: "logs1".to_owned(),
+                target_prefix: "logs/".to_owned(),
+                target_grants: None,
+            }),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books".to_owned();
+        let tally = uploader.access_log_stats().unwrap();
+        assert_eq!(tally.unreadable_lines, 0);
+        let episodes: Vec<(String, u64, u64)> = tally
+            .episodes()
+            .into_iter()
+            .map(|episode| (episode.key, episode.gets, episode.bytes_sent))
+            .collect();
+        assert_eq!(
+            episodes,
+            vec![
+                ("books/Chapter_1.mp3".to_owned(), 2, 15),
+                ("books/Chapter_2.mp3".to_owned(), 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_need_access_logs() {
+        let err = uploader(s3_mock::S3Mock::default())
+            .access_log_stats()
+            .unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert_contains!(err.message, "--enable-access-logs");
+    }
+
+    /// The keys `upload` sends, one at a time, for the plan fixtures in `order`.
+    fn keys_uploaded_in(order: UploadOrder) -> Vec<String> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            concurrency: 1,
+            upload_order: order,
+            ..Default::default()
+        };
+        let files = vec![
+            plan_fixture("feed.xml"),
+            plan_fixture("cover.jpg"),
+            plan_fixture("Chapter_1.mp3"),
+        ];
+        uploader_with_options(s3, options).upload(files).unwrap();
+        let keys = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.key.clone())
+            .collect();
+        keys
+    }
+
+    #[test]
+    fn uploads_in_the_chosen_order_with_feeds_last() {
+        assert_eq!(
+            keys_uploaded_in(UploadOrder::Size),
+            vec!["Chapter_1.mp3", "cover.jpg", "feed.xml"]
+        );
+        assert_eq!(
+            keys_uploaded_in(UploadOrder::Input),
+            vec!["cover.jpg", "Chapter_1.mp3", "feed.xml"]
+        );
+    }
+
+    /// A feed with two episodes under books/Candide in bucket1, as published by `uploader`.
+    const PUBLISHED_FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+  <channel>
+    <title>Candide</title>
+    <link>https://bucket1.s3.region1.amazonaws.com/books/Candide</link>
+    <description>Candide</description>
+    <podcast:locked>yes</podcast:locked>
+    <item>
+      <title>Chapter 1</title>
+      <enclosure url="https://bucket1.s3.region1.amazonaws.com/books/Candide/Chapter_1.mp3" length="12" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title>Chapter 2</title>
+      <enclosure url="https://bucket1.s3.region1.amazonaws.com/books/Candide/Chapter_2.mp3" length="12" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    /// What `remove_episode` saw: the result, the S3 calls made, the objects put and the keys
+    /// deleted.
+    struct Removed {
+        result: Result<(), UploadError>,
+        calls: Vec<&'static str>,
+        put: Vec<s3_mock::PutObjectData>,
+        deleted: Vec<String>,
+    }
+
+    /// Removes `episode` from the published feed under books/Candide.
+    fn remove_episode(episode: &str, ignore_missing: bool, options: UploadOptions) -> Removed {
+        let mut object_bodies = HashMap::new();
+        object_bodies.insert(
+            "books/Candide/feed.xml".to_owned(),
+            PUBLISHED_FEED.as_bytes().to_vec(),
+        );
+        let s3 = s3_mock::S3Mock {
+            object_bodies,
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let put = Arc::clone(&s3.put_object_requests);
+        let deleted = Arc::clone(&s3.delete_object_requests);
+        let mut uploader = uploader_with_options(s3, options);
+        uploader.prefix = "books/Candide".to_owned();
+        let result = uploader.remove(episode, "feed.xml", ignore_missing);
+        let put = put.lock().unwrap().drain(..).collect();
+        let deleted = deleted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.key.clone())
+            .collect();
+        let calls = calls.lock().unwrap().clone();
+        Removed {
+            result,
+            calls,
+            put,
+            deleted,
+        }
+    }
+
+    #[test]
+    fn remove_uploads_the_feed_without_the_episode_then_deletes_it() {
+        let removed = remove_episode("Chapter_2.mp3", false, Default::default());
+        removed.result.unwrap();
+        assert_eq!(
+            removed.calls,
+            vec!["get_object", "put_object", "delete_object"]
+        );
+        assert_eq!(removed.put.len(), 1);
+        let feed = &removed.put[0];
+        assert_eq!(feed.key, "books/Candide/feed.xml");
+        assert_eq!(feed.content_type.as_deref(), Some("application/rss+xml"));
+        assert_eq!(feed.cache_control.as_deref(), Some(FEED_CACHE_CONTROL));
+        assert!(feed
+            .metadata
+            .as_ref()
+            .unwrap()
+            .contains_key("sloop-version"));
+        assert!(feed.body_len < PUBLISHED_FEED.len());
+        assert_eq!(removed.deleted, vec!["books/Candide/Chapter_2.mp3"]);
+    }
+
+    #[test]
+    fn remove_accepts_the_episodes_key() {
+        let removed = remove_episode("books/Candide/Chapter_2.mp3", false, Default::default());
+        removed.result.unwrap();
+        assert_eq!(removed.deleted, vec!["books/Candide/Chapter_2.mp3"]);
+        let removed = remove_episode("books/Zadig/Chapter_2.mp3", false, Default::default());
+        assert_eq!(removed.result.unwrap_err().outcome(), Outcome::Invalid);
+        assert_eq!(removed.calls, Vec::<&str>::new());
+    }
+
+    #[test]
+    fn remove_refuses_an_episode_missing_from_the_feed() {
+        let removed = remove_episode("Chapter_3.mp3", false, Default::default());
+        let err = removed.result.unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert!(err.message.contains("--ignore-missing"), "{}", err.message);
+        assert_eq!(removed.calls, vec!["get_object"]);
+    }
+
+    #[test]
+    fn remove_with_ignore_missing_deletes_without_changing_the_feed() {
+        let removed = remove_episode("Chapter_3.mp3", true, Default::default());
+        removed.result.unwrap();
+        assert_eq!(removed.calls, vec!["get_object", "delete_object"]);
+        assert_eq!(removed.deleted, vec!["books/Candide/Chapter_3.mp3"]);
+    }
+
+    #[test]
+    fn remove_in_a_dry_run_only_reads_the_feed() {
+        let options = UploadOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let removed = remove_episode("Chapter_2.mp3", false, options);
+        removed.result.unwrap();
+        assert_eq!(removed.calls, vec!["get_object"]);
+    }
 
-pub struct S3Uploader {
-    client: Box<dyn S3>,
-    region: String,
-    bucket_name: String,
-}
+    #[test]
+    fn remove_fails_without_a_feed() {
+        let s3 = s3_mock::S3Mock::default();
+        let err = uploader(s3)
+            .remove("Chapter_1.mp3", "feed.xml", false)
+            .unwrap_err();
+        assert_eq!(err.message, "There is no feed.xml in bucket bucket1");
+    }
 
-#[derive(Debug)]
-pub struct UploadError {
-    pub message: String,
-}
+    /// What `publish_pruning` saw: the result, the lines logged, the S3 calls made and the keys
+    /// sent in each `delete_objects` request.
+    struct Pruned {
+        result: Result<UploadSummary, UploadError>,
+        lines: Vec<String>,
+        calls: Vec<&'static str>,
+        deleted: Vec<Vec<String>>,
+    }
 
-impl S3Uploader {
-    pub fn new(region: &str, bucket_name: &str) -> Result<Self, UploadError> {
-        let rusoto_region = Region::from_str(region).map_err(|_| UploadError {
-            message: format!("Invalid region: {}", region),
-        })?;
-        let client = S3Client::new(rusoto_region);
-        Ok(Self {
-            client: Box::new(client),
-            region: region.to_owned(),
-            bucket_name: bucket_name.to_owned(),
-        })
+    /// Publishes the plan fixtures with `--prune` under `prefix` to a bucket holding `objects`.
+    fn publish_pruning(
+        objects: Vec<rusoto_s3::Object>,
+        prefix: &str,
+        s3: s3_mock::S3Mock,
+        options: UploadOptions,
+    ) -> Pruned {
+        let s3 = s3_mock::S3Mock { objects, ..s3 };
+        let calls = Arc::clone(&s3.calls);
+        let requests = Arc::clone(&s3.delete_objects_requests);
+        let options = UploadOptions {
+            prune: true,
+            ..options
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader_with_options(s3, options);
+        uploader.prefix = prefix.to_owned();
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        let files = vec![plan_fixture("Chapter_1.mp3"), plan_fixture("Chapter_2.mp3")];
+        let result = uploader.publish(&[plan_fixture("feed.xml")], files, None);
+        let deleted = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                assert_eq!(request.bucket, "bucket1");
+                request
+                    .delete
+                    .objects
+                    .iter()
+                    .map(|object| object.key.clone())
+                    .collect()
+            })
+            .collect();
+        let lines = lines.lock().unwrap().clone();
+        let calls = calls.lock().unwrap().clone();
+        Pruned {
+            result,
+            lines,
+            calls,
+            deleted,
+        }
     }
 
-    pub fn base_url(&self) -> String {
-        format!(
-            "https://{}.s3-{}.amazonaws.com",
-            self.bucket_name, self.region
-        )
+    #[test]
+    fn prune_deletes_stale_objects_in_the_folder_after_the_feeds() {
+        let objects = vec![
+            object(
+                "books/Candide/Chapter_1.mp3",
+                12,
+                "4df09984294ac43bc2ae49200c02be5d",
+            ),
+            object("books/Candide/Chapter_9.mp3", 1, "e1"),
+            object("books/Candide/old-cover.jpg", 1, "e2"),
+            object("books/Candide/extras/Interview.mp3", 1, "e3"),
+            object("books/Candide2/feed.xml", 1, "e4"),
+            object("books/Zadig/feed.xml", 1, "e5"),
+        ];
+        let Pruned {
+            result,
+            lines,
+            calls,
+            deleted,
+        } = publish_pruning(
+            objects,
+            "books/Candide",
+            Default::default(),
+            Default::default(),
+        );
+        let summary = result.unwrap();
+        assert_eq!(summary.deleted, 2);
+        assert_eq!(
+            deleted,
+            vec![vec![
+                "books/Candide/Chapter_9.mp3".to_owned(),
+                "books/Candide/old-cover.jpg".to_owned()
+            ]]
+        );
+        assert_eq!(calls.last(), Some(&"delete_objects"));
+        assert!(
+            lines.contains(&"Deleting books/Candide/Chapter_9.mp3".to_owned()),
+            "{:?}",
+            lines
+        );
     }
 
-    pub fn url_for_file(&self, file: &Path) -> String {
-        format!(
-            "{}/{}",
-            self.base_url(),
-            file.file_name().unwrap().to_str().unwrap()
-        )
+    #[test]
+    fn prune_without_a_prefix_keeps_objects_in_subfolders() {
+        let objects = vec![
+            object("Chapter_9.mp3", 1, "e1"),
+            object("books/Zadig/feed.xml", 1, "e2"),
+        ];
+        let Pruned {
+            result, deleted, ..
+        } = publish_pruning(objects, "", Default::default(), Default::default());
+        assert_eq!(result.unwrap().deleted, 1);
+        assert_eq!(deleted, vec![vec!["Chapter_9.mp3".to_owned()]]);
     }
 
-    pub fn upload(&self, files: Vec<PathBuf>) -> Result<(), UploadError> {
-        self.create_bucket()?;
-        self.make_bucket_public()?;
-        self.upload_files(files)?;
-        Ok(())
+    #[test]
+    fn prune_deletes_in_batches_of_1000() {
+        let objects = (0..1001)
+            .map(|i| object(&format!("old{:04}.mp3", i), 1, "e"))
+            .collect();
+        let Pruned {
+            result, deleted, ..
+        } = publish_pruning(objects, "", Default::default(), Default::default());
+        assert_eq!(result.unwrap().deleted, 1001);
+        let sizes: Vec<usize> = deleted.iter().map(|keys| keys.len()).collect();
+        assert_eq!(sizes, vec![1000, 1]);
     }
 
-    fn create_bucket(&self) -> Result<(), UploadError> {
-        let request = CreateBucketRequest {
-            bucket: self.bucket_name.clone(),
-            create_bucket_configuration: Some(CreateBucketConfiguration {
-                location_constraint: Some(self.region.clone()),
-            }),
+    #[test]
+    fn prune_in_a_dry_run_deletes_nothing() {
+        let options = UploadOptions {
+            dry_run: true,
             ..Default::default()
         };
-        if let Some(err) = self.client.create_bucket(request).sync().err() {
-            match err {
-                RusotoError::Service(BucketAlreadyOwnedByYou(_)) => {}
-                _ => {
-                    return Err(UploadError {
-                        message: format!("Failed to create bucket: {}", err),
-                    });
-                }
-            }
-        }
-        Ok(())
+        let objects = vec![object("Chapter_9.mp3", 1, "e1")];
+        let Pruned {
+            result,
+            lines,
+            calls,
+            ..
+        } = publish_pruning(objects, "", Default::default(), options);
+        assert_eq!(result.unwrap().deleted, 0);
+        assert!(calls.is_empty(), "{:?}", calls);
+        assert!(
+            lines.contains(
+                &"Would delete any other objects in https://bucket1.s3.region1.amazonaws.com"
+                    .to_owned()
+            ),
+            "{:?}",
+            lines
+        );
     }
 
-    fn make_bucket_public(&self) -> Result<(), UploadError> {
-        self.client
-            .delete_public_access_block(DeletePublicAccessBlockRequest {
-                bucket: self.bucket_name.to_owned(),
-            })
-            .sync()
-            .map_err(|_| UploadError {
-                message: "Failed to delete public access block".to_owned(),
-            })?;
-        let policy = json!({
-            "Version": "2012-10-17",
-            "Statement": [{
-                "Sid": "AddPerm",
-                "Effect": "Allow",
-                "Principal": "*",
-                "Action": ["s3:GetObject"],
-                "Resource": [format!("arn:aws:s3:::{}/*", &self.bucket_name)],
-            }]
-        })
-        .to_string();
-        let policy_request = PutBucketPolicyRequest {
-            bucket: self.bucket_name.to_owned(),
-            policy,
+    #[test]
+    fn failing_to_delete_is_a_partial_upload() {
+        let s3 = s3_mock::S3Mock {
+            delete_object_errors: vec!["Chapter_9.mp3".to_owned()],
             ..Default::default()
         };
-        self.client
-            .put_bucket_policy(policy_request)
-            .sync()
-            .map_err(|_| UploadError {
-                message: "Failed to set bucket policy".to_owned(),
-            })
+        let objects = vec![object("Chapter_9.mp3", 1, "e1")];
+        let Pruned { result, .. } = publish_pruning(objects, "", s3, Default::default());
+        let err = result.unwrap_err();
+        assert_eq!(err.outcome(), Outcome::PartialUpload);
+        assert_eq!(err.message, "Failed to delete Chapter_9.mp3: Access Denied");
     }
 
-    fn upload_files(&self, files: Vec<PathBuf>) -> Result<(), UploadError> {
-        for p in files {
-            let file_name = p.file_name().unwrap().to_str().unwrap();
-            let mut file = fs::File::open(&p).unwrap();
-            let mut body = vec![];
-            file.read_to_end(&mut body).unwrap();
-            let content_md5 = Some(base64.encode(md5::compute(&body).0));
-            let request = PutObjectRequest {
-                body: Some(body.into()),
-                bucket: self.bucket_name.clone(),
-                key: file_name.to_owned(),
-                content_md5,
-                ..Default::default()
-            };
-            println!("Uploading {}", &file_name);
-            self.client.put_object(request).sync().unwrap();
-        }
-        Ok(())
+    #[test]
+    fn plan_shows_objects_prune_would_delete() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object("books/Candide/Chapter_9.mp3", 7, "e1"),
+                object("books/Candide/extras/Interview.mp3", 1, "e2"),
+            ],
+            ..Default::default()
+        };
+        let options = UploadOptions {
+            prune: true,
+            ..Default::default()
+        };
+        let mut planner = uploader_with_options(s3, options);
+        planner.prefix = "books/Candide".to_owned();
+        let planned = planner
+            .plan(
+                &[plan_fixture("feed.xml")],
+                &[plan_fixture("Chapter_1.mp3")],
+            )
+            .unwrap();
+        assert_eq!(
+            planned
+                .iter()
+                .find(|object| object.action == Action::Delete),
+            Some(&PlannedObject {
+                key: "books/Candide/Chapter_9.mp3".to_owned(),
+                action: Action::Delete,
+                size: 7,
+                content_type: "audio/mpeg".to_owned(),
+            })
+        );
+        assert_eq!(planned.len(), 3);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    mod s3_mock;
+    #[test]
+    fn plan_makes_no_changes_and_agrees_with_publish() {
+        let objects = vec![
+            object(
+                "books/Candide/Chapter_1.mp3",
+                12,
+                "4df09984294ac43bc2ae49200c02be5d",
+            ),
+            object(
+                "books/Candide/Chapter_2.mp3",
+                11,
+                "7e9392e2eb2c21d24e493ca8c9ee305c",
+            ),
+        ];
+        let files = vec![
+            plan_fixture("cover.jpg"),
+            plan_fixture("Chapter_1.mp3"),
+            plan_fixture("Chapter_2.mp3"),
+        ];
+        let feeds = vec![plan_fixture("feed.xml")];
+        let s3 = s3_mock::S3Mock {
+            objects: objects.clone(),
+            ..Default::default()
+        };
+        let calls = Arc::clone(&s3.calls);
+        let mut planner = uploader(s3);
+        planner.prefix = "books/Candide".to_owned();
+        let planned = planner.plan(&feeds, &files).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["list_objects_v2"]);
 
-    use super::*;
-    use rusoto_s3::CreateBucketError::BucketAlreadyExists;
-    use serde::Deserialize;
-    use std::cell::RefCell;
-    use std::path::Path;
-    use std::rc::Rc;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            objects,
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Candide".to_owned();
+        uploader.publish(&feeds, files, None).unwrap();
+        let mut sent: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.key.clone())
+            .collect();
+        sent.sort();
+        let to_send: Vec<String> = planned
+            .into_iter()
+            .filter(|object| object.action != Action::Skip)
+            .map(|object| object.key)
+            .collect();
+        assert_eq!(sent, to_send);
+    }
 
-    #[derive(Deserialize)]
-    #[allow(non_snake_case)]
-    struct BucketPolicy {
-        Version: String,
-        Statement: Vec<BucketPolicyStatement>,
+    #[test]
+    fn plan_treats_missing_bucket_as_empty() {
+        let s3 = s3_mock::S3Mock {
+            no_such_bucket: true,
+            ..Default::default()
+        };
+        let planned = uploader(s3)
+            .plan(
+                &[plan_fixture("feed.xml")],
+                &[plan_fixture("Chapter_1.mp3")],
+            )
+            .unwrap();
+        let actions: Vec<Action> = planned.iter().map(|object| object.action).collect();
+        assert_eq!(actions, vec![Action::Create, Action::Create]);
     }
 
-    #[derive(Deserialize)]
-    #[allow(non_snake_case)]
-    struct BucketPolicyStatement {
-        Sid: String,
-        Effect: String,
-        Principal: String,
-        Action: Vec<String>,
-        Resource: Vec<String>,
+    /// Pins the `--plan` output for the project in `test_fixtures/plan`. If the format changes on
+    /// purpose, update `plan.txt` to match.
+    #[test]
+    fn plan_output_matches_golden_file() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![
+                object(
+                    "books/Candide/Chapter_1.mp3",
+                    12,
+                    "4df09984294ac43bc2ae49200c02be5d",
+                ),
+                object(
+                    "books/Candide/Chapter_2.mp3",
+                    12,
+                    "00000000000000000000000000000000",
+                ),
+                object(
+                    "books/Candide/old.mp3",
+                    5,
+                    "00000000000000000000000000000000",
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        uploader.prefix = "books/Candide".to_owned();
+        let media = vec![plan_fixture("Chapter_1.mp3"), plan_fixture("Chapter_2.mp3")];
+        let generator = crate::feed::FeedGenerator {
+            title: "Candide".to_owned(),
+            subtitle: Some("Or, Optimism".to_owned()),
+            urls: uploader.object_urls(),
+            image: Some(crate::feed::Image {
+                path: plan_fixture("cover.jpg"),
+            }),
+            ..Default::default()
+        };
+        let episodes = generator
+            .episodes(crate::feed::resolve_inputs(&media, "", false).unwrap())
+            .unwrap();
+        let mut files = vec![plan_fixture("cover.jpg")];
+        files.extend(media);
+        let plan = crate::plan::Plan {
+            target: uploader.base_url(),
+            channel: generator.channel_summary(&episodes),
+            objects: uploader.plan(&[plan_fixture("feed.xml")], &files).unwrap(),
+        };
+        let expected = fs::read_to_string(plan_fixture("plan.txt")).unwrap();
+        assert_eq!(plan.to_string(), expected);
     }
 
     #[test]
-    fn creates_an_s3_bucket() {
-        let requests = Rc::new(RefCell::new(Vec::new()));
+    fn dry_run_sends_no_requests() {
+        let (dir, mut files) = temp_files("dry-run", 2);
+        let large = dir.join("large.mp3");
+        fs::write(&large, "0123456789").unwrap();
+        files.push(large);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
         let s3 = s3_mock::S3Mock {
-            create_bucket_requests: Rc::clone(&requests),
+            calls: Arc::clone(&calls),
             ..Default::default()
         };
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
+        let options = UploadOptions {
+            dry_run: true,
+            concurrency: 1,
+            multipart_threshold: 8,
+            part_size: 2,
+            ..Default::default()
         };
-        uploader.upload(vec![]).unwrap();
-        let request = requests.borrow().get(0).unwrap().clone();
-        assert_eq!(request.bucket, "bucket1");
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = uploader_with_options(s3, options);
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        let summary = uploader
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap();
+        assert!(calls.lock().unwrap().is_empty(), "{:?}", calls);
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines[0], "Would create bucket bucket1 in region1");
+        assert!(lines[1].starts_with(
+            "Would check Block Public Access on bucket1 and apply a public read policy:\n"
+        ));
         assert_eq!(
-            request
-                .create_bucket_configuration
-                .expect("bucket configuration not set")
-                .location_constraint,
-            Some(String::from("region1")),
+            lines[2..],
+            [
+                "Would upload file0.mp3 (6 B, audio/mpeg)",
+                "Would upload file1.mp3 (6 B, audio/mpeg)",
+                "Would upload large.mp3 (10 B, audio/mpeg) in 5 parts",
+                "Would upload feed.xml (6 B, application/rss+xml)",
+            ]
         );
+        assert_eq!(summary.bytes, 28);
+        fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    fn handles_bucket_already_owned_by_user() {
+    fn dry_run_leaves_interrupted_uploads_alone() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
         let s3 = s3_mock::S3Mock {
-            create_bucket_error: Some(BucketAlreadyOwnedByYou(String::new())),
+            calls: Arc::clone(&calls),
+            multipart_uploads: vec![multipart_upload("other.mp3", "orphan-id")],
             ..Default::default()
         };
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
+        let options = UploadOptions {
+            dry_run: true,
+            ..Default::default()
         };
-        uploader.upload(vec![]).unwrap();
+        uploader_with_options(s3, options).upload(vec![]).unwrap();
+        assert!(calls.lock().unwrap().is_empty(), "{:?}", calls);
     }
 
     #[test]
-    fn throws_for_bucket_already_exists() {
+    fn publish_skips_feed_when_media_upload_fails() {
+        let (dir, files) = temp_files("publish-failure", 3);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
         let s3 = s3_mock::S3Mock {
-            create_bucket_error: Some(BucketAlreadyExists(String::new())),
+            put_object_requests: Arc::clone(&requests),
+            put_object_errors: vec!["file1.mp3".to_owned()],
             ..Default::default()
         };
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
+        assert!(uploader(s3)
+            .publish(std::slice::from_ref(&feed), files, None)
+            .is_err());
+        assert!(requests.lock().unwrap().iter().all(|r| r.key != "feed.xml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn publish_skips_feed_when_range_check_fails() {
+        let (dir, files) = temp_files("publish-range", 2);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
         };
-        assert!(uploader.upload(vec![]).is_err());
+        let http = MockHttp::new(200);
+        assert!(uploader(s3)
+            .publish(std::slice::from_ref(&feed), files, Some(&http))
+            .is_err());
+        assert_eq!(
+            *http.urls.lock().unwrap(),
+            vec!["https://bucket1.s3.region1.amazonaws.com/file0.mp3"]
+        );
+        assert!(requests.lock().unwrap().iter().all(|r| r.key != "feed.xml"));
+        fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    fn sets_bucket_policy_to_public() {
-        let requests = Rc::new(RefCell::new(Vec::new()));
+    fn publish_refuses_feeds_whose_files_changed_after_they_were_written() {
+        let (dir, files) = temp_files("publish-changed", 2);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
         let s3 = s3_mock::S3Mock {
-            put_bucket_policy_requests: Rc::clone(&requests),
+            put_object_requests: Arc::clone(&requests),
             ..Default::default()
         };
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
+        let mut uploader = uploader(s3);
+        for file in &files {
+            uploader.expect_length(file, fs::metadata(file).unwrap().len());
+        }
+        fs::write(&files[1], "re-encoded data1\n").unwrap();
+        let err = uploader
+            .publish(std::slice::from_ref(&feed), files.clone(), None)
+            .unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Invalid);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Refusing to upload the feed, as files changed after it was written:\n  \
+                 {} is 17 bytes but the feed gives 6\n\
+                 Run sloop again to write the feed for the files as they are now",
+                files[1].display()
+            )
+        );
+        assert!(requests.lock().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Appends to a file when the range check fetches the first episode, as if the file were
+    /// rewritten while the others uploaded.
+    struct RewritingHttp(PathBuf);
+
+    impl HttpGet for RewritingHttp {
+        fn get(&self, _url: &str, _headers: &[(&str, &str)]) -> Result<u16, String> {
+            let mut file = fs::OpenOptions::new().append(true).open(&self.0).unwrap();
+            std::io::Write::write_all(&mut file, b"more").unwrap();
+            Ok(206)
+        }
+    }
+
+    #[test]
+    fn publish_checks_lengths_again_before_uploading_the_feed() {
+        let (dir, files) = temp_files("publish-rewritten", 2);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            put_object_requests: Arc::clone(&requests),
+            ..Default::default()
         };
-        uploader.upload(vec![]).unwrap();
-        let request = requests.borrow().get(0).unwrap().clone();
-        assert_eq!(request.bucket, "bucket1");
-        let policy: BucketPolicy = serde_json::from_str(&request.policy).unwrap();
-        assert_eq!(policy.Version, "2012-10-17");
-        let statement = &policy.Statement[0];
-        assert_eq!(statement.Sid, "AddPerm");
-        assert_eq!(statement.Effect, "Allow");
-        assert_eq!(statement.Principal, "*");
-        assert_eq!(statement.Action[0], "s3:GetObject");
-        assert_eq!(statement.Resource[0], "arn:aws:s3:::bucket1/*");
+        let mut uploader = uploader(s3);
+        for file in &files {
+            uploader.expect_length(file, fs::metadata(file).unwrap().len());
+        }
+        let http = RewritingHttp(files[0].clone());
+        let err = uploader
+            .publish(std::slice::from_ref(&feed), files.clone(), Some(&http))
+            .unwrap_err();
+        assert_eq!(err.outcome(), Outcome::PartialUpload);
+        assert!(err.to_string().contains(&format!(
+            "{} is 10 bytes but the feed gives 6",
+            files[0].display()
+        )));
+        let mut keys: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.key.clone())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["file0.mp3", "file1.mp3"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Cancels the run once `after` bytes have been sent, as Ctrl-C partway through would.
+    struct CancellingProgress {
+        cancel: CancelToken,
+        after: u64,
+        sent: Mutex<u64>,
+    }
+
+    impl CancellingProgress {
+        fn new(cancel: &CancelToken, after: u64) -> Arc<Self> {
+            Arc::new(Self {
+                cancel: cancel.clone(),
+                after,
+                sent: Mutex::new(0),
+            })
+        }
+    }
+
+    impl Progress for CancellingProgress {
+        fn start_file(&self, _key: &str, _number: usize, _total_files: usize, _len: u64) {}
+
+        fn advance(&self, _key: &str, bytes: u64) {
+            let mut sent = self.sent.lock().unwrap();
+            *sent += bytes;
+            if *sent >= self.after {
+                self.cancel.cancel();
+            }
+        }
+
+        fn finish_file(&self, _key: &str) {}
     }
 
     #[test]
-    fn returns_error_if_setting_bucket_policy_fails() {
+    fn cancelling_stops_uploading_between_chunks_and_leaves_the_feed() {
+        let (dir, files) = temp_files("cancel", 3);
+        let feed = dir.join("feed.xml");
+        fs::write(&feed, "<rss/>").unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
         let s3 = s3_mock::S3Mock {
-            put_bucket_policy_error: true,
+            calls: Arc::clone(&calls),
+            put_object_requests: Arc::clone(&requests),
             ..Default::default()
         };
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
+        let options = UploadOptions {
+            concurrency: 1,
+            ..Default::default()
         };
-        assert!(uploader.upload(vec![]).is_err(), "expected error");
+        let mut uploader = uploader_with_options(s3, options);
+        let cancel = CancelToken::default();
+        uploader.set_cancel(cancel.clone());
+        uploader.set_progress(CancellingProgress::new(&cancel, 1));
+        let err = uploader
+            .publish(std::slice::from_ref(&feed), files, None)
+            .unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Cancelled);
+        assert_eq!(err.to_string(), "Cancelled");
+        let puts = calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| **call == "put_object")
+            .count();
+        assert_eq!(puts, 1);
+        assert!(requests.lock().unwrap().is_empty());
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["feed.xml", "file0.mp3", "file1.mp3", "file2.mp3"]);
+        fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
-    fn uploads_files_in_directory() {
-        let requests = Rc::new(RefCell::new(Vec::new()));
+    fn cancelling_leaves_multipart_uploads_to_resume() {
+        let (dir, files) = temp_files("cancel-multipart", 1);
+        let parts = Arc::new(Mutex::new(Vec::new()));
+        let aborts = Arc::new(Mutex::new(Vec::new()));
+        let s3 = s3_mock::S3Mock {
+            upload_part_requests: Arc::clone(&parts),
+            abort_multipart_upload_requests: Arc::clone(&aborts),
+            ..Default::default()
+        };
+        let mut uploader = uploader_with_options(s3, multipart_options());
+        let cancel = CancelToken::default();
+        uploader.set_cancel(cancel.clone());
+        uploader.set_progress(CancellingProgress::new(&cancel, 2));
+        let err = uploader.upload(files).unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Cancelled);
+        assert_eq!(parts.lock().unwrap().len(), 1);
+        assert!(aborts.lock().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelling_stops_hashing() {
+        let s3 = s3_mock::S3Mock {
+            objects: vec![object(
+                "Chapter_1.mp3",
+                12,
+                "4df09984294ac43bc2ae49200c02be5d",
+            )],
+            ..Default::default()
+        };
+        let mut uploader = uploader(s3);
+        let cancel = CancelToken::default();
+        uploader.set_cancel(cancel.clone());
+        let files = [plan_fixture("Chapter_1.mp3")];
+        assert_eq!(uploader.plan(&[], &files).unwrap()[0].action, Action::Skip);
+        cancel.cancel();
+        let err = uploader.plan(&[], &files).unwrap_err();
+        assert_eq!(err.outcome(), Outcome::Cancelled);
+    }
+
+    #[test]
+    fn sets_content_type_from_extension() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
         {
             let s3 = s3_mock::S3Mock {
-                put_object_requests: Rc::clone(&requests),
+                put_object_requests: Arc::clone(&requests),
                 ..Default::default()
             };
-            let uploader = S3Uploader {
-                client: Box::new(s3),
-                region: String::from("region1"),
-                bucket_name: String::from("bucket1"),
+            let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
+            uploader(s3).upload(files).unwrap();
+        }
+        let request = requests.lock().unwrap().remove(0);
+        assert_eq!(request.content_type, Some("audio/mpeg".to_owned()));
+    }
+
+    #[test]
+    fn sets_content_type_when_starting_multipart_upload() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        {
+            let s3 = s3_mock::S3Mock {
+                create_multipart_upload_requests: Arc::clone(&requests),
+                ..Default::default()
             };
             let files = vec![Path::new("test_fixtures/dir1/file1.mp3").to_path_buf()];
-            uploader.upload(files).unwrap();
+            uploader_with_options(s3, multipart_options())
+                .upload(files)
+                .unwrap();
         }
-        let requests = Rc::try_unwrap(requests).unwrap().into_inner();
-        let request = requests.get(0).unwrap().clone();
-        assert_eq!(request.bucket, String::from("bucket1"));
-        assert_eq!(request.key, String::from("file1.mp3"));
-        assert_eq!(request.body, b"data1\n");
+        let request = requests.lock().unwrap().remove(0);
+        assert_eq!(request.content_type, Some("audio/mpeg".to_owned()));
+    }
+
+    #[test]
+    fn falls_back_to_octet_stream_for_unknown_extensions() {
         assert_eq!(
-            request.content_md5,
-            Some("qzwQPf7mliTEhrdNPJDbZQ==".to_owned())
+            content_type(Path::new("notes.txt")),
+            "application/octet-stream"
         );
+        assert_eq!(content_type(Path::new("feed.xml")), "application/rss+xml");
     }
 
     #[test]
-    fn base_url_returns_url_for_bucket() {
-        let s3: s3_mock::S3Mock = Default::default();
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
-        };
+    fn verify_range_requests_first_kilobyte() {
+        let http = MockHttp::new(206);
+        let uploader = uploader(Default::default());
+        uploader
+            .verify_range(&http, Path::new("dir/file1.mp3"))
+            .unwrap();
         assert_eq!(
-            uploader.base_url(),
-            "https://bucket1.s3-region1.amazonaws.com"
+            *http.urls.lock().unwrap(),
+            vec!["https://bucket1.s3.region1.amazonaws.com/file1.mp3"]
         );
+        assert_eq!(*http.headers.lock().unwrap(), vec!["Range: bytes=0-1023"]);
     }
 
     #[test]
-    fn constructs_url_for_file() {
-        let s3: s3_mock::S3Mock = Default::default();
-        let uploader = S3Uploader {
-            client: Box::new(s3),
-            region: String::from("region1"),
-            bucket_name: String::from("bucket1"),
-        };
+    fn verify_range_reports_key_when_range_is_ignored() {
+        let http = MockHttp::new(200);
+        let uploader = uploader(Default::default());
+        let err = uploader
+            .verify_range(&http, Path::new("dir/file1.mp3"))
+            .unwrap_err();
         assert_eq!(
-            uploader.url_for_file(&PathBuf::from("/tmp/file1.txt")),
-            "https://bucket1.s3-region1.amazonaws.com/file1.txt"
+            err.message,
+            "Range request for file1.mp3 returned status 200 instead of 206"
+        );
+    }
+
+    #[test]
+    fn reports_missing_credentials_with_advice() {
+        let provider =
+            rusoto_core::credential::EnvironmentProvider::with_prefix("SLOOP_TEST_SCRUBBED");
+        let err = check_credentials(&provider).unwrap_err();
+        assert!(
+            err.message.starts_with("No AWS credentials found"),
+            "unexpected message: {}",
+            err.message
         );
+        assert_contains!(err.message, "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY");
+        assert_contains!(err.message, "~/.aws/credentials");
+        assert_contains!(err.message, "AWS_PROFILE");
+    }
+
+    #[test]
+    fn accepts_available_credentials() {
+        let provider = StaticProvider::new_minimal("key1".to_owned(), "secret1".to_owned());
+        check_credentials(&provider).unwrap();
+    }
+
+    #[test]
+    fn skips_credentials_check_for_custom_endpoints() {
+        let config = ClientConfig {
+            endpoint: Some(String::from("http://localhost:9000")),
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new("us-east-1", "bucket1", config, Default::default()).unwrap();
+        assert!(!uploader.check_credentials);
+        let uploader = S3Uploader::new(
+            "us-east-1",
+            "bucket1",
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        assert!(uploader.check_credentials);
     }
 }
@@ -0,0 +1,121 @@
+use super::file_name;
+use sloop::naming::{self, ObjectKey};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The keys and URLs files get on a backend that a web server serves at `public_url`, with the
+/// keys they'd have in a bucket as their paths under it.
+pub struct Layout {
+    /// The URL the backend's root is served at, without a trailing slash.
+    public_url: String,
+    /// The folder files are published to, without leading or trailing slashes.
+    prefix: String,
+    names: HashMap<PathBuf, String>,
+}
+
+impl Layout {
+    pub fn new(public_url: &str, prefix: &str) -> Self {
+        Self {
+            public_url: public_url.trim_end_matches('/').to_owned(),
+            prefix: prefix.trim_matches('/').to_owned(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// The URL files are served under, including any prefix.
+    pub fn base_url(&self) -> String {
+        naming::url_for_key(&self.public_url, &self.prefix)
+    }
+
+    /// The name `path` is published as, within the prefix.
+    pub fn name<'a>(&'a self, path: &'a Path) -> &'a str {
+        match self.names.get(path) {
+            Some(name) => name,
+            None => file_name(path),
+        }
+    }
+
+    /// The key `path` is published as: its name under the prefix.
+    pub fn key(&self, path: &Path) -> String {
+        self.key_of(self.name(path))
+    }
+
+    /// The key of the file published as `name`.
+    pub fn key_of(&self, name: &str) -> String {
+        ObjectKey::new(&self.prefix, name).key().to_owned()
+    }
+
+    /// Publishes `path` as `name` rather than under its own file name.
+    pub fn upload_as(&mut self, path: &Path, name: &str) {
+        self.names.insert(path.to_path_buf(), name.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::human::Numbers;
+    use crate::upload::{LocalUploader, SftpTarget, SftpUploader, Uploader};
+
+    #[test]
+    fn keys_are_names_under_the_prefix() {
+        let mut layout = Layout::new("https://eg.test/", "/books/candide/");
+        let feed = Path::new("/tmp/out/feed.xml");
+        assert_eq!(
+            layout.key(Path::new("/audio/Chapter 1.mp3")),
+            "books/candide/Chapter 1.mp3"
+        );
+        assert_eq!(layout.key(feed), "books/candide/feed.xml");
+        layout.upload_as(feed, "Candide feed.xml");
+        assert_eq!(layout.name(feed), "Candide feed.xml");
+        assert_eq!(layout.key(feed), "books/candide/Candide feed.xml");
+        assert_eq!(Layout::new("https://eg.test", "").key(feed), "feed.xml");
+    }
+
+    #[test]
+    fn base_urls_put_the_prefix_under_the_public_url() {
+        assert_eq!(
+            Layout::new("https://eg.test/", "").base_url(),
+            "https://eg.test"
+        );
+        assert_eq!(
+            Layout::new("http://eg.test:8080/podcasts", "books/candide").base_url(),
+            "http://eg.test:8080/podcasts/books/candide"
+        );
+    }
+
+    #[test]
+    fn backends_serving_a_public_url_agree_on_keys_and_urls() {
+        let local = LocalUploader::new(
+            Path::new("/srv/www"),
+            "https://eg.test/podcasts",
+            "books",
+            true,
+            false,
+            Numbers::default(),
+        );
+        let sftp = SftpUploader::new(
+            SftpTarget {
+                host: "eg.test".to_owned(),
+                port: None,
+                user: None,
+                remote_dir: "/srv/www".to_owned(),
+            },
+            "https://eg.test/podcasts",
+            "books",
+            true,
+            false,
+            Numbers::default(),
+        );
+        let backends: [&dyn Uploader; 2] = [&local, &sftp];
+        let episode = Path::new("/audio/Chapter 1.mp3");
+        for backend in backends.iter() {
+            assert_eq!(backend.base_url(), "https://eg.test/podcasts/books");
+            assert_eq!(backend.key(episode), "books/Chapter 1.mp3");
+            assert_eq!(
+                backend.url_for_file(episode),
+                "https://eg.test/podcasts/books/Chapter%201.mp3"
+            );
+        }
+    }
+}
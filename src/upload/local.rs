@@ -0,0 +1,424 @@
+use super::layout::Layout;
+use super::{
+    check_files, content_type, file_len, file_md5, publish_in_order, UploadError, UploadSummary,
+    Uploader,
+};
+use crate::cancel::CancelToken;
+use crate::http::HttpGet;
+use crate::human::Numbers;
+use crate::log::{self, Log};
+use crate::output::PartialFile;
+use crate::plan::{Action, PlannedObject};
+use crate::progress::{NoProgress, Progress};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Publishes by copying files into a directory that a web server serves at `public_url`, with
+/// the keys they'd have in a bucket as their paths under it.
+pub struct LocalUploader {
+    output_dir: PathBuf,
+    /// The keys files are copied as, under `output_dir`, and the URLs they're served at.
+    layout: Layout,
+    enclosure_lengths: Vec<(PathBuf, u64)>,
+    /// Log what would be copied instead of copying it.
+    dry_run: bool,
+    /// Copy every file, even those already there unchanged.
+    force: bool,
+    numbers: Numbers,
+    log: Log,
+    progress: Arc<dyn Progress>,
+}
+
+impl LocalUploader {
+    pub fn new(
+        output_dir: &Path,
+        public_url: &str,
+        prefix: &str,
+        dry_run: bool,
+        force: bool,
+        numbers: Numbers,
+    ) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            layout: Layout::new(public_url, prefix),
+            enclosure_lengths: Vec::new(),
+            dry_run,
+            force,
+            numbers,
+            log: log::stdout(),
+            progress: Arc::new(NoProgress),
+        }
+    }
+
+    /// Where `path` is copied to.
+    fn target(&self, path: &Path) -> PathBuf {
+        self.path_of(&self.key(path))
+    }
+
+    /// Where the file with key `key` is, under `output_dir`.
+    fn path_of(&self, key: &str) -> PathBuf {
+        key.split('/')
+            .fold(self.output_dir.clone(), |target, segment| {
+                target.join(segment)
+            })
+    }
+
+    /// Whether `path` needs copying, from the file already at its target. With `force`, every
+    /// file does.
+    fn plan_object(&self, path: &Path) -> Result<PlannedObject, UploadError> {
+        let target = self.target(path);
+        let size = file_len(path)?;
+        let action = match file_len(&target) {
+            Err(_) => Action::Create,
+            Ok(_) if self.force => Action::Update,
+            Ok(existing) if existing != size => Action::Update,
+            Ok(_) => match same_contents(path, &target)? {
+                true => Action::Skip,
+                false => Action::Update,
+            },
+        };
+        Ok(PlannedObject {
+            key: self.key(path),
+            action,
+            size,
+            content_type: content_type(path),
+        })
+    }
+
+    /// Copies each of `files` that changed, logging those that didn't, and returns the summary
+    /// of what was copied, with `started` as the start of the run.
+    fn copy_files(
+        &self,
+        files: &[PathBuf],
+        started: Instant,
+    ) -> Result<UploadSummary, UploadError> {
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for path in files {
+            let object = self.plan_object(path)?;
+            if object.action == Action::Skip {
+                (self.log)(&format!("Skipping {}, which is unchanged", object.key));
+                unchanged += 1;
+            } else {
+                changed.push((path, object));
+            }
+        }
+        let total_files = changed.len();
+        let mut bytes = 0;
+        for (i, (path, object)) in changed.iter().enumerate() {
+            let target = self.target(path);
+            if self.dry_run {
+                (self.log)(&format!(
+                    "Would copy {} ({}, {}) to {}",
+                    object.key,
+                    self.numbers.bytes(object.size),
+                    object.content_type,
+                    target.display()
+                ));
+            } else {
+                (self.log)(&format!("Copying {} to {}", object.key, target.display()));
+                self.progress
+                    .start_file(&object.key, i + 1, total_files, object.size);
+                let copied = copy(path, &target);
+                if copied.is_ok() {
+                    self.progress.advance(&object.key, object.size);
+                }
+                self.progress.finish_file(&object.key);
+                bytes +=
+                    copied.map_err(|err| UploadError::io("copy", path, err).after_uploading(i))?;
+            }
+        }
+        Ok(UploadSummary {
+            files: total_files,
+            bytes,
+            elapsed: started.elapsed(),
+            unchanged,
+            deleted: 0,
+            keys: changed.into_iter().map(|(_, object)| object.key).collect(),
+        })
+    }
+}
+
+impl Uploader for LocalUploader {
+    fn base_url(&self) -> String {
+        self.layout.base_url()
+    }
+
+    fn url_for_file(&self, file: &Path) -> String {
+        self.object_urls().url(self.layout.name(file))
+    }
+
+    fn key(&self, path: &Path) -> String {
+        self.layout.key(path)
+    }
+
+    fn upload_as(&mut self, path: &Path, name: &str) {
+        self.layout.upload_as(path, name);
+    }
+
+    fn expect_length(&mut self, path: &Path, length: u64) {
+        self.enclosure_lengths.push((path.to_path_buf(), length));
+    }
+
+    fn set_log(&mut self, log: Log) {
+        self.log = log;
+    }
+
+    fn set_progress(&mut self, progress: Arc<dyn Progress>) {
+        self.progress = progress;
+    }
+
+    fn upload(&self, files: Vec<PathBuf>) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        check_files(&files)?;
+        self.copy_files(&files, started)
+    }
+
+    fn plan(
+        &self,
+        feeds: &[PathBuf],
+        files: &[PathBuf],
+    ) -> Result<Vec<PlannedObject>, UploadError> {
+        let mut all_files = files.to_vec();
+        all_files.extend(feeds.iter().cloned());
+        check_files(&all_files)?;
+        let mut objects = all_files
+            .iter()
+            .map(|path| self.plan_object(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    fn publish(
+        &self,
+        feeds: &[PathBuf],
+        files: Vec<PathBuf>,
+        http: Option<&dyn HttpGet>,
+    ) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        publish_in_order(
+            self,
+            &self.enclosure_lengths,
+            feeds,
+            &files,
+            http.filter(|_| !self.dry_run),
+            &|files| self.copy_files(files, started),
+        )
+    }
+
+    fn download(&self, path: &Path) -> Result<Option<Vec<u8>>, UploadError> {
+        let target = self.target(path);
+        match std::fs::read(&target) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(UploadError::io("read", &target, err)),
+        }
+    }
+
+    fn same_contents(&self, path: &Path, name: &str) -> Result<Option<bool>, UploadError> {
+        let published = self.path_of(&self.layout.key_of(name));
+        match file_len(&published) {
+            Err(_) => Ok(None),
+            Ok(len) if len != file_len(path)? => Ok(Some(false)),
+            Ok(_) => same_contents(path, &published).map(Some),
+        }
+    }
+}
+
+/// Copies `path` to `target`, creating its directory, and only replacing any file already
+/// there once the copy is complete, so the web server never serves half a file.
+fn copy(path: &Path, target: &Path) -> io::Result<u64> {
+    let mut source = File::open(path)?;
+    let mut partial = PartialFile::create(target, true)?;
+    let bytes = io::copy(&mut source, &mut partial)?;
+    partial.finish()?;
+    Ok(bytes)
+}
+
+/// Whether the files at `a` and `b`, already known to be the same size, hold the same bytes.
+fn same_contents(a: &Path, b: &Path) -> Result<bool, UploadError> {
+    let digest = |path: &Path| {
+        File::open(path)
+            .and_then(|mut file| file_md5(&mut file, &CancelToken::default()))
+            .map_err(|err| UploadError::io("read", path, err))
+    };
+    Ok(digest(a)? == digest(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::tests::scratch;
+    use sloop::naming;
+    use std::fs;
+    use std::sync::Mutex;
+
+    /// A scratch directory holding `Chapter 1.mp3`, `cover.jpg` and `feed.xml`, and the
+    /// directory within it, not yet created, that's published to.
+    fn workspace(name: &str) -> (PathBuf, PathBuf) {
+        let dir = scratch::workspace("local", name);
+        let output_dir = dir.join("www");
+        (dir, output_dir)
+    }
+
+    fn uploader(output_dir: &Path, prefix: &str) -> (LocalUploader, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut uploader = LocalUploader::new(
+            output_dir,
+            "http://eg.test:8080/podcasts/",
+            prefix,
+            false,
+            false,
+            Numbers::default(),
+        );
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        (uploader, lines)
+    }
+
+    #[test]
+    fn copies_files_into_the_prefix_creating_directories() {
+        let (dir, output_dir) = workspace("copies");
+        let (mut uploader, _) = uploader(&output_dir, "/books/candide/");
+        let feed = dir.join("feed.xml");
+        uploader.upload_as(&feed, "Candide feed.xml");
+        let summary = uploader
+            .publish(
+                std::slice::from_ref(&feed),
+                vec![dir.join("Chapter 1.mp3"), dir.join("cover.jpg")],
+                None,
+            )
+            .unwrap();
+        let published = output_dir.join("books").join("candide");
+        assert_eq!(
+            fs::read_to_string(published.join("Chapter 1.mp3")).unwrap(),
+            "episode one\n"
+        );
+        assert_eq!(
+            fs::read_to_string(published.join("cover.jpg")).unwrap(),
+            "jpeg"
+        );
+        assert_eq!(
+            fs::read_to_string(published.join("Candide feed.xml")).unwrap(),
+            "<rss/>"
+        );
+        assert_eq!(
+            summary.keys,
+            [
+                "books/candide/Chapter 1.mp3",
+                "books/candide/cover.jpg",
+                "books/candide/Candide feed.xml"
+            ]
+        );
+        assert_eq!((summary.files, summary.bytes), (3, 22));
+        assert_eq!(uploader.download(&feed).unwrap(), Some(b"<rss/>".to_vec()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overwrites_files_that_changed_and_skips_the_rest() {
+        let (dir, output_dir) = workspace("overwrites");
+        let (mut uploader, lines) = uploader(&output_dir, "");
+        let files = vec![dir.join("Chapter 1.mp3"), dir.join("cover.jpg")];
+        uploader.upload(files.clone()).unwrap();
+        fs::write(dir.join("cover.jpg"), "JPEG").unwrap();
+        lines.lock().unwrap().clear();
+
+        let plan = uploader.plan(&[], &files).unwrap();
+        let actions: Vec<(&str, Action)> = plan
+            .iter()
+            .map(|object| (object.key.as_str(), object.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("Chapter 1.mp3", Action::Skip),
+                ("cover.jpg", Action::Update)
+            ]
+        );
+        let summary = uploader.upload(files.clone()).unwrap();
+        assert_eq!((summary.files, summary.unchanged), (1, 1));
+        assert_eq!(
+            fs::read_to_string(output_dir.join("cover.jpg")).unwrap(),
+            "JPEG"
+        );
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "Skipping Chapter 1.mp3, which is unchanged".to_owned(),
+                format!(
+                    "Copying cover.jpg to {}",
+                    output_dir.join("cover.jpg").display()
+                ),
+            ]
+        );
+        assert!(!output_dir.join("cover.jpg.partial").exists());
+
+        uploader.force = true;
+        let summary = uploader.upload(files).unwrap();
+        assert_eq!((summary.files, summary.unchanged), (2, 0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn urls_match_the_names_files_are_copied_as() {
+        let (dir, output_dir) = workspace("urls");
+        let (uploader, _) = uploader(&output_dir, "books");
+        let episode = dir.join("Chapter 1.mp3");
+        assert_eq!(uploader.base_url(), "http://eg.test:8080/podcasts/books");
+        assert_eq!(
+            uploader.url_for_file(&episode),
+            "http://eg.test:8080/podcasts/books/Chapter%201.mp3"
+        );
+        uploader.upload(vec![episode.clone()]).unwrap();
+        let copied = fs::read_dir(output_dir.join("books"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(copied, ["Chapter 1.mp3"]);
+        assert_eq!(
+            naming::url_for_key(&uploader.base_url(), &copied[0]),
+            uploader.url_for_file(&episode)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compares_files_with_those_copied_under_other_names() {
+        let (dir, output_dir) = workspace("same-contents");
+        let (uploader, _) = uploader(&output_dir, "books");
+        uploader.upload(vec![dir.join("Chapter 1.mp3")]).unwrap();
+        fs::write(dir.join("Chapter 01.mp3"), "episode one\n").unwrap();
+        fs::write(dir.join("Chapter 2.mp3"), "episode two\n").unwrap();
+        let same = |file: &str, name: &str| uploader.same_contents(&dir.join(file), name).unwrap();
+        assert_eq!(same("Chapter 01.mp3", "Chapter 1.mp3"), Some(true));
+        assert_eq!(same("Chapter 2.mp3", "Chapter 1.mp3"), Some(false));
+        assert_eq!(same("cover.jpg", "Chapter 1.mp3"), Some(false));
+        assert_eq!(same("Chapter 01.mp3", "Chapter 3.mp3"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_copy_nothing() {
+        let (dir, output_dir) = workspace("dry-run");
+        let (mut uploader, lines) = uploader(&output_dir, "");
+        uploader.dry_run = true;
+        let summary = uploader.upload(vec![dir.join("cover.jpg")]).unwrap();
+        assert_eq!(summary.keys, ["cover.jpg"]);
+        assert!(!output_dir.exists());
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [format!(
+                "Would copy cover.jpg (4 B, image/jpeg) to {}",
+                output_dir.join("cover.jpg").display()
+            )]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use super::PublicMode;
+use std::str::FromStr;
+
+/// An S3-compatible service, and how sloop has to talk to it: where its API is, how objects are
+/// addressed, how buckets are made public and where the objects are served from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Provider {
+    /// What `--provider` calls it.
+    pub name: &'static str,
+    /// What the service calls itself, for messages.
+    pub title: &'static str,
+    pub endpoint: Endpoint,
+    /// Address objects as `{endpoint}/{bucket}/{key}` rather than `{bucket}.{endpoint}/{key}`.
+    pub path_style: bool,
+    /// The public modes the service supports, the first being the default. Services without
+    /// bucket policies and Block Public Access leave out `bucket-policy`, so neither is tried.
+    pub public_modes: &'static [PublicMode],
+    /// Whether buckets serve their objects at their API URL. If not, feeds link to
+    /// `--public-url` instead, e.g. a custom domain, so it must be given.
+    pub serves_objects: bool,
+    /// The region to use without `--region`, for services with a single region name.
+    pub default_region: Option<&'static str>,
+}
+
+/// Where a provider's S3 API is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endpoint {
+    /// AWS's own, found from the region.
+    Aws,
+    /// A URL with `{region}` and `{account_id}` standing for `--region` and `--account-id`.
+    Template(&'static str),
+    /// A server the user runs, so `--endpoint-url` must be given.
+    Custom,
+}
+
+/// Every provider `--provider` knows, in the order of `Provider::VALUES`.
+pub const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: "aws",
+        title: "Amazon S3",
+        endpoint: Endpoint::Aws,
+        path_style: false,
+        public_modes: &[
+            PublicMode::BucketPolicy,
+            PublicMode::ObjectAcl,
+            PublicMode::None,
+        ],
+        serves_objects: true,
+        default_region: None,
+    },
+    Provider {
+        name: "r2",
+        title: "Cloudflare R2",
+        endpoint: Endpoint::Template("https://{account_id}.r2.cloudflarestorage.com"),
+        path_style: true,
+        // R2 buckets are made public in the Cloudflare dashboard, by connecting a domain.
+        public_modes: &[PublicMode::None],
+        serves_objects: false,
+        default_region: Some("auto"),
+    },
+    Provider {
+        name: "b2",
+        title: "Backblaze B2",
+        endpoint: Endpoint::Template("https://s3.{region}.backblazeb2.com"),
+        path_style: false,
+        // B2 buckets are public or private as a whole, as set in the Backblaze console.
+        public_modes: &[PublicMode::None],
+        serves_objects: true,
+        default_region: None,
+    },
+    Provider {
+        name: "spaces",
+        title: "DigitalOcean Spaces",
+        endpoint: Endpoint::Template("https://{region}.digitaloceanspaces.com"),
+        path_style: false,
+        public_modes: &[PublicMode::ObjectAcl, PublicMode::None],
+        serves_objects: true,
+        default_region: None,
+    },
+    Provider {
+        name: "minio",
+        title: "MinIO",
+        endpoint: Endpoint::Custom,
+        path_style: true,
+        public_modes: &[PublicMode::BucketPolicy, PublicMode::None],
+        serves_objects: true,
+        default_region: Some("us-east-1"),
+    },
+];
+
+impl Provider {
+    pub const VALUES: &'static [&'static str] = &["aws", "r2", "b2", "spaces", "minio"];
+
+    /// The URL of the provider's S3 API for `region`, or `None` for AWS's own.
+    pub fn endpoint_url(
+        &self,
+        region: &str,
+        account_id: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        match self.endpoint {
+            Endpoint::Aws => Ok(None),
+            Endpoint::Template(template) => {
+                if template.contains("{account_id}") && account_id.is_none() {
+                    return Err(format!(
+                        "--provider {} needs --account-id, the {} account its endpoint is under",
+                        self.name, self.title
+                    ));
+                }
+                let url = template
+                    .replace("{region}", region)
+                    .replace("{account_id}", account_id.unwrap_or_default());
+                Ok(Some(url))
+            }
+            Endpoint::Custom => Err(format!(
+                "--provider {} needs --endpoint-url, the URL of your {} server",
+                self.name, self.title
+            )),
+        }
+    }
+
+    /// Whether the endpoint is under an account, which `--account-id` gives.
+    pub fn takes_account_id(&self) -> bool {
+        matches!(self.endpoint, Endpoint::Template(template) if template.contains("{account_id}"))
+    }
+
+    /// The public mode to use without `--public-mode`.
+    pub fn default_public_mode(&self) -> PublicMode {
+        self.public_modes[0]
+    }
+
+    /// Checks the service supports publishing in `public_mode`, and can serve the objects at
+    /// their bucket's URL unless `public_url` is given or the URLs are presigned.
+    pub fn check(
+        &self,
+        public_mode: PublicMode,
+        public_url: bool,
+        presigned: bool,
+    ) -> Result<(), String> {
+        if !self.public_modes.contains(&public_mode) {
+            let modes: Vec<&str> = self.public_modes.iter().map(|mode| mode.name()).collect();
+            return Err(format!(
+                "{} doesn't support --public-mode {}; use {}",
+                self.title,
+                public_mode.name(),
+                modes.join(" or ")
+            ));
+        }
+        if !self.serves_objects && !public_url && !presigned {
+            return Err(format!(
+                "{} doesn't serve buckets at their API URL, so --provider {} needs --public-url, \
+                 the URL the bucket is served at, e.g. a custom domain",
+                self.title, self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        PROVIDERS
+            .iter()
+            .find(|provider| provider.name == name)
+            .copied()
+            .ok_or_else(|| format!("{} is not one of {}", name, Provider::VALUES.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(name: &str) -> Provider {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn every_provider_is_named_in_values() {
+        let names: Vec<&str> = PROVIDERS.iter().map(|provider| provider.name).collect();
+        assert_eq!(names, Provider::VALUES);
+        assert_eq!(
+            "s3".parse::<Provider>().unwrap_err(),
+            "s3 is not one of aws, r2, b2, spaces, minio"
+        );
+    }
+
+    #[test]
+    fn every_provider_defaults_to_a_public_mode_it_supports() {
+        for provider in PROVIDERS {
+            assert!(
+                provider
+                    .check(provider.default_public_mode(), true, false)
+                    .is_ok(),
+                "{}",
+                provider.name
+            );
+            assert!(
+                provider.public_modes.contains(&PublicMode::None),
+                "{} can't publish private buckets",
+                provider.name
+            );
+        }
+    }
+
+    #[test]
+    fn endpoints_fill_in_the_region_and_account() {
+        assert_eq!(provider("aws").endpoint_url("eu-west-1", None), Ok(None));
+        assert_eq!(
+            provider("r2").endpoint_url("auto", Some("0a1b2c")),
+            Ok(Some("https://0a1b2c.r2.cloudflarestorage.com".to_owned()))
+        );
+        assert_eq!(
+            provider("b2").endpoint_url("us-west-004", None),
+            Ok(Some("https://s3.us-west-004.backblazeb2.com".to_owned()))
+        );
+        assert_eq!(
+            provider("spaces").endpoint_url("nyc3", None),
+            Ok(Some("https://nyc3.digitaloceanspaces.com".to_owned()))
+        );
+    }
+
+    #[test]
+    fn endpoints_that_cant_be_formed_say_what_they_need() {
+        assert_eq!(
+            provider("r2").endpoint_url("auto", None).unwrap_err(),
+            "--provider r2 needs --account-id, the Cloudflare R2 account its endpoint is under"
+        );
+        assert_eq!(
+            provider("minio")
+                .endpoint_url("us-east-1", None)
+                .unwrap_err(),
+            "--provider minio needs --endpoint-url, the URL of your MinIO server"
+        );
+        let takes_account_id: Vec<&str> = PROVIDERS
+            .iter()
+            .filter(|provider| provider.takes_account_id())
+            .map(|provider| provider.name)
+            .collect();
+        assert_eq!(takes_account_id, ["r2"]);
+    }
+
+    #[test]
+    fn unsupported_public_modes_are_refused() {
+        assert_eq!(
+            provider("spaces")
+                .check(PublicMode::BucketPolicy, false, false)
+                .unwrap_err(),
+            "DigitalOcean Spaces doesn't support --public-mode bucket-policy; use object-acl or \
+             none"
+        );
+        assert!(provider("aws")
+            .check(PublicMode::ObjectAcl, false, false)
+            .is_ok());
+    }
+
+    #[test]
+    fn services_that_dont_serve_buckets_need_a_public_url() {
+        let r2 = provider("r2");
+        assert_eq!(
+            r2.check(PublicMode::None, false, false).unwrap_err(),
+            "Cloudflare R2 doesn't serve buckets at their API URL, so --provider r2 needs \
+             --public-url, the URL the bucket is served at, e.g. a custom domain"
+        );
+        assert!(r2.check(PublicMode::None, true, false).is_ok());
+        // Presigned URLs are served by the API.
+        assert!(r2.check(PublicMode::None, false, true).is_ok());
+    }
+}
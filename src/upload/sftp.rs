@@ -0,0 +1,814 @@
+use super::layout::Layout;
+use super::{
+    check_files, content_type, file_len, is_feed, publish_in_order, UploadError, UploadSummary,
+    Uploader,
+};
+use crate::http::HttpGet;
+use crate::human::Numbers;
+use crate::log::{self, Log};
+use crate::plan::{Action, PlannedObject};
+use crate::progress::{NoProgress, Progress};
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+/// Where the sftp backend publishes: `remote_dir` on `host`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SftpTarget {
+    pub host: String,
+    /// The SSH port, if not the one `~/.ssh/config` gives the host, or 22.
+    pub port: Option<u16>,
+    /// Who to log in as, if not the user `~/.ssh/config` gives the host, or the local user.
+    pub user: Option<String>,
+    /// The directory the web server serves at the public URL, relative to the login directory
+    /// unless absolute.
+    pub remote_dir: String,
+}
+
+impl SftpTarget {
+    /// `[user@]host`, as ssh takes it.
+    fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    /// The remote path of the object `key`.
+    fn path(&self, key: &str) -> String {
+        format!("{}/{}", self.remote_dir.trim_end_matches('/'), key)
+    }
+}
+
+/// A regular file on the server, as a directory listing gives it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listed {
+    pub name: String,
+    pub size: u64,
+    /// When it was last modified, in local time, as `ls -l` shows it: to the minute if that
+    /// was in the last six months, e.g. `Oct 15 10:00`, or else to the day, e.g. `Jan 1 2025`.
+    pub modified: String,
+}
+
+/// The SFTP operations publishing takes. Abstracted so the uploader can be tested without a
+/// server.
+pub trait SftpClient {
+    /// The regular files in `dir`, or none if `dir` doesn't exist.
+    fn list(&self, dir: &str) -> Result<Vec<Listed>, String>;
+
+    /// Creates `dir` and any of its parents that are missing.
+    fn create_dir_all(&self, dir: &str) -> Result<(), String>;
+
+    /// Sends `local` to `remote` with mode `mode` and `local`'s modification time, only
+    /// replacing any file already there once it's complete.
+    fn put(&self, local: &Path, remote: &str, mode: u32) -> Result<(), String>;
+
+    /// The contents of `remote`.
+    fn get(&self, remote: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Runs OpenSSH's `sftp` in batch mode, so connections are made as `ssh` makes them: with the
+/// settings in `~/.ssh/config`, keys from ssh-agent and hosts checked against `known_hosts`.
+/// Batch mode never prompts, so only key-based logins work.
+struct SftpCommand {
+    target: SftpTarget,
+}
+
+impl SftpCommand {
+    /// Runs `commands` in one session, returning what they printed. A command prefixed with `-`
+    /// may fail without failing the rest.
+    fn run(&self, commands: &[String]) -> Result<String, String> {
+        let mut command = Command::new("sftp");
+        // English month names in listings, as `listed_time_matches` formats them.
+        command.args(["-b", "-"]).env("LC_ALL", "C");
+        if let Some(port) = self.target.port {
+            command.arg("-P").arg(port.to_string());
+        }
+        let mut child = command
+            .arg(self.target.destination())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => {
+                    "sftp isn't installed; --backend sftp runs OpenSSH's sftp".to_owned()
+                }
+                _ => format!("Failed to run sftp: {}", err),
+            })?;
+        let batch = commands.join("\n") + "\n";
+        if let Some(mut stdin) = child.stdin.take() {
+            // sftp exits early if it can't connect, so a broken pipe is reported by its status.
+            let _ = stdin.write_all(batch.as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("Failed to run sftp: {}", err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!(
+                "sftp to {} failed: {}",
+                self.target.destination(),
+                stderr.trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl SftpClient for SftpCommand {
+    fn list(&self, dir: &str) -> Result<Vec<Listed>, String> {
+        let output = self.run(&[format!("-ls -ln {}", quote(dir)?)])?;
+        Ok(output.lines().filter_map(parse_listing).collect())
+    }
+
+    fn create_dir_all(&self, dir: &str) -> Result<(), String> {
+        let mut commands = ancestors(dir)
+            .iter()
+            .map(|dir| Ok(format!("-mkdir {}", quote(dir)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        // Each mkdir fails harmlessly if the directory exists, so check the last one does.
+        commands.push(format!("cd {}", quote(dir)?));
+        self.run(&commands).map(|_| ())
+    }
+
+    fn put(&self, local: &Path, remote: &str, mode: u32) -> Result<(), String> {
+        let partial = quote(&format!("{}.partial", remote))?;
+        // -p keeps the file's modification time, by which it's found unchanged next time.
+        self.run(&[
+            format!("put -p {} {}", quote(&local.to_string_lossy())?, partial),
+            format!("chmod {:o} {}", mode, partial),
+            format!("rename {} {}", partial, quote(remote)?),
+        ])
+        .map(|_| ())
+    }
+
+    fn get(&self, remote: &str) -> Result<Vec<u8>, String> {
+        static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+        let local = env::temp_dir().join(format!(
+            "sloop-sftp-{}-{}",
+            process::id(),
+            DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let got = self.run(&[format!(
+            "get {} {}",
+            quote(remote)?,
+            quote(&local.to_string_lossy())?
+        )]);
+        let contents = got.and_then(|_| {
+            fs::read(&local).map_err(|err| format!("Failed to read {}: {}", local.display(), err))
+        });
+        let _ = fs::remove_file(&local);
+        contents
+    }
+}
+
+/// `path` as an sftp batch argument: quoted, with the quote, backslash and the glob characters
+/// sftp expands escaped. Paths with control characters are refused, since a newline would end
+/// the command and start another, and nothing can escape it.
+fn quote(path: &str) -> Result<String, String> {
+    if path.chars().any(char::is_control) {
+        return Err(format!(
+            "{:?} has a control character in it, which sftp can't be given",
+            path
+        ));
+    }
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('"');
+    for c in path.chars() {
+        if matches!(c, '"' | '\\' | '*' | '?' | '[' | ']') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Ok(quoted)
+}
+
+/// A regular file from a line of `ls -ln` output, e.g.
+/// `-rw-r--r--    1 1000     1000         12 Oct 15 10:00 Chapter 1.mp3`.
+fn parse_listing(line: &str) -> Option<Listed> {
+    if !line.starts_with('-') {
+        return None;
+    }
+    // The name is whatever follows the eight fields before it, spaces and all.
+    let mut rest = line;
+    let mut fields = Vec::with_capacity(8);
+    for _ in 0..8 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    let name = rest.strip_prefix(' ')?;
+    Some(Listed {
+        name: name.to_owned(),
+        size: fields[4].parse().ok()?,
+        modified: fields[5..].join(" "),
+    })
+}
+
+/// Whether a file modified at `modified` would be listed with the time `listed`. sftp lists
+/// recent files to the minute and others to the day, so both are tried.
+fn listed_time_matches(listed: &str, modified: SystemTime) -> bool {
+    let modified = DateTime::<Local>::from(modified);
+    ["%b %e %H:%M", "%b %e %Y"].iter().any(|format| {
+        let shown = modified.format(format).to_string();
+        shown.split_whitespace().eq(listed.split(' '))
+    })
+}
+
+/// `dir` and each of its parents, outermost first, e.g. `/srv`, `/srv/www` for `/srv/www`.
+fn ancestors(dir: &str) -> Vec<String> {
+    let mut ancestors = Vec::new();
+    let mut path = String::new();
+    for (i, segment) in dir.split('/').enumerate() {
+        if i > 0 {
+            path.push('/');
+        }
+        path.push_str(segment);
+        if !segment.is_empty() && segment != "." {
+            ancestors.push(path.clone());
+        }
+    }
+    ancestors
+}
+
+/// The mode `path` is uploaded with: its own, made readable by everyone so the web server can
+/// serve it, and never executable.
+fn upload_mode(path: &Path) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = fs::metadata(path) {
+            return metadata.permissions().mode() & 0o666 | 0o444;
+        }
+    }
+    let _ = path;
+    0o644
+}
+
+/// Publishes over SFTP to a directory that a web server serves at `public_url`, with the keys
+/// they'd have in a bucket as their paths under it.
+pub struct SftpUploader {
+    target: SftpTarget,
+    client: Box<dyn SftpClient>,
+    /// The keys files are uploaded as, under the remote directory, and the URLs they're served
+    /// at.
+    layout: Layout,
+    enclosure_lengths: Vec<(PathBuf, u64)>,
+    /// Log what would be uploaded instead of uploading it.
+    dry_run: bool,
+    /// Upload every file, even those already on the server unchanged.
+    force: bool,
+    numbers: Numbers,
+    log: Log,
+    progress: Arc<dyn Progress>,
+}
+
+impl SftpUploader {
+    pub fn new(
+        target: SftpTarget,
+        public_url: &str,
+        prefix: &str,
+        dry_run: bool,
+        force: bool,
+        numbers: Numbers,
+    ) -> Self {
+        Self {
+            client: Box::new(SftpCommand {
+                target: target.clone(),
+            }),
+            target,
+            layout: Layout::new(public_url, prefix),
+            enclosure_lengths: Vec::new(),
+            dry_run,
+            force,
+            numbers,
+            log: log::stdout(),
+            progress: Arc::new(NoProgress),
+        }
+    }
+
+    /// Where `path` is uploaded to on the server.
+    fn remote_path(&self, path: &Path) -> String {
+        self.target.path(&self.layout.key(path))
+    }
+
+    /// The files already on the server where `paths` would go, by remote path, listing each
+    /// directory they go in once.
+    fn existing(&self, paths: &[PathBuf]) -> Result<HashMap<String, Listed>, UploadError> {
+        let mut existing = HashMap::new();
+        let dirs: HashSet<String> = paths
+            .iter()
+            .map(|path| parent(&self.remote_path(path)).to_owned())
+            .collect();
+        for dir in dirs {
+            let files = self.client.list(&dir).map_err(UploadError::new)?;
+            for file in files {
+                existing.insert(format!("{}/{}", dir, file.name), file);
+            }
+        }
+        Ok(existing)
+    }
+
+    /// Whether `path` needs uploading, from the file already at its remote path. Feeds of the
+    /// same size have their contents compared, and other files count as unchanged if they're
+    /// the same size and were last modified when the file on the server was, which uploads
+    /// keep. Comparing contents would mean downloading every episode. With `force`, every file
+    /// needs uploading.
+    fn plan_object(
+        &self,
+        path: &Path,
+        existing: &HashMap<String, Listed>,
+    ) -> Result<PlannedObject, UploadError> {
+        let size = file_len(path)?;
+        let action = match existing.get(&self.remote_path(path)) {
+            None => Action::Create,
+            Some(_) if self.force => Action::Update,
+            Some(existing) if existing.size != size => Action::Update,
+            Some(_) if is_feed(path) => {
+                let contents = fs::read(path).map_err(|err| UploadError::io("read", path, err))?;
+                match self.download(path)? {
+                    Some(published) if published == contents => Action::Skip,
+                    _ => Action::Update,
+                }
+            }
+            Some(existing) => {
+                let modified = fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|err| UploadError::io("read", path, err))?;
+                match listed_time_matches(&existing.modified, modified) {
+                    true => Action::Skip,
+                    false => Action::Update,
+                }
+            }
+        };
+        Ok(PlannedObject {
+            key: self.layout.key(path),
+            action,
+            size,
+            content_type: content_type(path),
+        })
+    }
+
+    /// Uploads each of `files` that changed, creating the directories they go in, and logging
+    /// those that didn't change. Returns the summary of what was uploaded, with `started` as the
+    /// start of the run.
+    fn upload_files(
+        &self,
+        files: &[PathBuf],
+        started: Instant,
+    ) -> Result<UploadSummary, UploadError> {
+        let existing = self.existing(files)?;
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for path in files {
+            let object = self.plan_object(path, &existing)?;
+            if object.action == Action::Skip {
+                (self.log)(&format!("Skipping {}, which is unchanged", object.key));
+                unchanged += 1;
+            } else {
+                changed.push((path, object));
+            }
+        }
+        let total_files = changed.len();
+        let mut created_dirs = HashSet::new();
+        let mut bytes = 0;
+        for (i, (path, object)) in changed.iter().enumerate() {
+            let remote = self.remote_path(path);
+            let destination = format!("{}:{}", self.target.destination(), remote);
+            if self.dry_run {
+                (self.log)(&format!(
+                    "Would upload {} ({}, {}) to {}",
+                    object.key,
+                    self.numbers.bytes(object.size),
+                    object.content_type,
+                    destination
+                ));
+                continue;
+            }
+            let dir = parent(&remote);
+            if !existing.keys().any(|path| parent(path) == dir)
+                && created_dirs.insert(dir.to_owned())
+            {
+                self.client
+                    .create_dir_all(dir)
+                    .map_err(|err| UploadError::new(err).after_uploading(i))?;
+            }
+            (self.log)(&format!("Uploading {} to {}", object.key, destination));
+            self.progress
+                .start_file(&object.key, i + 1, total_files, object.size);
+            let sent = self.client.put(path, &remote, upload_mode(path));
+            if sent.is_ok() {
+                self.progress.advance(&object.key, object.size);
+            }
+            self.progress.finish_file(&object.key);
+            sent.map_err(|err| UploadError::new(err).with_path(path).after_uploading(i))?;
+            bytes += object.size;
+        }
+        Ok(UploadSummary {
+            files: total_files,
+            bytes,
+            elapsed: started.elapsed(),
+            unchanged,
+            deleted: 0,
+            keys: changed.into_iter().map(|(_, object)| object.key).collect(),
+        })
+    }
+}
+
+/// The directory of the remote path `path`.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+impl Uploader for SftpUploader {
+    fn base_url(&self) -> String {
+        self.layout.base_url()
+    }
+
+    fn url_for_file(&self, file: &Path) -> String {
+        self.object_urls().url(self.layout.name(file))
+    }
+
+    fn key(&self, path: &Path) -> String {
+        self.layout.key(path)
+    }
+
+    fn upload_as(&mut self, path: &Path, name: &str) {
+        self.layout.upload_as(path, name);
+    }
+
+    fn expect_length(&mut self, path: &Path, length: u64) {
+        self.enclosure_lengths.push((path.to_path_buf(), length));
+    }
+
+    fn set_log(&mut self, log: Log) {
+        self.log = log;
+    }
+
+    fn set_progress(&mut self, progress: Arc<dyn Progress>) {
+        self.progress = progress;
+    }
+
+    fn upload(&self, files: Vec<PathBuf>) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        check_files(&files)?;
+        self.upload_files(&files, started)
+    }
+
+    fn plan(
+        &self,
+        feeds: &[PathBuf],
+        files: &[PathBuf],
+    ) -> Result<Vec<PlannedObject>, UploadError> {
+        let all_files = [files, feeds].concat();
+        check_files(&all_files)?;
+        let existing = self.existing(&all_files)?;
+        let mut objects = all_files
+            .iter()
+            .map(|path| self.plan_object(path, &existing))
+            .collect::<Result<Vec<_>, _>>()?;
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    fn publish(
+        &self,
+        feeds: &[PathBuf],
+        files: Vec<PathBuf>,
+        http: Option<&dyn HttpGet>,
+    ) -> Result<UploadSummary, UploadError> {
+        let started = Instant::now();
+        publish_in_order(
+            self,
+            &self.enclosure_lengths,
+            feeds,
+            &files,
+            http.filter(|_| !self.dry_run),
+            &|files| self.upload_files(files, started),
+        )
+    }
+
+    fn download(&self, path: &Path) -> Result<Option<Vec<u8>>, UploadError> {
+        let remote = self.remote_path(path);
+        let dir = parent(&remote);
+        let listed = self.client.list(dir).map_err(UploadError::new)?;
+        if !listed
+            .iter()
+            .any(|file| format!("{}/{}", dir, file.name) == remote)
+        {
+            return Ok(None);
+        }
+        self.client.get(&remote).map(Some).map_err(UploadError::new)
+    }
+
+    /// Telling would mean downloading the published file, so renamed files are recognised by
+    /// their length and duration instead.
+    fn same_contents(&self, _path: &Path, _name: &str) -> Result<Option<bool>, UploadError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::tests::scratch;
+    use std::sync::Mutex;
+
+    /// The contents and modification time of a file on the mock server.
+    type MockFile = (Vec<u8>, SystemTime);
+
+    /// A server holding files in memory with their modification times, recording the calls
+    /// made that change anything.
+    #[derive(Default, Clone)]
+    struct MockServer {
+        files: Arc<Mutex<HashMap<String, MockFile>>>,
+        dirs: Arc<Mutex<HashSet<String>>>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SftpClient for MockServer {
+        fn list(&self, dir: &str) -> Result<Vec<Listed>, String> {
+            let files = self.files.lock().unwrap();
+            let mut listed: Vec<Listed> = files
+                .iter()
+                .filter(|(path, _)| parent(path) == dir)
+                .map(|(path, (contents, modified))| Listed {
+                    name: path[dir.len() + 1..].to_owned(),
+                    size: contents.len() as u64,
+                    modified: DateTime::<Local>::from(*modified)
+                        .format("%b %e %H:%M")
+                        .to_string()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                })
+                .collect();
+            listed.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(listed)
+        }
+
+        fn create_dir_all(&self, dir: &str) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!("mkdir {}", dir));
+            self.dirs.lock().unwrap().insert(dir.to_owned());
+            Ok(())
+        }
+
+        fn put(&self, local: &Path, remote: &str, mode: u32) -> Result<(), String> {
+            if !self.dirs.lock().unwrap().contains(parent(remote)) {
+                return Err(format!("{}: No such file or directory", remote));
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("put {} {:o}", remote, mode));
+            let contents = fs::read(local).map_err(|err| err.to_string())?;
+            let modified = fs::metadata(local)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|err| err.to_string())?;
+            self.files
+                .lock()
+                .unwrap()
+                .insert(remote.to_owned(), (contents, modified));
+            Ok(())
+        }
+
+        fn get(&self, remote: &str) -> Result<Vec<u8>, String> {
+            self.files
+                .lock()
+                .unwrap()
+                .get(remote)
+                .map(|(contents, _)| contents.clone())
+                .ok_or_else(|| format!("{} not found", remote))
+        }
+    }
+
+    fn uploader(server: &MockServer, prefix: &str) -> (SftpUploader, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let target = SftpTarget {
+            host: "eg.test".to_owned(),
+            port: Some(2222),
+            user: Some("podcasts".to_owned()),
+            remote_dir: "/srv/www/".to_owned(),
+        };
+        let mut uploader = SftpUploader::new(
+            target,
+            "https://eg.test/",
+            prefix,
+            false,
+            false,
+            Numbers::default(),
+        );
+        uploader.client = Box::new(server.clone());
+        uploader.set_log({
+            let lines = Arc::clone(&lines);
+            Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_owned()))
+        });
+        (uploader, lines)
+    }
+
+    #[test]
+    fn uploads_into_the_prefix_creating_the_directory() {
+        let dir = scratch::workspace("sftp", "uploads");
+        let server = MockServer::default();
+        let (mut uploader, lines) = uploader(&server, "books/candide");
+        let feed = dir.join("feed.xml");
+        uploader.upload_as(&feed, "Candide feed.xml");
+        let summary = uploader
+            .publish(
+                std::slice::from_ref(&feed),
+                vec![dir.join("Chapter 1.mp3"), dir.join("cover.jpg")],
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            summary.keys,
+            [
+                "books/candide/Chapter 1.mp3",
+                "books/candide/cover.jpg",
+                "books/candide/Candide feed.xml"
+            ]
+        );
+        assert_eq!((summary.files, summary.bytes), (3, 22));
+        let calls = server.calls.lock().unwrap().clone();
+        assert_eq!(calls[0], "mkdir /srv/www/books/candide");
+        assert_eq!(calls.len(), 4);
+        assert_eq!(
+            lines.lock().unwrap()[0],
+            "Uploading books/candide/Chapter 1.mp3 to \
+             podcasts@eg.test:/srv/www/books/candide/Chapter 1.mp3"
+        );
+        assert_eq!(uploader.download(&feed).unwrap(), Some(b"<rss/>".to_vec()));
+        assert_eq!(uploader.download(&dir.join("other.xml")).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_files_already_uploaded() {
+        let dir = scratch::workspace("sftp", "skips");
+        let server = MockServer::default();
+        let (mut uploader, lines) = uploader(&server, "");
+        let files = vec![dir.join("Chapter 1.mp3"), dir.join("feed.xml")];
+        uploader.upload(files.clone()).unwrap();
+        // The same size, so only the feed's contents reveal the change.
+        fs::write(dir.join("feed.xml"), "<RSS/>").unwrap();
+        lines.lock().unwrap().clear();
+        server.calls.lock().unwrap().clear();
+
+        let plan = uploader.plan(&[], &files).unwrap();
+        let actions: Vec<(&str, Action)> = plan
+            .iter()
+            .map(|object| (object.key.as_str(), object.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("Chapter 1.mp3", Action::Skip),
+                ("feed.xml", Action::Update)
+            ]
+        );
+        // An episode the same size as the one on the server, but modified since.
+        let edited = dir.join("Chapter 1.mp3");
+        fs::write(&edited, "episode ONE\n").unwrap();
+        let earlier = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&edited)
+            .and_then(|file| file.set_modified(earlier))
+            .unwrap();
+        let plan = uploader.plan(&[], &files[..1]).unwrap();
+        assert_eq!(plan[0].action, Action::Update);
+        uploader.upload(files[..1].to_vec()).unwrap();
+        assert_eq!(
+            uploader.plan(&[], &files[..1]).unwrap()[0].action,
+            Action::Skip
+        );
+        server.calls.lock().unwrap().clear();
+
+        lines.lock().unwrap().clear();
+        let summary = uploader.upload(files.clone()).unwrap();
+        assert_eq!((summary.files, summary.unchanged), (1, 1));
+        assert_eq!(*server.calls.lock().unwrap(), ["put /srv/www/feed.xml 644"]);
+        assert_eq!(
+            lines.lock().unwrap()[0],
+            "Skipping Chapter 1.mp3, which is unchanged"
+        );
+
+        uploader.force = true;
+        assert_eq!(
+            uploader.plan(&[], &files[..1]).unwrap()[0].action,
+            Action::Update
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_upload_nothing() {
+        let dir = scratch::workspace("sftp", "dry-run");
+        let server = MockServer::default();
+        let (mut uploader, lines) = uploader(&server, "");
+        uploader.dry_run = true;
+        let summary = uploader.upload(vec![dir.join("cover.jpg")]).unwrap();
+        assert_eq!(summary.keys, ["cover.jpg"]);
+        assert!(server.calls.lock().unwrap().is_empty());
+        assert_eq!(
+            *lines.lock().unwrap(),
+            ["Would upload cover.jpg (4 B, image/jpeg) to podcasts@eg.test:/srv/www/cover.jpg"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_are_readable_by_everyone_but_never_executable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch::workspace("sftp", "modes");
+        let path = dir.join("cover.jpg");
+        for (mode, uploaded) in [(0o600, 0o644), (0o664, 0o664), (0o755, 0o644)] {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            assert_eq!(upload_mode(&path), uploaded, "{:o}", mode);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn batch_arguments_are_quoted() {
+        assert_eq!(
+            quote("/srv/www/Chapter 1.mp3").unwrap(),
+            r#""/srv/www/Chapter 1.mp3""#
+        );
+        assert_eq!(
+            quote(r#"a "b" [c]*?\"#).unwrap(),
+            r#""a \"b\" \[c\]\*\?\\""#
+        );
+    }
+
+    #[test]
+    fn paths_with_control_characters_are_refused() {
+        let injected = "Chapter 1.mp3\"\nrm \"feed.xml";
+        assert_eq!(
+            quote(injected).unwrap_err(),
+            r#""Chapter 1.mp3\"\nrm \"feed.xml" has a control character in it, which sftp can't be given"#
+        );
+        assert!(quote("Chapter\r1.mp3").is_err());
+        assert!(quote("Chapter\t1.mp3").is_err());
+    }
+
+    #[test]
+    fn files_are_unchanged_if_listed_as_modified_when_they_were() {
+        let modified = SystemTime::now() - std::time::Duration::from_secs(3600);
+        let local = DateTime::<Local>::from(modified);
+        let recent = local.format("%b %e %H:%M").to_string();
+        let recent = recent.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(listed_time_matches(&recent, modified));
+        let old = local.format("%b %e %Y").to_string();
+        let old = old.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(listed_time_matches(&old, modified));
+        let later = modified + std::time::Duration::from_secs(120);
+        assert!(!listed_time_matches(&recent, later));
+        assert!(!listed_time_matches("Jan 1 1999", modified));
+    }
+
+    #[test]
+    fn listings_give_the_names_and_sizes_of_files() {
+        let listing = "sftp> -ls -ln \"/srv/www\"\n\
+                       drwxr-xr-x    2 1000     1000         4096 Oct 15 10:00 art\n\
+                       -rw-r--r--    1 1000     1000           12 Oct 15 10:00 Chapter  1.mp3\n\
+                       -rw-r--r--    1 1000     1000            6 Jan  1  2025 feed.xml\n";
+        let files: Vec<Listed> = listing.lines().filter_map(parse_listing).collect();
+        let listed = |name: &str, size, modified: &str| Listed {
+            name: name.to_owned(),
+            size,
+            modified: modified.to_owned(),
+        };
+        assert_eq!(
+            files,
+            [
+                listed("Chapter  1.mp3", 12, "Oct 15 10:00"),
+                listed("feed.xml", 6, "Jan 1 2025")
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_are_created_from_the_outermost() {
+        assert_eq!(
+            ancestors("/srv/www/books"),
+            ["/srv", "/srv/www", "/srv/www/books"]
+        );
+        assert_eq!(
+            ancestors("public_html/books"),
+            ["public_html", "public_html/books"]
+        );
+        assert_eq!(ancestors("./books"), ["./books"]);
+    }
+}
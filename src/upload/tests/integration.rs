@@ -0,0 +1,121 @@
+//! Runs the full upload flow against a real S3-compatible API such as LocalStack or MinIO.
+//!
+//! These tests only run when `SLOOP_TEST_ENDPOINT` is set, e.g.:
+//!
+//! ```text
+//! SLOOP_TEST_ENDPOINT=http://localhost:4566 cargo test integration
+//! ```
+//!
+//! `SLOOP_TEST_REGION`, `SLOOP_TEST_ACCESS_KEY` and `SLOOP_TEST_SECRET_KEY` default to values
+//! that work with LocalStack.
+
+use super::super::*;
+use rusoto_s3::{DeleteBucketRequest, DeleteObjectRequest, ListObjectsV2Request};
+use std::env;
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+struct TestBucket {
+    uploader: S3Uploader,
+}
+
+impl TestBucket {
+    fn create() -> Option<Self> {
+        let endpoint = env::var("SLOOP_TEST_ENDPOINT").ok()?;
+        let region = env::var("SLOOP_TEST_REGION").unwrap_or_else(|_| "eu-west-1".to_owned());
+        let access_key = env::var("SLOOP_TEST_ACCESS_KEY").unwrap_or_else(|_| "test".to_owned());
+        let secret_key = env::var("SLOOP_TEST_SECRET_KEY").unwrap_or_else(|_| "test".to_owned());
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos();
+        let bucket_name = format!("sloop-test-{}-{}", std::process::id(), nanos);
+        let config = ClientConfig {
+            endpoint: Some(endpoint),
+            credentials: Some((access_key, secret_key)),
+            path_style: true,
+            ..Default::default()
+        };
+        let uploader = S3Uploader::new(&region, &bucket_name, config, Default::default()).unwrap();
+        Some(Self { uploader })
+    }
+}
+
+impl Drop for TestBucket {
+    fn drop(&mut self) {
+        let client = &self.uploader.client;
+        let bucket = self.uploader.bucket_name.clone();
+        let listing = client
+            .list_objects_v2(ListObjectsV2Request {
+                bucket: bucket.clone(),
+                ..Default::default()
+            })
+            .sync();
+        if let Ok(listing) = listing {
+            for object in listing.contents.unwrap_or_default() {
+                let _ = client
+                    .delete_object(DeleteObjectRequest {
+                        bucket: bucket.clone(),
+                        key: object.key.unwrap_or_default(),
+                        ..Default::default()
+                    })
+                    .sync();
+            }
+        }
+        let _ = client.delete_bucket(DeleteBucketRequest { bucket }).sync();
+    }
+}
+
+/// Minimal HTTP/1.1 GET returning the status code and body. Test endpoints are plain HTTP.
+fn http_get(url: &str) -> (u16, Vec<u8>) {
+    let rest = url.trim_start_matches("http://");
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let mut stream = TcpStream::connect(host).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    )
+    .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("malformed HTTP response");
+    let head = String::from_utf8_lossy(&response[..split]).to_string();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, response[split + 4..].to_vec())
+}
+
+#[test]
+fn uploaded_files_are_publicly_readable() {
+    let bucket = match TestBucket::create() {
+        Some(bucket) => bucket,
+        None => return,
+    };
+    let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+    bucket.uploader.upload(vec![path.clone()]).unwrap();
+    let (status, body) = http_get(&bucket.uploader.url_for_file(&path));
+    assert_eq!(status, 200);
+    assert!(
+        body.windows(6).any(|w| w == b"data1\n"),
+        "unexpected body: {:?}",
+        body
+    );
+}
+
+#[test]
+fn upload_is_idempotent() {
+    let bucket = match TestBucket::create() {
+        Some(bucket) => bucket,
+        None => return,
+    };
+    let path = Path::new("test_fixtures/dir1/file1.mp3").to_path_buf();
+    bucket.uploader.upload(vec![path.clone()]).unwrap();
+    bucket.uploader.upload(vec![path]).unwrap();
+}
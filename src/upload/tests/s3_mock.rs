@@ -1,25 +1,127 @@
+use futures::Stream;
+use http::{HeaderMap, StatusCode};
+use rusoto_core::request::BufferedHttpResponse;
 use rusoto_core::{RusotoError, RusotoFuture};
 use rusoto_s3::CreateBucketError::{BucketAlreadyExists, BucketAlreadyOwnedByYou};
 use rusoto_s3::*;
-use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::Read;
-use std::rc::Rc;
+use std::sync::{Arc, Barrier, Mutex};
 
 #[derive(Debug)]
 pub struct PutObjectData {
     pub bucket: String,
     pub key: String,
-    pub body: Vec<u8>,
+    pub body_len: usize,
+    /// Size of the biggest piece the body was streamed in.
+    pub largest_chunk: usize,
     pub content_md5: Option<String>,
+    pub content_length: Option<i64>,
+    pub content_encoding: Option<String>,
+    pub content_type: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub acl: Option<String>,
+    pub server_side_encryption: Option<String>,
+    pub ssekms_key_id: Option<String>,
+    pub storage_class: Option<String>,
+    pub cache_control: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct UploadPartData {
+    pub key: String,
+    pub upload_id: String,
+    pub part_number: i64,
+    pub body: Vec<u8>,
 }
 
 #[derive(Default)]
 pub struct S3Mock {
-    pub create_bucket_requests: Rc<RefCell<Vec<CreateBucketRequest>>>,
+    /// The name of every S3 method called, in order.
+    pub calls: Arc<Mutex<Vec<&'static str>>>,
+    pub create_bucket_requests: Arc<Mutex<Vec<CreateBucketRequest>>>,
     pub create_bucket_error: Option<CreateBucketError>,
-    pub put_bucket_policy_requests: Rc<RefCell<Vec<PutBucketPolicyRequest>>>,
-    pub put_bucket_policy_error: bool,
-    pub put_object_requests: Rc<RefCell<Vec<PutObjectData>>>,
+    /// Rejects `create_bucket` requests without a location constraint, as AWS does outside
+    /// us-east-1.
+    pub require_location_constraint: bool,
+    pub put_bucket_policy_requests: Arc<Mutex<Vec<PutBucketPolicyRequest>>>,
+    pub put_bucket_policy_error: Option<ErrorResponse>,
+    pub put_object_requests: Arc<Mutex<Vec<PutObjectData>>>,
+    /// Keys whose `put_object` calls fail.
+    pub put_object_errors: Vec<String>,
+    /// Makes each `put_object` call wait for the others, to control the order of concurrent calls.
+    pub put_object_barrier: Option<Arc<Barrier>>,
+    pub multipart_uploads: Vec<MultipartUpload>,
+    pub parts: HashMap<String, Vec<Part>>,
+    pub create_multipart_upload_requests: Arc<Mutex<Vec<CreateMultipartUploadRequest>>>,
+    pub upload_part_requests: Arc<Mutex<Vec<UploadPartData>>>,
+    pub upload_part_error: bool,
+    pub complete_multipart_upload_requests: Arc<Mutex<Vec<CompleteMultipartUploadRequest>>>,
+    pub abort_multipart_upload_requests: Arc<Mutex<Vec<AbortMultipartUploadRequest>>>,
+    /// Objects already in the bucket, listed in pages of `list_objects_page_size` (all at once
+    /// if 0).
+    pub objects: Vec<Object>,
+    pub list_objects_page_size: usize,
+    /// Makes `list_objects_v2` fail as if the bucket didn't exist.
+    pub no_such_bucket: bool,
+    /// The bucket's Block Public Access settings; `get_public_access_block` fails if unset, as
+    /// S3 does.
+    pub public_access_block: Option<PublicAccessBlockConfiguration>,
+    /// Makes `get_public_access_block` fail, e.g. with `NotImplemented` as MinIO does.
+    pub get_public_access_block_error: Option<ErrorResponse>,
+    pub put_public_access_block_requests: Arc<Mutex<Vec<PutPublicAccessBlockRequest>>>,
+    pub delete_objects_requests: Arc<Mutex<Vec<DeleteObjectsRequest>>>,
+    pub delete_object_requests: Arc<Mutex<Vec<DeleteObjectRequest>>>,
+    /// The contents `get_object` returns, by key; other keys don't exist.
+    pub object_bodies: HashMap<String, Vec<u8>>,
+    /// The user metadata `head_object` returns, by key; other keys have none.
+    pub object_metadata: HashMap<String, HashMap<String, String>>,
+    /// Keys that `delete_objects` reports it failed to delete.
+    pub delete_object_errors: Vec<String>,
+    pub put_bucket_logging_requests: Arc<Mutex<Vec<PutBucketLoggingRequest>>>,
+    /// Where the bucket's access logs go, as `get_bucket_logging` reports it.
+    pub logging_enabled: Option<LoggingEnabled>,
+}
+
+/// An error response as S3 returns it, including the IDs that identify the request.
+#[derive(Clone)]
+pub struct ErrorResponse {
+    pub code: &'static str,
+    /// Value of the `Date` header, if any.
+    pub date: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: &'static str) -> Self {
+        Self { code, date: None }
+    }
+
+    fn to_error<E>(&self) -> RusotoError<E> {
+        let mut headers = HeaderMap::<String>::default();
+        headers.insert("x-amz-request-id", "request-id-1".to_owned());
+        headers.insert("x-amz-id-2", "host-id-1".to_owned());
+        if let Some(date) = &self.date {
+            headers.insert("date", date.clone());
+        }
+        let body = format!(
+            "<Error><Code>{}</Code><Message>Error message</Message></Error>",
+            self.code
+        );
+        RusotoError::Unknown(BufferedHttpResponse {
+            status: StatusCode::FORBIDDEN,
+            body: body.into(),
+            headers,
+        })
+    }
+}
+
+fn read_body(body: Option<StreamingBody>) -> Vec<u8> {
+    let mut buffer = vec![];
+    body.unwrap()
+        .into_blocking_read()
+        .read_to_end(&mut buffer)
+        .unwrap();
+    buffer
 }
 
 impl S3 for S3Mock {
@@ -27,7 +129,12 @@ impl S3 for S3Mock {
         &self,
         request: CreateBucketRequest,
     ) -> RusotoFuture<CreateBucketOutput, CreateBucketError> {
-        self.create_bucket_requests.borrow_mut().push(request);
+        self.calls.lock().unwrap().push("create_bucket");
+        let constrained = request.create_bucket_configuration.is_some();
+        self.create_bucket_requests.lock().unwrap().push(request);
+        if self.require_location_constraint && !constrained {
+            return Err(ErrorResponse::new("IllegalLocationConstraintException").to_error()).into();
+        }
         match &self.create_bucket_error {
             None => Ok(Default::default()).into(),
             Some(e) => match e {
@@ -46,16 +153,20 @@ impl S3 for S3Mock {
         &self,
         _input: DeletePublicAccessBlockRequest,
     ) -> RusotoFuture<(), DeletePublicAccessBlockError> {
-        Ok(()).into()
+        unimplemented!();
     }
 
     fn put_bucket_policy(
         &self,
         request: PutBucketPolicyRequest,
     ) -> RusotoFuture<(), PutBucketPolicyError> {
-        self.put_bucket_policy_requests.borrow_mut().push(request);
-        if self.put_bucket_policy_error {
-            Err(RusotoError::ParseError("".to_owned())).into()
+        self.calls.lock().unwrap().push("put_bucket_policy");
+        self.put_bucket_policy_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        if let Some(error) = &self.put_bucket_policy_error {
+            Err(error.to_error()).into()
         } else {
             Ok(()).into()
         }
@@ -65,34 +176,68 @@ impl S3 for S3Mock {
         &self,
         request: PutObjectRequest,
     ) -> RusotoFuture<PutObjectOutput, PutObjectError> {
-        let mut body = vec![];
-        request
+        self.calls.lock().unwrap().push("put_object");
+        // A body that fails to stream fails the request, as the connection would be dropped.
+        let chunks: Vec<usize> = match request
             .body
             .unwrap()
-            .into_blocking_read()
-            .read_to_end(&mut body)
-            .unwrap();
-        self.put_object_requests.borrow_mut().push(PutObjectData {
-            bucket: request.bucket,
-            key: request.key,
-            body,
-            content_md5: request.content_md5,
-        });
+            .wait()
+            .map(|chunk| chunk.map(|chunk| chunk.len()))
+            .collect()
+        {
+            Ok(chunks) => chunks,
+            Err(err) => return Err(RusotoError::HttpDispatch(err.into())).into(),
+        };
+        self.put_object_requests
+            .lock()
+            .unwrap()
+            .push(PutObjectData {
+                bucket: request.bucket,
+                key: request.key.clone(),
+                body_len: chunks.iter().sum(),
+                largest_chunk: chunks.into_iter().max().unwrap_or(0),
+                content_md5: request.content_md5,
+                content_length: request.content_length,
+                metadata: request.metadata,
+                acl: request.acl,
+                content_encoding: request.content_encoding,
+                content_type: request.content_type,
+                server_side_encryption: request.server_side_encryption,
+                ssekms_key_id: request.ssekms_key_id,
+                storage_class: request.storage_class,
+                cache_control: request.cache_control,
+            });
+        if let Some(barrier) = &self.put_object_barrier {
+            barrier.wait();
+        }
+        if self.put_object_errors.contains(&request.key) {
+            return Err(ErrorResponse::new("AccessDenied").to_error()).into();
+        }
         Ok(Default::default()).into()
     }
 
     fn abort_multipart_upload(
         &self,
-        _input: AbortMultipartUploadRequest,
+        request: AbortMultipartUploadRequest,
     ) -> RusotoFuture<AbortMultipartUploadOutput, AbortMultipartUploadError> {
-        unimplemented!();
+        self.calls.lock().unwrap().push("abort_multipart_upload");
+        self.abort_multipart_upload_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        Ok(Default::default()).into()
     }
 
     fn complete_multipart_upload(
         &self,
-        _: CompleteMultipartUploadRequest,
+        request: CompleteMultipartUploadRequest,
     ) -> RusotoFuture<CompleteMultipartUploadOutput, CompleteMultipartUploadError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("complete_multipart_upload");
+        self.complete_multipart_upload_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        Ok(Default::default()).into()
     }
 
     fn copy_object(&self, _: CopyObjectRequest) -> RusotoFuture<CopyObjectOutput, CopyObjectError> {
@@ -101,9 +246,18 @@ impl S3 for S3Mock {
 
     fn create_multipart_upload(
         &self,
-        _: CreateMultipartUploadRequest,
+        request: CreateMultipartUploadRequest,
     ) -> RusotoFuture<CreateMultipartUploadOutput, CreateMultipartUploadError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("create_multipart_upload");
+        self.create_multipart_upload_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        Ok(CreateMultipartUploadOutput {
+            upload_id: Some("new-upload-id".to_owned()),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn delete_bucket(&self, _: DeleteBucketRequest) -> RusotoFuture<(), DeleteBucketError> {
@@ -182,9 +336,11 @@ impl S3 for S3Mock {
 
     fn delete_object(
         &self,
-        _: DeleteObjectRequest,
+        request: DeleteObjectRequest,
     ) -> RusotoFuture<DeleteObjectOutput, DeleteObjectError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("delete_object");
+        self.delete_object_requests.lock().unwrap().push(request);
+        Ok(DeleteObjectOutput::default()).into()
     }
 
     fn delete_object_tagging(
@@ -196,9 +352,27 @@ impl S3 for S3Mock {
 
     fn delete_objects(
         &self,
-        _: DeleteObjectsRequest,
+        request: DeleteObjectsRequest,
     ) -> RusotoFuture<DeleteObjectsOutput, DeleteObjectsError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("delete_objects");
+        let errors: Vec<S3Error> = request
+            .delete
+            .objects
+            .iter()
+            .filter(|object| self.delete_object_errors.contains(&object.key))
+            .map(|object| S3Error {
+                key: Some(object.key.clone()),
+                code: Some("AccessDenied".to_owned()),
+                message: Some("Access Denied".to_owned()),
+                version_id: None,
+            })
+            .collect();
+        self.delete_objects_requests.lock().unwrap().push(request);
+        Ok(DeleteObjectsOutput {
+            errors: Some(errors).filter(|errors| !errors.is_empty()),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn get_bucket_accelerate_configuration(
@@ -272,7 +446,11 @@ impl S3 for S3Mock {
         &self,
         _: GetBucketLoggingRequest,
     ) -> RusotoFuture<GetBucketLoggingOutput, GetBucketLoggingError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("get_bucket_logging");
+        Ok(GetBucketLoggingOutput {
+            logging_enabled: self.logging_enabled.clone(),
+        })
+        .into()
     }
 
     fn get_bucket_metrics_configuration(
@@ -338,8 +516,22 @@ impl S3 for S3Mock {
         unimplemented!()
     }
 
-    fn get_object(&self, _: GetObjectRequest) -> RusotoFuture<GetObjectOutput, GetObjectError> {
-        unimplemented!()
+    fn get_object(
+        &self,
+        request: GetObjectRequest,
+    ) -> RusotoFuture<GetObjectOutput, GetObjectError> {
+        self.calls.lock().unwrap().push("get_object");
+        match self.object_bodies.get(&request.key) {
+            Some(body) => Ok(GetObjectOutput {
+                body: Some(body.clone().into()),
+                ..Default::default()
+            })
+            .into(),
+            None => {
+                let err = GetObjectError::NoSuchKey("The specified key does not exist.".to_owned());
+                Err(RusotoError::Service(err)).into()
+            }
+        }
     }
 
     fn get_object_acl(
@@ -367,8 +559,16 @@ impl S3 for S3Mock {
         unimplemented!()
     }
 
-    fn head_object(&self, _: HeadObjectRequest) -> RusotoFuture<HeadObjectOutput, HeadObjectError> {
-        unimplemented!()
+    fn head_object(
+        &self,
+        request: HeadObjectRequest,
+    ) -> RusotoFuture<HeadObjectOutput, HeadObjectError> {
+        self.calls.lock().unwrap().push("head_object");
+        Ok(HeadObjectOutput {
+            metadata: self.object_metadata.get(&request.key).cloned(),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn list_bucket_analytics_configurations(
@@ -401,9 +601,21 @@ impl S3 for S3Mock {
 
     fn list_multipart_uploads(
         &self,
-        _: ListMultipartUploadsRequest,
+        request: ListMultipartUploadsRequest,
     ) -> RusotoFuture<ListMultipartUploadsOutput, ListMultipartUploadsError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("list_multipart_uploads");
+        let prefix = request.prefix.unwrap_or_default();
+        let uploads = self
+            .multipart_uploads
+            .iter()
+            .filter(|upload| upload.key.as_deref().unwrap_or("").starts_with(&prefix))
+            .cloned()
+            .collect();
+        Ok(ListMultipartUploadsOutput {
+            uploads: Some(uploads),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn list_object_versions(
@@ -422,13 +634,48 @@ impl S3 for S3Mock {
 
     fn list_objects_v2(
         &self,
-        _: ListObjectsV2Request,
+        request: ListObjectsV2Request,
     ) -> RusotoFuture<ListObjectsV2Output, ListObjectsV2Error> {
-        unimplemented!()
-    }
-
-    fn list_parts(&self, _: ListPartsRequest) -> RusotoFuture<ListPartsOutput, ListPartsError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("list_objects_v2");
+        if self.no_such_bucket {
+            let err = ListObjectsV2Error::NoSuchBucket("The bucket does not exist".to_owned());
+            return Err(RusotoError::Service(err)).into();
+        }
+        let prefix = request.prefix.unwrap_or_default();
+        let matching: Vec<&Object> = self
+            .objects
+            .iter()
+            .filter(|object| object.key.as_deref().unwrap_or("").starts_with(&prefix))
+            .collect();
+        // The continuation token is the index of the first object on the page.
+        let start: usize = request
+            .continuation_token
+            .map(|token| token.parse().unwrap())
+            .unwrap_or(0);
+        let end = match self.list_objects_page_size {
+            0 => matching.len(),
+            size => (start + size).min(matching.len()),
+        };
+        let is_truncated = end < matching.len();
+        Ok(ListObjectsV2Output {
+            contents: Some(matching[start..end].iter().map(|&o| o.clone()).collect()),
+            is_truncated: Some(is_truncated),
+            next_continuation_token: Some(end.to_string()).filter(|_| is_truncated),
+            ..Default::default()
+        })
+        .into()
+    }
+
+    fn list_parts(
+        &self,
+        request: ListPartsRequest,
+    ) -> RusotoFuture<ListPartsOutput, ListPartsError> {
+        self.calls.lock().unwrap().push("list_parts");
+        Ok(ListPartsOutput {
+            parts: self.parts.get(&request.upload_id).cloned(),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn put_bucket_accelerate_configuration(
@@ -483,9 +730,14 @@ impl S3 for S3Mock {
 
     fn put_bucket_logging(
         &self,
-        _: PutBucketLoggingRequest,
+        request: PutBucketLoggingRequest,
     ) -> RusotoFuture<(), PutBucketLoggingError> {
-        unimplemented!()
+        self.calls.lock().unwrap().push("put_bucket_logging");
+        self.put_bucket_logging_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        Ok(()).into()
     }
 
     fn put_bucket_metrics_configuration(
@@ -572,8 +824,30 @@ impl S3 for S3Mock {
         unimplemented!()
     }
 
-    fn upload_part(&self, _: UploadPartRequest) -> RusotoFuture<UploadPartOutput, UploadPartError> {
-        unimplemented!()
+    fn upload_part(
+        &self,
+        request: UploadPartRequest,
+    ) -> RusotoFuture<UploadPartOutput, UploadPartError> {
+        self.calls.lock().unwrap().push("upload_part");
+        if self.upload_part_error {
+            return Err(RusotoError::ParseError("".to_owned())).into();
+        }
+        let body = read_body(request.body);
+        let e_tag = format!("\"{:x}\"", md5::compute(&body));
+        self.upload_part_requests
+            .lock()
+            .unwrap()
+            .push(UploadPartData {
+                key: request.key,
+                upload_id: request.upload_id,
+                part_number: request.part_number,
+                body,
+            });
+        Ok(UploadPartOutput {
+            e_tag: Some(e_tag),
+            ..Default::default()
+        })
+        .into()
     }
 
     fn upload_part_copy(
@@ -615,7 +889,19 @@ impl S3 for S3Mock {
         &self,
         _input: GetPublicAccessBlockRequest,
     ) -> RusotoFuture<GetPublicAccessBlockOutput, GetPublicAccessBlockError> {
-        unimplemented!();
+        self.calls.lock().unwrap().push("get_public_access_block");
+        if let Some(error) = &self.get_public_access_block_error {
+            return Err(error.to_error()).into();
+        }
+        match &self.public_access_block {
+            Some(config) => Ok(GetPublicAccessBlockOutput {
+                public_access_block_configuration: Some(config.clone()),
+            })
+            .into(),
+            None => {
+                Err(ErrorResponse::new("NoSuchPublicAccessBlockConfiguration").to_error()).into()
+            }
+        }
     }
 
     fn put_object_legal_hold(
@@ -641,8 +927,13 @@ impl S3 for S3Mock {
 
     fn put_public_access_block(
         &self,
-        _input: PutPublicAccessBlockRequest,
+        request: PutPublicAccessBlockRequest,
     ) -> RusotoFuture<(), PutPublicAccessBlockError> {
-        unimplemented!();
+        self.calls.lock().unwrap().push("put_public_access_block");
+        self.put_public_access_block_requests
+            .lock()
+            .unwrap()
+            .push(request);
+        Ok(()).into()
     }
 }
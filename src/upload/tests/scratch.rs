@@ -0,0 +1,17 @@
+//! Scratch files for the tests of the backends that publish to a directory a web server serves.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A new scratch directory for `backend`'s test `name`, holding `Chapter 1.mp3`, `cover.jpg`
+/// and `feed.xml`.
+pub fn workspace(backend: &str, name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("sloop-{}-{}-{}", backend, name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Chapter 1.mp3"), "episode one\n").unwrap();
+    fs::write(dir.join("cover.jpg"), "jpeg").unwrap();
+    fs::write(dir.join("feed.xml"), "<rss/>").unwrap();
+    dir
+}
@@ -0,0 +1,189 @@
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use sloop::naming::{self, ObjectKey};
+use std::time::Duration;
+
+/// The longest S3 accepts for a presigned URL.
+pub const MAX_PRESIGNED_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How feeds link to the objects sloop uploads.
+#[derive(Debug, Clone)]
+pub enum ObjectUrls {
+    /// Plain URLs under the public URL of the podcast's folder.
+    Public { base_url: String },
+    /// Presigned GET URLs, for buckets that aren't public.
+    Presigned(Presigner),
+}
+
+impl Default for ObjectUrls {
+    fn default() -> Self {
+        ObjectUrls::public("")
+    }
+}
+
+impl ObjectUrls {
+    pub fn public(base_url: &str) -> Self {
+        ObjectUrls::Public {
+            base_url: base_url.to_owned(),
+        }
+    }
+
+    /// The URL to fetch the file `name` in the podcast's folder from.
+    pub fn url(&self, name: &str) -> String {
+        match self {
+            ObjectUrls::Public { base_url } => ObjectKey::new("", name).url(base_url),
+            ObjectUrls::Presigned(presigner) => {
+                presigner.presign(&ObjectKey::new(&presigner.prefix, name))
+            }
+        }
+    }
+
+    /// An identifier for the file `name` that stays the same from run to run, even when its
+    /// URL is re-signed: the object's URL without any signature.
+    pub fn stable_id(&self, name: &str) -> String {
+        ObjectKey::new("", name).url(&self.base_url())
+    }
+
+    /// The name of the file in the podcast's folder that `url` links to, ignoring any
+    /// signature, or `None` if it links somewhere else.
+    pub fn name(&self, url: &str) -> Option<String> {
+        let url = url.split('?').next().unwrap_or(url);
+        let name = url.strip_prefix(&format!("{}/", self.base_url()))?;
+        let name = percent_encoding::percent_decode_str(name)
+            .decode_utf8()
+            .ok()?;
+        match name.is_empty() || name.contains('/') {
+            true => None,
+            false => Some(name.into_owned()),
+        }
+    }
+
+    /// The URL of the podcast's folder, which every object URL starts with.
+    pub fn base_url(&self) -> String {
+        match self {
+            ObjectUrls::Public { base_url } => base_url.clone(),
+            ObjectUrls::Presigned(presigner) => {
+                naming::url_for_key(&presigner.bucket_url, &presigner.prefix)
+            }
+        }
+    }
+}
+
+/// Signs GET requests for objects so they can be fetched without the bucket being public.
+#[derive(Debug, Clone)]
+pub struct Presigner {
+    /// E.g. `https://{bucket}.s3.{region}.amazonaws.com` or `http://localhost:9000/{bucket}`.
+    pub bucket_url: String,
+    pub prefix: String,
+    pub region: String,
+    pub credentials: AwsCredentials,
+    pub expires_in: Duration,
+}
+
+impl Presigner {
+    /// A URL for `key` that works until `expires_in` from now.
+    pub fn presign(&self, key: &ObjectKey) -> String {
+        let (scheme, rest) = match self.bucket_url.find("://") {
+            Some(i) => (&self.bucket_url[..i], &self.bucket_url[i + 3..]),
+            None => ("https", self.bucket_url.as_str()),
+        };
+        let (host, bucket_path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        // Only the endpoint's scheme and the region name matter for signing; the host is set
+        // explicitly so virtual-hosted and path-style URLs both keep their form.
+        let region = Region::Custom {
+            name: self.region.clone(),
+            endpoint: format!("{}://{}", scheme, host),
+        };
+        let path = format!("{}/{}", bucket_path, key.key());
+        let mut request = SignedRequest::new("GET", "s3", &region, &path);
+        request.set_hostname(Some(host.to_owned()));
+        request.generate_presigned_url(&self.credentials, &self.expires_in, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presigner(bucket_url: &str) -> Presigner {
+        Presigner {
+            bucket_url: bucket_url.to_owned(),
+            prefix: "books/candide".to_owned(),
+            region: "ap-southeast-2".to_owned(),
+            credentials: AwsCredentials::new("AKIDEXAMPLE", "secret", None, None),
+            expires_in: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn public_urls_are_under_the_base_url() {
+        let urls = ObjectUrls::public("https://eg.test/books");
+        assert_eq!(
+            urls.url("Chapter 1.mp3"),
+            "https://eg.test/books/Chapter%201.mp3"
+        );
+        assert_eq!(
+            urls.stable_id("Chapter 1.mp3"),
+            "https://eg.test/books/Chapter%201.mp3"
+        );
+        assert_eq!(urls.base_url(), "https://eg.test/books");
+    }
+
+    #[test]
+    fn names_are_found_from_the_urls_linking_to_them() {
+        let urls = ObjectUrls::public("https://eg.test/books");
+        assert_eq!(
+            urls.name(&urls.url("Chapter 1.mp3")).as_deref(),
+            Some("Chapter 1.mp3")
+        );
+        assert_eq!(urls.name("https://eg.test/other/Chapter%201.mp3"), None);
+        assert_eq!(
+            urls.name("https://eg.test/books/disc/Chapter%201.mp3"),
+            None
+        );
+        let urls = ObjectUrls::Presigned(presigner("https://candide.s3.amazonaws.com"));
+        assert_eq!(
+            urls.name(&urls.url("Chapter 1.mp3")).as_deref(),
+            Some("Chapter 1.mp3")
+        );
+    }
+
+    #[test]
+    fn presigned_urls_keep_the_object_url_and_add_a_signature() {
+        let urls =
+            ObjectUrls::Presigned(presigner("https://candide.s3.ap-southeast-2.amazonaws.com"));
+        let url = urls.url("Chapter 1.mp3");
+        let (object_url, query) = url.split_once('?').unwrap();
+        assert_eq!(
+            object_url,
+            "https://candide.s3.ap-southeast-2.amazonaws.com/books/candide/Chapter%201.mp3"
+        );
+        assert!(query.contains("X-Amz-Expires=3600"), "{}", query);
+        assert!(
+            query.contains("X-Amz-Credential=AKIDEXAMPLE%2F"),
+            "{}",
+            query
+        );
+        assert!(query.contains("X-Amz-Signature="), "{}", query);
+        assert_eq!(
+            urls.stable_id("Chapter 1.mp3"),
+            "https://candide.s3.ap-southeast-2.amazonaws.com/books/candide/Chapter%201.mp3"
+        );
+        assert!(url.starts_with(&format!("{}/", urls.base_url())));
+    }
+
+    #[test]
+    fn presigned_urls_support_path_style_endpoints() {
+        let presigner = presigner("http://localhost:9000/candide");
+        let url = presigner.presign(&ObjectKey::new("", "feed.xml"));
+        assert!(
+            url.starts_with("http://localhost:9000/candide/feed.xml?"),
+            "{}",
+            url
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use serde_json::{json, Value};
+
+/// The version of sloop that is running.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The commit sloop was built from, if it was built in a git checkout.
+pub const GIT_HASH: Option<&str> = option_env!("SLOOP_GIT_HASH");
+
+/// Optional cargo features sloop was built with. There are none yet.
+pub const FEATURES: &[&str] = &[];
+
+/// E.g. `1.1.0 (3f2a9c1)`, or just the version without a git hash.
+pub fn describe() -> String {
+    match GIT_HASH {
+        Some(hash) => format!("{} ({})", VERSION, hash),
+        None => VERSION.to_owned(),
+    }
+}
+
+/// What `sloop version --json` prints.
+pub fn to_json() -> Value {
+    json!({
+        "version": VERSION,
+        "git_hash": GIT_HASH,
+        "features": FEATURES,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_version_and_commit() {
+        assert!(describe().starts_with(VERSION));
+        assert_eq!(describe().contains('('), GIT_HASH.is_some());
+        let json = to_json();
+        assert_eq!(json["version"], VERSION);
+        assert_eq!(json["git_hash"], json!(GIT_HASH));
+        assert_eq!(json["features"], json!([]));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::log::{self, Log};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// The warnings of this run so far, kept only with `--strict`.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Keeps every warning from now on, so that `take` can fail the run with them once it's over.
+pub fn set_strict() {
+    STRICT.store(true, Ordering::SeqCst);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::SeqCst)
+}
+
+/// Prints a warning to stderr, or wherever `log::redirect` sends this thread's messages.
+pub fn warn(message: &str) {
+    log::message(&format!("Warning: {}", message));
+    record(message);
+}
+
+/// Sends a warning to `log` along with the progress lines around it, e.g. to a daemon client.
+pub fn warn_in(log: &Log, message: &str) {
+    log(&format!("Warning: {}", message));
+    record(message);
+}
+
+fn record(message: &str) {
+    if is_strict() {
+        WARNINGS.lock().unwrap().push(message.to_owned());
+    }
+}
+
+/// The warnings kept since `set_strict`, oldest first, leaving none.
+pub fn take() -> Vec<String> {
+    std::mem::take(&mut *WARNINGS.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn strict_runs_keep_their_warnings() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log: Log = {
+            let lines = lines.clone();
+            Arc::new(move |line| lines.lock().unwrap().push(line.to_owned()))
+        };
+        set_strict();
+        warn_in(&log, "the cover is too small");
+        assert_eq!(*lines.lock().unwrap(), ["Warning: the cover is too small"]);
+        assert!(take().contains(&"the cover is too small".to_owned()));
+        assert!(!take().contains(&"the cover is too small".to_owned()));
+    }
+}
@@ -0,0 +1,1343 @@
+//! Runs the sloop binary end to end on scratch directories of media files, checking the feeds it
+//! writes and what it prints, so that the wiring in `main.rs` is covered as well as the modules
+//! it joins up. Publishing runs as a dry run, which needs no bucket; `exit_codes` publishes to a
+//! real S3-compatible API when `SLOOP_TEST_ENDPOINT` is set.
+
+mod common;
+
+use common::{exit_code, stderr, stdout, Workspace};
+use roxmltree::{Document, Node};
+use std::fs;
+
+/// The options every feed here is written with: a fixed bucket, and a fixed start date so the
+/// publication dates don't move with the day the tests run.
+const FEED: &[&str] = &[
+    "feed",
+    "--title",
+    "Candide",
+    "--bucket",
+    "candide-a5e21f",
+    "--region",
+    "eu-west-1",
+    "--start-date",
+    "2024-03-01",
+];
+
+/// Runs `sloop feed` with `FEED`'s options followed by `args`.
+fn feed(workspace: &Workspace, args: &[&str]) -> std::process::Output {
+    workspace.sloop(&[FEED, args].concat())
+}
+
+fn child<'a>(node: Node<'a, 'a>, name: &str) -> Node<'a, 'a> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+        .unwrap_or_else(|| panic!("no <{}> in <{}>", name, node.tag_name().name()))
+}
+
+fn text<'a>(node: Node<'a, 'a>, name: &str) -> &'a str {
+    child(node, name).text().unwrap_or_default()
+}
+
+#[test]
+fn feed_writes_an_rss_item_per_file() {
+    let workspace = Workspace::new("cli-rss");
+    let chapter_2 = workspace.write("Chapter_2.mp3", "episode two, longer\n");
+    let output = feed(
+        &workspace,
+        &[
+            "--out",
+            "feed.xml",
+            &workspace.path("Chapter_1.mp3"),
+            &chapter_2,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let channel = child(doc.root_element(), "channel");
+    assert_eq!(text(channel, "title"), "Candide");
+    assert_eq!(
+        text(channel, "generator"),
+        format!("sloop {}", env!("CARGO_PKG_VERSION"))
+    );
+    assert_eq!(text(channel, "block"), "Yes");
+    let items: Vec<Node> = channel
+        .children()
+        .filter(|node| node.tag_name().name() == "item")
+        .collect();
+    let summary: Vec<_> = items
+        .iter()
+        .map(|&item| {
+            let enclosure = child(item, "enclosure");
+            (
+                text(item, "title"),
+                text(item, "pubDate"),
+                enclosure.attribute("url").unwrap(),
+                enclosure.attribute("length").unwrap(),
+                enclosure.attribute("type").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "Chapter 1",
+                "Fri, 01 Mar 2024 00:00:00 +0000",
+                "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/Chapter_1.mp3",
+                "12",
+                "audio/mpeg",
+            ),
+            (
+                "Chapter 2",
+                "Thu, 29 Feb 2024 00:00:00 +0000",
+                "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/Chapter_2.mp3",
+                "20",
+                "audio/mpeg",
+            ),
+        ]
+    );
+}
+
+#[test]
+fn feed_writes_every_format_asked_for() {
+    let workspace = Workspace::new("cli-formats");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = feed(
+        &workspace,
+        &["--out", "feed.xml", "--out", "feed.json", &episode],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert!(workspace
+        .read("feed.xml")
+        .contains("<title>Chapter 1</title>"));
+    let json: serde_json::Value = serde_json::from_str(&workspace.read("feed.json")).unwrap();
+    assert_eq!(json["title"], "Candide");
+    assert_eq!(json["items"][0]["title"], "Chapter 1");
+    assert_eq!(
+        json["items"][0]["date_published"],
+        "2024-03-01T00:00:00+00:00"
+    );
+}
+
+#[test]
+fn files_that_arent_audio_fail_naming_them_unless_skipped() {
+    let workspace = Workspace::new("cli-invalid");
+    let notes = workspace.write("chapter01", "not audio\n");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = feed(&workspace, &["--out", "feed.xml", &episode, &notes]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains(&format!(
+            "file '{}' has no extension; cannot determine MIME type",
+            notes
+        )),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!workspace.dir.join("feed.xml").exists());
+
+    let output = feed(
+        &workspace,
+        &["--out", "feed.xml", "--skip-invalid", &episode, &notes],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("Warning: file '"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(workspace.read("feed.xml").matches("<item>").count(), 1);
+}
+
+#[test]
+fn list_files_prints_the_feed_order_without_writing_it() {
+    let workspace = Workspace::new("cli-list");
+    let chapter_2 = workspace.write("Disc 2/Chapter_2.mp3", "episode two\n");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = feed(
+        &workspace,
+        &[
+            "--prefix",
+            "candide",
+            "--out",
+            "feed.xml",
+            "--list-files",
+            &chapter_2,
+            &episode,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "1\t{}\tcandide/Chapter_2.mp3\tChapter 2\n2\t{}\tcandide/Chapter_1.mp3\tChapter 1\n",
+            chapter_2, episode
+        )
+    );
+    assert!(!workspace.dir.join("feed.xml").exists());
+}
+
+#[test]
+fn dry_runs_upload_each_file_and_feed_once() {
+    let workspace = Workspace::new("cli-dry-run");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = feed(
+        &workspace,
+        &[
+            "--out",
+            "feed.xml",
+            "--out",
+            "feed.json",
+            "--upload",
+            "--dry-run",
+            &episode,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let printed = format!("{}{}", stdout(&output), stderr(&output));
+    // Each line names the file, then its size and type, e.g. `(12 B, audio/mpeg)`. The feeds'
+    // sizes vary with sloop's version, which they name.
+    let uploads: Vec<(&str, &str)> = printed
+        .lines()
+        .filter_map(|line| line.strip_prefix("Would upload "))
+        .map(|line| {
+            let (name, details) = line.split_once(" (").unwrap();
+            (name, details.rsplit(", ").next().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        uploads,
+        [
+            ("Chapter_1.mp3", "audio/mpeg)"),
+            ("feed.xml", "application/rss+xml)"),
+            ("feed.json", "application/feed+json)"),
+        ]
+    );
+    assert!(
+        printed.contains("Podcast would be available at https://candide-a5e21f.s3.eu-west-1"),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn strict_runs_fail_once_the_feed_is_written() {
+    let workspace = Workspace::new("cli-strict");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = workspace.sloop(&[&["--strict"], FEED, &["--out", "feed.xml", &episode]].concat());
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("Failing because of --strict, as the run warned:"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(stderr(&output).contains("couldn't read the metadata"));
+}
+
+#[test]
+fn natural_sort_puts_chapter_2_before_chapter_10() {
+    let workspace = Workspace::new("cli-sort");
+    let chapter_10 = workspace.write("Chapter_10.mp3", "episode ten\n");
+    let chapter_2 = workspace.write("chapter_2.mp3", "episode two\n");
+    let chapter_1 = workspace.path("Chapter_1.mp3");
+    let output = feed(
+        &workspace,
+        &[
+            "--sort",
+            "natural",
+            "--out",
+            "feed.xml",
+            "--list-files",
+            &chapter_10,
+            &chapter_1,
+            &chapter_2,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let titles: Vec<String> = stdout(&output)
+        .lines()
+        .map(|line| line.rsplit('\t').next().unwrap().to_owned())
+        .collect();
+    assert_eq!(titles, ["Chapter 1", "chapter 2", "Chapter 10"]);
+}
+
+#[test]
+fn directories_given_are_walked_for_audio_files() {
+    let workspace = Workspace::new("cli-dirs");
+    workspace.write("book/CD 2/Track 1.mp3", "episode three\n");
+    workspace.write("book/CD 1/Track 10.mp3", "episode two\n");
+    workspace.write("book/CD 1/Track 9.m4a", "episode one\n");
+    workspace.write("book/CD 1/cover.jpg", "not audio\n");
+    workspace.write("book/.DS_Store", "not audio\n");
+    let output = feed(
+        &workspace,
+        &["--out", "feed.xml", "--list-files", &workspace.path("book")],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains(&format!("Found 3 files in {}", workspace.path("book"))),
+        "{}",
+        stderr(&output)
+    );
+    let keys: Vec<String> = stdout(&output)
+        .lines()
+        .map(|line| line.split('\t').nth(2).unwrap().to_owned())
+        .collect();
+    assert_eq!(keys, ["Track 9.m4a", "Track 10.mp3", "Track 1.mp3"]);
+
+    let output = feed(
+        &workspace,
+        &[
+            "--include-ext",
+            "mp3",
+            "--out",
+            "feed.xml",
+            "--list-files",
+            &workspace.path("book"),
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(stdout(&output).lines().count(), 2);
+}
+
+/// An MP3 of a second or so of silent frames, with an ID3v2.3 tag titling it `title`.
+fn tagged_mp3(title: &str) -> Vec<u8> {
+    let mut frame = b"TIT2".to_vec();
+    frame.extend_from_slice(&(title.len() as u32 + 1).to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0]);
+    frame.extend_from_slice(title.as_bytes());
+    let mut file = b"ID3\x03\0\0\0\0\0".to_vec();
+    file.push(frame.len() as u8);
+    file.extend(frame);
+    // MPEG-1 layer III frames at 128 kbit/s and 44.1 kHz.
+    for _ in 0..40 {
+        file.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        file.extend_from_slice(&[0; 413]);
+    }
+    file
+}
+
+#[test]
+fn titles_can_come_from_id3_tags() {
+    let workspace = Workspace::new("cli-tags");
+    let tagged = workspace.write("01.mp3", tagged_mp3("How Candide Was Brought Up"));
+    let untagged = workspace.path("Chapter_1.mp3");
+    let output = feed(
+        &workspace,
+        &[
+            "--titles-from",
+            "tags",
+            "--out",
+            "feed.xml",
+            &tagged,
+            &untagged,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let titles: Vec<&str> = child(doc.root_element(), "channel")
+        .children()
+        .filter(|node| node.tag_name().name() == "item")
+        .map(|item| text(item, "title"))
+        .collect();
+    assert_eq!(titles, ["How Candide Was Brought Up", "Chapter 1"]);
+}
+
+#[test]
+fn help_shows_every_title_template_placeholder() {
+    let workspace = Workspace::new("cli-help");
+    let output = workspace.sloop(&["feed", "--help"]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    // clap wraps help at the terminal width, and would turn a bare {n} into a line break.
+    let help = stdout(&output)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    for described in [
+        "{title} is the title",
+        "{stem} the file name",
+        "{total} the number of episodes",
+        "{feed_title} the --title",
+        "and n, as in {n:02}, the episode's position",
+    ] {
+        assert!(help.contains(described), "{:?} in {}", described, help);
+    }
+}
+
+#[test]
+fn subcommands_are_described_by_their_own_docs() {
+    let workspace = Workspace::new("cli-help-subcommands");
+    let output = workspace.sloop(&["--help"]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let help = stdout(&output);
+    // Doc comments on flattened argument structs would replace the subcommand's own.
+    assert!(!help.contains("Where files are published"), "{}", help);
+    assert!(help.contains("Serve an HTTP API"), "{}", help);
+}
+
+#[test]
+fn manifests_title_order_and_date_episodes() {
+    let workspace = Workspace::new("cli-manifest");
+    workspace.write("Chapter_1.mp3", "episode one\n");
+    let chapter_2 = workspace.write("Chapter_2.mp3", "episode two\n");
+    workspace.write(
+        "episodes.toml",
+        r#"
+[channel]
+title = "Candide, Annotated"
+
+[[episode]]
+file = "Chapter_2.mp3"
+title = "How Candide Found Himself Among the Bulgarians"
+description = "Press-ganged & <flogged>."
+pub_date = 2024-03-02
+
+[[episode]]
+file = "Chapter_1.mp3"
+"#,
+    );
+    let args = [
+        "feed",
+        "--manifest",
+        "episodes.toml",
+        "--bucket",
+        "candide-a5e21f",
+        "--region",
+        "eu-west-1",
+        "--start-date",
+        "2024-03-01",
+        "--out",
+        "feed.xml",
+    ];
+    let output = workspace.sloop(&args);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let channel = child(doc.root_element(), "channel");
+    assert_eq!(text(channel, "title"), "Candide, Annotated");
+    let items: Vec<(&str, &str, &str)> = channel
+        .children()
+        .filter(|node| node.tag_name().name() == "item")
+        .map(|item| {
+            let description = item
+                .children()
+                .find(|node| node.tag_name().name() == "description")
+                .and_then(|node| node.text())
+                .unwrap_or_default();
+            (text(item, "title"), description, text(item, "pubDate"))
+        })
+        .collect();
+    assert_eq!(
+        items,
+        [
+            (
+                "How Candide Found Himself Among the Bulgarians",
+                "Press-ganged & <flogged>.",
+                "Sat, 02 Mar 2024 00:00:00 +0000"
+            ),
+            ("Chapter 1", "", "Thu, 29 Feb 2024 00:00:00 +0000"),
+        ]
+    );
+
+    workspace.write(
+        "episodes.toml",
+        "strict = true\n[channel]\ntitle = \"Candide\"\n[[episode]]\nfile = \"Chapter_1.mp3\"\n",
+    );
+    let output = workspace.sloop(&[&args[..], &[chapter_2.as_str()]].concat());
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("isn't in the manifest episodes.toml, which is strict"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn manifests_mix_hosted_episodes_with_uploaded_ones() {
+    let workspace = Workspace::new("cli-hosted");
+    workspace.write("Chapter_2.mp3", "episode two\n");
+    workspace.write(
+        "episodes.toml",
+        r#"
+[channel]
+title = "Candide"
+
+[[episode]]
+file = "Chapter_1.mp3"
+
+[[episode]]
+url = "https://archive.org/download/candide/Chapter_2.mp3"
+length = 4321
+title = "Chapter 2, from the archive"
+
+[[episode]]
+file = "Chapter_2.mp3"
+title = "Chapter 2, read again"
+"#,
+    );
+    let args = [
+        "feed",
+        "--manifest",
+        "episodes.toml",
+        "--bucket",
+        "candide-a5e21f",
+        "--region",
+        "eu-west-1",
+        "--out",
+        "feed.xml",
+        "--upload",
+        "--dry-run",
+        // One at a time, so the files are listed in order.
+        "--concurrency",
+        "1",
+    ];
+    let output = workspace.sloop(&args);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let printed = format!("{}{}", stdout(&output), stderr(&output));
+    let uploads: Vec<&str> = printed
+        .lines()
+        .filter_map(|line| line.strip_prefix("Would upload "))
+        .map(|line| line.split(" (").next().unwrap())
+        .collect();
+    assert_eq!(uploads, ["Chapter_1.mp3", "Chapter_2.mp3", "feed.xml"]);
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let enclosures: Vec<(&str, &str, &str)> = doc
+        .descendants()
+        .filter(|node| node.tag_name().name() == "item")
+        .map(|item| {
+            let enclosure = child(item, "enclosure");
+            (
+                text(item, "title"),
+                enclosure.attribute("url").unwrap(),
+                enclosure.attribute("length").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        enclosures,
+        [
+            (
+                "Chapter 1",
+                "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/Chapter_1.mp3",
+                "12"
+            ),
+            (
+                "Chapter 2, from the archive",
+                "https://archive.org/download/candide/Chapter_2.mp3",
+                "4321"
+            ),
+            (
+                "Chapter 2, read again",
+                "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/Chapter_2.mp3",
+                "12"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn embedded_art_is_published_for_its_episode() {
+    let workspace = Workspace::new("cli-art");
+    let art = std::fs::read("test_fixtures/art/Chapter_1.mp3").unwrap();
+    let with_art = workspace.write("Chapter_1.mp3", art);
+    let without = workspace.write("Chapter_2.mp3", tagged_mp3("Chapter Two"));
+    let output = feed(
+        &workspace,
+        &[
+            "--extract-art",
+            "--out",
+            "feed.xml",
+            "--upload",
+            "--dry-run",
+            // One at a time, so the files are listed in order.
+            "--concurrency",
+            "1",
+            &with_art,
+            &without,
+        ],
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let printed = format!("{}{}", stdout(&output), stderr(&output));
+    let uploads: Vec<&str> = printed
+        .lines()
+        .filter_map(|line| line.strip_prefix("Would upload "))
+        .map(|line| line.split(" (").next().unwrap())
+        .collect();
+    assert_eq!(
+        uploads,
+        [
+            "art/Chapter_1.mp3.jpg",
+            "Chapter_1.mp3",
+            "Chapter_2.mp3",
+            "feed.xml"
+        ]
+    );
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let images: Vec<Option<&str>> = doc
+        .descendants()
+        .filter(|node| node.tag_name().name() == "item")
+        .map(|item| {
+            item.children()
+                .find(|node| node.tag_name().name() == "image")
+                .and_then(|image| image.attribute("href"))
+        })
+        .collect();
+    assert_eq!(
+        images,
+        [
+            Some("https://candide-a5e21f.s3.eu-west-1.amazonaws.com/art/Chapter_1.mp3.jpg"),
+            None
+        ]
+    );
+}
+
+#[test]
+fn feeds_link_to_where_they_are_published() {
+    let workspace = Workspace::new("cli-self-link");
+    let episode = workspace.path("Chapter_1.mp3");
+    let self_link = |args: &[&str]| {
+        let output = feed(
+            &workspace,
+            &[args, &["--out", "feed.xml", &episode]].concat(),
+        );
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let xml = workspace.read("feed.xml");
+        let doc = Document::parse(&xml).unwrap();
+        let channel = child(doc.root_element(), "channel");
+        let link = channel
+            .children()
+            .find(|node| node.has_tag_name(("http://www.w3.org/2005/Atom", "link")))
+            .unwrap_or_else(|| panic!("no atom:link in {}", xml));
+        assert_eq!(link.attribute("rel"), Some("self"));
+        assert_eq!(link.attribute("type"), Some("application/rss+xml"));
+        link.attribute("href").unwrap().to_owned()
+    };
+
+    assert_eq!(
+        self_link(&[]),
+        "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/feed.xml"
+    );
+    assert_eq!(
+        self_link(&[
+            "--prefix",
+            "books/candide",
+            "--feed-key",
+            "Candide feed.xml"
+        ]),
+        "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/books/candide/Candide%20feed.xml"
+    );
+    assert_eq!(
+        self_link(&["--endpoint-url", "https://s3.eg.test/", "--path-style"]),
+        "https://s3.eg.test/candide-a5e21f/feed.xml"
+    );
+}
+
+#[test]
+fn providers_set_where_feeds_link_to() {
+    let workspace = Workspace::new("cli-provider");
+    let episode = workspace.path("Chapter_1.mp3");
+    let feed = ["feed", "--title", "Candide", "--bucket", "candide-a5e21f"];
+    let enclosure = |args: &[&str]| {
+        let output = workspace.sloop(&[&feed[..], args, &["--out", "feed.xml", &episode]].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let xml = workspace.read("feed.xml");
+        let doc = Document::parse(&xml).unwrap();
+        let item = child(child(doc.root_element(), "channel"), "item");
+        child(item, "enclosure")
+            .attribute("url")
+            .unwrap()
+            .to_owned()
+    };
+    assert_eq!(
+        enclosure(&[
+            "--provider",
+            "r2",
+            "--account-id",
+            "0a1b2c",
+            "--public-url",
+            "https://pub-8f3e.r2.dev"
+        ]),
+        "https://pub-8f3e.r2.dev/Chapter_1.mp3"
+    );
+    assert_eq!(
+        enclosure(&["--provider", "b2", "--region", "us-west-004"]),
+        "https://candide-a5e21f.s3.us-west-004.backblazeb2.com/Chapter_1.mp3"
+    );
+    assert_eq!(
+        enclosure(&["--provider", "spaces", "--region", "nyc3", "--path-style"]),
+        "https://nyc3.digitaloceanspaces.com/candide-a5e21f/Chapter_1.mp3"
+    );
+    assert_eq!(
+        enclosure(&[
+            "--provider",
+            "minio",
+            "--endpoint-url",
+            "http://nas.eg.test:9000"
+        ]),
+        "http://nas.eg.test:9000/candide-a5e21f/Chapter_1.mp3"
+    );
+
+    let fails = |args: &[&str], message: &str| {
+        let output = workspace.sloop(&[&feed[..], args, &["--out", "feed.xml", &episode]].concat());
+        assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    };
+    fails(
+        &[
+            "--provider",
+            "r2",
+            "--public-url",
+            "https://pub-8f3e.r2.dev",
+        ],
+        "--provider r2 needs --account-id",
+    );
+    fails(
+        &["--provider", "r2", "--account-id", "0a1b2c"],
+        "--provider r2 needs --public-url",
+    );
+    fails(
+        &["--provider", "minio"],
+        "--provider minio needs --endpoint-url",
+    );
+    fails(
+        &[
+            "--provider",
+            "spaces",
+            "--region",
+            "nyc3",
+            "--public-mode",
+            "bucket-policy",
+        ],
+        "DigitalOcean Spaces doesn't support --public-mode bucket-policy; use object-acl or none",
+    );
+    fails(
+        &["--region", "eu-west-1", "--account-id", "0a1b2c"],
+        "--account-id is only for --provider r2",
+    );
+    fails(
+        &["--provider", "wasabi", "--region", "eu-west-1"],
+        "'wasabi' isn't a valid value for '--provider",
+    );
+}
+
+/// Pins every format of a feed built on a fixed `--build-date`, with sloop's version, which the
+/// feeds name, left out. If the format changes on purpose, update the files to match.
+#[test]
+fn feeds_built_on_a_given_date_match_their_snapshots() {
+    let workspace = Workspace::new("cli-snapshot");
+    let episode = std::fs::read("test_fixtures/art/Chapter_1.mp3").unwrap();
+    let files = [
+        workspace.write("Chapter_1.mp3", &episode),
+        workspace.write("Chapter_2.mp3", &episode),
+    ];
+    let build = |out: &str| {
+        let args = [
+            "feed",
+            "--title",
+            "Candide",
+            "--bucket",
+            "candide-a5e21f",
+            "--region",
+            "eu-west-1",
+            "--build-date",
+            "2024-03-01T06:00:00Z",
+            "--timezone",
+            "+10:00",
+            "--out",
+            &format!("{}.xml", out),
+            "--out",
+            &format!("{}.json", out),
+        ];
+        let file_args: Vec<&str> = files.iter().map(String::as_str).collect();
+        let output = workspace.sloop(&[&args[..], &file_args].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        ["xml", "json"].map(|extension| {
+            workspace
+                .read(&format!("{}.{}", out, extension))
+                .replace(env!("CARGO_PKG_VERSION"), "{version}")
+        })
+    };
+    let first = build("first");
+    assert_eq!(build("second"), first);
+    let snapshot = |name| std::fs::read_to_string(format!("test_fixtures/snapshot/{}", name));
+    assert_eq!(first[0], snapshot("feed.xml").unwrap());
+    assert_eq!(first[1], snapshot("feed.json").unwrap());
+}
+
+#[test]
+fn max_items_keeps_the_newest_episodes_but_uploads_them_all() {
+    let workspace = Workspace::new("cli-max-items");
+    let paths: Vec<String> = (1..=3)
+        .map(|n| workspace.write(&format!("Chapter_{}.mp3", n), tagged_mp3("")))
+        .collect();
+    let chapters: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let titles = |workspace: &Workspace| -> Vec<String> {
+        let xml = workspace.read("feed.xml");
+        let doc = Document::parse(&xml).unwrap();
+        doc.descendants()
+            .filter(|node| node.tag_name().name() == "item")
+            .map(|item| text(item, "title").to_owned())
+            .collect()
+    };
+
+    // Oldest first, the newest episodes are the last files.
+    let options = [
+        "--order",
+        "oldest-first",
+        "--max-items",
+        "2",
+        "--out",
+        "feed.xml",
+        "--upload",
+        "--dry-run",
+        // One at a time, so the files are listed in order.
+        "--concurrency",
+        "1",
+    ];
+    let output = feed(&workspace, &[&options[..], &chapters].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(titles(&workspace), ["Chapter 2", "Chapter 3"]);
+    assert!(
+        stderr(&output).contains("Trimmed the 1 oldest item from the feed, keeping the newest 2"),
+        "{}",
+        stderr(&output)
+    );
+    let printed = format!("{}{}", stdout(&output), stderr(&output));
+    let uploads: Vec<&str> = printed
+        .lines()
+        .filter_map(|line| line.strip_prefix("Would upload "))
+        .map(|line| line.split(" (").next().unwrap())
+        .collect();
+    assert_eq!(
+        uploads,
+        [
+            "Chapter_1.mp3",
+            "Chapter_2.mp3",
+            "Chapter_3.mp3",
+            "feed.xml"
+        ]
+    );
+
+    // Appending drops the oldest items already published to make room.
+    let output = feed(
+        &workspace,
+        &[&["--out", "feed.xml"], &chapters[..2]].concat(),
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(titles(&workspace), ["Chapter 1", "Chapter 2"]);
+    let options = ["--append", "--max-items", "2", "--out", "feed.xml"];
+    let output = feed(&workspace, &[&options[..], &chapters].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(titles(&workspace), ["Chapter 1", "Chapter 3"]);
+
+    let output = feed(&workspace, &["--max-items", "0", "--out", "feed.xml"]);
+    assert!(
+        stderr(&output).contains("0 is not a number of items"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn intervals_too_long_to_date_episodes_by_are_refused() {
+    let workspace = Workspace::new("cli-interval-overflow");
+    let chapter_2 = workspace.write("Chapter_2.mp3", "episode two\n");
+    let fails = |interval: &str, message: &str| {
+        let output = feed(
+            &workspace,
+            &[
+                "--interval",
+                interval,
+                "--out",
+                "feed.xml",
+                &workspace.path("Chapter_1.mp3"),
+                &chapter_2,
+            ],
+        );
+        assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+        assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+    };
+    fails(
+        "99999999999999999w",
+        "Invalid value for '--interval <interval>': 99999999999999999w is too long a duration",
+    );
+    fails(
+        "20000000000w",
+        "20000000000w is too long an interval to date episodes by",
+    );
+    fails(
+        "9999999999w",
+        "--interval 1679999999832h 0m is too long to date 2 episodes that far apart",
+    );
+}
+
+#[test]
+fn base_urls_write_feeds_without_a_bucket() {
+    let workspace = Workspace::new("cli-base-url");
+    let episode = workspace.path("Chapter_1.mp3");
+    let links = |args: &[&str]| {
+        let options = ["feed", "--title", "Candide", "--out", "feed.xml"];
+        let output = workspace.sloop(&[&options[..], args, &[&episode]].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let xml = workspace.read("feed.xml");
+        let doc = Document::parse(&xml).unwrap();
+        let channel = child(doc.root_element(), "channel");
+        let self_link = channel
+            .children()
+            .find(|node| node.has_tag_name(("http://www.w3.org/2005/Atom", "link")))
+            .unwrap_or_else(|| panic!("no atom:link in {}", xml));
+        let enclosure = child(child(channel, "item"), "enclosure");
+        (
+            self_link.attribute("href").unwrap().to_owned(),
+            enclosure.attribute("url").unwrap().to_owned(),
+        )
+    };
+
+    assert_eq!(
+        links(&["--base-url", "https://eg.test/podcast/"]),
+        (
+            "https://eg.test/podcast/feed.xml".to_owned(),
+            "https://eg.test/podcast/Chapter_1.mp3".to_owned()
+        )
+    );
+    // A bucket given as well, e.g. by a profile, is left alone.
+    assert_eq!(
+        links(&[
+            "--base-url",
+            "https://eg.test",
+            "--bucket",
+            "candide-a5e21f",
+            "--region",
+            "eu-west-1",
+        ]),
+        (
+            "https://eg.test/feed.xml".to_owned(),
+            "https://eg.test/Chapter_1.mp3".to_owned()
+        )
+    );
+    assert_eq!(
+        links(&["--bucket", "candide-a5e21f", "--region", "eu-west-1"]).1,
+        "https://candide-a5e21f.s3.eu-west-1.amazonaws.com/Chapter_1.mp3"
+    );
+}
+
+#[test]
+fn feeds_need_a_bucket_or_a_base_url_to_link_to() {
+    let workspace = Workspace::new("cli-base-url-invalid");
+    let episode = workspace.path("Chapter_1.mp3");
+    let fails = |args: &[&str], message: &str| {
+        let options = ["feed", "--title", "Candide", "--out", "feed.xml"];
+        let output = workspace.sloop(&[&options[..], args, &[&episode]].concat());
+        assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    };
+
+    fails(&[], "Pass --bucket and --region");
+    fails(&["--bucket", "candide-a5e21f"], "--region is required");
+    fails(&["--region", "eu-west-1"], "--bucket is required");
+    fails(
+        &["--base-url", "eg.test/podcast"],
+        "eg.test/podcast is not a valid URL",
+    );
+    fails(
+        &["--bucket", "candide-a5e21f", "--upload", "--dry-run"],
+        "--region is required",
+    );
+    for publishing in &[&["--upload", "--dry-run"][..], &["--plan"]] {
+        fails(
+            &[&["--base-url", "https://eg.test"], *publishing].concat(),
+            "--base-url writes the feed without a bucket",
+        );
+    }
+    fails(
+        &["--base-url", "https://eg.test", "--private"],
+        "--private links to the bucket with presigned URLs",
+    );
+    // --from-bucket takes no files.
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        "feed.xml",
+        "--base-url",
+        "https://eg.test",
+        "--from-bucket",
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("--from-bucket lists the bucket"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!std::path::Path::new(&workspace.path("feed.xml")).exists());
+}
+
+#[test]
+fn dry_runs_say_which_feeds_cloudfront_would_invalidate() {
+    let workspace = Workspace::new("cli-cloudfront");
+    let image = workspace.write("cover.jpg", "jpeg");
+    let options = [
+        "--out",
+        "feed.xml",
+        "--image",
+        &image,
+        "--prefix",
+        "books/candide",
+        "--public-url",
+        "https://cdn.eg.test/audio",
+        "--cloudfront-distribution-id",
+        "E2QWRUHAPOMQZL",
+        "--upload",
+        "--dry-run",
+    ];
+    let output = feed(
+        &workspace,
+        &[&options[..], &[&workspace.path("Chapter_1.mp3")]].concat(),
+    );
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let printed = format!("{}{}", stdout(&output), stderr(&output));
+    assert!(
+        printed.contains(
+            "Would invalidate /audio/books/candide/cover.jpg, /audio/books/candide/feed.xml in \
+             CloudFront distribution E2QWRUHAPOMQZL"
+        ),
+        "{}",
+        printed
+    );
+
+    let output = feed(&workspace, &["--out", "feed.xml", "--no-wait"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+}
+
+#[test]
+fn local_backends_copy_the_files_for_a_web_server() {
+    let workspace = Workspace::new("cli-local-backend");
+    let episode = workspace.path("Chapter_1.mp3");
+    let www = workspace.path("www");
+    let options = [
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        "feed.xml",
+        "--backend",
+        "local",
+        "--output-dir",
+        &www,
+        "--public-url",
+        "https://eg.test/podcasts",
+        "--prefix",
+        "candide",
+    ];
+    let output = workspace.sloop(&[&options[..], &["--upload", &episode]].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("Podcast available at https://eg.test/podcasts/candide/feed.xml"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(workspace.read("www/candide/Chapter_1.mp3"), "episode one\n");
+    let xml = workspace.read("www/candide/feed.xml");
+    assert_eq!(xml, workspace.read("feed.xml"));
+    let doc = Document::parse(&xml).unwrap();
+    let item = child(child(doc.root_element(), "channel"), "item");
+    assert_eq!(
+        child(item, "enclosure").attribute("url"),
+        Some("https://eg.test/podcasts/candide/Chapter_1.mp3")
+    );
+
+    // Nothing changed, so nothing is copied.
+    let output = workspace.sloop(&[&options[..], &["--upload", &episode]].concat());
+    assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+
+    let fails = |args: &[&str], message: &str| {
+        let output = workspace.sloop(&[&options[..5], args, &["--upload", &episode]].concat());
+        assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    };
+    fails(
+        &["--backend", "local"],
+        "--backend local needs --output-dir",
+    );
+    fails(
+        &["--backend", "local", "--output-dir", &www],
+        "--backend local needs --public-url",
+    );
+    fails(
+        &["--output-dir", &www],
+        "--output-dir is only for --backend local",
+    );
+    fails(
+        &["--backend", "ftp"],
+        "'ftp' isn't a valid value for '--backend",
+    );
+}
+
+#[test]
+fn upload_copies_files_with_the_local_backend() {
+    let workspace = Workspace::new("cli-upload-local");
+    let episode = workspace.path("Chapter_1.mp3");
+    let www = workspace.path("www");
+    let options = [
+        "upload",
+        "--backend",
+        "local",
+        "--output-dir",
+        &www,
+        "--public-url",
+        "https://eg.test/podcasts",
+        "--prefix",
+        "candide",
+    ];
+    let output = workspace.sloop(&[&options[..], &[&episode]].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(workspace.read("www/candide/Chapter_1.mp3"), "episode one\n");
+    let output = workspace.sloop(&[&options[..], &[&episode]].concat());
+    assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+
+    let output = workspace.sloop(&[
+        "upload",
+        "--backend",
+        "local",
+        "--output-dir",
+        &www,
+        &episode,
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("--backend local needs --public-url"),
+        "{}",
+        stderr(&output)
+    );
+    let output = workspace.sloop(&["upload", "--remote-dir", "www", &episode]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("--remote-dir is only for --backend sftp"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn seasons_from_dirs_publish_repeated_file_names_in_their_own_folders() {
+    let workspace = Workspace::new("cli-seasons");
+    let www = workspace.path("www");
+    let paths = [
+        workspace.write("Book/Disc 1/track.mp3", "disc one\n"),
+        workspace.write("Book/Disc 2/track.mp3", "disc two\n"),
+    ];
+    let discs: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let options = [
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        "feed.xml",
+        "--seasons-from-dirs",
+        "--backend",
+        "local",
+        "--output-dir",
+        &www,
+        "--public-url",
+        "https://eg.test",
+        "--upload",
+    ];
+    let output = workspace.sloop(&[&options[..], &discs].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let urls: Vec<&str> = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("enclosure"))
+        .map(|enclosure| enclosure.attribute("url").unwrap())
+        .collect();
+    assert_eq!(
+        urls,
+        [
+            "https://eg.test/Disc%201/track.mp3",
+            "https://eg.test/Disc%202/track.mp3"
+        ]
+    );
+    assert_eq!(workspace.read("www/Disc 1/track.mp3"), "disc one\n");
+    assert_eq!(workspace.read("www/Disc 2/track.mp3"), "disc two\n");
+
+    // Without seasons they'd be published under the same key.
+    let options = [
+        "feed",
+        "--title",
+        "Candide",
+        "--base-url",
+        "https://eg.test/",
+        "--out",
+        "feed.xml",
+    ];
+    let output = workspace.sloop(&[&options[..], &discs].concat());
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("would both be published as track.mp3"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn appending_points_items_for_renamed_files_at_their_new_names() {
+    let workspace = Workspace::new("cli-rename");
+    let www = workspace.path("www");
+    let first = workspace.path("Chapter_1.mp3");
+    workspace.write("Chapter_2.mp3", "episode two\n");
+    let options = [
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        "feed.xml",
+        "--backend",
+        "local",
+        "--output-dir",
+        &www,
+        "--public-url",
+        "https://eg.test",
+        "--append",
+        "--upload",
+    ];
+    let publish = |args: &[&str]| {
+        let output = workspace.sloop(&[&options[..], args].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let xml = workspace.read("feed.xml");
+        let doc = Document::parse(&xml).unwrap();
+        let items: Vec<(String, String, String)> = child(doc.root_element(), "channel")
+            .children()
+            .filter(|node| node.has_tag_name("item"))
+            .map(|item| {
+                let text = |name| child(item, name).text().unwrap_or_default().to_owned();
+                let url = child(item, "enclosure")
+                    .attribute("url")
+                    .unwrap_or_default();
+                (text("title"), url.to_owned(), text("guid"))
+            })
+            .collect();
+        (items, stderr(&output))
+    };
+    let (published, _) = publish(&[&first, &workspace.path("Chapter_2.mp3")]);
+
+    fs::rename(
+        workspace.path("Chapter_2.mp3"),
+        workspace.path("Chapter_02.mp3"),
+    )
+    .unwrap();
+    let renamed = workspace.path("Chapter_02.mp3");
+    let (items, printed) = publish(&[&first, &renamed]);
+    assert_eq!(
+        items,
+        [
+            published[0].clone(),
+            (
+                "Chapter 02".to_owned(),
+                "https://eg.test/Chapter_02.mp3".to_owned(),
+                "https://eg.test/Chapter_2.mp3".to_owned()
+            )
+        ]
+    );
+    assert!(
+        printed.contains(
+            "Pointing the item for https://eg.test/Chapter_2.mp3 at its new name, \
+             https://eg.test/Chapter_02.mp3"
+        ),
+        "{}",
+        printed
+    );
+
+    // A file with other contents is a new episode, whatever its length.
+    workspace.write("Chapter_3.mp3", "episode six\n");
+    let third = workspace.path("Chapter_3.mp3");
+    let (items, _) = publish(&[&first, &renamed, &third]);
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[2].1, "https://eg.test/Chapter_3.mp3");
+
+    fs::rename(&third, workspace.path("Chapter_03.mp3")).unwrap();
+    let third = workspace.path("Chapter_03.mp3");
+    let (items, _) = publish(&["--no-rename-detection", &first, &renamed, &third]);
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[3].1, "https://eg.test/Chapter_03.mp3");
+}
+
+#[test]
+fn sftp_backends_link_to_the_public_url_of_the_remote_dir() {
+    let workspace = Workspace::new("cli-sftp-backend");
+    let episode = workspace.path("Chapter_1.mp3");
+    let options = [
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        "feed.xml",
+        "--backend",
+        "sftp",
+        "--host",
+        "eg.test",
+        "--remote-dir",
+        "public_html/podcasts",
+        "--public-url",
+        "https://eg.test/podcasts",
+        "--prefix",
+        "candide",
+    ];
+    // Writing the feed alone doesn't connect to the host.
+    let output = workspace.sloop(&[&options[..], &[&episode]].concat());
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let xml = workspace.read("feed.xml");
+    let doc = Document::parse(&xml).unwrap();
+    let item = child(child(doc.root_element(), "channel"), "item");
+    assert_eq!(
+        child(item, "enclosure").attribute("url"),
+        Some("https://eg.test/podcasts/candide/Chapter_1.mp3")
+    );
+
+    let fails = |args: &[&str], message: &str| {
+        let output = workspace.sloop(&[&options[..5], args, &["--upload", &episode]].concat());
+        assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    };
+    fails(&["--backend", "sftp"], "--backend sftp needs --host");
+    fails(
+        &["--backend", "sftp", "--host", "eg.test"],
+        "--backend sftp needs --remote-dir",
+    );
+    fails(
+        &[
+            "--backend",
+            "sftp",
+            "--host",
+            "eg.test",
+            "--remote-dir",
+            "www",
+        ],
+        "--backend sftp needs --public-url, the URL --remote-dir is served at",
+    );
+    fails(
+        &["--backend", "local", "--host", "eg.test"],
+        "--host is only for --backend sftp",
+    );
+    fails(
+        &[
+            "--remote-dir",
+            "www",
+            "--bucket",
+            "b",
+            "--region",
+            "eu-west-1",
+        ],
+        "--remote-dir is only for --backend sftp",
+    );
+    fails(
+        &[&options[5..], &["--base-url", "https://eg.test"]].concat(),
+        "--backend sftp links to --public-url, so can't be used with --base-url",
+    );
+}
@@ -0,0 +1,86 @@
+//! The harness the CLI tests share: a scratch directory of media files to run the sloop binary
+//! in, and readers for what it printed.
+
+// Each test crate compiles this module on its own and uses only some of it.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+/// A scratch directory holding one small episode, `Chapter_1.mp3`, removed when dropped.
+pub struct Workspace {
+    pub dir: std::path::PathBuf,
+}
+
+impl Workspace {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("sloop-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let workspace = Self { dir };
+        workspace.write("Chapter_1.mp3", "episode one\n");
+        workspace
+    }
+
+    /// Writes the file `name`, dated an hour ago so sloop doesn't take it for a file still
+    /// being written, returning its path.
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(an_hour_ago))
+            .unwrap();
+        path.display().to_string()
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.dir.join(name)).unwrap()
+    }
+
+    /// Runs sloop with its state kept in the workspace. Credentials come from
+    /// `SLOOP_TEST_ACCESS_KEY` and `SLOOP_TEST_SECRET_KEY`, defaulting to dummy values.
+    pub fn sloop(&self, args: &[&str]) -> Output {
+        let access_key = env::var("SLOOP_TEST_ACCESS_KEY").unwrap_or_else(|_| "test".to_owned());
+        let secret_key = env::var("SLOOP_TEST_SECRET_KEY").unwrap_or_else(|_| "test".to_owned());
+        Command::new(env!("CARGO_BIN_EXE_sloop"))
+            .arg("--state-dir")
+            .arg(self.dir.join("state"))
+            .args(args)
+            .current_dir(&self.dir)
+            .env("AWS_ACCESS_KEY_ID", access_key)
+            .env("AWS_SECRET_ACCESS_KEY", secret_key)
+            .env_remove("AWS_ENDPOINT_URL")
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn exit_code(output: &Output) -> i32 {
+    output.status.code().expect("sloop was killed by a signal")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,723 @@
+//! Runs the sloop binary to each outcome and checks its exit code. See `Outcome` for the scheme.
+//!
+//! The tests in `integration` publish to a real S3-compatible API and only run when
+//! `SLOOP_TEST_ENDPOINT` is set, as for the upload integration tests. Those in
+//! `sftp_integration` publish to a real SFTP server and only run when `SLOOP_TEST_SFTP_HOST` is
+//! set.
+
+mod common;
+
+use common::{exit_code, stderr, Workspace};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// An endpoint nothing listens on, so any request fails straight away.
+const UNREACHABLE_ENDPOINT: &str = "http://127.0.0.1:9";
+
+#[test]
+fn invalid_arguments_exit_4() {
+    let workspace = Workspace::new("arguments");
+    let output = workspace.sloop(&["feed", "--out", "feed.xml"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+}
+
+#[test]
+fn unknown_category_exits_4_suggesting_one() {
+    let workspace = Workspace::new("category");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--category",
+        "Arts:Book",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--out",
+        &workspace.path("feed.xml"),
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(stderr(&output).contains("did you mean Books?"));
+}
+
+#[test]
+fn missing_media_file_exits_4() {
+    let workspace = Workspace::new("missing");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &workspace.path("feed.xml"),
+        "--upload",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--endpoint-url",
+        UNREACHABLE_ENDPOINT,
+        &workspace.path("missing.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+}
+
+#[test]
+fn feed_failing_self_check_exits_4() {
+    let workspace = Workspace::new("self-check");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &workspace.path("feed.xml"),
+        "--upload",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--endpoint-url",
+        UNREACHABLE_ENDPOINT,
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(stderr(&output).contains("Refusing to upload feed"));
+}
+
+#[test]
+fn upload_of_missing_file_exits_4() {
+    let workspace = Workspace::new("upload-missing");
+    let output = workspace.sloop(&[
+        "upload",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--endpoint-url",
+        UNREACHABLE_ENDPOINT,
+        &workspace.path("missing.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+}
+
+#[test]
+fn unreachable_endpoint_exits_1() {
+    let workspace = Workspace::new("unreachable");
+    let output = workspace.sloop(&[
+        "upload",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--endpoint-url",
+        UNREACHABLE_ENDPOINT,
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 1, "{}", stderr(&output));
+}
+
+#[test]
+fn unreachable_sftp_host_exits_1() {
+    let workspace = Workspace::new("unreachable-sftp");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--backend",
+        "sftp",
+        "--host",
+        "127.0.0.1",
+        "--port",
+        "9",
+        "--remote-dir",
+        "podcasts",
+        "--public-url",
+        "https://eg.test/podcasts",
+        "--upload",
+        "--out",
+        &workspace.path("feed.xml"),
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 1, "{}", stderr(&output));
+}
+
+#[test]
+fn stats_without_a_source_exits_4() {
+    let workspace = Workspace::new("stats");
+    let output = workspace.sloop(&[
+        "stats",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--endpoint-url",
+        UNREACHABLE_ENDPOINT,
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(stderr(&output).contains("--from-logs"));
+}
+
+#[test]
+fn writing_a_feed_without_uploading_exits_0() {
+    let workspace = Workspace::new("write");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &workspace.path("feed.xml"),
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert!(Path::new(&workspace.path("feed.xml")).exists());
+}
+
+#[test]
+fn listing_files_alone_exits_0_without_writing_the_feed() {
+    let workspace = Workspace::new("list");
+    let episode = workspace.path("Chapter_1.mp3");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &workspace.path("feed.xml"),
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        "--prefix",
+        "candide",
+        "--list-files",
+        &episode,
+    ]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("1\t{}\tcandide/Chapter_1.mp3\tChapter 1\n", episode)
+    );
+    assert!(!Path::new(&workspace.path("feed.xml")).exists());
+}
+
+#[test]
+fn files_still_being_written_are_skipped() {
+    let workspace = Workspace::new("settle");
+    let episode = workspace.path("Chapter_1.mp3");
+    let fresh = workspace.path("Chapter_2.mp3");
+    fs::write(&fresh, "episode two\n").unwrap();
+    let list = |extra: &[&str]| {
+        let mut args = vec![
+            "feed",
+            "--title",
+            "Candide",
+            "--out",
+            "feed.xml",
+            "--bucket",
+            "bucket1",
+            "--region",
+            "eu-west-1",
+            "--list-files",
+            &episode,
+            &fresh,
+        ];
+        args.extend_from_slice(extra);
+        workspace.sloop(&args)
+    };
+    let output = list(&[]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+    assert!(
+        stderr(&output).contains(&format!("Warning: skipping {}: it was modified", fresh)),
+        "{}",
+        stderr(&output)
+    );
+    for extra in [&["--settle-time", "0"][..], &["--include-unsettled"]] {
+        let output = list(extra);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 2);
+    }
+}
+
+#[test]
+fn warnings_exit_4_with_strict() {
+    let workspace = Workspace::new("strict");
+    let fresh = workspace.path("Chapter_2.mp3");
+    fs::write(&fresh, "episode two\n").unwrap();
+    let feed = workspace.path("feed.xml");
+    let write = |extra: &[&str]| {
+        let mut args = vec![
+            "feed",
+            "--title",
+            "Candide",
+            "--out",
+            &feed,
+            "--bucket",
+            "bucket1",
+            "--region",
+            "eu-west-1",
+        ];
+        args.extend_from_slice(extra);
+        args.push(&fresh);
+        workspace.sloop(&args)
+    };
+    let output = write(&[]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let output = write(&["--strict"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains(&format!(
+            "Failing because of --strict, as the run warned:\n  skipping {}",
+            fresh
+        )),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn failing_pre_generate_hooks_exit_4_before_writing_the_feed() {
+    let workspace = Workspace::new("hooks");
+    let feed = workspace.path("feed.xml");
+    let written = workspace.path("written");
+    let write = |hook: &str| {
+        workspace.sloop(&[
+            "feed",
+            "--title",
+            "Candide",
+            "--out",
+            &feed,
+            "--bucket",
+            "bucket1",
+            "--region",
+            "eu-west-1",
+            "--hook",
+            hook,
+            "--hook",
+            &format!("post_generate=echo \"$SLOOP_FEED_URL\" > {}; echo", written),
+            &workspace.path("Chapter_1.mp3"),
+        ])
+    };
+    let output = write("pre_generate=exit 2");
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(stderr(&output).contains("Stopping, as the pre_generate hook \"exit 2\" failed"));
+    assert!(!Path::new(&feed).exists());
+    assert!(!Path::new(&written).exists());
+
+    let output = write("pre_generate=true");
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", feed)
+    );
+    assert_eq!(
+        fs::read_to_string(&written).unwrap(),
+        "https://bucket1.s3.eu-west-1.amazonaws.com/feed.xml\n"
+    );
+}
+
+#[test]
+fn feeds_are_published_as_feed_xml_whatever_the_local_name() {
+    let workspace = Workspace::new("feed-key");
+    let out = workspace.path("build/feed-v2.xml");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &out,
+        "--dry-run",
+        "--quiet",
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    let feed_url = "https://bucket1.s3.eu-west-1.amazonaws.com/feed.xml";
+    assert!(stderr(&output).contains(feed_url), "{}", stderr(&output));
+    let feed = fs::read_to_string(&out).unwrap();
+    assert!(
+        feed.contains(&format!("<atom:link href=\"{}\"", feed_url)),
+        "{}",
+        feed
+    );
+}
+
+#[test]
+fn feeds_sharing_a_key_exit_4() {
+    let workspace = Workspace::new("feed-key-clash");
+    let output = workspace.sloop(&[
+        "feed",
+        "--title",
+        "Candide",
+        "--out",
+        &workspace.path("a.xml"),
+        "--out",
+        &workspace.path("b.xml"),
+        "--bucket",
+        "bucket1",
+        "--region",
+        "eu-west-1",
+        &workspace.path("Chapter_1.mp3"),
+    ]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(stderr(&output).contains("--feed-key"));
+}
+
+/// Two podcasts, in the config file `config.toml` of `workspace`, which is returned.
+fn write_profiles(workspace: &Workspace) -> String {
+    let config = workspace.path("config.toml");
+    fs::write(
+        &config,
+        "[podcasts.dune]\n\
+         title = \"Dune\"\n\
+         bucket = \"books\"\n\
+         region = \"eu-west-1\"\n\
+         prefix = \"dune\"\n\
+         out = [\"feed.xml\"]\n\
+         \n\
+         [podcasts.lectures]\n\
+         title = \"Lectures\"\n\
+         bucket = \"lectures\"\n",
+    )
+    .unwrap();
+    config
+}
+
+#[test]
+fn podcast_profile_supplies_options_the_command_line_overrides() {
+    let workspace = Workspace::new("profile");
+    let config = write_profiles(&workspace);
+    let episode = workspace.path("Chapter_1.mp3");
+    let list = |extra: &[&str]| {
+        let mut args = vec![
+            "--config",
+            &config,
+            "feed",
+            "--podcast",
+            "dune",
+            "--list-files",
+        ];
+        args.extend_from_slice(extra);
+        args.push(&episode);
+        workspace.sloop(&args)
+    };
+    let output = list(&[]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("1\t{}\tdune/Chapter_1.mp3\tChapter 1\n", episode)
+    );
+    let output = list(&["--prefix", "messiah"]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("1\t{}\tmessiah/Chapter_1.mp3\tChapter 1\n", episode)
+    );
+}
+
+#[test]
+fn unknown_podcast_exits_4_listing_the_podcasts() {
+    let workspace = Workspace::new("profile-unknown");
+    let config = write_profiles(&workspace);
+    let output = workspace.sloop(&["--config", &config, "--podcast", "dnue", "feed"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("dune, lectures"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn unresolvable_keyring_values_exit_4_naming_the_entry() {
+    let workspace = Workspace::new("keyring");
+    let config = workspace.path("config.toml");
+    fs::write(
+        &config,
+        "[podcasts.dune]\nbucket = \"keyring:sloop-exit-test/no-such-secret\"\n",
+    )
+    .unwrap();
+    let output = workspace.sloop(&["--config", &config, "--podcast", "dune", "list"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("secret sloop-exit-test/no-such-secret"),
+        "{}",
+        stderr(&output)
+    );
+    let output = workspace.sloop(&["secret", "get", "no-such-secret"]);
+    assert_eq!(exit_code(&output), 4, "{}", stderr(&output));
+}
+
+#[test]
+fn list_podcasts_prints_each_profile() {
+    let workspace = Workspace::new("list-podcasts");
+    let config = write_profiles(&workspace);
+    let output = workspace.sloop(&["--config", &config, "list-podcasts"]);
+    assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "dune\tbooks/dune\tDune\nlectures\tlectures\tLectures\n"
+    );
+}
+
+mod integration {
+    use super::*;
+    use rusoto_core::credential::StaticProvider;
+    use rusoto_core::{HttpClient, Region};
+    use rusoto_s3::{DeleteBucketRequest, DeleteObjectRequest, ListObjectsV2Request, S3Client, S3};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A uniquely named bucket on the test endpoint, which sloop creates and which is deleted
+    /// along with its objects when dropped.
+    struct TestBucket {
+        name: String,
+        endpoint: String,
+        region: String,
+    }
+
+    impl TestBucket {
+        fn new() -> Option<Self> {
+            let endpoint = env::var("SLOOP_TEST_ENDPOINT").ok()?;
+            let region = env::var("SLOOP_TEST_REGION").unwrap_or_else(|_| "eu-west-1".to_owned());
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos();
+            let name = format!("sloop-exit-{}-{}", std::process::id(), nanos);
+            Some(Self {
+                name,
+                endpoint,
+                region,
+            })
+        }
+    }
+
+    impl Drop for TestBucket {
+        fn drop(&mut self) {
+            let access_key =
+                env::var("SLOOP_TEST_ACCESS_KEY").unwrap_or_else(|_| "test".to_owned());
+            let secret_key =
+                env::var("SLOOP_TEST_SECRET_KEY").unwrap_or_else(|_| "test".to_owned());
+            let client = S3Client::new_with(
+                HttpClient::new().unwrap(),
+                StaticProvider::new_minimal(access_key, secret_key),
+                Region::Custom {
+                    name: self.region.clone(),
+                    endpoint: self.endpoint.clone(),
+                },
+            );
+            let bucket = self.name.clone();
+            let listing = client
+                .list_objects_v2(ListObjectsV2Request {
+                    bucket: bucket.clone(),
+                    ..Default::default()
+                })
+                .sync();
+            if let Ok(listing) = listing {
+                for object in listing.contents.unwrap_or_default() {
+                    let _ = client
+                        .delete_object(DeleteObjectRequest {
+                            bucket: bucket.clone(),
+                            key: object.key.unwrap_or_default(),
+                            ..Default::default()
+                        })
+                        .sync();
+                }
+            }
+            let _ = client.delete_bucket(DeleteBucketRequest { bucket }).sync();
+        }
+    }
+
+    #[test]
+    fn publishing_exits_0_then_3_once_nothing_has_changed() {
+        let bucket = match TestBucket::new() {
+            Some(bucket) => bucket,
+            None => return,
+        };
+        let workspace = Workspace::new("publish");
+        let out = workspace.path("feed.xml");
+        let episode = workspace.path("Chapter_1.mp3");
+        let publish = |action: &str| {
+            workspace.sloop(&[
+                "feed",
+                "--title",
+                "Candide",
+                "--out",
+                &out,
+                "--bucket",
+                &bucket.name,
+                "--region",
+                &bucket.region,
+                "--endpoint-url",
+                &bucket.endpoint,
+                "--quiet",
+                action,
+                &episode,
+            ])
+        };
+        let output = publish("--plan");
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let output = publish("--upload");
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let output = publish("--upload");
+        assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+        let output = publish("--plan");
+        assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+    }
+
+    #[test]
+    fn feeds_from_the_bucket_exit_0_then_3_once_nothing_has_changed() {
+        let bucket = match TestBucket::new() {
+            Some(bucket) => bucket,
+            None => return,
+        };
+        let workspace = Workspace::new("from-bucket");
+        let s3 = [
+            "--bucket",
+            &bucket.name,
+            "--region",
+            &bucket.region,
+            "--endpoint-url",
+            &bucket.endpoint,
+            "--prefix",
+            "candide",
+            "--quiet",
+        ];
+        let episode = workspace.path("Chapter_1.mp3");
+        let output = workspace.sloop(&[&["upload"], &s3[..], &[&episode]].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let out = workspace.path("feed.xml");
+        let feed = ["feed", "--title", "Candide", "--from-bucket", "--out", &out];
+        let output = workspace.sloop(&[&feed[..], &s3, &["--upload"]].concat());
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let xml = fs::read_to_string(&out).unwrap();
+        assert!(
+            xml.contains("/candide/Chapter_1.mp3\" length=\"12\""),
+            "{}",
+            xml
+        );
+        let output = workspace.sloop(&[&feed[..], &s3, &["--upload"]].concat());
+        assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+    }
+}
+
+mod sftp_integration {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A uniquely named directory on the test SFTP server, which sloop creates and which is
+    /// deleted along with its files when dropped. The server is reached as `ssh` would reach
+    /// `SLOOP_TEST_SFTP_HOST`, on `SLOOP_TEST_SFTP_PORT` as `SLOOP_TEST_SFTP_USER` if given,
+    /// under `SLOOP_TEST_SFTP_DIR`, which defaults to `upload`.
+    struct TestDir {
+        host: String,
+        port: Option<String>,
+        user: Option<String>,
+        path: String,
+    }
+
+    impl TestDir {
+        fn new() -> Option<Self> {
+            let host = env::var("SLOOP_TEST_SFTP_HOST").ok()?;
+            let dir = env::var("SLOOP_TEST_SFTP_DIR").unwrap_or_else(|_| "upload".to_owned());
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos();
+            Some(Self {
+                host,
+                port: env::var("SLOOP_TEST_SFTP_PORT").ok(),
+                user: env::var("SLOOP_TEST_SFTP_USER").ok(),
+                path: format!("{}/sloop-exit-{}-{}", dir, std::process::id(), nanos),
+            })
+        }
+
+        /// The options publishing to the directory with `--backend sftp`.
+        fn args(&self) -> Vec<&str> {
+            let mut args = vec!["--backend", "sftp", "--host", &self.host];
+            if let Some(port) = &self.port {
+                args.extend(["--port", port]);
+            }
+            if let Some(user) = &self.user {
+                args.extend(["--user", user]);
+            }
+            args.extend([
+                "--remote-dir",
+                &self.path,
+                "--public-url",
+                "https://eg.test/podcasts",
+            ]);
+            args
+        }
+
+        /// Runs the sftp `commands` against the server, returning what they printed.
+        fn sftp(&self, commands: &str) -> String {
+            let mut command = Command::new("sftp");
+            command.args(["-b", "-"]);
+            if let Some(port) = &self.port {
+                command.args(["-P", port]);
+            }
+            let destination = match &self.user {
+                Some(user) => format!("{}@{}", user, self.host),
+                None => self.host.clone(),
+            };
+            let mut child = command
+                .arg(destination)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            let _ = child.stdin.take().unwrap().write_all(commands.as_bytes());
+            let output = child.wait_with_output().unwrap();
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            self.sftp(&format!(
+                "-rm \"{0}/candide/*\"\n-rmdir \"{0}/candide\"\n-rmdir \"{0}\"\n",
+                self.path
+            ));
+        }
+    }
+
+    #[test]
+    fn publishing_exits_0_then_3_once_nothing_has_changed() {
+        let dir = match TestDir::new() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let workspace = Workspace::new("sftp-publish");
+        let out = workspace.path("feed.xml");
+        let episode = workspace.path("Chapter_1.mp3");
+        let publish = |action: &str| {
+            let feed = ["feed", "--title", "Candide", "--out", &out, "--quiet"];
+            let target = ["--prefix", "candide", action, &episode];
+            workspace.sloop(&[&feed[..], &dir.args(), &target].concat())
+        };
+        let output = publish("--plan");
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let output = publish("--upload");
+        assert_eq!(exit_code(&output), 0, "{}", stderr(&output));
+        let listing = dir.sftp(&format!("ls -ln \"{}/candide\"\n", dir.path));
+        assert!(listing.contains(" 12 "), "{}", listing);
+        assert!(listing.contains("Chapter_1.mp3"), "{}", listing);
+        assert!(listing.contains("feed.xml"), "{}", listing);
+        assert!(listing.contains("-rw-r--r--"), "{}", listing);
+        let output = publish("--upload");
+        assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+        let output = publish("--plan");
+        assert_eq!(exit_code(&output), 3, "{}", stderr(&output));
+    }
+}